@@ -4,8 +4,8 @@ use anyhow::anyhow;
 use futures_util::StreamExt;
 use moly_protocol::{
     open_ai::{
-        ChatResponse, ChatResponseChunkData, ChatResponseData, ChunkChoiceData, MessageData, Role,
-        StopReason,
+        ChatRequestData, ChatResponse, ChatResponseChunkData, ChatResponseData, ChoiceData,
+        ChunkChoiceData, MessageData, Role, StopReason, UsageData,
     },
     protocol::LoadModelOptions,
 };
@@ -13,7 +13,7 @@ use wasmedge_sdk::{wasi::WasiModule, Module, Store, Vm};
 
 use crate::store::download_files::DownloadedFile;
 
-use super::BackendModel;
+use super::{BackendModel, Supervisor};
 
 // From https://github.com/L-jasmine/LlamaEdge/tree/feat/support_unload_and_exit
 // A repo that fork from LlamaEdge/LlamaEdge for support unload model and exit
@@ -30,6 +30,7 @@ pub struct LLamaEdgeApiServer {
     #[allow(dead_code)]
     model_thread: std::thread::JoinHandle<()>,
     failed: bool,
+    supervisor: Supervisor,
 }
 
 fn create_wasi(
@@ -127,31 +128,45 @@ pub fn run_wasm_by_downloaded_file(
     file: DownloadedFile,
     load_model: LoadModelOptions,
     embedding: Option<(std::path::PathBuf, u64)>,
-) {
+) -> anyhow::Result<()> {
     use wasmedge_sdk::AsInstance;
 
     let mut instances = HashMap::new();
 
-    let mut wasi = create_wasi(listen_addr, &file, &load_model, embedding).unwrap();
+    let mut wasi = create_wasi(listen_addr, &file, &load_model, embedding)
+        .map_err(|e| anyhow!("Failed to create the WASI module: {e}"))?;
     instances.insert(wasi.name().to_string(), wasi.as_mut());
 
-    let mut wasi_nn = wasmedge_sdk::plugin::PluginManager::load_plugin_wasi_nn().unwrap();
-    instances.insert(wasi_nn.name().unwrap(), &mut wasi_nn);
+    let mut wasi_nn = wasmedge_sdk::plugin::PluginManager::load_plugin_wasi_nn()
+        .map_err(|e| anyhow!("Failed to load the wasi_nn plugin: {e}"))?;
+    let wasi_nn_name = wasi_nn
+        .name()
+        .ok_or_else(|| anyhow!("The wasi_nn plugin has no name"))?;
+    instances.insert(wasi_nn_name, &mut wasi_nn);
 
     let mut wasi_logger = wasmedge_sdk::plugin::PluginManager::create_plugin_instance(
         "wasi_logging",
         "wasi:logging/logging",
     )
-    .unwrap();
-    instances.insert(wasi_logger.name().unwrap(), &mut wasi_logger);
-
-    let store = Store::new(None, instances).unwrap();
+    .map_err(|e| anyhow!("Failed to load the wasi_logging plugin: {e}"))?;
+    let wasi_logger_name = wasi_logger
+        .name()
+        .ok_or_else(|| anyhow!("The wasi_logging plugin has no name"))?;
+    instances.insert(wasi_logger_name, &mut wasi_logger);
+
+    let store =
+        Store::new(None, instances).map_err(|e| anyhow!("Failed to create the store: {e}"))?;
     let mut vm = Vm::new(store);
-    vm.register_module(None, wasm_module.clone()).unwrap();
+    vm.register_module(None, wasm_module.clone())
+        .map_err(|e| anyhow!("Failed to register the server module: {e}"))?;
 
-    let _ = vm.run_func(None, "_start", []);
+    let r = vm
+        .run_func(None, "_start", [])
+        .map(|_| ())
+        .map_err(|e| anyhow!("{e}"));
 
     log::debug!("wasm exit");
+    r
 }
 
 fn stop_chunk(reason: StopReason) -> ChatResponseChunkData {
@@ -169,10 +184,116 @@ fn stop_chunk(reason: StopReason) -> ChatResponseChunkData {
         created: 0,
         model: String::new(),
         system_fingerprint: String::new(),
+        usage: None,
         object: "chat.completion.chunk".to_string(),
     }
 }
 
+// Based on https://platform.openai.com/docs/api-reference/completions, the server
+// takes the prompt as it is
+#[derive(serde::Serialize)]
+struct CompletionRequest {
+    model: String,
+    prompt: String,
+    max_tokens: Option<u32>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    stop: Option<Vec<String>>,
+    presence_penalty: Option<f32>,
+    frequency_penalty: Option<f32>,
+    stream: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct CompletionChoice {
+    text: String,
+    finish_reason: Option<String>,
+}
+
+impl CompletionChoice {
+    fn stop_reason(&self) -> Option<StopReason> {
+        match self.finish_reason.as_deref() {
+            Some("length") => Some(StopReason::Length),
+            Some(_) => Some(StopReason::Stop),
+            None => None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CompletionResponse {
+    id: String,
+    choices: Vec<CompletionChoice>,
+    created: u32,
+    usage: UsageData,
+}
+
+impl CompletionResponse {
+    /// The answer as the chat completion the frontend asked for.
+    fn into_chat_response(self) -> ChatResponseData {
+        let choice = self.choices.first();
+        let content = choice.map(|c| c.text.clone()).unwrap_or_default();
+        let reason = choice
+            .and_then(CompletionChoice::stop_reason)
+            .unwrap_or(StopReason::Stop);
+
+        ChatResponseData {
+            id: self.id,
+            choices: vec![ChoiceData {
+                finish_reason: reason,
+                index: 0,
+                message: MessageData {
+                    content,
+                    role: Role::Assistant,
+                },
+                logprobs: None,
+            }],
+            created: self.created,
+            model: "moly-chat".to_string(),
+            system_fingerprint: String::new(),
+            usage: self.usage,
+            object: "chat.completion".to_string(),
+        }
+    }
+}
+
+// A streamed piece of the answer, sent as server sent events
+#[derive(serde::Deserialize)]
+struct CompletionChunk {
+    id: String,
+    choices: Vec<CompletionChoice>,
+    created: u32,
+    #[serde(default)]
+    usage: Option<UsageData>,
+}
+
+impl CompletionChunk {
+    /// The piece of the answer as the chat completion chunk the frontend asked for.
+    fn into_chat_chunk(self) -> ChatResponseChunkData {
+        ChatResponseChunkData {
+            id: self.id,
+            choices: self
+                .choices
+                .into_iter()
+                .map(|choice| ChunkChoiceData {
+                    finish_reason: choice.stop_reason(),
+                    index: 0,
+                    delta: MessageData {
+                        content: choice.text,
+                        role: Role::Assistant,
+                    },
+                    logprobs: None,
+                })
+                .collect(),
+            created: self.created,
+            model: "moly-chat".to_string(),
+            system_fingerprint: String::new(),
+            usage: self.usage,
+            object: "chat.completion.chunk".to_string(),
+        }
+    }
+}
+
 impl BackendModel for LLamaEdgeApiServer {
     fn new_or_reload(
         async_rt: &tokio::runtime::Runtime,
@@ -181,6 +302,7 @@ impl BackendModel for LLamaEdgeApiServer {
         options: moly_protocol::protocol::LoadModelOptions,
         tx: std::sync::mpsc::Sender<anyhow::Result<moly_protocol::protocol::LoadModelResponse>>,
         embedding: Option<(std::path::PathBuf, u64)>,
+        supervisor: Supervisor,
     ) -> Self {
         let load_model_options = options.clone();
         let mut need_reload = true;
@@ -203,6 +325,9 @@ impl BackendModel for LLamaEdgeApiServer {
                 && listen_addr == old_model.listen_addr
                 && old_model.load_model_options.n_ctx == options.n_ctx
                 && old_model.load_model_options.n_batch == options.n_batch
+                && old_model.load_model_options.gpu_layers == options.gpu_layers
+                && old_model.load_model_options.use_mlock == options.use_mlock
+                && old_model.load_model_options.prompt_template == options.prompt_template
                 && old_model.embedding == embedding
             {
                 need_reload = false;
@@ -249,6 +374,8 @@ impl BackendModel for LLamaEdgeApiServer {
         if let Some(old_model) = old_model {
             if !old_model.failed {
                 old_model.stop(async_rt);
+            } else {
+                old_model.supervisor.stop();
             }
         }
 
@@ -263,12 +390,21 @@ impl BackendModel for LLamaEdgeApiServer {
 
         let embedding_ = embedding.clone();
 
-        let model_thread = std::thread::spawn(move || {
+        let events = supervisor.events();
+        events.server_log(format!(
+            "Starting the model server of {} on {}",
+            file_id, listen_addr
+        ));
+        let model_thread = supervisor.spawn(move || {
             run_wasm_by_downloaded_file(listen_addr, wasm_module_, file, options, embedding_)
         });
 
         let mut test_server = false;
         for _i in 0..20 {
+            // The server exited, the supervisor failed the load with the reason
+            if supervisor.is_stopped() {
+                break;
+            }
             let r = reqwest::blocking::ClientBuilder::new()
                 .timeout(Duration::from_secs(3))
                 .no_proxy()
@@ -285,6 +421,10 @@ impl BackendModel for LLamaEdgeApiServer {
             let _ = std::thread::sleep(std::time::Duration::from_secs(3));
         }
         if test_server {
+            events.server_log(format!(
+                "The model server of {} is listening on port {}",
+                file_id, listen_port
+            ));
             let _ = tx.send(Ok(moly_protocol::protocol::LoadModelResponse::Completed(
                 moly_protocol::protocol::LoadedModelInfo {
                     file_id: file_.id.to_string(),
@@ -293,8 +433,14 @@ impl BackendModel for LLamaEdgeApiServer {
                     listen_port,
                 },
             )));
-        } else {
+            supervisor.watch_health(url);
+        } else if !supervisor.is_stopped() {
+            events.server_log(format!(
+                "The model server of {} did not answer on {}",
+                file_id, url
+            ));
             let _ = tx.send(Err(anyhow!("Failed to start the model")));
+            supervisor.stop();
         }
 
         let running_controller = tokio::sync::broadcast::channel(1).0;
@@ -308,6 +454,7 @@ impl BackendModel for LLamaEdgeApiServer {
             model_thread,
             load_model_options,
             failed: !test_server,
+            supervisor,
         };
 
         new_model
@@ -325,10 +472,12 @@ impl BackendModel for LLamaEdgeApiServer {
             self.listen_addr.port()
         );
         let mut cancel = self.running_controller.subscribe();
+        let activity = self.supervisor.begin_request();
 
         data.model = "moly-chat".to_string();
 
         async_rt.spawn(async move {
+            let _activity = activity;
             let request_body = serde_json::to_string(&data).unwrap();
             let request = reqwest::ClientBuilder::new()
                 .no_proxy()
@@ -405,11 +554,122 @@ impl BackendModel for LLamaEdgeApiServer {
         true
     }
 
+    fn complete(
+        &self,
+        async_rt: &tokio::runtime::Runtime,
+        prompt: String,
+        data: ChatRequestData,
+        tx: std::sync::mpsc::Sender<anyhow::Result<ChatResponse>>,
+    ) -> bool {
+        let is_stream = data.stream.unwrap_or(false);
+        let url = format!(
+            "http://localhost:{}/v1/completions",
+            self.listen_addr.port()
+        );
+        let mut cancel = self.running_controller.subscribe();
+        let activity = self.supervisor.begin_request();
+
+        let request = CompletionRequest {
+            model: "moly-chat".to_string(),
+            prompt,
+            max_tokens: data.max_tokens,
+            temperature: data.temperature,
+            top_p: data.top_p,
+            stop: data.stop,
+            presence_penalty: data.presence_penalty,
+            frequency_penalty: data.frequency_penalty,
+            stream: is_stream,
+        };
+
+        async_rt.spawn(async move {
+            let _activity = activity;
+            let request = reqwest::ClientBuilder::new()
+                .no_proxy()
+                .build()
+                .unwrap()
+                .post(url)
+                .json(&request);
+
+            let resp = tokio::select! {
+                res = async { request.send().await?.error_for_status() } => Some(res.map_err(|e| anyhow!(e))),
+                _ = cancel.recv() => None,
+            };
+
+            let resp = match resp {
+                Some(Ok(resp)) => resp,
+                Some(Err(e)) => {
+                    let _ = tx.send(Err(e));
+                    let _ = tx.send(Ok(ChatResponse::ChatResponseChunk(stop_chunk(
+                        StopReason::Stop,
+                    ))));
+                    return;
+                }
+                None => {
+                    let _ = tx.send(Ok(ChatResponse::ChatResponseChunk(stop_chunk(
+                        StopReason::Stop,
+                    ))));
+                    return;
+                }
+            };
+
+            if is_stream {
+                let mut stream = resp.bytes_stream();
+
+                while let Some(chunk) = tokio::select! {
+                    chunk = stream.next() => chunk,
+                    _ = cancel.recv() => None,
+                } {
+                    match chunk {
+                        Ok(chunk) => {
+                            if chunk.starts_with(b"data: [DONE]") {
+                                break;
+                            }
+                            let resp: Result<CompletionChunk, anyhow::Error> =
+                                serde_json::from_slice(&chunk[5..]).map_err(|e| anyhow!(e));
+                            let _ = tx.send(
+                                resp.map(|chunk| ChatResponse::ChatResponseChunk(chunk.into_chat_chunk())),
+                            );
+                        }
+                        Err(e) => {
+                            let _ = tx.send(Err(anyhow!(e)));
+                            return;
+                        }
+                    }
+                }
+
+                let _ = tx.send(Ok(ChatResponse::ChatResponseChunk(stop_chunk(
+                    StopReason::Stop,
+                ))));
+            } else {
+                let resp = tokio::select! {
+                    res = resp.json::<CompletionResponse>() => Some(res.map_err(|e| anyhow!(e))),
+                    _ = cancel.recv() => None,
+                };
+
+                match resp {
+                    Some(resp) => {
+                        let _ = tx.send(resp.map(|resp| {
+                            ChatResponse::ChatFinalResponseData(resp.into_chat_response())
+                        }));
+                    }
+                    None => {
+                        let _ = tx.send(Ok(ChatResponse::ChatResponseChunk(stop_chunk(
+                            StopReason::Stop,
+                        ))));
+                    }
+                }
+            }
+        });
+
+        true
+    }
+
     fn stop_chat(&self, _async_rt: &tokio::runtime::Runtime) {
         let _ = self.running_controller.send(());
     }
 
-    fn stop(self, _async_rt: &tokio::runtime::Runtime) {
+    fn exit(self, _async_rt: &tokio::runtime::Runtime) -> std::thread::JoinHandle<()> {
+        self.supervisor.stop();
         let url = format!("http://localhost:{}/admin/exit", self.listen_addr.port());
         let _ = reqwest::blocking::ClientBuilder::new()
             .timeout(Duration::from_secs(2))
@@ -419,6 +679,6 @@ impl BackendModel for LLamaEdgeApiServer {
             .get(url)
             .send();
 
-        let _ = self.model_thread.join();
+        self.model_thread
     }
 }
@@ -0,0 +1,212 @@
+use std::path::Path;
+
+use anyhow::anyhow;
+use minijinja::{
+    context,
+    value::{from_args, Value, ValueKind},
+    Environment, Error, ErrorKind, State,
+};
+use moly_protocol::{
+    data::ChatTemplateSource,
+    open_ai::{Message, Role},
+};
+
+use crate::store::gguf;
+
+/// A Jinja chat template, rendered on our side into the raw prompt of a chat.
+///
+/// Covers the subset of Jinja used by the templates of Hugging Face tokenizers, the
+/// ones embedded in GGUF files as `tokenizer.chat_template`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatTemplate {
+    pub source: String,
+    pub bos_token: String,
+    pub eos_token: String,
+}
+
+impl ChatTemplate {
+    /// The template to format chats with the GGUF file at `path`, `None` when the
+    /// server formats them.
+    ///
+    /// Custom templates still get the special tokens of the file, when it has them.
+    pub fn for_file(path: &Path, source: &ChatTemplateSource) -> anyhow::Result<Option<Self>> {
+        let template = match source {
+            ChatTemplateSource::Server => return Ok(None),
+            ChatTemplateSource::Embedded => {
+                let metadata = gguf::read_chat_metadata(path)?;
+                let Some(template) = metadata.chat_template.clone() else {
+                    return Err(anyhow!("The model file has no chat template"));
+                };
+                Self::new(template, metadata)
+            }
+            ChatTemplateSource::Custom(template) => {
+                let metadata = gguf::read_chat_metadata(path).unwrap_or_else(|e| {
+                    log::warn!("read chat metadata of {:?} error: {e}", path);
+                    Default::default()
+                });
+                Self::new(template.clone(), metadata)
+            }
+        };
+
+        Ok(Some(template))
+    }
+
+    fn new(source: String, metadata: gguf::ChatMetadata) -> Self {
+        Self {
+            source,
+            bos_token: metadata.bos_token.unwrap_or_default(),
+            eos_token: metadata.eos_token.unwrap_or_default(),
+        }
+    }
+
+    /// The prompt for the messages, ending where the assistant answers.
+    pub fn render(&self, messages: &[Message]) -> anyhow::Result<String> {
+        let mut env = Environment::new();
+        // Like the transformers library renders them
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env.set_unknown_method_callback(python_method);
+        env.add_function(
+            "raise_exception",
+            |message: String| -> Result<String, Error> {
+                Err(Error::new(ErrorKind::InvalidOperation, message))
+            },
+        );
+
+        let messages: Vec<_> = messages
+            .iter()
+            .map(|message| {
+                context! {
+                    role => message.role,
+                    content => message.content,
+                }
+            })
+            .collect();
+
+        env.render_str(
+            &self.source,
+            context! {
+                messages => messages,
+                add_generation_prompt => true,
+                bos_token => self.bos_token,
+                eos_token => self.eos_token,
+            },
+        )
+        .map_err(|e| anyhow!("Failed to render the chat template: {e}"))
+    }
+}
+
+/// The Python string and dict methods templates call, like `message['content'].strip()`.
+fn python_method(
+    state: &State,
+    value: &Value,
+    method: &str,
+    args: &[Value],
+) -> Result<Value, Error> {
+    if let Some(s) = value.as_str() {
+        match method {
+            "strip" | "lstrip" | "rstrip" => {
+                let (chars,): (Option<&str>,) = from_args(args)?;
+                let is_stripped = |c: char| match chars {
+                    Some(chars) => chars.contains(c),
+                    None => c.is_whitespace(),
+                };
+                let stripped = match method {
+                    "lstrip" => s.trim_start_matches(is_stripped),
+                    "rstrip" => s.trim_end_matches(is_stripped),
+                    _ => s.trim_matches(is_stripped),
+                };
+                return Ok(Value::from(stripped));
+            }
+            "startswith" => {
+                let (prefix,): (&str,) = from_args(args)?;
+                return Ok(Value::from(s.starts_with(prefix)));
+            }
+            "endswith" => {
+                let (suffix,): (&str,) = from_args(args)?;
+                return Ok(Value::from(s.ends_with(suffix)));
+            }
+            "split" => {
+                let (separator,): (Option<&str>,) = from_args(args)?;
+                let parts: Vec<String> = match separator {
+                    Some(separator) => s.split(separator).map(str::to_string).collect(),
+                    None => s.split_whitespace().map(str::to_string).collect(),
+                };
+                return Ok(Value::from(parts));
+            }
+            "replace" => {
+                let (from, to): (&str, &str) = from_args(args)?;
+                return Ok(Value::from(s.replace(from, to)));
+            }
+            "upper" | "lower" | "title" | "capitalize" => {
+                let _: () = from_args(args)?;
+                return state.apply_filter(method, std::slice::from_ref(value));
+            }
+            _ => {}
+        }
+    } else if value.kind() == ValueKind::Map {
+        match method {
+            "items" => {
+                let _: () = from_args(args)?;
+                return state.apply_filter("items", std::slice::from_ref(value));
+            }
+            "get" => {
+                let (key, default): (Value, Option<Value>) = from_args(args)?;
+                let item = value.get_item(&key)?;
+                return Ok(match item.is_undefined() {
+                    true => default.unwrap_or(Value::from(())),
+                    false => item,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::UnknownMethod,
+        format!("{method} is not a method of {}", value.kind()),
+    ))
+}
+
+/// A short conversation to check that a template renders.
+pub fn sample_messages() -> Vec<Message> {
+    let message = |role: Role, content: &str| Message {
+        content: content.to_string(),
+        role,
+        name: None,
+    };
+
+    vec![
+        message(Role::System, "You are a helpful assistant."),
+        message(Role::User, "Hello!"),
+        message(Role::Assistant, "Hi, how can I help you?"),
+        message(Role::User, "What is the capital of France?"),
+    ]
+}
+
+#[test]
+fn test_render() {
+    // The template of the Zephyr models
+    let template = ChatTemplate {
+        source: "{% for message in messages %}\n{% if message['role'] == 'user' %}\n{{ '<|user|>\n' + message['content'] + eos_token }}\n{% elif message['role'] == 'system' %}\n{{ '<|system|>\n' + message['content'] + eos_token }}\n{% elif message['role'] == 'assistant' %}\n{{ '<|assistant|>\n'  + message['content'] + eos_token }}\n{% endif %}\n{% if loop.last and add_generation_prompt %}\n{{ '<|assistant|>' }}\n{% endif %}\n{% endfor %}".to_string(),
+        bos_token: "<s>".to_string(),
+        eos_token: "</s>".to_string(),
+    };
+
+    let prompt = template.render(&sample_messages()[..2]).unwrap();
+    assert_eq!(
+        prompt,
+        "<|system|>\nYou are a helpful assistant.</s>\n<|user|>\nHello!</s>\n<|assistant|>\n"
+    );
+
+    // Python methods and raised errors
+    let template = ChatTemplate {
+        source: "{% if messages[0]['role'] != 'user' %}{{ raise_exception('Conversation roles must start with user') }}{% endif %}{{ messages[0]['content'].strip() }}".to_string(),
+        ..Default::default()
+    };
+    let mut messages = sample_messages();
+    assert!(template.render(&messages).is_err());
+    messages.remove(0);
+    messages[0].content = "  Hello!  ".to_string();
+    assert_eq!(template.render(&messages).unwrap(), "Hello!");
+}
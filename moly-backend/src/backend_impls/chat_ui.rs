@@ -48,6 +48,8 @@ use wasmedge_sdk::{
 
 use crate::store::download_files::DownloadedFile;
 
+use super::Supervisor;
+
 #[derive(Debug)]
 pub struct ChatBotUi {
     pub current_req: std::io::Cursor<Vec<u8>>,
@@ -153,6 +155,7 @@ impl ChatBotUi {
                 created: 0,
                 model: String::new(),
                 system_fingerprint: String::new(),
+                usage: None,
                 object: "chat.completion.chunk".to_string(),
             })));
         };
@@ -178,6 +181,7 @@ impl ChatBotUi {
             created: 0,
             model: String::new(),
             system_fingerprint: String::new(),
+            usage: None,
             object: "chat.completion.chunk".to_string(),
         })));
         true
@@ -385,13 +389,14 @@ pub fn run_wasm_by_downloaded_file(
     load_model: LoadModelOptions,
     tx: Sender<anyhow::Result<LoadModelResponse>>,
     embedding: Option<(PathBuf, u64)>,
-) {
+) -> anyhow::Result<()> {
     use wasmedge_sdk::vm::SyncInst;
     use wasmedge_sdk::AsInstance;
 
     let mut instances: HashMap<String, &mut (dyn SyncInst)> = HashMap::new();
 
-    let mut wasi = create_wasi(&file, &load_model, embedding).unwrap();
+    let mut wasi = create_wasi(&file, &load_model, embedding)
+        .map_err(|e| anyhow::anyhow!("Failed to create the WASI module: {e}"))?;
     let mut chatui = module(ChatBotUi::new(
         request_rx,
         model_running_controller,
@@ -399,20 +404,33 @@ pub fn run_wasm_by_downloaded_file(
         load_model,
         tx,
     ))
-    .unwrap();
+    .map_err(|e| anyhow::anyhow!("Failed to create the chat_ui module: {e}"))?;
 
     instances.insert(wasi.name().to_string(), wasi.as_mut());
-    let mut wasi_nn = wasmedge_sdk::plugin::PluginManager::load_plugin_wasi_nn().unwrap();
-    instances.insert(wasi_nn.name().unwrap(), &mut wasi_nn);
-    instances.insert(chatui.name().unwrap(), &mut chatui);
-
-    let store = Store::new(None, instances).unwrap();
+    let mut wasi_nn = wasmedge_sdk::plugin::PluginManager::load_plugin_wasi_nn()
+        .map_err(|e| anyhow::anyhow!("Failed to load the wasi_nn plugin: {e}"))?;
+    let wasi_nn_name = wasi_nn
+        .name()
+        .ok_or_else(|| anyhow::anyhow!("The wasi_nn plugin has no name"))?;
+    instances.insert(wasi_nn_name, &mut wasi_nn);
+    let chatui_name = chatui
+        .name()
+        .ok_or_else(|| anyhow::anyhow!("The chat_ui module has no name"))?;
+    instances.insert(chatui_name, &mut chatui);
+
+    let store = Store::new(None, instances)
+        .map_err(|e| anyhow::anyhow!("Failed to create the store: {e}"))?;
     let mut vm = Vm::new(store);
-    vm.register_module(None, wasm_module.clone()).unwrap();
+    vm.register_module(None, wasm_module.clone())
+        .map_err(|e| anyhow::anyhow!("Failed to register the chat module: {e}"))?;
 
-    let _ = vm.run_func(None, "_start", []);
+    let r = vm
+        .run_func(None, "_start", [])
+        .map(|_| ())
+        .map_err(|e| anyhow::anyhow!("{e}"));
 
     log::debug!("wasm exit");
+    r
 }
 
 pub struct ChatBotModel {
@@ -421,6 +439,7 @@ pub struct ChatBotModel {
     pub model_tx: Sender<(ChatRequestData, Sender<anyhow::Result<ChatResponse>>)>,
     pub model_running_controller: Arc<AtomicBool>,
     pub model_thread: JoinHandle<()>,
+    supervisor: Supervisor,
 }
 
 static WASM: &[u8] = include_bytes!("../../wasm/chat_ui.wasm");
@@ -433,6 +452,7 @@ impl super::BackendModel for ChatBotModel {
         options: LoadModelOptions,
         tx: Sender<anyhow::Result<LoadModelResponse>>,
         embedding: Option<(PathBuf, u64)>,
+        supervisor: Supervisor,
     ) -> Self {
         let mut need_reload = true;
 
@@ -463,7 +483,10 @@ impl super::BackendModel for ChatBotModel {
 
         let file_id = file.id.to_string();
 
-        let model_thread = std::thread::spawn(move || {
+        supervisor
+            .events()
+            .server_log(format!("Starting the model of {}", file_id));
+        let model_thread = supervisor.spawn(move || {
             run_wasm_by_downloaded_file(
                 wasm_module_,
                 request_rx,
@@ -481,6 +504,7 @@ impl super::BackendModel for ChatBotModel {
             model_thread,
             model_running_controller,
             wasm_module,
+            supervisor,
         };
 
         if let Some(old_model) = old_model {
@@ -499,18 +523,33 @@ impl super::BackendModel for ChatBotModel {
         self.model_tx.send((data, tx)).is_ok()
     }
 
+    fn complete(
+        &self,
+        _async_rt: &tokio::runtime::Runtime,
+        _prompt: String,
+        _data: ChatRequestData,
+        tx: Sender<anyhow::Result<ChatResponse>>,
+    ) -> bool {
+        let _ = tx.send(Err(anyhow::anyhow!(
+            "Chat templates are not supported by this backend"
+        )));
+        false
+    }
+
     fn stop_chat(&self, _async_rt: &tokio::runtime::Runtime) {
         self.model_running_controller
             .store(false, Ordering::Release);
     }
 
-    fn stop(self, _async_rt: &tokio::runtime::Runtime) {
+    fn exit(self, _async_rt: &tokio::runtime::Runtime) -> JoinHandle<()> {
         let Self {
             model_tx,
             model_thread,
+            supervisor,
             ..
         } = self;
+        supervisor.stop();
         drop(model_tx);
-        let _ = model_thread.join();
+        model_thread
     }
 }
@@ -0,0 +1,239 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+};
+
+use moly_protocol::{
+    data::{CatalogSyncStatus, FileID},
+    protocol::{BackendEvent, FileDownloadResponse, LoadModelResponse, LoadedModelInfo},
+};
+
+/// What the events sent so far say about the backend, for the queries of clients
+/// that subscribed late.
+#[derive(Debug, Default)]
+struct EventState {
+    // Last progress of the running downloads
+    download_progress: HashMap<FileID, f32>,
+    loaded_model: Option<LoadedModelInfo>,
+}
+
+impl EventState {
+    fn apply(&mut self, event: &BackendEvent) {
+        match event {
+            BackendEvent::DownloadProgress(file_id, progress) => {
+                self.download_progress.insert(file_id.clone(), *progress);
+            }
+            BackendEvent::DownloadCompleted(file) => {
+                self.download_progress.remove(&file.file.id);
+            }
+            BackendEvent::DownloadPaused(file_id)
+            | BackendEvent::DownloadCancelled(file_id)
+            | BackendEvent::DownloadFailed(file_id, _) => {
+                self.download_progress.remove(file_id);
+            }
+            BackendEvent::ModelLoaded(info) => self.loaded_model = Some(info.clone()),
+            BackendEvent::ModelLoadFailed(file_id, _)
+            | BackendEvent::ModelEjected(file_id)
+            | BackendEvent::ModelCrashed(file_id, _) => {
+                if self
+                    .loaded_model
+                    .as_ref()
+                    .is_some_and(|info| info.file_id == *file_id)
+                {
+                    self.loaded_model = None;
+                }
+            }
+            BackendEvent::DownloadQueued(_)
+            | BackendEvent::CatalogUpdated(_)
+            | BackendEvent::ServerLog(_) => {}
+        }
+    }
+}
+
+/// Sends backend events to every subscriber, whatever command started what they
+/// are about.
+///
+/// Responses of downloads and model loads go through a relay that turns them into
+/// events before handing them to the sender of the command, which may be gone.
+#[derive(Debug, Clone, Default)]
+pub struct Events {
+    subscribers: Arc<Mutex<Vec<Sender<BackendEvent>>>>,
+    state: Arc<Mutex<EventState>>,
+}
+
+impl Events {
+    pub fn subscribe(&self, tx: Sender<BackendEvent>) {
+        self.subscribers.lock().unwrap().push(tx);
+    }
+
+    /// Send an event to the subscribers, forgetting the ones that went away.
+    pub fn emit(&self, event: BackendEvent) {
+        self.state.lock().unwrap().apply(&event);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|tx| tx.send(event.clone()).is_ok());
+    }
+
+    pub fn server_log(&self, line: impl Into<String>) {
+        self.emit(BackendEvent::ServerLog(line.into()));
+    }
+
+    /// Progress of a running download, as last reported by the downloader.
+    pub fn download_progress(&self, file_id: &FileID) -> Option<f32> {
+        self.state
+            .lock()
+            .unwrap()
+            .download_progress
+            .get(file_id)
+            .copied()
+    }
+
+    pub fn loaded_model(&self) -> Option<LoadedModelInfo> {
+        self.state.lock().unwrap().loaded_model.clone()
+    }
+
+    /// Forward the responses of a download to `tx`, sending each one as an event
+    /// first.
+    ///
+    /// The relay outlives `tx`, so the download is still followed when the client
+    /// that started it stops listening.
+    pub fn relay_download(
+        &self,
+        file_id: FileID,
+        tx: Sender<anyhow::Result<FileDownloadResponse>>,
+    ) -> Sender<anyhow::Result<FileDownloadResponse>> {
+        let (relay_tx, relay_rx) = channel::<anyhow::Result<FileDownloadResponse>>();
+        let events = self.clone();
+
+        std::thread::spawn(move || {
+            for response in relay_rx {
+                let event = match &response {
+                    Ok(FileDownloadResponse::Queued(file_id)) => {
+                        BackendEvent::DownloadQueued(file_id.clone())
+                    }
+                    Ok(FileDownloadResponse::Progress(file_id, progress)) => {
+                        BackendEvent::DownloadProgress(file_id.clone(), *progress)
+                    }
+                    Ok(FileDownloadResponse::Completed(file)) => {
+                        BackendEvent::DownloadCompleted(Box::new(file.clone()))
+                    }
+                    Err(e) => BackendEvent::DownloadFailed(file_id.clone(), e.to_string()),
+                };
+                events.emit(event);
+                let _ = tx.send(response);
+            }
+
+            // Paused or stopped, the saved segments tell how far it got
+            events
+                .state
+                .lock()
+                .unwrap()
+                .download_progress
+                .remove(&file_id);
+        });
+
+        relay_tx
+    }
+
+    /// Forward the responses of a model load to `tx`, sending the outcome as an
+    /// event first.
+    pub fn relay_load(
+        &self,
+        file_id: FileID,
+        tx: Sender<anyhow::Result<LoadModelResponse>>,
+    ) -> Sender<anyhow::Result<LoadModelResponse>> {
+        let (relay_tx, relay_rx) = channel::<anyhow::Result<LoadModelResponse>>();
+        let events = self.clone();
+
+        std::thread::spawn(move || {
+            for response in relay_rx {
+                match &response {
+                    Ok(LoadModelResponse::Completed(info)) => {
+                        events.emit(BackendEvent::ModelLoaded(info.clone()))
+                    }
+                    Err(e) => events.emit(BackendEvent::ModelLoadFailed(
+                        file_id.clone(),
+                        e.to_string(),
+                    )),
+                    Ok(_) => {}
+                }
+                let _ = tx.send(response);
+            }
+        });
+
+        relay_tx
+    }
+
+    /// A subscriber to the catalog sync that sends every status as an event.
+    pub fn relay_catalog(&self) -> Sender<CatalogSyncStatus> {
+        let (relay_tx, relay_rx) = channel();
+        let events = self.clone();
+
+        std::thread::spawn(move || {
+            for status in relay_rx {
+                events.emit(BackendEvent::CatalogUpdated(status));
+            }
+        });
+
+        relay_tx
+    }
+}
+
+#[test]
+fn test_events() {
+    let events = Events::default();
+    let (tx, rx) = channel();
+    events.subscribe(tx);
+
+    let (download_tx, download_rx) = channel();
+    let relay_tx = events.relay_download("org/model#file.gguf".to_string(), download_tx);
+    // The client that started the download is gone, events still flow
+    drop(download_rx);
+    relay_tx
+        .send(Ok(FileDownloadResponse::Progress(
+            "org/model#file.gguf".to_string(),
+            42.0,
+        )))
+        .unwrap();
+
+    match rx.recv().unwrap() {
+        BackendEvent::DownloadProgress(file_id, progress) => {
+            assert_eq!(file_id, "org/model#file.gguf");
+            assert_eq!(progress, 42.0);
+        }
+        event => panic!("unexpected event {event:?}"),
+    }
+    assert_eq!(
+        events.download_progress(&"org/model#file.gguf".to_string()),
+        Some(42.0)
+    );
+
+    relay_tx
+        .send(Err(anyhow::anyhow!("network error")))
+        .unwrap();
+    assert!(matches!(
+        rx.recv().unwrap(),
+        BackendEvent::DownloadFailed(_, e) if e == "network error"
+    ));
+    assert_eq!(
+        events.download_progress(&"org/model#file.gguf".to_string()),
+        None
+    );
+
+    let info = LoadedModelInfo {
+        file_id: "org/model#file.gguf".to_string(),
+        model_id: "org/model".to_string(),
+        listen_port: 8080,
+        information: String::new(),
+    };
+    events.emit(BackendEvent::ModelLoaded(info));
+    assert_eq!(events.loaded_model().unwrap().listen_port, 8080);
+    events.emit(BackendEvent::ModelEjected(
+        "org/model#file.gguf".to_string(),
+    ));
+    assert!(events.loaded_model().is_none());
+}
@@ -1,39 +1,107 @@
 use std::{
+    collections::HashMap,
     path::{Path, PathBuf},
     sync::{
-        mpsc::{Receiver, Sender},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
         Arc, Mutex,
     },
+    time::{Duration, Instant},
 };
 
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use moly_protocol::{
-    data::{DownloadedFile, FileID, Model, PendingDownload},
-    open_ai::{ChatRequestData, ChatResponse},
+    data::{
+        CatalogSource, CatalogSyncStatus, CatalogTrust, ChatTemplateSettings, ChatTemplateSource,
+        CleanupPlan, DownloadPriority, DownloadSourceSettings, DownloadedFile, FileID, LoadPreset,
+        LoadPresets, Model, ModelCollection, ModelID, PendingDownload, SearchQuery, SearchResults,
+        StorageQuota, StorageUsage, UsageStats,
+    },
+    open_ai::{ChatRequestData, ChatResponse, Message},
     protocol::{
-        Command, FileDownloadResponse, LoadModelOptions, LoadModelResponse, LocalServerConfig,
-        LocalServerResponse,
+        BackendEvent, CatalogTrustError, Command, FileDownloadResponse, LoadModelOptions,
+        LoadModelResponse, LoadedModelInfo, LocalServerConfig, LocalServerResponse,
     },
 };
 
 use crate::store::{
     self,
-    model_cards::{self, ModelCard, ModelCardManager},
-    ModelFileDownloader,
+    catalog_sync::CatalogSync,
+    license_acceptances::LicenseAcceptance,
+    mirrors::DownloadSources,
+    model_cards::{CatalogUpdate, ModelCardManager},
+    DownloadKind, ModelFileDownloader,
 };
 
 mod api_server;
+mod chat_template;
 mod chat_ui;
+mod events;
+mod supervisor;
+mod usage;
+
+use events::Events;
+use supervisor::{ModelCrash, Supervisor};
+
+// How often the run loop checks for crashes of the model between commands
+const SUPERVISION_INTERVAL: Duration = Duration::from_secs(1);
+// Delay before loading a crashed model again, doubled with each crash in a row
+const RELOAD_DELAY: Duration = Duration::from_secs(2);
+const MAX_RELOAD_DELAY: Duration = Duration::from_secs(60);
+const MAX_RELOAD_ATTEMPTS: u32 = 5;
+// A model running longer than this before crashing starts over with the delays
+const CRASH_RESET: Duration = Duration::from_secs(10 * 60);
+// How long a shutdown waits for the running downloads to save their progress, and
+// then for the model to exit
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Clone, Debug)]
 enum ModelManagementCommand {
+    Subscribe(Sender<BackendEvent>),
     GetFeaturedModels(Sender<anyhow::Result<Vec<Model>>>),
-    SearchModels(String, Sender<anyhow::Result<Vec<Model>>>),
+    GetFeaturedCollections(Sender<anyhow::Result<Vec<ModelCollection>>>),
+    SearchModels(SearchQuery, Sender<anyhow::Result<SearchResults>>),
+    GetCatalogSyncStatus(Sender<anyhow::Result<CatalogSyncStatus>>),
+    SubscribeCatalogUpdates(Sender<CatalogSyncStatus>),
+    RefreshCatalog(Sender<anyhow::Result<()>>),
+    GetCatalogSources(Sender<anyhow::Result<Vec<CatalogSource>>>),
+    SetCatalogSources(Vec<CatalogSource>, Sender<anyhow::Result<()>>),
+    AcceptModelLicense(ModelID, Sender<anyhow::Result<DateTime<Utc>>>),
     DownloadFile(FileID, Sender<anyhow::Result<FileDownloadResponse>>),
+    UpdateFile(FileID, Sender<anyhow::Result<FileDownloadResponse>>),
     PauseDownload(FileID, Sender<anyhow::Result<()>>),
     CancelDownload(FileID, Sender<anyhow::Result<()>>),
+    SetDownloadBandwidthLimit(Option<u64>, Sender<anyhow::Result<()>>),
+    SetFileBandwidthLimit(FileID, Option<u64>, Sender<anyhow::Result<()>>),
+    SetDownloadPriority(FileID, DownloadPriority, Sender<anyhow::Result<()>>),
+    ReorderDownloadQueue(Vec<FileID>, Sender<anyhow::Result<()>>),
+    StartDownloadNext(FileID, Sender<anyhow::Result<()>>),
+    GetDownloadSourceSettings(Sender<anyhow::Result<DownloadSourceSettings>>),
+    SetDownloadMirrors(Vec<String>, Sender<anyhow::Result<()>>),
+    SetRegion(Option<String>, Sender<anyhow::Result<()>>),
+    SetAllowUnverifiedDownloads(bool, Sender<anyhow::Result<()>>),
+    SetAccessToken(String, Option<String>, Sender<anyhow::Result<()>>),
     GetCurrentDownloads(Sender<anyhow::Result<Vec<PendingDownload>>>),
     GetDownloadedFiles(Sender<anyhow::Result<Vec<DownloadedFile>>>),
+    GetStorageUsage(Sender<anyhow::Result<StorageUsage>>),
+    SetStorageQuota(StorageQuota, Sender<anyhow::Result<()>>),
+    SetFilePinned(FileID, bool, Sender<anyhow::Result<()>>),
+    PlanStorageCleanup(Vec<FileID>, Sender<anyhow::Result<Option<CleanupPlan>>>),
+    GetUsageStats(FileID, Sender<anyhow::Result<UsageStats>>),
+    GetLoadPresets(FileID, Sender<anyhow::Result<LoadPresets>>),
+    SaveLoadPreset(FileID, LoadPreset, Sender<anyhow::Result<()>>),
+    RemoveLoadPreset(FileID, String, Sender<anyhow::Result<()>>),
+    SelectLoadPreset(FileID, Option<String>, Sender<anyhow::Result<()>>),
+    GetChatTemplate(FileID, Sender<anyhow::Result<ChatTemplateSettings>>),
+    SetChatTemplate(FileID, ChatTemplateSource, Sender<anyhow::Result<()>>),
+    PreviewChatTemplate(
+        FileID,
+        ChatTemplateSource,
+        Vec<Message>,
+        Sender<anyhow::Result<String>>,
+    ),
+    GetEmbeddingModel(Sender<anyhow::Result<Option<FileID>>>),
+    SetEmbeddingModel(Option<FileID>, Sender<anyhow::Result<()>>),
+    GetDefaultEmbeddingModel(Sender<anyhow::Result<Option<Model>>>),
     DeleteFile(FileID, Sender<anyhow::Result<()>>),
     ChangeModelsLocation(PathBuf),
 }
@@ -46,6 +114,9 @@ enum ModelInteractionCommand {
         Sender<anyhow::Result<LoadModelResponse>>,
     ),
     EjectModel(Sender<anyhow::Result<()>>),
+    GetLoadedModel(Sender<anyhow::Result<Option<LoadedModelInfo>>>),
+    GetAutoReloadModel(Sender<anyhow::Result<bool>>),
+    SetAutoReloadModel(bool, Sender<anyhow::Result<()>>),
     Chat(ChatRequestData, Sender<anyhow::Result<ChatResponse>>),
     StopChatCompletion(Sender<anyhow::Result<()>>),
     // Command to start a local server to interact with chat models
@@ -61,20 +132,47 @@ enum ModelInteractionCommand {
 enum BuiltInCommand {
     Model(ModelManagementCommand),
     Interaction(ModelInteractionCommand),
+    Shutdown(Sender<anyhow::Result<()>>),
 }
 
 impl From<Command> for BuiltInCommand {
     fn from(value: Command) -> Self {
         match value {
+            Command::Subscribe(tx) => Self::Model(ModelManagementCommand::Subscribe(tx)),
+            Command::GetLoadedModel(tx) => {
+                Self::Interaction(ModelInteractionCommand::GetLoadedModel(tx))
+            }
             Command::GetFeaturedModels(tx) => {
                 Self::Model(ModelManagementCommand::GetFeaturedModels(tx))
             }
+            Command::GetFeaturedCollections(tx) => {
+                Self::Model(ModelManagementCommand::GetFeaturedCollections(tx))
+            }
             Command::SearchModels(request, tx) => {
                 Self::Model(ModelManagementCommand::SearchModels(request, tx))
             }
+            Command::GetCatalogSyncStatus(tx) => {
+                Self::Model(ModelManagementCommand::GetCatalogSyncStatus(tx))
+            }
+            Command::SubscribeCatalogUpdates(tx) => {
+                Self::Model(ModelManagementCommand::SubscribeCatalogUpdates(tx))
+            }
+            Command::RefreshCatalog(tx) => Self::Model(ModelManagementCommand::RefreshCatalog(tx)),
+            Command::GetCatalogSources(tx) => {
+                Self::Model(ModelManagementCommand::GetCatalogSources(tx))
+            }
+            Command::SetCatalogSources(sources, tx) => {
+                Self::Model(ModelManagementCommand::SetCatalogSources(sources, tx))
+            }
+            Command::AcceptModelLicense(model_id, tx) => {
+                Self::Model(ModelManagementCommand::AcceptModelLicense(model_id, tx))
+            }
             Command::DownloadFile(file_id, tx) => {
                 Self::Model(ModelManagementCommand::DownloadFile(file_id, tx))
             }
+            Command::UpdateFile(file_id, tx) => {
+                Self::Model(ModelManagementCommand::UpdateFile(file_id, tx))
+            }
             Command::PauseDownload(file_id, tx) => {
                 Self::Model(ModelManagementCommand::PauseDownload(file_id, tx))
             }
@@ -84,16 +182,97 @@ impl From<Command> for BuiltInCommand {
             Command::DeleteFile(file_id, tx) => {
                 Self::Model(ModelManagementCommand::DeleteFile(file_id, tx))
             }
+            Command::SetDownloadBandwidthLimit(limit, tx) => {
+                Self::Model(ModelManagementCommand::SetDownloadBandwidthLimit(limit, tx))
+            }
+            Command::SetFileBandwidthLimit(file_id, limit, tx) => Self::Model(
+                ModelManagementCommand::SetFileBandwidthLimit(file_id, limit, tx),
+            ),
+            Command::SetDownloadPriority(file_id, priority, tx) => Self::Model(
+                ModelManagementCommand::SetDownloadPriority(file_id, priority, tx),
+            ),
+            Command::ReorderDownloadQueue(file_ids, tx) => {
+                Self::Model(ModelManagementCommand::ReorderDownloadQueue(file_ids, tx))
+            }
+            Command::StartDownloadNext(file_id, tx) => {
+                Self::Model(ModelManagementCommand::StartDownloadNext(file_id, tx))
+            }
+            Command::GetDownloadSourceSettings(tx) => {
+                Self::Model(ModelManagementCommand::GetDownloadSourceSettings(tx))
+            }
+            Command::SetDownloadMirrors(mirrors, tx) => {
+                Self::Model(ModelManagementCommand::SetDownloadMirrors(mirrors, tx))
+            }
+            Command::SetRegion(region, tx) => {
+                Self::Model(ModelManagementCommand::SetRegion(region, tx))
+            }
+            Command::SetAllowUnverifiedDownloads(allow, tx) => Self::Model(
+                ModelManagementCommand::SetAllowUnverifiedDownloads(allow, tx),
+            ),
+            Command::SetAccessToken(host, token, tx) => {
+                Self::Model(ModelManagementCommand::SetAccessToken(host, token, tx))
+            }
             Command::GetCurrentDownloads(tx) => {
                 Self::Model(ModelManagementCommand::GetCurrentDownloads(tx))
             }
             Command::GetDownloadedFiles(tx) => {
                 Self::Model(ModelManagementCommand::GetDownloadedFiles(tx))
             }
+            Command::GetStorageUsage(tx) => {
+                Self::Model(ModelManagementCommand::GetStorageUsage(tx))
+            }
+            Command::SetStorageQuota(quota, tx) => {
+                Self::Model(ModelManagementCommand::SetStorageQuota(quota, tx))
+            }
+            Command::SetFilePinned(file_id, pinned, tx) => {
+                Self::Model(ModelManagementCommand::SetFilePinned(file_id, pinned, tx))
+            }
+            Command::PlanStorageCleanup(protected, tx) => {
+                Self::Model(ModelManagementCommand::PlanStorageCleanup(protected, tx))
+            }
+            Command::GetUsageStats(file_id, tx) => {
+                Self::Model(ModelManagementCommand::GetUsageStats(file_id, tx))
+            }
+            Command::GetLoadPresets(file_id, tx) => {
+                Self::Model(ModelManagementCommand::GetLoadPresets(file_id, tx))
+            }
+            Command::SaveLoadPreset(file_id, preset, tx) => {
+                Self::Model(ModelManagementCommand::SaveLoadPreset(file_id, preset, tx))
+            }
+            Command::RemoveLoadPreset(file_id, name, tx) => {
+                Self::Model(ModelManagementCommand::RemoveLoadPreset(file_id, name, tx))
+            }
+            Command::SelectLoadPreset(file_id, name, tx) => {
+                Self::Model(ModelManagementCommand::SelectLoadPreset(file_id, name, tx))
+            }
+            Command::GetChatTemplate(file_id, tx) => {
+                Self::Model(ModelManagementCommand::GetChatTemplate(file_id, tx))
+            }
+            Command::SetChatTemplate(file_id, source, tx) => {
+                Self::Model(ModelManagementCommand::SetChatTemplate(file_id, source, tx))
+            }
+            Command::PreviewChatTemplate(file_id, source, messages, tx) => Self::Model(
+                ModelManagementCommand::PreviewChatTemplate(file_id, source, messages, tx),
+            ),
+            Command::GetEmbeddingModel(tx) => {
+                Self::Model(ModelManagementCommand::GetEmbeddingModel(tx))
+            }
+            Command::SetEmbeddingModel(file_id, tx) => {
+                Self::Model(ModelManagementCommand::SetEmbeddingModel(file_id, tx))
+            }
+            Command::GetDefaultEmbeddingModel(tx) => {
+                Self::Model(ModelManagementCommand::GetDefaultEmbeddingModel(tx))
+            }
             Command::LoadModel(file_id, options, tx) => {
                 Self::Interaction(ModelInteractionCommand::LoadModel(file_id, options, tx))
             }
             Command::EjectModel(tx) => Self::Interaction(ModelInteractionCommand::EjectModel(tx)),
+            Command::GetAutoReloadModel(tx) => {
+                Self::Interaction(ModelInteractionCommand::GetAutoReloadModel(tx))
+            }
+            Command::SetAutoReloadModel(enabled, tx) => {
+                Self::Interaction(ModelInteractionCommand::SetAutoReloadModel(enabled, tx))
+            }
             Command::Chat(request, tx) => {
                 Self::Interaction(ModelInteractionCommand::Chat(request, tx))
             }
@@ -109,6 +288,7 @@ impl From<Command> for BuiltInCommand {
             Command::ChangeModelsDir(path) => {
                 Self::Model(ModelManagementCommand::ChangeModelsLocation(path))
             }
+            Command::Shutdown(tx) => Self::Shutdown(tx),
         }
     }
 }
@@ -122,6 +302,7 @@ fn test_chat() {
         format!("{home}/ai/models"),
         format!("{home}/ai/models"),
         3,
+        4,
     );
 
     let (tx, rx) = std::sync::mpsc::channel();
@@ -198,6 +379,7 @@ fn test_chat_stop() {
         format!("{home}/ai/models"),
         format!("{home}/ai/models"),
         3,
+        4,
     );
 
     let (tx, rx) = std::sync::mpsc::channel();
@@ -287,14 +469,15 @@ fn test_download_file() {
         format!("{home}/ai/models"),
         format!("{home}/ai/models"),
         3,
+        4,
     );
 
     let (tx, rx) = std::sync::mpsc::channel();
-    let cmd = Command::SearchModels("llama".to_string(), tx);
+    let cmd = Command::SearchModels(SearchQuery::new("llama"), tx);
     bk.send(cmd).unwrap();
     let models = rx.recv().unwrap();
     assert!(models.is_ok());
-    let models = models.unwrap();
+    let models = models.unwrap().models;
     println!("{models:?}");
 
     let file = models[0].files[0].clone();
@@ -314,6 +497,9 @@ fn test_download_file() {
 
     while let Ok(r) = rx.recv() {
         match r {
+            Ok(FileDownloadResponse::Queued(file_id)) => {
+                println!("{file_id} queued");
+            }
             Ok(FileDownloadResponse::Progress(file_id, progress)) => {
                 println!("{file_id} progress: {:.2}%", progress);
             }
@@ -335,6 +521,7 @@ fn test_get_download_file() {
         format!("{home}/ai/models"),
         format!("{home}/ai/models"),
         3,
+        4,
     );
 
     let (tx, rx) = std::sync::mpsc::channel();
@@ -362,6 +549,7 @@ pub trait BackendModel: Sized {
         options: LoadModelOptions,
         tx: Sender<anyhow::Result<LoadModelResponse>>,
         embedding: Option<(PathBuf, u64)>,
+        supervisor: Supervisor,
     ) -> Self;
     fn chat(
         &self,
@@ -369,24 +557,60 @@ pub trait BackendModel: Sized {
         data: ChatRequestData,
         tx: Sender<anyhow::Result<ChatResponse>>,
     ) -> bool;
+    /// Generate the answer of a chat from its prompt, already rendered with a chat
+    /// template. The sampling settings are taken from `data`.
+    fn complete(
+        &self,
+        async_rt: &tokio::runtime::Runtime,
+        prompt: String,
+        data: ChatRequestData,
+        tx: Sender<anyhow::Result<ChatResponse>>,
+    ) -> bool;
     fn stop_chat(&self, async_rt: &tokio::runtime::Runtime);
-    fn stop(self, async_rt: &tokio::runtime::Runtime);
+    /// Ask the model to exit and return its thread, without waiting for it.
+    fn exit(self, async_rt: &tokio::runtime::Runtime) -> std::thread::JoinHandle<()>;
+    fn stop(self, async_rt: &tokio::runtime::Runtime) {
+        let _ = self.exit(async_rt).join();
+    }
+}
+
+/// A load of a model that crashed, once its delay is over.
+struct ModelReload {
+    file_id: FileID,
+    options: LoadModelOptions,
+    at: Instant,
 }
 
 pub struct BackendImpl<Model: BackendModel> {
     sql_conn: Arc<Mutex<rusqlite::Connection>>,
     model_indexs: ModelCardManager,
-    #[allow(unused)]
+    catalog_sync: Arc<CatalogSync>,
+    catalog_update_tx: Sender<CatalogUpdate>,
+    catalog_update_rx: Receiver<CatalogUpdate>,
     app_data_dir: PathBuf,
     models_dir: PathBuf,
     pub rx: Receiver<Command>,
-    download_tx: tokio::sync::mpsc::UnboundedSender<(
-        store::models::Model,
-        store::download_files::DownloadedFile,
-        model_cards::RemoteFile,
-        Sender<anyhow::Result<FileDownloadResponse>>,
-    )>,
+    download_tx: tokio::sync::mpsc::UnboundedSender<store::DownloadRequest>,
+    downloader: ModelFileDownloader,
     model: Option<Model>,
+    // The file of the model, chats are recorded as its usage
+    loaded_file: Option<FileID>,
+    // Renders the prompts of chats with the loaded model, `None` when the server
+    // formats them
+    chat_template: Option<chat_template::ChatTemplate>,
+    events: Events,
+    crash_tx: Sender<ModelCrash>,
+    crash_rx: Receiver<ModelCrash>,
+    // Watches the model, to forget it when its load fails
+    supervisor: Option<Supervisor>,
+    // The last model loaded and its options, to load it again after a crash
+    last_load: Option<(FileID, LoadModelOptions)>,
+    reload: Option<ModelReload>,
+    // Crashes in a row of the model, and when the last one happened
+    crashes: u32,
+    last_crash: Option<Instant>,
+    // Set by the shutdown command, the run loop ends after it
+    shut_down: bool,
 
     #[allow(unused)]
     async_rt: tokio::runtime::Runtime,
@@ -398,10 +622,12 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
     /// * `app_data_dir` - The directory where application data should be stored.
     /// * `models_dir` - The directory where models should be downloaded.
     /// * `max_download_threads` - Maximum limit on simultaneous file downloads.
+    /// * `download_connections` - Number of parallel connections used for a single file.
     pub fn build_command_sender<A: AsRef<Path>, M: AsRef<Path>>(
         app_data_dir: A,
         models_dir: M,
         max_download_threads: usize,
+        download_connections: usize,
     ) -> Sender<Command> {
         let app_data_dir = app_data_dir.as_ref().to_path_buf();
 
@@ -415,27 +641,62 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
             )
         });
 
-        let model_indexs = store::model_cards::sync_model_cards_repo(&app_data_dir);
-        let model_indexs = match model_indexs {
-            Ok(model_indexs) => {
-                log::info!("sync model cards repo success");
-                model_indexs
-            }
-            Err(e) => {
-                log::error!("sync model cards repo error: {e}");
-                ModelCardManager::empty(app_data_dir.clone())
-            }
-        };
+        let mut sql_conn = rusqlite::Connection::open(app_data_dir.join("data.sqlite")).unwrap();
+        store::migrations::migrate(&mut sql_conn)
+            .unwrap_or_else(|e| panic!("Failed to open the Moly database: {e:#}"));
+
+        let region: Option<String> = store::settings::get(&sql_conn, store::settings::REGION)
+            .unwrap_or_else(|e| {
+                log::error!("read region setting error: {e}");
+                None
+            });
+        let mirrors: Vec<String> =
+            store::settings::get(&sql_conn, store::settings::DOWNLOAD_MIRRORS)
+                .unwrap_or_else(|e| {
+                    log::error!("read download mirrors setting error: {e}");
+                    None
+                })
+                .unwrap_or_default();
+        let access_tokens: HashMap<String, String> =
+            store::settings::get(&sql_conn, store::settings::ACCESS_TOKENS)
+                .unwrap_or_else(|e| {
+                    log::error!("read access tokens setting error: {e}");
+                    None
+                })
+                .unwrap_or_default();
+        let catalog_sources = store::catalog_sources::get_catalog_sources(&sql_conn)
+            .unwrap_or_else(|e| {
+                log::error!("read catalog sources setting error: {e}");
+                vec![CatalogSource::public()]
+            });
+
+        // Start from the catalog on disk, the refresh happens in the background
+        let model_indexs = store::model_cards::load_cached_model_cards(
+            &app_data_dir,
+            region.as_deref(),
+            &catalog_sources,
+        );
+        let catalog_sync = Arc::new(CatalogSync::new(
+            &sql_conn,
+            model_indexs.untrusted_sources().to_vec(),
+        ));
 
-        let sql_conn = rusqlite::Connection::open(app_data_dir.join("data.sqlite")).unwrap();
+        let sql_conn = Arc::new(Mutex::new(sql_conn));
 
-        // TODO Reorganize these bunch of functions, needs a little more of thought
-        let _ = store::models::create_table_models(&sql_conn).unwrap();
-        let _ = store::download_files::create_table_download_files(&sql_conn).unwrap();
+        let events = Events::default();
+        catalog_sync.subscribe(events.relay_catalog());
 
-        let sql_conn = Arc::new(Mutex::new(sql_conn));
+        let (catalog_update_tx, catalog_update_rx) = std::sync::mpsc::channel();
+        catalog_sync.spawn(
+            app_data_dir.clone(),
+            region.clone(),
+            catalog_sources,
+            sql_conn.clone(),
+            catalog_update_tx.clone(),
+        );
 
         let (tx, rx) = std::sync::mpsc::channel();
+        let (crash_tx, crash_rx) = std::sync::mpsc::channel();
 
         let async_rt = tokio::runtime::Builder::new_multi_thread()
             .enable_all()
@@ -444,25 +705,49 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
         let (control_tx, _control_rx) = tokio::sync::broadcast::channel(100);
         let (download_tx, download_rx) = tokio::sync::mpsc::unbounded_channel();
 
-        {
-            let client = reqwest::Client::new();
-            let downloader =
-                ModelFileDownloader::new(client, sql_conn.clone(), control_tx.clone(),model_indexs.country_code.clone(), 0.1);
-            async_rt.spawn(ModelFileDownloader::run_loop(
-                downloader,
-                max_download_threads.max(3),
-                download_rx,
-            ));
-        }
+        let downloader = ModelFileDownloader::new(
+            reqwest::Client::new(),
+            sql_conn.clone(),
+            control_tx.clone(),
+            DownloadSources::new(
+                region
+                    .map(|region| region.to_ascii_uppercase())
+                    .unwrap_or(model_indexs.country_code.clone()),
+                mirrors,
+                access_tokens,
+            ),
+            max_download_threads.max(3),
+            download_connections,
+            0.1,
+        );
+        async_rt.spawn(ModelFileDownloader::run_loop(
+            downloader.clone(),
+            download_rx,
+        ));
 
         let mut backend = Self {
             sql_conn,
             model_indexs,
+            catalog_sync,
+            catalog_update_tx,
+            catalog_update_rx,
             app_data_dir,
             models_dir: models_dir.as_ref().into(),
             rx,
             download_tx,
+            downloader,
             model: None,
+            loaded_file: None,
+            chat_template: None,
+            events,
+            crash_tx,
+            crash_rx,
+            supervisor: None,
+            last_load: None,
+            reload: None,
+            crashes: 0,
+            last_crash: None,
+            shut_down: false,
             async_rt,
             control_tx,
         };
@@ -477,118 +762,165 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
         match built_in_cmd {
             BuiltInCommand::Model(file) => match file {
                 ModelManagementCommand::GetFeaturedModels(tx) => {
-                    let res = self.model_indexs.get_featured_model(100, 0);
-                    match res {
-                        Ok(indexs) => {
-                            let mut models = Vec::new();
-                            for index in indexs {
-                                if let Ok(card) = self.model_indexs.load_model_card(&index) {
-                                    models.push(card);
-                                }
-                            }
+                    let cards = self.model_indexs.get_featured_model(100, 0);
 
-                            let sql_conn = self.sql_conn.lock().unwrap();
-                            let models = ModelCard::to_model(&models, &sql_conn)
-                                .map_err(|e| anyhow::anyhow!("get featured error: {e}"));
+                    let sql_conn = self.sql_conn.lock().unwrap();
+                    let models = self
+                        .model_indexs
+                        .to_models(&cards, &sql_conn)
+                        .map_err(|e| anyhow::anyhow!("get featured error: {e}"));
 
-                            let _ = tx.send(models);
-                        }
-                        Err(e) => {
-                            let _ = tx.send(Err(anyhow::anyhow!("get featured models error: {e}")));
-                        }
-                    }
+                    let _ = tx.send(models);
                 }
-                ModelManagementCommand::SearchModels(search_text, tx) => {
-                    let res = self.model_indexs.search(&search_text, 100, 0);
-                    match res {
-                        Ok(indexs) => {
-                            log::debug!("search models: {}", indexs.len());
-                            let sql_conn = self.sql_conn.lock().unwrap();
+                ModelManagementCommand::GetFeaturedCollections(tx) => {
+                    let sql_conn = self.sql_conn.lock().unwrap();
+                    let collections = self.model_indexs.get_collections();
+
+                    let collections = if collections.is_empty() {
+                        let cards = self.model_indexs.get_featured_model(100, 0);
+                        self.model_indexs
+                            .to_models(&cards, &sql_conn)
+                            .map(|models| {
+                                vec![ModelCollection {
+                                    id: ModelCollection::FEATURED_ID.to_string(),
+                                    name: "Featured".to_string(),
+                                    models,
+                                }]
+                            })
+                    } else {
+                        collections
+                            .into_iter()
+                            .map(|(collection, cards)| {
+                                self.model_indexs
+                                    .to_models(&cards, &sql_conn)
+                                    .map(|models| ModelCollection {
+                                        id: collection.id.clone(),
+                                        name: collection.name.clone(),
+                                        models,
+                                    })
+                            })
+                            .collect()
+                    };
 
-                            let mut models = Vec::new();
-                            for index in indexs {
-                                match self.model_indexs.load_model_card(&index) {
-                                    Ok(card) => {
-                                        models.push(card);
-                                    }
-                                    Err(e) => {
-                                        log::error!("load model card {} error: {e}", index.id);
-                                    }
-                                }
-                            }
+                    let _ = tx.send(
+                        collections
+                            .map_err(|e| anyhow::anyhow!("get featured collections error: {e}")),
+                    );
+                }
+                ModelManagementCommand::SearchModels(query, tx) => {
+                    let (cards, total) = self.model_indexs.search(&query);
+                    log::debug!("search models: {} of {total}", cards.len());
+
+                    let sql_conn = self.sql_conn.lock().unwrap();
+                    let results = self
+                        .model_indexs
+                        .to_models(&cards, &sql_conn)
+                        .map(|models| SearchResults { models, total })
+                        .map_err(|e| anyhow::anyhow!("search models error: {e}"));
+
+                    let _ = tx.send(results);
+                }
+
+                ModelManagementCommand::GetCatalogSyncStatus(tx) => {
+                    let _ = tx.send(Ok(self.catalog_sync.status()));
+                }
+
+                ModelManagementCommand::SubscribeCatalogUpdates(tx) => {
+                    self.catalog_sync.subscribe(tx);
+                }
+
+                ModelManagementCommand::Subscribe(tx) => {
+                    self.events.subscribe(tx);
+                }
+
+                ModelManagementCommand::RefreshCatalog(tx) => {
+                    let _ = tx.send(self.refresh_catalog());
+                }
+
+                ModelManagementCommand::GetCatalogSources(tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let _ = tx.send(store::catalog_sources::get_catalog_sources(&conn));
+                }
+
+                ModelManagementCommand::SetCatalogSources(sources, tx) => {
+                    let r = {
+                        let conn = self.sql_conn.lock().unwrap();
+                        store::catalog_sources::set_catalog_sources(&conn, sources)
+                    };
+                    // A refresh already running finishes with the previous sources
+                    let _ = tx.send(r.and_then(|_| self.refresh_catalog()));
+                }
 
-                            let models = ModelCard::to_model(&models, &sql_conn)
-                                .map_err(|e| anyhow::anyhow!("search models error: {e}"));
+                ModelManagementCommand::AcceptModelLicense(model_id, tx) => {
+                    let r = match self.model_indexs.get_card(&model_id) {
+                        Some(card) => {
+                            let acceptance = LicenseAcceptance {
+                                model_id,
+                                license_id: card.license.clone(),
+                                license_url: card.license_url.clone(),
+                                accepted_at: Utc::now(),
+                            };
+                            // Accepting twice keeps the first acceptance
+                            let conn = self.sql_conn.lock().unwrap();
+                            let accepted = acceptance.insert_into_db(&conn).and_then(|_| {
+                                LicenseAcceptance::get(
+                                    &conn,
+                                    &acceptance.model_id,
+                                    &acceptance.license_id,
+                                )
+                            });
+                            accepted
+                                .map(|accepted| accepted.unwrap_or(acceptance).accepted_at)
+                                .map_err(|e| e.into())
+                        }
+                        None => Err(anyhow::anyhow!("No model found")),
+                    };
+                    let _ = tx.send(r);
+                }
 
-                            let _ = tx.send(models);
+                ModelManagementCommand::DownloadFile(file_id, tx) => {
+                    let tx = self.events.relay_download(file_id.clone(), tx);
+                    match self.prepare_download(&file_id) {
+                        Ok((model, file, remote_file, source_id)) => {
+                            let _ = self.download_tx.send((
+                                model,
+                                file,
+                                remote_file,
+                                source_id,
+                                DownloadKind::New,
+                                tx,
+                            ));
                         }
                         Err(e) => {
-                            let _ = tx.send(Err(anyhow::anyhow!("search models error: {e}")));
+                            let _ = tx.send(Err(e));
                         }
                     }
                 }
-                ModelManagementCommand::DownloadFile(file_id, tx) => {
-                    //search model from remote
-                    let mut search_model_from_remote = || -> anyhow::Result<( crate::store::models::Model , crate::store::download_files::DownloadedFile,crate::store::model_cards::RemoteFile)> {
-                        let (model_id, file) = file_id
-                            .split_once("#")
-                            .ok_or_else(|| anyhow::anyhow!("Illegal file_id"))?;
-
-                        let index = self.model_indexs.get_index_by_id(model_id).ok_or(anyhow::anyhow!("No model found"))?.clone();
-                        let remote_model = self.model_indexs.load_model_card(&index)?;
-                    
-
-                        let remote_file = remote_model
-                            .files
-                            .into_iter()
-                            .find(|f| f.name == file)
-                            .ok_or_else(|| anyhow::anyhow!("file not found"))?;
-
-                        let remote_file_ = remote_file.clone();
-
-                        let download_model = crate::store::models::Model {
-                            id: Arc::new(remote_model.id),
-                            name: remote_model.name,
-                            summary: remote_model.summary,
-                            size: remote_model.size,
-                            requires: remote_model.requires,
-                            architecture: remote_model.architecture,
-                            released_at: remote_model.released_at,
-                            prompt_template: remote_model.prompt_template.clone(),
-                            reverse_prompt: remote_model.reverse_prompt.clone(),
-                            author: Arc::new(crate::store::model_cards::Author {
-                                name: remote_model.author.name,
-                                url: remote_model.author.url,
-                                description: remote_model.author.description,
-                            }),
-                            like_count: remote_model.like_count,
-                            download_count: remote_model.download_count,
-                        };
-
-                        let download_file = crate::store::download_files::DownloadedFile {
-                            id: Arc::new(file_id.clone()),
-                            model_id: model_id.to_string(),
-                            name: file.to_string(),
-                            size: remote_file.size,
-                            quantization: remote_file.quantization,
-                            prompt_template: remote_model.prompt_template,
-                            reverse_prompt: remote_model.reverse_prompt,
-                            context_size:remote_model.context_size,
-                            downloaded: false,
-                            file_size: 0,
-                            download_dir: self.models_dir.to_string_lossy().to_string(),
-                            downloaded_at: Utc::now(),
-                            tags:remote_file.tags,
-                            featured: false,
-                            sha256: remote_file.sha256.unwrap_or_default(),
-                        };
 
-                        Ok((download_model,download_file,remote_file_))
+                ModelManagementCommand::UpdateFile(file_id, tx) => {
+                    let tx = self.events.relay_download(file_id.clone(), tx);
+                    let downloaded = {
+                        let conn = self.sql_conn.lock().unwrap();
+                        store::download_files::DownloadedFile::get_by_id(&conn, &file_id)
+                            .map(|file| file.downloaded)
+                            .unwrap_or(false)
+                    };
+                    let request = if downloaded {
+                        self.prepare_download(&file_id)
+                    } else {
+                        Err(anyhow::anyhow!("{} is not downloaded", file_id))
                     };
 
-                    match search_model_from_remote() {
-                        Ok((model, file,remote_file)) => {
-                            let _ = self.download_tx.send((model, file,remote_file, tx));
+                    match request {
+                        Ok((model, file, remote_file, source_id)) => {
+                            let _ = self.download_tx.send((
+                                model,
+                                file,
+                                remote_file,
+                                source_id,
+                                DownloadKind::Update,
+                                tx,
+                            ));
                         }
                         Err(e) => {
                             let _ = tx.send(Err(e));
@@ -597,55 +929,378 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
                 }
 
                 ModelManagementCommand::PauseDownload(file_id, tx) => {
-                    let _ = self.control_tx.send(DownloadControlCommand::Stop(file_id));
+                    self.downloader.remove_queued(&file_id);
+                    let _ = self
+                        .control_tx
+                        .send(DownloadControlCommand::Stop(file_id.clone()));
+                    self.events.emit(BackendEvent::DownloadPaused(file_id));
                     let _ = tx.send(Ok(()));
                 }
 
                 ModelManagementCommand::CancelDownload(file_id, tx) => {
                     let file_id_ = file_id.clone();
+                    self.downloader.forget(&file_id);
                     let _ = self.control_tx.send(DownloadControlCommand::Stop(file_id_));
 
-                    {
+                    // A downloaded file is being updated, only the new revision goes
+                    let downloaded = {
                         let conn = self.sql_conn.lock().unwrap();
-                        let _ = store::download_files::DownloadedFile::remove(&file_id, &conn);
+                        store::download_files::DownloadedFile::get_by_id(&conn, &file_id)
+                            .is_ok_and(|file| file.downloaded)
+                    };
+                    if downloaded {
+                        self.remove_update(&file_id);
+                    } else {
+                        self.remove_file(file_id.clone());
                     }
-                    let _ = store::remove_downloaded_file(
-                        self.models_dir.to_string_lossy().to_string(),
-                        file_id,
-                    );
-
+                    self.events.emit(BackendEvent::DownloadCancelled(file_id));
                     let _ = tx.send(Ok(()));
                 }
 
                 ModelManagementCommand::DeleteFile(file_id, tx) => {
-                    {
-                        let conn = self.sql_conn.lock().unwrap();
-                        let _ = store::download_files::DownloadedFile::remove(&file_id, &conn);
-                    }
-
-                    let _ = store::remove_downloaded_file(
-                        self.models_dir.to_string_lossy().to_string(),
-                        file_id,
-                    );
+                    self.remove_file(file_id);
                     let _ = tx.send(Ok(()));
                 }
 
                 ModelManagementCommand::GetDownloadedFiles(tx) => {
                     let downloads = {
                         let conn = self.sql_conn.lock().unwrap();
-                        store::get_all_download_file(&conn)
-                            .map_err(|e| anyhow::anyhow!("get download file error: {e}"))
+                        store::get_all_download_file(&conn, |file| {
+                            self.model_indexs.is_outdated(file)
+                        })
+                        .map_err(|e| anyhow::anyhow!("get download file error: {e}"))
                     };
 
                     let _ = tx.send(downloads);
                 }
 
+                ModelManagementCommand::GetStorageUsage(tx) => {
+                    let files = {
+                        let conn = self.sql_conn.lock().unwrap();
+                        store::get_files_usage(&conn)
+                    };
+                    let usage = files.map_err(|e| e.into()).and_then(|files| {
+                        Ok(StorageUsage {
+                            files,
+                            available: store::disk_space::available_space(&self.models_dir)?,
+                            reserved: self.downloader.reserved_space(),
+                            quota: self.storage_quota(),
+                        })
+                    });
+                    let _ = tx.send(usage);
+                }
+
+                ModelManagementCommand::SetStorageQuota(quota, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let _ = tx.send(store::settings::set(
+                        &conn,
+                        store::settings::STORAGE_QUOTA,
+                        &quota,
+                    ));
+                }
+
+                ModelManagementCommand::SetFilePinned(file_id, pinned, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let r = store::download_files::DownloadedFile::update_pinned(
+                        &conn, &file_id, pinned,
+                    );
+                    let _ = tx.send(r.map_err(|e| e.into()));
+                }
+
+                ModelManagementCommand::PlanStorageCleanup(mut protected, tx) => {
+                    let quota = self.storage_quota();
+                    let conn = self.sql_conn.lock().unwrap();
+                    let embedding: Option<FileID> =
+                        store::settings::get(&conn, store::settings::EMBEDDING_MODEL)
+                            .unwrap_or_default();
+                    match embedding {
+                        Some(file_id) => protected.push(file_id),
+                        None => protected.extend(
+                            self.model_indexs
+                                .default_embedding_file()
+                                .map(str::to_string),
+                        ),
+                    }
+
+                    let r = store::get_files_usage(&conn)
+                        .map(|files| store::disk_space::plan_cleanup(&files, quota, &protected));
+                    let _ = tx.send(r.map_err(|e| e.into()));
+                }
+
+                ModelManagementCommand::GetUsageStats(file_id, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let stats = store::usage_events::get_stats(&conn, &file_id);
+                    let _ = tx.send(stats.map_err(|e| e.into()));
+                }
+
+                ModelManagementCommand::GetLoadPresets(file_id, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let presets = store::load_presets::get(&conn, &file_id);
+                    let _ = tx.send(presets.map_err(|e| e.into()));
+                }
+
+                ModelManagementCommand::SaveLoadPreset(file_id, mut preset, tx) => {
+                    preset.name = preset.name.trim().to_string();
+                    let r = if preset.name.is_empty() {
+                        Err(anyhow::anyhow!("A preset needs a name"))
+                    } else {
+                        let conn = self.sql_conn.lock().unwrap();
+                        store::load_presets::save(&conn, &file_id, &preset).map_err(|e| e.into())
+                    };
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::RemoveLoadPreset(file_id, name, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let r = store::load_presets::remove(&conn, &file_id, &name);
+                    let _ = tx.send(r.map_err(|e| e.into()));
+                }
+
+                ModelManagementCommand::SelectLoadPreset(file_id, name, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let r = match name {
+                        Some(name) => store::load_presets::get(&conn, &file_id)
+                            .map_err(|e| e.into())
+                            .and_then(|presets| {
+                                if presets.presets.iter().any(|preset| preset.name == name) {
+                                    store::load_presets::select(&conn, &file_id, Some(&name))
+                                        .map_err(|e| e.into())
+                                } else {
+                                    Err(anyhow::anyhow!("No preset named {:?}", name))
+                                }
+                            }),
+                        None => {
+                            store::load_presets::select(&conn, &file_id, None).map_err(|e| e.into())
+                        }
+                    };
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::GetChatTemplate(file_id, tx) => {
+                    let r = self.downloaded_file_path(&file_id).map(|path| {
+                        let embedded = store::gguf::read_chat_metadata(&path)
+                            .map(|metadata| metadata.chat_template)
+                            .unwrap_or_else(|e| {
+                                log::warn!("read chat template of {file_id} error: {e}");
+                                None
+                            });
+                        ChatTemplateSettings {
+                            source: self.chat_template_source(&file_id),
+                            embedded,
+                        }
+                    });
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::SetChatTemplate(file_id, source, tx) => {
+                    let _ = tx.send(self.set_chat_template(file_id, source));
+                }
+
+                ModelManagementCommand::PreviewChatTemplate(file_id, source, messages, tx) => {
+                    let r = self
+                        .downloaded_file_path(&file_id)
+                        .and_then(|path| chat_template::ChatTemplate::for_file(&path, &source))
+                        .and_then(|template| match template {
+                            Some(template) => template.render(&messages),
+                            None => Err(anyhow::anyhow!(
+                                "The server formats the prompt with the template of the model card"
+                            )),
+                        });
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::GetEmbeddingModel(tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let _ = tx.send(store::settings::get(
+                        &conn,
+                        store::settings::EMBEDDING_MODEL,
+                    ));
+                }
+
+                ModelManagementCommand::SetEmbeddingModel(file_id, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let r = match file_id {
+                        Some(file_id) => {
+                            store::get_embedding_file(&conn, &file_id).and_then(|_| {
+                                store::settings::set(
+                                    &conn,
+                                    store::settings::EMBEDDING_MODEL,
+                                    &file_id,
+                                )
+                            })
+                        }
+                        None => store::settings::remove(&conn, store::settings::EMBEDDING_MODEL),
+                    };
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::GetDefaultEmbeddingModel(tx) => {
+                    let default = self
+                        .model_indexs
+                        .default_embedding_file()
+                        .and_then(|file_id| {
+                            let (model_id, _) = file_id.split_once('#')?;
+                            let card = self.model_indexs.get_card(model_id)?.clone();
+                            Some((file_id.to_string(), card))
+                        });
+
+                    let r = match default {
+                        Some((file_id, card)) => {
+                            let sql_conn = self.sql_conn.lock().unwrap();
+                            self.model_indexs
+                                .to_models(&[card], &sql_conn)
+                                .map(|models| {
+                                    models.into_iter().next().map(|mut model| {
+                                        model.files.retain(|file| file.id == file_id);
+                                        model
+                                    })
+                                })
+                                .map_err(|e| {
+                                    anyhow::anyhow!("get default embedding model error: {e}")
+                                })
+                        }
+                        None => Ok(None),
+                    };
+                    // Refused before the download starts, not to fail it on every launch
+                    let r = r.and_then(|model| match &model {
+                        Some(m) if m.files.iter().any(|file| !file.downloaded) => {
+                            self.check_card_trust(&m.id)?;
+                            Ok(model)
+                        }
+                        _ => Ok(model),
+                    });
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::SetDownloadBandwidthLimit(limit, tx) => {
+                    self.downloader.set_bandwidth_limit(limit);
+                    let _ = tx.send(Ok(()));
+                }
+
+                ModelManagementCommand::SetFileBandwidthLimit(file_id, limit, tx) => {
+                    self.downloader.set_file_bandwidth_limit(file_id, limit);
+                    let _ = tx.send(Ok(()));
+                }
+
+                ModelManagementCommand::SetDownloadPriority(file_id, priority, tx) => {
+                    let _ = tx.send(self.downloader.set_priority(&file_id, priority));
+                }
+
+                ModelManagementCommand::ReorderDownloadQueue(file_ids, tx) => {
+                    self.downloader.reorder_queue(&file_ids);
+                    let _ = tx.send(Ok(()));
+                }
+
+                ModelManagementCommand::StartDownloadNext(file_id, tx) => {
+                    let _ = tx.send(self.downloader.start_next(&file_id));
+                }
+
+                ModelManagementCommand::GetDownloadSourceSettings(tx) => {
+                    let region = {
+                        let conn = self.sql_conn.lock().unwrap();
+                        store::settings::get(&conn, store::settings::REGION)
+                    };
+                    let sources = self.downloader.sources();
+                    let mut access_token_hosts: Vec<String> =
+                        sources.access_tokens.into_keys().collect();
+                    access_token_hosts.sort();
+
+                    let _ = tx.send(region.map(|region| DownloadSourceSettings {
+                        mirrors: sources.mirrors,
+                        region,
+                        active_region: sources.region,
+                        allow_unverified: self.allow_unverified_downloads(),
+                        access_token_hosts,
+                    }));
+                }
+
+                ModelManagementCommand::SetDownloadMirrors(mirrors, tx) => {
+                    let mirrors: Vec<String> = mirrors
+                        .iter()
+                        .map(|mirror| mirror.trim().to_string())
+                        .filter(|mirror| !mirror.is_empty())
+                        .collect();
+
+                    let r = {
+                        let conn = self.sql_conn.lock().unwrap();
+                        store::settings::set(&conn, store::settings::DOWNLOAD_MIRRORS, &mirrors)
+                    };
+                    self.downloader.set_mirrors(mirrors);
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::SetRegion(region, tx) => {
+                    let region = region
+                        .map(|region| region.trim().to_ascii_uppercase())
+                        .filter(|region| !region.is_empty());
+
+                    let r = {
+                        let conn = self.sql_conn.lock().unwrap();
+                        match &region {
+                            Some(region) => {
+                                store::settings::set(&conn, store::settings::REGION, region)
+                            }
+                            None => store::settings::remove(&conn, store::settings::REGION),
+                        }
+                    };
+                    let r = r.and_then(|()| match region {
+                        Some(region) => {
+                            self.downloader.set_region(region);
+                            Ok(())
+                        }
+                        // The region is detected by the catalog sync, not to wait on
+                        // the network here, and given to the downloader once it is done
+                        None => self.refresh_catalog(),
+                    });
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::SetAccessToken(host, token, tx) => {
+                    let token = token
+                        .map(|token| token.trim().to_string())
+                        .filter(|token| !token.is_empty());
+
+                    let r = match store::mirrors::normalize_host(&host) {
+                        Some(host) => {
+                            self.downloader.set_access_token(host, token);
+                            let conn = self.sql_conn.lock().unwrap();
+                            store::settings::set(
+                                &conn,
+                                store::settings::ACCESS_TOKENS,
+                                &self.downloader.sources().access_tokens,
+                            )
+                        }
+                        None => Err(anyhow::anyhow!("Invalid host {:?}", host)),
+                    };
+                    let _ = tx.send(r);
+                }
+
+                ModelManagementCommand::SetAllowUnverifiedDownloads(allow, tx) => {
+                    let conn = self.sql_conn.lock().unwrap();
+                    let _ = tx.send(store::settings::set(
+                        &conn,
+                        store::settings::ALLOW_UNVERIFIED_DOWNLOADS,
+                        &allow,
+                    ));
+                }
+
                 ModelManagementCommand::GetCurrentDownloads(tx) => {
                     let pending_downloads = {
                         let conn = self.sql_conn.lock().unwrap();
                         store::get_all_pending_downloads(&conn)
                             .map_err(|e| anyhow::anyhow!("get pending download file error: {e}"))
                     };
+                    let pending_downloads = pending_downloads.map(|mut pending_downloads| {
+                        self.downloader.fill_queue_status(&mut pending_downloads);
+                        // The saved segments lag behind a running download
+                        for download in pending_downloads.iter_mut() {
+                            if let Some(progress) = self.events.download_progress(&download.file.id)
+                            {
+                                download.progress = progress as f64;
+                            }
+                        }
+                        pending_downloads
+                    });
                     let _ = tx.send(pending_downloads);
                 }
 
@@ -653,39 +1308,59 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
             },
             BuiltInCommand::Interaction(model_cmd) => match model_cmd {
                 ModelInteractionCommand::LoadModel(file_id, options, tx) => {
-                    let conn = self.sql_conn.lock().unwrap();
-                    let download_file =
-                        store::download_files::DownloadedFile::get_by_id(&conn, &file_id);
-
-                    match download_file {
-                        Ok(file) => {
-                            nn_preload_file(&file, self.model_indexs.embedding_model());
-                            let old_model = self.model.take();
-
-                            let model = Model::new_or_reload(
-                                &self.async_rt,
-                                old_model,
-                                file,
-                                options,
-                                tx,
-                                self.model_indexs.embedding_model(),
-                            );
-                            self.model = Some(model);
-                        }
-                        Err(e) => {
-                            let _ = tx.send(Err(anyhow::anyhow!("Load model error: {e}")));
-                        }
-                    }
+                    self.reload = None;
+                    self.load_model(file_id, options, tx);
                 }
                 ModelInteractionCommand::EjectModel(tx) => {
-                    if let Some(model) = self.model.take() {
-                        model.stop(&self.async_rt);
-                    }
+                    self.eject_model();
                     let _ = tx.send(Ok(()));
                 }
+                ModelInteractionCommand::GetLoadedModel(tx) => {
+                    let _ = tx.send(Ok(self.events.loaded_model()));
+                }
+                ModelInteractionCommand::GetAutoReloadModel(tx) => {
+                    let _ = tx.send(Ok(self.auto_reload_model()));
+                }
+                ModelInteractionCommand::SetAutoReloadModel(enabled, tx) => {
+                    if !enabled {
+                        self.reload = None;
+                    }
+                    let conn = self.sql_conn.lock().unwrap();
+                    let _ = tx.send(store::settings::set(
+                        &conn,
+                        store::settings::AUTO_RELOAD_MODEL,
+                        &enabled,
+                    ));
+                }
                 ModelInteractionCommand::Chat(data, tx) => {
                     if let Some(model) = &self.model {
-                        model.chat(&self.async_rt, data, tx);
+                        let tx = match &self.loaded_file {
+                            Some(file_id) => usage::record_chat(
+                                self.sql_conn.clone(),
+                                file_id.clone(),
+                                &data,
+                                tx,
+                            ),
+                            None => tx,
+                        };
+                        match &self.chat_template {
+                            Some(template) => match template.render(&data.messages) {
+                                Ok(prompt) => {
+                                    let mut data = data;
+                                    // The prompt is raw text to the server, the answer ends at the eos token
+                                    if data.stop.is_none() && !template.eos_token.is_empty() {
+                                        data.stop = Some(vec![template.eos_token.clone()]);
+                                    }
+                                    model.complete(&self.async_rt, prompt, data, tx);
+                                }
+                                Err(e) => {
+                                    let _ = tx.send(Err(e));
+                                }
+                            },
+                            None => {
+                                model.chat(&self.async_rt, data, tx);
+                            }
+                        }
                     } else {
                         let _ = tx.send(Err(anyhow::anyhow!("Model not loaded")));
                     }
@@ -699,20 +1374,540 @@ impl<Model: BackendModel + Send + 'static> BackendImpl<Model> {
                 ModelInteractionCommand::StartLocalServer(_, _) => todo!(),
                 ModelInteractionCommand::StopLocalServer(_) => todo!(),
             },
+            BuiltInCommand::Shutdown(tx) => {
+                let r = self.shutdown();
+                self.shut_down = true;
+                let _ = tx.send(r);
+            }
+        }
+    }
+
+    fn load_model(
+        &mut self,
+        file_id: FileID,
+        options: LoadModelOptions,
+        tx: Sender<anyhow::Result<LoadModelResponse>>,
+    ) {
+        let download_file = {
+            let conn = self.sql_conn.lock().unwrap();
+            store::download_files::DownloadedFile::get_by_id(&conn, &file_id)
+        };
+
+        match download_file {
+            Ok(file) => {
+                {
+                    let conn = self.sql_conn.lock().unwrap();
+                    if let Err(e) = store::download_files::DownloadedFile::update_last_loaded(
+                        &conn,
+                        &file.id,
+                        Utc::now(),
+                    ) {
+                        log::warn!("update last loaded time of {} error: {e}", file.id);
+                    }
+                }
+
+                let embedding = self.embedding_model();
+                nn_preload_file(&file, embedding.clone());
+                let old_model = self.model.take();
+                self.loaded_file = Some(file.id.to_string());
+                self.last_load = Some((file.id.to_string(), options.clone()));
+                let source = self.chat_template_source(&file.id);
+                self.chat_template = chat_template::ChatTemplate::for_file(&file.path(), &source)
+                    .unwrap_or_else(|e| {
+                        log::error!("load chat template of {} error: {e}", file.id);
+                        None
+                    });
+                let tx = usage::record_load(self.sql_conn.clone(), tx);
+                let tx = self.events.relay_load(file.id.to_string(), tx);
+                let supervisor = Supervisor::new(
+                    file.id.to_string(),
+                    self.events.clone(),
+                    self.crash_tx.clone(),
+                );
+                let tx = supervisor.watch_load(tx);
+                self.supervisor = Some(supervisor.clone());
+
+                let model = Model::new_or_reload(
+                    &self.async_rt,
+                    old_model,
+                    file,
+                    options,
+                    tx,
+                    embedding,
+                    supervisor,
+                );
+                self.model = Some(model);
+            }
+            Err(e) => {
+                let _ = tx.send(Err(anyhow::anyhow!("Load model error: {e}")));
+            }
+        }
+    }
+
+    fn eject_model(&mut self) {
+        if let Some(model) = self.model.take() {
+            model.stop(&self.async_rt);
+        }
+        if let Some(file_id) = self.loaded_file.take() {
+            self.events.emit(BackendEvent::ModelEjected(file_id));
+        }
+        self.chat_template = None;
+        self.supervisor = None;
+        self.last_load = None;
+        self.reload = None;
+    }
+
+    /// Stop what the backend runs, leaving the database and the files on disk ready
+    /// for the next start.
+    fn shutdown(&mut self) -> anyhow::Result<()> {
+        let paused = self.downloader.pause_all();
+        // Stopped downloads resume from their last saved progress anyway
+        if !self.downloader.wait_stopped(SHUTDOWN_TIMEOUT) {
+            log::warn!("downloads did not stop within {:?}", SHUTDOWN_TIMEOUT);
+        }
+        for file_id in paused {
+            self.events.emit(BackendEvent::DownloadPaused(file_id));
+        }
+
+        // The local server is the one of the model, it stops with it. A hung model
+        // is left behind, the process exits anyway.
+        if let Some(model) = self.model.take() {
+            if !supervisor::join_timeout(model.exit(&self.async_rt), SHUTDOWN_TIMEOUT) {
+                log::warn!("the model did not stop within {:?}", SHUTDOWN_TIMEOUT);
+            }
+        }
+        self.eject_model();
+
+        let conn = self.sql_conn.lock().unwrap();
+        conn.cache_flush()
+            .map_err(|e| anyhow::anyhow!("Failed to flush the database: {e}"))
+    }
+
+    /// Forget the loaded model when it crashed, scheduling its reload when enabled.
+    fn handle_model_crashes(&mut self) {
+        while let Ok(crash) = self.crash_rx.try_recv() {
+            if self.loaded_file.as_ref() != Some(&crash.file_id) {
+                continue;
+            }
+            log::error!("model {} crashed: {}", crash.file_id, crash.reason);
+
+            if let Some(model) = self.model.take() {
+                // The chat running, if any, gets its stop chunk. A hung model is
+                // asked to exit but not joined, it may never do it.
+                model.stop_chat(&self.async_rt);
+                drop(model.exit(&self.async_rt));
+            }
+            self.loaded_file = None;
+            self.chat_template = None;
+            self.supervisor = None;
+
+            match self.last_crash {
+                Some(at) if at.elapsed() <= CRASH_RESET => {}
+                _ => self.crashes = 0,
+            }
+            self.crashes += 1;
+            self.last_crash = Some(Instant::now());
+
+            if !self.auto_reload_model() {
+                continue;
+            }
+            let Some((file_id, options)) = self
+                .last_load
+                .clone()
+                .filter(|(file_id, _)| *file_id == crash.file_id)
+            else {
+                continue;
+            };
+            if self.crashes > MAX_RELOAD_ATTEMPTS {
+                self.events.server_log(format!(
+                    "The model of {} crashed {} times in a row, it is not loaded again",
+                    file_id, self.crashes
+                ));
+                continue;
+            }
+
+            let delay = (RELOAD_DELAY * 2u32.pow(self.crashes - 1)).min(MAX_RELOAD_DELAY);
+            self.events.server_log(format!(
+                "Loading the model of {} again in {} seconds",
+                file_id,
+                delay.as_secs()
+            ));
+            self.reload = Some(ModelReload {
+                file_id,
+                options,
+                at: Instant::now() + delay,
+            });
+        }
+    }
+
+    /// Forget the file of a model whose load failed, it is neither loaded nor
+    /// loaded again after a crash.
+    fn handle_failed_load(&mut self) {
+        if !self
+            .supervisor
+            .as_ref()
+            .is_some_and(|supervisor| supervisor.load_failed())
+        {
+            return;
         }
+        self.supervisor = None;
+        self.loaded_file = None;
+        self.last_load = None;
+        self.chat_template = None;
+    }
+
+    /// Load the crashed model again once its delay is over.
+    fn reload_crashed_model(&mut self) {
+        let reload = match self.reload.take() {
+            Some(reload) if reload.at <= Instant::now() => reload,
+            reload => {
+                self.reload = reload;
+                return;
+            }
+        };
+
+        // Subscribers follow the load through the events
+        let (tx, _rx) = std::sync::mpsc::channel();
+        self.load_model(reload.file_id, reload.options, tx);
+    }
+
+    /// Whether a model that crashed is loaded again.
+    fn auto_reload_model(&self) -> bool {
+        let conn = self.sql_conn.lock().unwrap();
+        store::settings::get(&conn, store::settings::AUTO_RELOAD_MODEL)
+            .unwrap_or_else(|e| {
+                log::error!("read auto reload model setting error: {e}");
+                None
+            })
+            .unwrap_or(false)
+    }
+
+    /// Remove a file, and every shard of it for split models, from disk and db.
+    fn remove_file(&self, file_id: FileID) {
+        let parts = {
+            let conn = self.sql_conn.lock().unwrap();
+            let parts = store::download_files::DownloadedFile::get_by_id(&conn, &file_id)
+                .map(|file| file.parts)
+                .unwrap_or_default();
+
+            let _ = store::download_files::DownloadedFile::remove(&file_id, &conn);
+            let embedding: Option<FileID> =
+                store::settings::get(&conn, store::settings::EMBEDDING_MODEL).unwrap_or_default();
+            if embedding.as_ref() == Some(&file_id) {
+                let _ = store::settings::remove(&conn, store::settings::EMBEDDING_MODEL);
+            }
+            let _ = store::download_segments::DownloadSegment::remove_by_file(&file_id, &conn);
+            if let Some((model_id, _)) = file_id.split_once('#') {
+                for part in &parts {
+                    let _ = store::download_segments::DownloadSegment::remove_by_file(
+                        &format!("{model_id}#{part}"),
+                        &conn,
+                    );
+                }
+            }
+            parts
+        };
+
+        let _ = store::remove_downloaded_file(
+            self.models_dir.to_string_lossy().to_string(),
+            file_id,
+            &parts,
+        );
+    }
+
+    /// Drop the new revision being fetched for a downloaded file.
+    fn remove_update(&self, file_id: &FileID) {
+        let Some((model_id, name)) = file_id.split_once('#') else {
+            return;
+        };
+        // The parts of the new revision, as listed by the catalog
+        let names = self
+            .model_indexs
+            .get_card(model_id)
+            .and_then(|card| card.files.iter().find(|f| f.name == name))
+            .map(|remote_file| {
+                if remote_file.parts.is_empty() {
+                    vec![remote_file.name.clone()]
+                } else {
+                    remote_file.parts.iter().map(|p| p.name.clone()).collect()
+                }
+            })
+            .unwrap_or_else(|| vec![name.to_string()]);
+
+        let conn = self.sql_conn.lock().unwrap();
+        store::remote::remove_update(&conn, &self.models_dir, model_id, &names);
+    }
+
+    /// The embedding model served along the chat model, the downloaded one picked
+    /// by the user or else the default one of the catalog, once it is downloaded.
+    fn embedding_model(&self) -> Option<(PathBuf, u64)> {
+        let conn = self.sql_conn.lock().unwrap();
+        let picked = store::settings::get::<FileID>(&conn, store::settings::EMBEDDING_MODEL)
+            .ok()
+            .flatten()
+            .and_then(|file_id| {
+                store::get_embedding_file(&conn, &file_id)
+                    .map_err(|e| log::error!("picked embedding model error: {e}"))
+                    .ok()
+            });
+
+        let file = picked.or_else(|| {
+            let file_id = self.model_indexs.default_embedding_file()?;
+            store::get_embedding_file(&conn, file_id).ok()
+        })?;
+        Some((file.path(), file.context_size))
     }
 
     pub fn update_models_dir<M: AsRef<Path>>(&mut self, models_dir: M) {
         self.models_dir = models_dir.as_ref().to_path_buf();
     }
 
+    /// Look up a file of the catalog to download it.
+    fn prepare_download(
+        &self,
+        file_id: &str,
+    ) -> anyhow::Result<(
+        crate::store::models::Model,
+        crate::store::download_files::DownloadedFile,
+        crate::store::model_cards::RemoteFile,
+        String,
+    )> {
+        let (model_id, file) = file_id
+            .split_once("#")
+            .ok_or_else(|| anyhow::anyhow!("Illegal file_id"))?;
+
+        let remote_model = self
+            .model_indexs
+            .get_card(model_id)
+            .ok_or(anyhow::anyhow!("No model found"))?
+            .clone();
+        // The mirrors know the model by its id in the catalog source, without the namespace
+        let source_id = self
+            .model_indexs
+            .get_source_id(model_id)
+            .unwrap_or(model_id)
+            .to_string();
+
+        // The download urls come from the card, they must not be tampered with
+        self.check_card_trust(model_id)?;
+
+        // Restrictive terms need a recorded acceptance of the current license
+        if remote_model.license_requires_acceptance {
+            let accepted = {
+                let conn = self.sql_conn.lock().unwrap();
+                LicenseAcceptance::get(&conn, model_id, &remote_model.license)?
+            };
+            if accepted.is_none() {
+                return Err(anyhow::anyhow!(
+                    "The license {} of {} must be accepted before downloading it",
+                    remote_model.license,
+                    model_id
+                ));
+            }
+        }
+
+        let remote_file = remote_model
+            .files
+            .into_iter()
+            .find(|f| f.name == file)
+            .ok_or_else(|| anyhow::anyhow!("file not found"))?;
+
+        let remote_file_ = remote_file.clone();
+
+        let download_model = crate::store::models::Model {
+            id: Arc::new(remote_model.id),
+            name: remote_model.name,
+            summary: remote_model.summary,
+            size: remote_model.size,
+            requires: remote_model.requires,
+            architecture: remote_model.architecture,
+            model_type: remote_model.model_type,
+            released_at: remote_model.released_at,
+            prompt_template: remote_model.prompt_template.clone(),
+            reverse_prompt: remote_model.reverse_prompt.clone(),
+            author: Arc::new(crate::store::model_cards::Author {
+                name: remote_model.author.name,
+                url: remote_model.author.url,
+                description: remote_model.author.description,
+            }),
+            like_count: remote_model.like_count,
+            download_count: remote_model.download_count,
+        };
+
+        let download_file = crate::store::download_files::DownloadedFile {
+            id: Arc::new(file_id.to_string()),
+            model_id: model_id.to_string(),
+            name: file.to_string(),
+            size: remote_file.size,
+            quantization: remote_file.quantization,
+            prompt_template: remote_model.prompt_template,
+            reverse_prompt: remote_model.reverse_prompt,
+            context_size: remote_model.context_size,
+            downloaded: false,
+            file_size: 0,
+            download_dir: self.models_dir.to_string_lossy().to_string(),
+            downloaded_at: Utc::now(),
+            tags: remote_file.tags,
+            featured: false,
+            sha256: remote_file.sha256.unwrap_or_default(),
+            parts: remote_file.parts.iter().map(|p| p.name.clone()).collect(),
+            last_loaded_at: None,
+            pinned: false,
+        };
+
+        Ok((download_model, download_file, remote_file_, source_id))
+    }
+
+    /// Refuse the download of a model whose card may have been tampered with.
+    ///
+    /// A bad signature is refused even when unverified downloads are allowed, cards
+    /// are only expected to be signed by sources with trusted keys.
+    fn check_card_trust(&self, model_id: &str) -> Result<(), CatalogTrustError> {
+        let trust = self.model_indexs.get_card_trust(model_id);
+        let refused = match trust {
+            CatalogTrust::Invalid => true,
+            CatalogTrust::Unsigned => {
+                self.model_indexs.card_requires_signature(model_id)
+                    && !self.allow_unverified_downloads()
+            }
+            CatalogTrust::Verified => false,
+        };
+        if refused {
+            Err(CatalogTrustError {
+                model_id: model_id.to_string(),
+                trust,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether files of models with an unverified card may be downloaded.
+    fn allow_unverified_downloads(&self) -> bool {
+        let conn = self.sql_conn.lock().unwrap();
+        store::settings::get(&conn, store::settings::ALLOW_UNVERIFIED_DOWNLOADS)
+            .unwrap_or_else(|e| {
+                log::error!("read allow unverified downloads setting error: {e}");
+                None
+            })
+            .unwrap_or(false)
+    }
+
+    fn downloaded_file_path(&self, file_id: &FileID) -> anyhow::Result<PathBuf> {
+        let conn = self.sql_conn.lock().unwrap();
+        let file = store::download_files::DownloadedFile::get_by_id(&conn, file_id)
+            .map_err(|e| anyhow::anyhow!("Downloaded file {file_id} not found: {e}"))?;
+        Ok(file.path())
+    }
+
+    fn chat_template_source(&self, file_id: &FileID) -> ChatTemplateSource {
+        let conn = self.sql_conn.lock().unwrap();
+        store::settings::get::<HashMap<FileID, ChatTemplateSource>>(
+            &conn,
+            store::settings::CHAT_TEMPLATES,
+        )
+        .unwrap_or_else(|e| {
+            log::error!("read chat templates setting error: {e}");
+            None
+        })
+        .and_then(|mut sources| sources.remove(file_id))
+        .unwrap_or_default()
+    }
+
+    /// Format chats with a file using another template, once it renders a chat.
+    fn set_chat_template(
+        &mut self,
+        file_id: FileID,
+        source: ChatTemplateSource,
+    ) -> anyhow::Result<()> {
+        let template =
+            chat_template::ChatTemplate::for_file(&self.downloaded_file_path(&file_id)?, &source)?;
+        // Left without the system message, not every model takes one
+        if let Some(template) = &template {
+            template.render(&chat_template::sample_messages()[1..])?;
+        }
+
+        {
+            let conn = self.sql_conn.lock().unwrap();
+            let mut sources: HashMap<FileID, ChatTemplateSource> =
+                store::settings::get(&conn, store::settings::CHAT_TEMPLATES)?.unwrap_or_default();
+            if source == ChatTemplateSource::Server {
+                sources.remove(&file_id);
+            } else {
+                sources.insert(file_id.clone(), source);
+            }
+            store::settings::set(&conn, store::settings::CHAT_TEMPLATES, &sources)?;
+        }
+
+        if self.loaded_file.as_ref() == Some(&file_id) {
+            self.chat_template = template;
+        }
+        Ok(())
+    }
+
+    fn storage_quota(&self) -> StorageQuota {
+        let conn = self.sql_conn.lock().unwrap();
+        store::settings::get(&conn, store::settings::STORAGE_QUOTA)
+            .unwrap_or_else(|e| {
+                log::error!("read storage quota setting error: {e}");
+                None
+            })
+            .unwrap_or_default()
+    }
+
+    /// Sync the catalog in the background with the current settings.
+    fn refresh_catalog(&self) -> anyhow::Result<()> {
+        let (region, sources) = {
+            let conn = self.sql_conn.lock().unwrap();
+            (
+                store::settings::get(&conn, store::settings::REGION)?,
+                store::catalog_sources::get_catalog_sources(&conn)?,
+            )
+        };
+
+        self.catalog_sync.spawn(
+            self.app_data_dir.clone(),
+            region,
+            sources,
+            self.sql_conn.clone(),
+            self.catalog_update_tx.clone(),
+        );
+        Ok(())
+    }
+
+    /// Switch to the catalogs synced in the background since the last command.
+    fn apply_catalog_updates(&mut self) {
+        while let Ok(update) = self.catalog_update_rx.try_recv() {
+            let region: Option<String> = {
+                let conn = self.sql_conn.lock().unwrap();
+                store::settings::get(&conn, store::settings::REGION).unwrap_or_default()
+            };
+            if region.is_none() {
+                self.downloader.set_region(update.country_code.clone());
+            }
+
+            self.model_indexs.apply_update(update);
+        }
+    }
+
     fn run_loop(&mut self) {
         loop {
-            if let Ok(cmd) = self.rx.recv() {
-                self.handle_command(cmd.into());
-            } else {
+            match self.rx.recv_timeout(SUPERVISION_INTERVAL) {
+                Ok(cmd) => {
+                    self.handle_failed_load();
+                    self.handle_model_crashes();
+                    self.apply_catalog_updates();
+                    self.handle_command(cmd.into());
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if self.shut_down {
                 break;
             }
+            self.handle_failed_load();
+            self.handle_model_crashes();
+            self.reload_crashed_model();
         }
 
         log::debug!("BackendImpl stop");
@@ -723,6 +1918,8 @@ pub fn nn_preload_file(
     file: &store::download_files::DownloadedFile,
     embedding: Option<(PathBuf, u64)>,
 ) {
+    // For split models `name` is the first shard, llama.cpp loads the rest from
+    // the same directory.
     let file_path = Path::new(&file.download_dir)
         .join(&file.model_id)
         .join(&file.name);
@@ -0,0 +1,331 @@
+use std::{
+    panic::AssertUnwindSafe,
+    sync::{
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use moly_protocol::{
+    data::FileID,
+    protocol::{BackendEvent, LoadModelResponse},
+};
+
+use super::Events;
+
+// Time between two health checks of a model server
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+// Failed health checks in a row before a server is considered hung
+const HEALTH_CHECK_FAILURES: u32 = 3;
+// A server busy answering a chat may not answer the health checks. Only after this
+// long without finishing a request is it considered hung.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// A loaded model that stopped without being ejected.
+#[derive(Debug, Clone)]
+pub struct ModelCrash {
+    pub file_id: FileID,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+struct SupervisorState {
+    // The load completed, an exit from now on is a crash
+    loaded: bool,
+    // The model is being stopped on purpose, or the supervision is over
+    stopping: bool,
+    // The load failed, the model never ran
+    failed: bool,
+    // A sender of the load responses, to fail the load when the model exits first
+    load_tx: Option<Sender<anyhow::Result<LoadModelResponse>>>,
+    // Requests waiting for an answer of the model
+    requests: usize,
+    // Last time a request started or finished
+    last_activity: Option<Instant>,
+}
+
+/// Watches the thread running a model, and its server when it has one, to report
+/// when the model stops without being ejected.
+///
+/// Engines call [`Supervisor::stop`] before stopping their model, so the exit is not
+/// taken for a crash. A model is reported at most once.
+#[derive(Debug, Clone)]
+pub struct Supervisor {
+    file_id: FileID,
+    events: Events,
+    crash_tx: Sender<ModelCrash>,
+    state: Arc<Mutex<SupervisorState>>,
+}
+
+impl Supervisor {
+    pub fn new(file_id: FileID, events: Events, crash_tx: Sender<ModelCrash>) -> Self {
+        Self {
+            file_id,
+            events,
+            crash_tx,
+            state: Default::default(),
+        }
+    }
+
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    /// Forward the responses of the load to `tx`, following whether the model got
+    /// loaded.
+    ///
+    /// A model that exits before it is loaded fails the load with the reason,
+    /// instead of crashing.
+    pub fn watch_load(
+        &self,
+        tx: Sender<anyhow::Result<LoadModelResponse>>,
+    ) -> Sender<anyhow::Result<LoadModelResponse>> {
+        let (relay_tx, relay_rx) = channel::<anyhow::Result<LoadModelResponse>>();
+        self.state.lock().unwrap().load_tx = Some(relay_tx.clone());
+        let state = self.state.clone();
+
+        std::thread::spawn(move || {
+            for response in relay_rx {
+                match &response {
+                    Ok(LoadModelResponse::Completed(_)) => {
+                        let mut state = state.lock().unwrap();
+                        state.loaded = true;
+                        state.load_tx = None;
+                    }
+                    // Nothing running is left to watch
+                    Err(_) => {
+                        let mut state = state.lock().unwrap();
+                        state.stopping = true;
+                        state.failed = true;
+                        state.load_tx = None;
+                    }
+                    Ok(_) => {}
+                }
+                let _ = tx.send(response);
+            }
+        });
+
+        relay_tx
+    }
+
+    /// Run the model on a thread, reporting its exit, error or panic.
+    pub fn spawn<F>(&self, run: F) -> JoinHandle<()>
+    where
+        F: FnOnce() -> anyhow::Result<()> + Send + 'static,
+    {
+        let supervisor = self.clone();
+
+        std::thread::spawn(move || {
+            let reason = match std::panic::catch_unwind(AssertUnwindSafe(run)) {
+                Ok(Ok(())) => "The model exited".to_string(),
+                Ok(Err(e)) => format!("The model exited with an error: {e}"),
+                Err(panic) => {
+                    let message = panic
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| panic.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    format!("The model panicked: {message}")
+                }
+            };
+            supervisor
+                .events
+                .server_log(format!("{reason} ({})", supervisor.file_id));
+            supervisor.exited(reason);
+        })
+    }
+
+    /// Check that the server answers at `url` while the model is loaded, reporting
+    /// it as crashed when it stops answering.
+    pub fn watch_health(&self, url: String) {
+        let supervisor = self.clone();
+
+        std::thread::spawn(move || {
+            let client = match reqwest::blocking::ClientBuilder::new()
+                .timeout(Duration::from_secs(3))
+                .no_proxy()
+                .build()
+            {
+                Ok(client) => client,
+                Err(e) => {
+                    log::error!("create health check client error: {e}");
+                    return;
+                }
+            };
+
+            let mut failures = 0;
+            loop {
+                std::thread::sleep(HEALTH_CHECK_INTERVAL);
+
+                let busy = {
+                    let state = supervisor.state.lock().unwrap();
+                    if state.stopping {
+                        break;
+                    }
+                    if !state.loaded {
+                        continue;
+                    }
+                    state.requests > 0
+                        && state
+                            .last_activity
+                            .is_some_and(|last| last.elapsed() < BUSY_TIMEOUT)
+                };
+                if busy {
+                    failures = 0;
+                    continue;
+                }
+
+                match client.get(&url).send() {
+                    Ok(resp) if resp.status().is_success() => failures = 0,
+                    _ => failures += 1,
+                }
+
+                if failures >= HEALTH_CHECK_FAILURES {
+                    supervisor.crash(format!("The model server stopped answering on {url}"));
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Count a request to the model until the returned guard is dropped, a busy
+    /// server is given time before it is considered hung.
+    pub fn begin_request(&self) -> RequestGuard {
+        let mut state = self.state.lock().unwrap();
+        state.requests += 1;
+        state.last_activity = Some(Instant::now());
+        RequestGuard(self.state.clone())
+    }
+
+    /// The model is being stopped on purpose, its exit is not a crash.
+    pub fn stop(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.stopping = true;
+        state.load_tx = None;
+    }
+
+    /// Whether the supervision is over, because the model was stopped, exited or
+    /// failed to load.
+    pub fn is_stopped(&self) -> bool {
+        self.state.lock().unwrap().stopping
+    }
+
+    /// Whether the load of the model failed.
+    pub fn load_failed(&self) -> bool {
+        self.state.lock().unwrap().failed
+    }
+
+    fn exited(&self, reason: String) {
+        let load_tx = {
+            let mut state = self.state.lock().unwrap();
+            if state.stopping || state.loaded {
+                None
+            } else {
+                state.stopping = true;
+                state.load_tx.take()
+            }
+        };
+
+        match load_tx {
+            Some(load_tx) => {
+                let _ = load_tx.send(Err(anyhow!("{reason}")));
+            }
+            None => self.crash(reason),
+        }
+    }
+
+    fn crash(&self, reason: String) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.stopping || !state.loaded {
+                return;
+            }
+            state.stopping = true;
+        }
+
+        self.events.emit(BackendEvent::ModelCrashed(
+            self.file_id.clone(),
+            reason.clone(),
+        ));
+        let _ = self.crash_tx.send(ModelCrash {
+            file_id: self.file_id.clone(),
+            reason,
+        });
+    }
+}
+
+/// Wait until `thread` is done, for at most `timeout`. Returns whether it is done.
+pub fn join_timeout(thread: JoinHandle<()>, timeout: Duration) -> bool {
+    let (tx, rx) = channel::<()>();
+    std::thread::spawn(move || {
+        let _ = thread.join();
+        let _ = tx.send(());
+    });
+    !matches!(rx.recv_timeout(timeout), Err(RecvTimeoutError::Timeout))
+}
+
+pub struct RequestGuard(Arc<Mutex<SupervisorState>>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        let mut state = self.0.lock().unwrap();
+        state.requests -= 1;
+        state.last_activity = Some(Instant::now());
+    }
+}
+
+#[test]
+fn test_supervisor() {
+    use moly_protocol::protocol::LoadedModelInfo;
+
+    let (crash_tx, crash_rx) = channel();
+    let supervisor = Supervisor::new(
+        "org/model#file.gguf".to_string(),
+        Events::default(),
+        crash_tx,
+    );
+
+    // An exit before the load completes fails the load
+    let (tx, rx) = channel();
+    let _load_tx = supervisor.watch_load(tx);
+    supervisor
+        .spawn(|| Err(anyhow!("out of memory")))
+        .join()
+        .unwrap();
+    assert!(rx.recv().unwrap().is_err());
+    assert!(supervisor.is_stopped());
+    assert!(supervisor.load_failed());
+    assert!(crash_rx.try_recv().is_err());
+
+    let (crash_tx, crash_rx) = channel();
+    let supervisor = Supervisor::new(
+        "org/model#file.gguf".to_string(),
+        Events::default(),
+        crash_tx,
+    );
+    let (tx, rx) = channel();
+    let load_tx = supervisor.watch_load(tx);
+    load_tx
+        .send(Ok(LoadModelResponse::Completed(LoadedModelInfo {
+            file_id: "org/model#file.gguf".to_string(),
+            model_id: "org/model".to_string(),
+            listen_port: 8080,
+            information: String::new(),
+        })))
+        .unwrap();
+    rx.recv().unwrap().unwrap();
+    assert!(!supervisor.load_failed());
+
+    // A panic of a loaded model is a crash
+    supervisor.spawn(|| panic!("abort")).join().unwrap();
+    let crash = crash_rx.recv().unwrap();
+    assert_eq!(crash.file_id, "org/model#file.gguf");
+    assert_eq!(crash.reason, "The model panicked: abort");
+
+    // Reported once
+    supervisor.spawn(|| Ok(())).join().unwrap();
+    assert!(crash_rx.try_recv().is_err());
+}
@@ -0,0 +1,163 @@
+use std::{
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use chrono::Utc;
+use moly_protocol::{
+    data::FileID,
+    open_ai::{ChatRequestData, ChatResponse, Role, UsageData},
+    protocol::LoadModelResponse,
+};
+
+use crate::store::usage_events::{UsageEvent, UsageKind};
+
+fn record(sql_conn: &Mutex<rusqlite::Connection>, event: UsageEvent) {
+    let conn = sql_conn.lock().unwrap();
+    if let Err(e) = event.insert_into_db(&conn) {
+        log::warn!(
+            "record {:?} usage of {} error: {e}",
+            event.kind,
+            event.file_id
+        );
+    }
+}
+
+/// Forward the responses of a model load to `tx`, recording the load once it completes.
+///
+/// Works the same whatever runs the model, only the responses are looked at.
+pub fn record_load(
+    sql_conn: Arc<Mutex<rusqlite::Connection>>,
+    tx: Sender<anyhow::Result<LoadModelResponse>>,
+) -> Sender<anyhow::Result<LoadModelResponse>> {
+    let (relay_tx, relay_rx) = channel();
+    let started = Instant::now();
+
+    std::thread::spawn(move || {
+        for response in relay_rx {
+            if let Ok(LoadModelResponse::Completed(info)) = &response {
+                record(
+                    &sql_conn,
+                    UsageEvent {
+                        file_id: info.file_id.clone(),
+                        kind: UsageKind::Load,
+                        at: Utc::now(),
+                        duration_ms: started.elapsed().as_millis() as u64,
+                        prompt_tokens: 0,
+                        completion_tokens: 0,
+                        new_chat: false,
+                    },
+                );
+            }
+            if tx.send(response).is_err() {
+                break;
+            }
+        }
+    });
+
+    relay_tx
+}
+
+/// What a chat completion used, gathered from its responses.
+struct ChatUsage {
+    started: Instant,
+    first_token: Option<Instant>,
+    // Streamed chunks with content, about one token each
+    chunks: u32,
+    // Token counts from the server, when it sends them
+    reported: Option<UsageData>,
+}
+
+impl ChatUsage {
+    fn event(&self, file_id: FileID, new_chat: bool) -> UsageEvent {
+        let (prompt_tokens, completion_tokens) = match &self.reported {
+            Some(usage) => (usage.prompt_tokens, usage.completion_tokens),
+            None => (0, self.chunks),
+        };
+
+        UsageEvent {
+            file_id,
+            kind: UsageKind::Chat,
+            at: Utc::now(),
+            duration_ms: self
+                .first_token
+                .unwrap_or(self.started)
+                .elapsed()
+                .as_millis() as u64,
+            prompt_tokens,
+            completion_tokens,
+            new_chat,
+        }
+    }
+}
+
+/// Forward the responses of a chat completion to `tx`, recording the tokens used
+/// once it finishes.
+///
+/// A request with a single user message starts a chat.
+pub fn record_chat(
+    sql_conn: Arc<Mutex<rusqlite::Connection>>,
+    file_id: FileID,
+    data: &ChatRequestData,
+    tx: Sender<anyhow::Result<ChatResponse>>,
+) -> Sender<anyhow::Result<ChatResponse>> {
+    let (relay_tx, relay_rx) = channel();
+    let new_chat = data
+        .messages
+        .iter()
+        .filter(|m| m.role == Role::User)
+        .count()
+        == 1;
+    let mut usage = ChatUsage {
+        started: Instant::now(),
+        first_token: None,
+        chunks: 0,
+        reported: None,
+    };
+
+    std::thread::spawn(move || {
+        let mut recorded = false;
+
+        for response in relay_rx {
+            let finished = match &response {
+                Ok(ChatResponse::ChatResponseChunk(chunk)) => {
+                    if chunk.choices.iter().any(|c| !c.delta.content.is_empty()) {
+                        usage.first_token.get_or_insert_with(Instant::now);
+                        usage.chunks += 1;
+                    }
+                    if chunk.usage.is_some() {
+                        usage.reported = chunk.usage.clone();
+                    }
+                    chunk.choices.iter().any(|c| c.finish_reason.is_some())
+                }
+                Ok(ChatResponse::ChatFinalResponseData(data)) => {
+                    // Some backends leave the counts at zero
+                    if data.usage.total_tokens > 0 {
+                        usage.reported = Some(data.usage.clone());
+                    }
+                    true
+                }
+                // Failed completions are not usage
+                Err(_) => false,
+            };
+
+            if finished && !recorded {
+                recorded = true;
+                record(&sql_conn, usage.event(file_id.clone(), new_chat));
+            }
+            if tx.send(response).is_err() {
+                break;
+            }
+        }
+
+        // Stopped before the last chunk, what was generated still counts
+        if !recorded && usage.chunks > 0 {
+            record(&sql_conn, usage.event(file_id, new_chat));
+        }
+    });
+
+    relay_tx
+}
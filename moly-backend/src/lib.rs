@@ -13,10 +13,12 @@ impl Backend {
     /// * `app_data_dir` - The directory where application data should be stored.
     /// * `models_dir` - The directory where models should be downloaded.
     /// * `max_download_threads` - Maximum limit on simultaneous file downloads.
+    /// * `download_connections` - Number of parallel connections used for a single file.
     pub fn new<A: AsRef<Path>, M: AsRef<Path>>(
         app_data_dir: A,
         models_dir: M,
         max_download_threads: usize,
+        download_connections: usize,
     ) -> Backend {
         #[cfg(debug_assertions)]
         env_logger::init();
@@ -24,6 +26,7 @@ impl Backend {
             app_data_dir,
             models_dir,
             max_download_threads,
+            download_connections,
         );
         Backend { command_sender }
     }
@@ -0,0 +1,70 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Caps the throughput of every connection sharing it.
+///
+/// Each consumer pays for the bytes it has just received by waiting until the
+/// limiter would have allowed them, so the cap holds across any number of
+/// parallel connections and can be changed while they are running.
+#[derive(Debug)]
+pub struct BandwidthLimiter {
+    state: Mutex<LimiterState>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    bytes_per_sec: Option<u64>,
+    next_free: Instant,
+}
+
+impl BandwidthLimiter {
+    pub fn new(bytes_per_sec: Option<u64>) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                bytes_per_sec,
+                next_free: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn set_limit(&self, bytes_per_sec: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        state.bytes_per_sec = bytes_per_sec;
+        state.next_free = Instant::now();
+    }
+
+    pub fn limit(&self) -> Option<u64> {
+        self.state.lock().unwrap().bytes_per_sec
+    }
+
+    pub async fn consume(&self, bytes: u64) {
+        let wake_at = {
+            let mut state = self.state.lock().unwrap();
+            let Some(rate) = state.bytes_per_sec.filter(|rate| *rate > 0) else {
+                return;
+            };
+
+            let start = state.next_free.max(Instant::now());
+            state.next_free = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            state.next_free
+        };
+
+        tokio::time::sleep_until(wake_at).await;
+    }
+}
+
+/// The limiters that apply to one download: the global one and its own.
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    pub global: Arc<BandwidthLimiter>,
+    pub file: Arc<BandwidthLimiter>,
+}
+
+impl Throttle {
+    pub async fn consume(&self, bytes: u64) {
+        self.global.consume(bytes).await;
+        self.file.consume(bytes).await;
+    }
+}
@@ -0,0 +1,517 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use moly_protocol::data::{CatalogSource, CatalogTrust, SearchQuery};
+use serde::{Deserialize, Serialize};
+
+use super::catalog_trust::TrustedKeys;
+use super::model_cards::{self, ModelCard, ModelIndex};
+use super::{catalog_sources, search};
+
+/// A catalog entry along with its model card.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedCard {
+    pub index: ModelIndex,
+    pub card: ModelCard,
+    // Number of parameters parsed from the card size, `None` when unknown
+    #[serde(default)]
+    pub parameters: Option<u64>,
+    // Kept out of the card, so a card file can't claim to be verified
+    #[serde(default)]
+    pub trust: CatalogTrust,
+    // Id of the model in its source, before adding the namespace
+    #[serde(default)]
+    pub source_id: String,
+    // Its source has trusted keys, the card must be verified to download the model
+    #[serde(default)]
+    pub requires_signature: bool,
+}
+
+impl IndexedCard {
+    pub fn new(index: ModelIndex, card: ModelCard) -> Self {
+        Self {
+            parameters: search::parse_parameters(&card.size),
+            source_id: index.id.clone(),
+            index,
+            card,
+            trust: CatalogTrust::default(),
+            requires_signature: false,
+        }
+    }
+
+    /// The id to build the download urls of the model from, mirrors and
+    /// Hugging Face don't know the namespace of the source.
+    pub fn source_id(&self) -> &str {
+        if self.source_id.is_empty() {
+            &self.index.id
+        } else {
+            &self.source_id
+        }
+    }
+}
+
+/// A named list of models curated by the catalog, in `collections.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionIndex {
+    pub id: String,
+    pub name: String,
+    // Model ids, in the order they are shown
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+/// The cards of one catalog source, read from its local copy.
+#[derive(Debug)]
+pub struct SourceCards {
+    name: String,
+    index_trust: CatalogTrust,
+    requires_signature: bool,
+    entries: Vec<IndexedCard>,
+    collections: Vec<CollectionIndex>,
+    default_embedding: Option<String>,
+}
+
+impl SourceCards {
+    /// Read and verify the card of every index, skipping the ones that fail, and
+    /// prefix the model ids with the namespace of the source.
+    pub fn read(
+        source_dir: &Path,
+        source: &CatalogSource,
+        indexs: Vec<ModelIndex>,
+        index_trust: CatalogTrust,
+    ) -> Self {
+        let namespaced = |id: &str| catalog_sources::namespaced_id(source, id);
+        let keys = TrustedKeys::for_source(source);
+        let requires_signature = !keys.is_empty();
+
+        let mut entries: Vec<IndexedCard> = indexs
+            .into_iter()
+            .filter_map(|index| match index.load_model_card(source_dir) {
+                Ok(card) => {
+                    let trust = index
+                        .card_file()
+                        .map(|card_file| keys.verify_file(&source_dir.join(card_file)))
+                        .unwrap_or_default();
+                    if trust == CatalogTrust::Invalid {
+                        log::warn!("model card {} has an invalid signature", index.id);
+                    }
+
+                    let mut entry = IndexedCard {
+                        trust,
+                        requires_signature,
+                        ..IndexedCard::new(index, card)
+                    };
+                    entry.index.id = namespaced(&entry.source_id);
+                    entry.card.id = entry.index.id.clone();
+                    Some(entry)
+                }
+                Err(e) => {
+                    log::warn!("load model card {} error: {e}", index.id);
+                    None
+                }
+            })
+            .collect();
+
+        // Only the embedding models of the public catalog are used
+        let mut default_embedding = None;
+        if source.is_public() {
+            let embedding_path = source_dir.join("embedding.json");
+            match model_cards::read_embedding_index(&embedding_path) {
+                Ok(embedding_indexs) => {
+                    let trust = keys.verify_file(&embedding_path);
+                    default_embedding = embedding_indexs.first().map(|index| index.file_id());
+                    entries.extend(embedding_indexs.iter().map(|embedding| {
+                        let (index, card) = embedding.to_card();
+                        IndexedCard {
+                            trust,
+                            requires_signature,
+                            ..IndexedCard::new(index, card)
+                        }
+                    }));
+                }
+                Err(e) if embedding_path.exists() => {
+                    log::error!("read {:?} error: {e}", embedding_path)
+                }
+                Err(_) => {}
+            }
+        }
+
+        // Catalogs without collections only have the featured flag
+        let collections_path = source_dir.join("collections.json");
+        let mut collections: Vec<CollectionIndex> = match std::fs::read_to_string(&collections_path)
+        {
+            Ok(collections) => serde_json::from_str(&collections).unwrap_or_else(|e| {
+                log::error!("parse {:?} error: {e}", collections_path);
+                vec![]
+            }),
+            Err(_) => vec![],
+        };
+        for collection in collections.iter_mut() {
+            collection.id = namespaced(&collection.id);
+            for id in collection.models.iter_mut() {
+                *id = namespaced(id);
+            }
+        }
+
+        Self {
+            name: source.name.clone(),
+            index_trust,
+            requires_signature,
+            entries,
+            collections,
+            default_embedding,
+        }
+    }
+}
+
+/// What is saved to the cache file.
+#[derive(Serialize, Deserialize)]
+struct CardCache<'a> {
+    cards: Vec<std::borrow::Cow<'a, IndexedCard>>,
+    #[serde(default)]
+    collections: std::borrow::Cow<'a, [CollectionIndex]>,
+    #[serde(default)]
+    untrusted_sources: std::borrow::Cow<'a, [String]>,
+    #[serde(default)]
+    default_embedding: Option<std::borrow::Cow<'a, str>>,
+}
+
+/// The model cards of the whole catalog, kept in memory so searches and the
+/// featured list never read the card files of the catalog checkout.
+///
+/// It is built on the sync thread and saved to a single cache file, which is
+/// all the backend reads at startup.
+#[derive(Debug, Default)]
+pub struct CardIndex {
+    entries: HashMap<String, IndexedCard>,
+    // Ids of the featured models, most downloaded first
+    featured: Vec<String>,
+    collections: Vec<CollectionIndex>,
+    // Sources whose index is not verified
+    untrusted_sources: Vec<String>,
+    // File of the first embedding model of the public catalog
+    default_embedding: Option<String>,
+}
+
+impl CardIndex {
+    pub fn new(
+        entries: Vec<IndexedCard>,
+        collections: Vec<CollectionIndex>,
+        untrusted_sources: Vec<String>,
+    ) -> Self {
+        let mut featured: Vec<&IndexedCard> = entries
+            .iter()
+            .filter(|entry| entry.index.featured)
+            .collect();
+        featured.sort_by(|a, b| {
+            b.index
+                .download_count
+                .cmp(&a.index.download_count)
+                .then_with(|| a.index.id.cmp(&b.index.id))
+        });
+        let featured = featured
+            .into_iter()
+            .map(|entry| entry.index.id.clone())
+            .collect();
+
+        Self {
+            entries: entries
+                .into_iter()
+                .map(|entry| (entry.index.id.clone(), entry))
+                .collect(),
+            featured,
+            collections,
+            untrusted_sources,
+            default_embedding: None,
+        }
+    }
+
+    /// Merge the cards of several sources, highest priority first.
+    ///
+    /// A model listed by more than one source is kept once per source, under the
+    /// namespace of each, so files downloaded from any of them are still found.
+    /// The collections of every source are shown in the same order.
+    pub fn merge(sources: Vec<SourceCards>) -> Self {
+        let mut seen = HashSet::new();
+        let mut seen_collections = HashSet::new();
+        let mut entries = vec![];
+        let mut collections = vec![];
+        let mut untrusted_sources = vec![];
+        let mut default_embedding = None;
+        for source in sources {
+            // Sources without keys are not expected to be signed
+            if source.requires_signature && source.index_trust != CatalogTrust::Verified {
+                untrusted_sources.push(source.name);
+            }
+            default_embedding = default_embedding.or(source.default_embedding);
+            entries.extend(
+                source
+                    .entries
+                    .into_iter()
+                    .filter(|entry| seen.insert(entry.index.id.clone())),
+            );
+            collections.extend(
+                source
+                    .collections
+                    .into_iter()
+                    .filter(|collection| seen_collections.insert(collection.id.clone())),
+            );
+        }
+
+        Self {
+            default_embedding,
+            ..Self::new(entries, collections, untrusted_sources)
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let cache: CardCache = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        Ok(Self {
+            default_embedding: cache.default_embedding.map(|file_id| file_id.into_owned()),
+            ..Self::new(
+                cache
+                    .cards
+                    .into_iter()
+                    .map(|card| card.into_owned())
+                    .collect(),
+                cache.collections.into_owned(),
+                cache.untrusted_sources.into_owned(),
+            )
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let cache = CardCache {
+            cards: self
+                .entries
+                .values()
+                .map(std::borrow::Cow::Borrowed)
+                .collect(),
+            collections: std::borrow::Cow::Borrowed(&self.collections),
+            untrusted_sources: std::borrow::Cow::Borrowed(&self.untrusted_sources),
+            default_embedding: self
+                .default_embedding
+                .as_deref()
+                .map(std::borrow::Cow::Borrowed),
+        };
+        std::fs::write(path, serde_json::to_string(&cache)?)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&IndexedCard> {
+        self.entries.get(id)
+    }
+
+    pub fn source_id(&self, id: &str) -> Option<&str> {
+        self.entries.get(id).map(|entry| entry.source_id())
+    }
+
+    pub fn trust(&self, id: &str) -> CatalogTrust {
+        self.entries
+            .get(id)
+            .map(|entry| entry.trust)
+            .unwrap_or_default()
+    }
+
+    pub fn requires_signature(&self, id: &str) -> bool {
+        self.entries
+            .get(id)
+            .is_some_and(|entry| entry.requires_signature)
+    }
+
+    pub fn untrusted_sources(&self) -> &[String] {
+        &self.untrusted_sources
+    }
+
+    pub fn default_embedding(&self) -> Option<&str> {
+        self.default_embedding.as_deref()
+    }
+
+    pub fn featured(&self, limit: usize, offset: usize) -> Vec<ModelCard> {
+        self.featured
+            .iter()
+            .filter_map(|id| self.entries.get(id))
+            .skip(offset)
+            .take(limit)
+            .map(|entry| entry.card.clone())
+            .collect()
+    }
+
+    /// The curated collections with their cards, in the catalog order.
+    ///
+    /// Models missing from the catalog are skipped, and so are collections left empty.
+    pub fn collections(&self) -> Vec<(&CollectionIndex, Vec<ModelCard>)> {
+        self.collections
+            .iter()
+            .map(|collection| {
+                let cards = collection
+                    .models
+                    .iter()
+                    .filter_map(|id| self.entries.get(id))
+                    .map(|entry| entry.card.clone())
+                    .collect::<Vec<_>>();
+                (collection, cards)
+            })
+            .filter(|(_, cards)| !cards.is_empty())
+            .collect()
+    }
+
+    /// Models matching `query`, ranked and paginated, along with the total number of matches.
+    pub fn search(&self, query: &SearchQuery) -> (Vec<ModelCard>, usize) {
+        let tokens = search::tokenize(&query.text);
+        let mut candidates: Vec<search::Candidate> = self
+            .entries
+            .values()
+            .filter(|entry| {
+                search::matches_index_filters(&entry.index, query)
+                    && search::matches_card_filters(entry, query)
+            })
+            .filter_map(|entry| {
+                search::relevance(&entry.index, &tokens)
+                    .map(|score| search::Candidate { entry, score })
+            })
+            .collect();
+
+        search::sort_candidates(&mut candidates, query.sort);
+        let total = candidates.len();
+
+        let cards = candidates
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit)
+            .map(|candidate| candidate.entry.card.clone())
+            .collect();
+
+        (cards, total)
+    }
+}
+
+#[test]
+fn test_build_and_search() {
+    use base64::Engine;
+
+    let app_data_dir = std::env::temp_dir().join("moly-test-card-index");
+    let _ = std::fs::remove_dir_all(&app_data_dir);
+    let org_dir = app_data_dir.join("org");
+    std::fs::create_dir_all(&org_dir).unwrap();
+
+    let index = |name: &str, featured: bool, download_count: u32| ModelIndex {
+        id: format!("org/{name}"),
+        name: name.to_string(),
+        architecture: "llama".to_string(),
+        model_type: "chat".to_string(),
+        summary: String::new(),
+        featured,
+        like_count: 0,
+        download_count,
+    };
+    for (name, size) in [("Small-1B", "1B"), ("Large-70B", "70B")] {
+        let card = serde_json::json!({
+            "id": format!("org/{name}"),
+            "name": name,
+            "size": size,
+            "released_at": "2024-01-01T00:00:00Z",
+            "prompt_template": "",
+            "reverse_prompt": "",
+            "context_size": 4096,
+            "author": { "name": "org", "url": "", "description": "" },
+        });
+        std::fs::write(org_dir.join(format!("{name}.json")), card.to_string()).unwrap();
+    }
+
+    let collections = serde_json::json!([
+        { "id": "small", "name": "Small & fast", "models": ["org/Missing-7B", "org/Small-1B"] },
+        { "id": "empty", "name": "Empty", "models": ["org/Missing-7B"] },
+        { "id": "small", "name": "Small again", "models": ["org/Large-70B"] },
+    ]);
+    std::fs::write(
+        app_data_dir.join("collections.json"),
+        collections.to_string(),
+    )
+    .unwrap();
+
+    // The card of the last index is missing, it is left out
+    let indexs = vec![
+        index("Small-1B", true, 10),
+        index("Large-70B", true, 20),
+        index("Missing-7B", true, 30),
+    ];
+    let public = CatalogSource::public();
+    let cards = CardIndex::merge(vec![SourceCards::read(
+        &app_data_dir,
+        &public,
+        indexs.clone(),
+        CatalogTrust::Verified,
+    )]);
+    assert_eq!(cards.len(), 2);
+    assert_eq!(
+        cards.get("org/Small-1B").unwrap().parameters,
+        Some(1_000_000_000)
+    );
+
+    let cache_path = app_data_dir.join("cards.json");
+    cards.save(&cache_path).unwrap();
+    let cards = CardIndex::load(&cache_path).unwrap();
+
+    let featured: Vec<String> = cards.featured(10, 0).into_iter().map(|c| c.id).collect();
+    assert_eq!(featured, vec!["org/Large-70B", "org/Small-1B"]);
+
+    let collections = cards.collections();
+    assert_eq!(collections.len(), 1);
+    assert_eq!(collections[0].0.name, "Small & fast");
+    assert_eq!(collections[0].1[0].id, "org/Small-1B");
+
+    let query = SearchQuery {
+        max_parameters: Some(10_000_000_000),
+        ..SearchQuery::new("org")
+    };
+    let (results, total) = cards.search(&query);
+    assert_eq!(total, 1);
+    assert_eq!(results[0].id, "org/Small-1B");
+
+    // The ids of other sources are namespaced, the entries of every source are kept
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+    let internal = CatalogSource {
+        name: "internal".to_string(),
+        public_keys: vec![base64::engine::general_purpose::STANDARD
+            .encode(signing_key.verifying_key().as_bytes())],
+        ..Default::default()
+    };
+    let cards = CardIndex::merge(vec![
+        SourceCards::read(
+            &app_data_dir,
+            &internal,
+            indexs.clone(),
+            CatalogTrust::Unsigned,
+        ),
+        SourceCards::read(&app_data_dir, &public, indexs, CatalogTrust::Verified),
+    ]);
+    assert_eq!(cards.len(), 4);
+    assert_eq!(cards.get("org/Small-1B").unwrap().card.id, "org/Small-1B");
+    assert_eq!(
+        cards.get("internal/org/Small-1B").unwrap().card.id,
+        "internal/org/Small-1B"
+    );
+    assert_eq!(
+        cards.source_id("internal/org/Small-1B"),
+        Some("org/Small-1B")
+    );
+    assert_eq!(cards.source_id("org/Small-1B"), Some("org/Small-1B"));
+    let collections = cards.collections();
+    assert_eq!(collections.len(), 2);
+    assert_eq!(collections[0].0.id, "internal/small");
+    assert_eq!(collections[0].1[0].id, "internal/org/Small-1B");
+    assert_eq!(collections[1].0.id, "small");
+    assert_eq!(collections[1].1[0].id, "org/Small-1B");
+    assert_eq!(cards.untrusted_sources(), ["internal".to_string()]);
+    assert_eq!(cards.trust("internal/org/Small-1B"), CatalogTrust::Unsigned);
+    // Only the cards of sources with trusted keys have to be verified
+    assert!(cards.requires_signature("internal/org/Small-1B"));
+    assert!(!cards.requires_signature("org/Small-1B"));
+
+    let _ = std::fs::remove_dir_all(&app_data_dir);
+}
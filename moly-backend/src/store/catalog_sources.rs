@@ -0,0 +1,316 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use moly_protocol::data::{CatalogSource, CatalogSourceKind, CatalogTrust};
+
+use super::catalog_trust::{self, TrustedKeys};
+#[cfg(feature = "git-catalog")]
+use super::git_catalog;
+use super::model_cards::{self, ModelIndex, REPO_NAME};
+use super::{http_catalog, settings};
+
+/// The catalog sources, highest priority first. Only the public catalog is used
+/// until others are configured.
+pub fn get_catalog_sources(conn: &rusqlite::Connection) -> anyhow::Result<Vec<CatalogSource>> {
+    Ok(
+        settings::get(conn, settings::CATALOG_SOURCES)?.unwrap_or_else(|| {
+            vec![CatalogSource {
+                kind: DEFAULT_KIND,
+                ..CatalogSource::public()
+            }]
+        }),
+    )
+}
+
+/// How the public catalog is synced until another way is picked.
+#[cfg(feature = "git-catalog")]
+const DEFAULT_KIND: CatalogSourceKind = CatalogSourceKind::Git;
+#[cfg(not(feature = "git-catalog"))]
+const DEFAULT_KIND: CatalogSourceKind = CatalogSourceKind::Http;
+
+pub fn set_catalog_sources(
+    conn: &rusqlite::Connection,
+    sources: Vec<CatalogSource>,
+) -> anyhow::Result<Vec<CatalogSource>> {
+    let sources = validate(sources)?;
+    settings::set(conn, settings::CATALOG_SOURCES, &sources)?;
+    Ok(sources)
+}
+
+/// Trim the sources and check they can be synced.
+pub fn validate(sources: Vec<CatalogSource>) -> anyhow::Result<Vec<CatalogSource>> {
+    if sources.is_empty() {
+        return Err(anyhow::anyhow!("At least one catalog source is needed"));
+    }
+
+    let mut names = HashSet::new();
+    sources
+        .into_iter()
+        .map(|source| {
+            let source = CatalogSource {
+                name: source.name.trim().to_string(),
+                url: source.url.trim().to_string(),
+                pinned: source
+                    .pinned
+                    .map(|pinned| pinned.trim().to_string())
+                    .filter(|pinned| !pinned.is_empty()),
+                public_keys: source
+                    .public_keys
+                    .iter()
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty())
+                    .collect(),
+                ..source
+            };
+
+            // The name is used for the model ids and the directory of the source
+            if source.name.is_empty()
+                || !source
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(anyhow::anyhow!(
+                    "Invalid catalog source name {:?}, use letters, digits, - and _",
+                    source.name
+                ));
+            }
+            if !names.insert(source.name.to_ascii_lowercase()) {
+                return Err(anyhow::anyhow!("Duplicated catalog source {}", source.name));
+            }
+            // The public catalog defaults to the repository of the region
+            if source.url.is_empty() && !source.is_public() {
+                return Err(anyhow::anyhow!(
+                    "The catalog source {} has no url",
+                    source.name
+                ));
+            }
+            #[cfg(not(feature = "git-catalog"))]
+            if source.kind == CatalogSourceKind::Git {
+                return Err(anyhow::anyhow!(
+                    "Git catalog sources are not supported by this build, {} must use HTTP",
+                    source.name
+                ));
+            }
+            for key in &source.public_keys {
+                catalog_trust::parse_key(key)
+                    .map_err(|e| anyhow::anyhow!("Invalid public key for {}: {e}", source.name))?;
+            }
+            if source.pinned.is_some() && source.kind != CatalogSourceKind::Git {
+                return Err(anyhow::anyhow!(
+                    "Only git catalog sources can be pinned, {} is not",
+                    source.name
+                ));
+            }
+            Ok(source)
+        })
+        .collect()
+}
+
+/// The model id of a catalog entry, prefixed with the name of its source.
+pub fn namespaced_id(source: &CatalogSource, id: &str) -> String {
+    if source.is_public() {
+        id.to_string()
+    } else {
+        format!("{}/{}", source.name, id)
+    }
+}
+
+/// Directory holding the local copy of a source.
+pub fn source_dir(app_data_dir: &Path, source: &CatalogSource) -> PathBuf {
+    if source.is_public() {
+        app_data_dir.join(REPO_NAME)
+    } else {
+        app_data_dir.join(format!("{}-{}", REPO_NAME, source.name))
+    }
+}
+
+/// The index list of the local copy of a source, and whether it is signed.
+pub fn read_local_index(
+    source: &CatalogSource,
+    source_dir: &Path,
+) -> anyhow::Result<(Vec<ModelIndex>, CatalogTrust)> {
+    read_index(source_dir, &TrustedKeys::for_source(source))
+}
+
+fn read_index(
+    source_dir: &Path,
+    keys: &TrustedKeys,
+) -> anyhow::Result<(Vec<ModelIndex>, CatalogTrust)> {
+    let index_path = source_dir.join("index.json");
+    let index_list = model_cards::read_index_list(&index_path)?;
+    Ok((index_list, keys.verify_file(&index_path)))
+}
+
+/// Update the local copy of a source and return its index list, along with
+/// whether the index is signed.
+///
+/// `default_repo_url` is the repository of the public catalog for the region,
+/// used when the public source has no url.
+pub fn sync_source(
+    source: &CatalogSource,
+    source_dir: &Path,
+    default_repo_url: &str,
+) -> anyhow::Result<(Vec<ModelIndex>, CatalogTrust)> {
+    let keys = TrustedKeys::for_source(source);
+    match source.kind {
+        CatalogSourceKind::Git => {
+            let repo_url = if source.url.is_empty() {
+                default_repo_url
+            } else {
+                &source.url
+            };
+            sync_git(repo_url, source_dir, source.pinned.as_deref(), &keys)
+        }
+        CatalogSourceKind::Http => {
+            let url = if source.url.is_empty() {
+                raw_files_url(default_repo_url)
+            } else {
+                source.url.clone()
+            };
+            // Only the embedding models of the public catalog are used
+            let optional_files: &[&str] = if source.is_public() {
+                &["collections.json", "embedding.json"]
+            } else {
+                &["collections.json"]
+            };
+            http_catalog::sync(&url, source_dir, optional_files)?;
+            read_index(source_dir, &keys)
+        }
+    }
+}
+
+/// Url serving the files of the `main` branch of a repository.
+fn raw_files_url(repo_url: &str) -> String {
+    let repo_url = repo_url.trim_end_matches('/').trim_end_matches(".git");
+    match repo_url.strip_prefix("https://github.com/") {
+        Some(repo) => format!("https://raw.githubusercontent.com/{}/main", repo),
+        None => format!("{}/raw/main", repo_url),
+    }
+}
+
+#[cfg(feature = "git-catalog")]
+fn sync_git(
+    repo_url: &str,
+    source_dir: &Path,
+    pinned: Option<&str>,
+    keys: &TrustedKeys,
+) -> anyhow::Result<(Vec<ModelIndex>, CatalogTrust)> {
+    log::info!("Using model_cards repo: {}", repo_url);
+    // The source was synced over HTTP before, a clone needs an empty directory
+    if source_dir.exists() && !source_dir.join(".git").exists() {
+        std::fs::remove_dir_all(source_dir)?;
+    }
+    let repo = git_catalog::open_or_clone(repo_url, source_dir)?;
+
+    if let Some(pinned) = pinned {
+        git_catalog::checkout_rev(&repo, pinned).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to check out {} of the catalog: {}",
+                pinned,
+                e.message()
+            )
+        })?;
+        // The released index is the latest one, the pinned checkout has its own
+        return read_index(source_dir, keys);
+    }
+
+    let mut r = Ok(());
+    for _ in 0..2 {
+        r = git_catalog::pull(&repo, "origin", "main");
+        if r.is_ok() {
+            break;
+        }
+    }
+
+    if let Err(e) = r {
+        log::error!("Failed to pull: {:?}", e);
+        log::error!("please remove the repo({:?}) and try again", source_dir);
+        return Err(anyhow::anyhow!(
+            "Failed to update the model catalog: {}",
+            e.message()
+        ));
+    }
+
+    let index_url = format!("{}/releases/download/index_release/index.json", repo_url);
+    let remote_index = reqwest::blocking::get(&index_url)
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.bytes());
+    match remote_index.map(|index| (serde_json::from_slice::<Vec<ModelIndex>>(&index), index)) {
+        Ok((Ok(index_list), index)) => {
+            let signature = reqwest::blocking::get(format!("{}.sig", index_url))
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.text())
+                .ok();
+            Ok((index_list, keys.verify(&index, signature.as_deref())))
+        }
+        _ => read_index(source_dir, keys),
+    }
+}
+
+#[cfg(not(feature = "git-catalog"))]
+fn sync_git(
+    _repo_url: &str,
+    _source_dir: &Path,
+    _pinned: Option<&str>,
+    _keys: &TrustedKeys,
+) -> anyhow::Result<(Vec<ModelIndex>, CatalogTrust)> {
+    Err(anyhow::anyhow!(
+        "Git catalog sources are not supported by this build, use an HTTP source"
+    ))
+}
+
+#[test]
+fn test_validate() {
+    let source =
+        |name: &str, kind: CatalogSourceKind, url: &str, pinned: Option<&str>| CatalogSource {
+            name: name.to_string(),
+            kind,
+            url: url.to_string(),
+            pinned: pinned.map(str::to_string),
+            public_keys: vec![],
+        };
+
+    let sources = validate(vec![
+        source(
+            " internal ",
+            CatalogSourceKind::Git,
+            "https://git.example.com/models.git",
+            Some(" v2 "),
+        ),
+        CatalogSource::public(),
+    ])
+    .unwrap();
+    assert_eq!(sources[0].name, "internal");
+    assert_eq!(sources[0].pinned.as_deref(), Some("v2"));
+
+    assert!(validate(vec![]).is_err());
+    assert!(validate(vec![CatalogSource::public(), CatalogSource::public()]).is_err());
+    assert!(validate(vec![source(
+        "a/b",
+        CatalogSourceKind::Http,
+        "https://x",
+        None
+    )])
+    .is_err());
+    assert!(validate(vec![source("http", CatalogSourceKind::Http, "", None)]).is_err());
+    assert!(validate(vec![source(
+        "http",
+        CatalogSourceKind::Http,
+        "https://example.com/index.json",
+        Some("v1")
+    )])
+    .is_err());
+
+    let internal = &sources[0];
+    assert_eq!(namespaced_id(internal, "org/model"), "internal/org/model");
+    assert_eq!(
+        namespaced_id(&CatalogSource::public(), "org/model"),
+        "org/model"
+    );
+
+    assert_eq!(
+        raw_files_url("https://github.com/moxin-org/model-cards.git"),
+        "https://raw.githubusercontent.com/moxin-org/model-cards/main"
+    );
+}
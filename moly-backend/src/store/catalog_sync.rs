@@ -0,0 +1,107 @@
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use moly_protocol::data::{CatalogSource, CatalogSyncStatus};
+
+use super::model_cards::{self, CatalogUpdate};
+use super::settings;
+
+/// Refreshes the model catalog in the background and tells subscribers when it
+/// is done, so the backend never waits on the network to answer commands.
+#[derive(Debug, Default)]
+pub struct CatalogSync {
+    status: Mutex<CatalogSyncStatus>,
+    subscribers: Mutex<Vec<Sender<CatalogSyncStatus>>>,
+}
+
+impl CatalogSync {
+    /// `untrusted_sources` are the ones of the catalog loaded from the cache.
+    pub fn new(conn: &rusqlite::Connection, untrusted_sources: Vec<String>) -> Self {
+        let last_sync = settings::get::<String>(conn, settings::CATALOG_LAST_SYNC)
+            .ok()
+            .flatten()
+            .and_then(|last_sync| DateTime::parse_from_rfc3339(&last_sync).ok())
+            .map(|last_sync| last_sync.with_timezone(&Utc));
+
+        Self {
+            status: Mutex::new(CatalogSyncStatus {
+                last_sync,
+                untrusted_sources,
+                ..Default::default()
+            }),
+            subscribers: Default::default(),
+        }
+    }
+
+    pub fn status(&self) -> CatalogSyncStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn subscribe(&self, tx: Sender<CatalogSyncStatus>) {
+        self.subscribers.lock().unwrap().push(tx);
+    }
+
+    /// Start a refresh unless one is already running, returning whether it started.
+    ///
+    /// The new catalog is handed over through `update_tx` before subscribers are
+    /// notified, so any command they send afterwards already sees it.
+    pub fn spawn(
+        self: &Arc<Self>,
+        app_data_dir: PathBuf,
+        region: Option<String>,
+        sources: Vec<CatalogSource>,
+        sql_conn: Arc<Mutex<rusqlite::Connection>>,
+        update_tx: Sender<CatalogUpdate>,
+    ) -> bool {
+        {
+            let mut status = self.status.lock().unwrap();
+            if status.syncing {
+                return false;
+            }
+            status.syncing = true;
+        }
+
+        let sync = self.clone();
+        std::thread::spawn(move || {
+            let r = model_cards::sync_model_cards_repo(&app_data_dir, region.as_deref(), &sources);
+
+            let status = {
+                let mut status = sync.status.lock().unwrap();
+                status.syncing = false;
+                match r {
+                    Ok(update) => {
+                        log::info!("sync model cards repo success");
+                        let now = Utc::now();
+                        status.last_sync = Some(now);
+                        // Sources that failed are still reported, the others are up to date
+                        status.last_error =
+                            Some(update.errors.join("; ")).filter(|e| !e.is_empty());
+                        status.untrusted_sources = update.cards.untrusted_sources().to_vec();
+                        let _ = update_tx.send(update);
+
+                        let conn = sql_conn.lock().unwrap();
+                        if let Err(e) =
+                            settings::set(&conn, settings::CATALOG_LAST_SYNC, &now.to_rfc3339())
+                        {
+                            log::error!("save catalog sync time error: {e}");
+                        }
+                    }
+                    Err(e) => {
+                        log::error!("sync model cards repo error: {e}");
+                        status.last_error = Some(e.to_string());
+                    }
+                }
+                status.clone()
+            };
+
+            sync.subscribers
+                .lock()
+                .unwrap()
+                .retain(|tx| tx.send(status.clone()).is_ok());
+        });
+
+        true
+    }
+}
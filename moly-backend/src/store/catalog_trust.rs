@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use moly_protocol::data::{CatalogSource, CatalogTrust};
+
+/// Keys trusted for every source, bundled at build time as a comma separated
+/// list of base64 ed25519 public keys.
+const BUNDLED_KEYS: Option<&str> = option_env!("MOLY_CATALOG_PUBLIC_KEYS");
+
+pub fn parse_key(key: &str) -> anyhow::Result<VerifyingKey> {
+    let bytes = base64::engine::general_purpose::STANDARD.decode(key.trim())?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("An ed25519 public key is 32 bytes long"))?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+/// Path of the detached signature of a catalog file, like `index.json.sig`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+/// The keys allowed to sign the index and the cards of a source.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedKeys(Vec<VerifyingKey>);
+
+impl TrustedKeys {
+    pub fn for_source(source: &CatalogSource) -> Self {
+        let keys = BUNDLED_KEYS
+            .into_iter()
+            .flat_map(|keys| keys.split(','))
+            .filter(|key| !key.trim().is_empty())
+            .chain(source.public_keys.iter().map(String::as_str))
+            .filter_map(|key| match parse_key(key) {
+                Ok(key) => Some(key),
+                Err(e) => {
+                    log::error!("invalid catalog key for {}: {e}", source.name);
+                    None
+                }
+            })
+            .collect();
+        Self(keys)
+    }
+
+    /// Whether no key is trusted, nothing of the source can be verified then.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Check `data` against a base64 detached signature.
+    pub fn verify(&self, data: &[u8], signature: Option<&str>) -> CatalogTrust {
+        let Some(signature) = signature else {
+            return CatalogTrust::Unsigned;
+        };
+        if self.0.is_empty() {
+            return CatalogTrust::Unsigned;
+        }
+
+        let signature = base64::engine::general_purpose::STANDARD
+            .decode(signature.trim())
+            .ok()
+            .and_then(|signature| Signature::from_slice(&signature).ok());
+        match signature {
+            Some(signature)
+                if self
+                    .0
+                    .iter()
+                    .any(|key| key.verify(data, &signature).is_ok()) =>
+            {
+                CatalogTrust::Verified
+            }
+            _ => CatalogTrust::Invalid,
+        }
+    }
+
+    /// Check a file of the local copy of a catalog against the signature next to it.
+    pub fn verify_file(&self, path: &Path) -> CatalogTrust {
+        let Ok(data) = std::fs::read(path) else {
+            return CatalogTrust::Unsigned;
+        };
+        let signature = std::fs::read_to_string(signature_path(path)).ok();
+        self.verify(&data, signature.as_deref())
+    }
+}
+
+#[test]
+fn test_verify() {
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let signing_key = SigningKey::from_bytes(&[7; 32]);
+    let encode = |bytes: &[u8]| base64::engine::general_purpose::STANDARD.encode(bytes);
+    let source = CatalogSource {
+        name: "internal".to_string(),
+        public_keys: vec![encode(signing_key.verifying_key().as_bytes())],
+        ..Default::default()
+    };
+    let keys = TrustedKeys::for_source(&source);
+
+    let card = br#"{"id": "org/model"}"#;
+    let signature = encode(&signing_key.sign(card).to_bytes());
+    assert_eq!(keys.verify(card, Some(&signature)), CatalogTrust::Verified);
+    assert_eq!(keys.verify(card, None), CatalogTrust::Unsigned);
+    assert_eq!(
+        keys.verify(br#"{"id": "evil/model"}"#, Some(&signature)),
+        CatalogTrust::Invalid
+    );
+    assert_eq!(
+        keys.verify(card, Some("not a signature")),
+        CatalogTrust::Invalid
+    );
+
+    // Without keys nothing can be verified
+    assert_eq!(
+        TrustedKeys::default().verify(card, Some(&signature)),
+        CatalogTrust::Unsigned
+    );
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use moly_protocol::data::{CleanupPlan, FileID, FileUsage, StorageQuota};
+
+/// Free space kept on the models volume, a full disk breaks more than the download.
+pub const SPACE_MARGIN: u64 = 512 * 1024 * 1024;
+
+/// Free bytes on the volume holding `path`, which may not exist yet.
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|dir| dir.exists())
+        .unwrap_or(Path::new("."));
+    fs2::available_space(existing)
+}
+
+/// Space set aside for downloads, so a download doesn't start on the space another
+/// one is about to fill.
+///
+/// Each download holds the bytes it still has to write, updated as it runs.
+#[derive(Debug, Clone, Default)]
+pub struct SpaceReservations {
+    reservations: Arc<Mutex<HashMap<FileID, Arc<AtomicU64>>>>,
+}
+
+impl SpaceReservations {
+    pub fn reserve(&self, file_id: FileID, bytes: u64) -> Arc<AtomicU64> {
+        let remaining = Arc::new(AtomicU64::new(bytes));
+        self.reservations
+            .lock()
+            .unwrap()
+            .insert(file_id, remaining.clone());
+        remaining
+    }
+
+    /// The bytes still to write by a download, to update as it progresses.
+    pub fn remaining(&self, file_id: &FileID) -> Option<Arc<AtomicU64>> {
+        self.reservations.lock().unwrap().get(file_id).cloned()
+    }
+
+    pub fn release(&self, file_id: &FileID) {
+        self.reservations.lock().unwrap().remove(file_id);
+    }
+
+    /// Bytes still to write by every download, leaving one out if given.
+    pub fn total(&self, except: Option<&FileID>) -> u64 {
+        self.reservations
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(file_id, _)| Some(*file_id) != except)
+            .map(|(_, remaining)| remaining.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+/// The least recently used files to remove to get the usage under the quota, `None`
+/// when there is no quota or the usage is already under it.
+///
+/// Pinned and `protected` files are never picked, the plan may not reach the limit.
+/// Files go by when they were last loaded, or downloaded if they never were.
+pub fn plan_cleanup(
+    files: &[FileUsage],
+    quota: StorageQuota,
+    protected: &[FileID],
+) -> Option<CleanupPlan> {
+    let limit = quota.limit?;
+    let used: u64 = files.iter().map(|file| file.size).sum();
+    if used <= limit {
+        return None;
+    }
+
+    let mut candidates: Vec<&FileUsage> = files
+        .iter()
+        .filter(|file| !file.pinned && !protected.contains(&file.file_id))
+        .collect();
+    candidates.sort_by_key(|file| file.last_loaded_at.unwrap_or(file.downloaded_at));
+
+    let mut remaining = used;
+    let mut plan = CleanupPlan {
+        files: vec![],
+        used,
+        limit,
+        auto_cleanup: quota.auto_cleanup,
+    };
+    for file in candidates {
+        if remaining <= limit {
+            break;
+        }
+        remaining -= file.size;
+        plan.files.push(file.clone());
+    }
+
+    Some(plan)
+}
+
+#[test]
+fn test_reservations() {
+    let reservations = SpaceReservations::default();
+    let a = "a#a.gguf".to_string();
+    let b = "b#b.gguf".to_string();
+
+    reservations.reserve(a.clone(), 100);
+    let remaining = reservations.reserve(b.clone(), 50);
+    assert_eq!(reservations.total(None), 150);
+    assert_eq!(reservations.total(Some(&a)), 50);
+
+    remaining.store(20, Ordering::Relaxed);
+    assert_eq!(reservations.total(None), 120);
+
+    reservations.release(&a);
+    assert_eq!(reservations.total(None), 20);
+}
+
+#[test]
+fn test_plan_cleanup() {
+    let days_ago = |days: i64| chrono::Utc::now() - chrono::Duration::days(days);
+    let file = |id: &str, size: u64, loaded_days_ago: Option<i64>, pinned: bool| FileUsage {
+        file_id: id.to_string(),
+        size,
+        downloaded_at: days_ago(20),
+        last_loaded_at: loaded_days_ago.map(days_ago),
+        pinned,
+        ..Default::default()
+    };
+    let files = vec![
+        file("recent", 40, Some(1), false),
+        file("old", 30, Some(30), false),
+        file("never", 20, None, false),
+        file("pinned", 50, Some(60), true),
+        file("chat", 10, Some(90), false),
+    ];
+
+    let quota = |limit: u64| StorageQuota {
+        limit: Some(limit),
+        auto_cleanup: false,
+    };
+    assert!(plan_cleanup(&files, StorageQuota::default(), &[]).is_none());
+    assert!(plan_cleanup(&files, quota(150), &[]).is_none());
+
+    let plan = plan_cleanup(&files, quota(100), &["chat".to_string()]).unwrap();
+    let ids: Vec<&str> = plan.files.iter().map(|f| f.file_id.as_str()).collect();
+    assert_eq!(ids, ["old", "never"]);
+    assert_eq!(plan.size(), 50);
+    assert!(!plan.is_partial());
+
+    let plan = plan_cleanup(&files, quota(10), &["chat".to_string()]).unwrap();
+    assert_eq!(plan.files.len(), 3);
+    assert!(plan.is_partial());
+}
@@ -1,8 +1,14 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use chrono::{DateTime, Utc};
 use rusqlite::Row;
 
+use super::model_cards::RemoteFile;
+
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct DownloadedFile {
     pub id: Arc<String>,
@@ -20,6 +26,11 @@ pub struct DownloadedFile {
     pub tags: Vec<String>,
     pub featured: bool,
     pub sha256: String,
+    /// Shard file names of a split model, the first one is `name`.
+    pub parts: Vec<String>,
+    pub last_loaded_at: Option<DateTime<Utc>>,
+    /// Kept when cleaning up the least recently used files.
+    pub pinned: bool,
 }
 
 impl DownloadedFile {
@@ -28,8 +39,9 @@ impl DownloadedFile {
             "INSERT OR REPLACE INTO download_files (
                 id, model_id, name, size, quantization,
                 prompt_template, reverse_prompt, context_size,
-                downloaded, file_size, download_dir, downloaded_at, tags, featured, sha256)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                downloaded, file_size, download_dir, downloaded_at, tags, featured, sha256, parts,
+                last_loaded_at, pinned)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
             rusqlite::params![
                 self.id,
                 self.model_id,
@@ -46,6 +58,9 @@ impl DownloadedFile {
                 serde_json::to_string(&self.tags).unwrap(),
                 self.featured,
                 self.sha256,
+                serde_json::to_string(&self.parts).unwrap(),
+                self.last_loaded_at.map(|date| date.to_rfc3339()),
+                self.pinned,
             ],
         )?;
 
@@ -70,6 +85,30 @@ impl DownloadedFile {
         Ok(())
     }
 
+    pub fn update_last_loaded(
+        conn: &rusqlite::Connection,
+        id: &str,
+        last_loaded_at: DateTime<Utc>,
+    ) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_files SET last_loaded_at = ?2 WHERE id = ?1",
+            rusqlite::params![id, last_loaded_at.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_pinned(
+        conn: &rusqlite::Connection,
+        id: &str,
+        pinned: bool,
+    ) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_files SET pinned = ?2 WHERE id = ?1",
+            rusqlite::params![id, pinned],
+        )?;
+        Ok(())
+    }
+
     fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
         let downloaded_at =
             chrono::DateTime::parse_from_rfc3339(&row.get::<_, String>("downloaded_at")?)
@@ -77,6 +116,12 @@ impl DownloadedFile {
                 .unwrap_or_default();
 
         let tags = serde_json::from_str(row.get::<_, String>("tags")?.as_str()).unwrap_or_default();
+        let parts =
+            serde_json::from_str(row.get::<_, String>("parts")?.as_str()).unwrap_or_default();
+        let last_loaded_at = row
+            .get::<_, Option<String>>("last_loaded_at")?
+            .and_then(|date| chrono::DateTime::parse_from_rfc3339(&date).ok())
+            .map(|date| date.to_utc());
 
         Ok(DownloadedFile {
             id: Arc::new(row.get("id")?),
@@ -94,6 +139,9 @@ impl DownloadedFile {
             tags,
             featured: row.get("featured")?,
             sha256: row.get("sha256")?,
+            parts,
+            last_loaded_at,
+            pinned: row.get("pinned")?,
         })
     }
 
@@ -159,37 +207,49 @@ impl DownloadedFile {
         })
     }
 
-    pub fn remove(file_id: &str, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
-        conn.execute(
-            "DELETE FROM download_files WHERE id = ?1",
-            rusqlite::params![file_id],
-        )?;
-        Ok(())
+    /// Where the file is on disk, the first shard for split models.
+    pub fn path(&self) -> PathBuf {
+        Path::new(&self.download_dir)
+            .join(&self.model_id)
+            .join(&self.name)
     }
-}
 
-fn check_context_size(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
-    let mut stmt = conn.prepare("PRAGMA table_info(download_files)")?;
-    let mut rows = stmt.query_map([], |row| {
-        let name: String = row.get(1)?;
-        Ok(name)
-    })?;
+    /// Names of the files on disk, every shard for split models.
+    pub fn file_names(&self) -> Vec<String> {
+        if self.parts.is_empty() {
+            vec![self.name.clone()]
+        } else {
+            self.parts.clone()
+        }
+    }
 
-    let check = rows.find(|row| matches!(row.as_deref(), Ok("context_size")));
+    /// Whether the catalog now lists another revision of this file: a new digest,
+    /// a new size or other shards.
+    pub fn is_outdated(&self, remote_file: &RemoteFile) -> bool {
+        let sha256 = remote_file.sha256.as_deref().unwrap_or_default();
+        // Files downloaded before digests were known can't be compared by digest
+        let sha256_changed = !sha256.is_empty() && !self.sha256.is_empty() && sha256 != self.sha256;
+        let parts_changed = !remote_file
+            .parts
+            .iter()
+            .map(|part| &part.name)
+            .eq(self.parts.iter());
+
+        sha256_changed || parts_changed || remote_file.size != self.size
+    }
 
-    if check.is_none() {
+    pub fn remove(file_id: &str, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
         conn.execute(
-            "ALTER TABLE download_files ADD COLUMN context_size INT DEFAULT 1024",
-            [],
+            "DELETE FROM download_files WHERE id = ?1",
+            rusqlite::params![file_id],
         )?;
+        Ok(())
     }
-    Ok(())
 }
 
 pub fn create_table_download_files(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
     conn.execute_batch(
-        "BEGIN;
-        CREATE TABLE IF NOT EXISTS download_files (
+        "CREATE TABLE IF NOT EXISTS download_files (
             id TEXT PRIMARY KEY,
             model_id TEXT NOT NULL,
             name TEXT NOT NULL,
@@ -204,22 +264,20 @@ pub fn create_table_download_files(conn: &rusqlite::Connection) -> rusqlite::Res
             downloaded_at TEXT NOT NULL,
             tags TEXT NOT NULL,
             featured INTEGER DEFAULT 0,
-            sha256 TEXT NOT NULL DEFAULT ''
+            sha256 TEXT NOT NULL DEFAULT '',
+            parts TEXT NOT NULL DEFAULT '[]'
         );
         CREATE INDEX IF NOT EXISTS index_model_id ON download_files (model_id);
-        CREATE INDEX IF NOT EXISTS index_downloaded ON download_files (downloaded);
-        COMMIT;",
+        CREATE INDEX IF NOT EXISTS index_downloaded ON download_files (downloaded);",
     )?;
 
-    check_context_size(conn)?;
-
     Ok(())
 }
 
 #[test]
 fn test_sql() {
-    let conn: rusqlite::Connection = rusqlite::Connection::open_in_memory().unwrap();
-    create_table_download_files(&conn).unwrap();
+    let mut conn: rusqlite::Connection = rusqlite::Connection::open_in_memory().unwrap();
+    super::migrations::migrate(&mut conn).unwrap();
 
     let mut downloaded_file = DownloadedFile {
         id: Arc::new("test".to_string()),
@@ -237,6 +295,9 @@ fn test_sql() {
         tags: vec!["test".to_string()],
         featured: false,
         sha256: Default::default(),
+        parts: vec!["test-00001-of-00002.gguf".to_string()],
+        last_loaded_at: None,
+        pinned: false,
     };
 
     downloaded_file.insert_into_db(&conn).unwrap();
@@ -250,4 +311,48 @@ fn test_sql() {
     let files = DownloadedFile::get_finished(&conn).unwrap();
     assert_eq!(files.len(), 1);
     assert_eq!(files[&downloaded_file.id], downloaded_file);
+
+    let last_loaded_at = DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+        .unwrap()
+        .to_utc();
+    DownloadedFile::update_last_loaded(&conn, &downloaded_file.id, last_loaded_at).unwrap();
+    DownloadedFile::update_pinned(&conn, &downloaded_file.id, true).unwrap();
+
+    let file = DownloadedFile::get_by_id(&conn, &downloaded_file.id).unwrap();
+    assert_eq!(file.last_loaded_at, Some(last_loaded_at));
+    assert!(file.pinned);
+}
+
+#[test]
+fn test_is_outdated() {
+    let downloaded_file = DownloadedFile {
+        name: "model.Q4_K_M.gguf".to_string(),
+        size: "4.37 GB".to_string(),
+        sha256: "abc".to_string(),
+        ..Default::default()
+    };
+    let remote_file = RemoteFile {
+        name: "model.Q4_K_M.gguf".to_string(),
+        size: "4.37 GB".to_string(),
+        sha256: Some("abc".to_string()),
+        ..Default::default()
+    };
+    assert!(!downloaded_file.is_outdated(&remote_file));
+
+    assert!(downloaded_file.is_outdated(&RemoteFile {
+        sha256: Some("def".to_string()),
+        ..remote_file.clone()
+    }));
+    assert!(downloaded_file.is_outdated(&RemoteFile {
+        size: "4.41 GB".to_string(),
+        ..remote_file.clone()
+    }));
+    assert!(!DownloadedFile {
+        sha256: String::new(),
+        ..downloaded_file.clone()
+    }
+    .is_outdated(&RemoteFile {
+        sha256: Some("def".to_string()),
+        ..remote_file.clone()
+    }));
 }
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use moly_protocol::data::{DownloadPriority, FileID};
+
+#[derive(Debug)]
+pub struct QueuedDownload<T> {
+    pub file_id: FileID,
+    pub priority: DownloadPriority,
+    pub task: T,
+}
+
+/// Downloads waiting for a free slot plus the ones currently running.
+///
+/// The queue is kept ordered by priority, downloads with the same priority
+/// start in the order they were queued unless they are reordered. A running
+/// download gives way to a queued one with a higher priority, see
+/// [`DownloadQueue::preemptible`].
+#[derive(Debug)]
+pub struct DownloadQueue<T> {
+    queued: Vec<QueuedDownload<T>>,
+    active: HashMap<FileID, DownloadPriority>,
+    max_active: usize,
+}
+
+impl<T> DownloadQueue<T> {
+    pub fn new(max_active: usize) -> Self {
+        Self {
+            queued: Vec::new(),
+            active: HashMap::new(),
+            max_active,
+        }
+    }
+
+    pub fn push(&mut self, file_id: FileID, priority: DownloadPriority, task: T) {
+        self.remove(&file_id);
+        self.insert(QueuedDownload {
+            file_id,
+            priority,
+            task,
+        });
+    }
+
+    fn insert(&mut self, download: QueuedDownload<T>) {
+        let index = self
+            .queued
+            .iter()
+            .position(|d| d.priority < download.priority)
+            .unwrap_or(self.queued.len());
+        self.queued.insert(index, download);
+    }
+
+    /// Take the next download if there is a free slot, marking it as active.
+    pub fn pop_ready(&mut self) -> Option<QueuedDownload<T>> {
+        if self.active.len() >= self.max_active || self.queued.is_empty() {
+            return None;
+        }
+
+        let download = self.queued.remove(0);
+        self.active
+            .insert(download.file_id.clone(), download.priority);
+        Some(download)
+    }
+
+    pub fn finish(&mut self, file_id: &FileID) {
+        self.active.remove(file_id);
+    }
+
+    /// Drop a queued download, returning whether it was queued.
+    pub fn remove(&mut self, file_id: &FileID) -> bool {
+        let len = self.queued.len();
+        self.queued.retain(|d| &d.file_id != file_id);
+        len != self.queued.len()
+    }
+
+    pub fn set_priority(
+        &mut self,
+        file_id: &FileID,
+        priority: DownloadPriority,
+    ) -> anyhow::Result<()> {
+        if let Some(active_priority) = self.active.get_mut(file_id) {
+            *active_priority = priority;
+            return Ok(());
+        }
+
+        let index = self
+            .queued
+            .iter()
+            .position(|d| &d.file_id == file_id)
+            .ok_or_else(|| anyhow::anyhow!("Download {file_id} not found"))?;
+        let mut download = self.queued.remove(index);
+        download.priority = priority;
+        self.insert(download);
+        Ok(())
+    }
+
+    /// Move the given downloads ahead of the others with the same priority.
+    pub fn reorder(&mut self, file_ids: &[FileID]) {
+        let mut reordered = Vec::with_capacity(self.queued.len());
+        for file_id in file_ids {
+            if let Some(index) = self.queued.iter().position(|d| &d.file_id == file_id) {
+                reordered.push(self.queued.remove(index));
+            }
+        }
+        reordered.append(&mut self.queued);
+        reordered.sort_by_key(|d| std::cmp::Reverse(d.priority));
+        self.queued = reordered;
+    }
+
+    /// Make a queued download the next one to start. It gets the priority of the
+    /// first one, not more, so it doesn't stop a running download.
+    pub fn start_next(&mut self, file_id: &FileID) -> anyhow::Result<()> {
+        let index = self
+            .queued
+            .iter()
+            .position(|d| &d.file_id == file_id)
+            .ok_or_else(|| anyhow::anyhow!("Download {file_id} is not queued"))?;
+        let mut download = self.queued.remove(index);
+        if let Some(first) = self.queued.first() {
+            download.priority = download.priority.max(first.priority);
+        }
+        self.queued.insert(0, download);
+        Ok(())
+    }
+
+    /// The running download to stop for the next queued one, when every slot is
+    /// taken and the next one has a higher priority than the lowest running one.
+    pub fn preemptible(&self) -> Option<FileID> {
+        if self.active.len() < self.max_active {
+            return None;
+        }
+        let next = self.queued.first()?;
+        self.active
+            .iter()
+            .min_by_key(|(_, priority)| **priority)
+            .filter(|(_, priority)| **priority < next.priority)
+            .map(|(file_id, _)| file_id.clone())
+    }
+
+    pub fn position(&self, file_id: &FileID) -> Option<usize> {
+        self.queued.iter().position(|d| &d.file_id == file_id)
+    }
+
+    pub fn is_active(&self, file_id: &FileID) -> bool {
+        self.active.contains_key(file_id)
+    }
+
+    /// The files of the downloads waiting for a slot, in the order they start.
+    pub fn queued_ids(&self) -> Vec<FileID> {
+        self.queued.iter().map(|d| d.file_id.clone()).collect()
+    }
+
+    pub fn active_ids(&self) -> Vec<FileID> {
+        self.active.keys().cloned().collect()
+    }
+
+    pub fn priority(&self, file_id: &FileID) -> Option<DownloadPriority> {
+        self.active.get(file_id).copied().or_else(|| {
+            self.queued
+                .iter()
+                .find(|d| &d.file_id == file_id)
+                .map(|d| d.priority)
+        })
+    }
+}
+
+#[test]
+fn test_queue_order() {
+    let mut queue = DownloadQueue::new(1);
+    let ids = ["a", "b", "c", "d"].map(String::from);
+
+    queue.push(ids[0].clone(), DownloadPriority::Normal, ());
+    queue.push(ids[1].clone(), DownloadPriority::Normal, ());
+    queue.push(ids[2].clone(), DownloadPriority::High, ());
+    queue.push(ids[3].clone(), DownloadPriority::Low, ());
+
+    assert_eq!(queue.position(&ids[2]), Some(0));
+    assert_eq!(queue.position(&ids[3]), Some(3));
+
+    queue.reorder(&[ids[1].clone()]);
+    assert_eq!(queue.position(&ids[1]), Some(1));
+
+    queue.start_next(&ids[3]).unwrap();
+    assert_eq!(queue.pop_ready().unwrap().file_id, ids[3]);
+    assert!(queue.pop_ready().is_none());
+
+    queue.finish(&ids[3]);
+    assert_eq!(queue.pop_ready().unwrap().file_id, ids[2]);
+
+    assert!(queue.remove(&ids[0]));
+    assert!(queue.set_priority(&ids[0], DownloadPriority::High).is_err());
+
+    assert_eq!(queue.queued_ids(), vec![ids[1].clone()]);
+    assert_eq!(queue.active_ids(), vec![ids[2].clone()]);
+
+    // A running download gives way to a queued one with a higher priority only
+    assert_eq!(queue.preemptible(), None);
+    queue.set_priority(&ids[2], DownloadPriority::Low).unwrap();
+    assert_eq!(queue.preemptible(), Some(ids[2].clone()));
+    queue.set_priority(&ids[1], DownloadPriority::Low).unwrap();
+    assert_eq!(queue.preemptible(), None);
+}
@@ -0,0 +1,190 @@
+use std::{collections::HashMap, sync::Arc};
+
+use rusqlite::Row;
+
+/// Segments smaller than this are not worth opening another connection for.
+pub const MIN_SEGMENT_SIZE: u64 = 8 * 1024 * 1024;
+
+/// A byte range of a file being downloaded by its own connection.
+///
+/// The segment covers `start..end` and `downloaded` counts the bytes already
+/// written from `start`, so an interrupted segment can resume on its own.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct DownloadSegment {
+    pub file_id: Arc<String>,
+    pub idx: u32,
+    pub start: u64,
+    pub end: u64,
+    pub downloaded: u64,
+}
+
+impl DownloadSegment {
+    /// Split a file of `content_length` bytes into at most `connections` segments.
+    ///
+    /// The first `already_downloaded` bytes (e.g. left by a single stream download)
+    /// are kept as a finished segment.
+    pub fn plan(
+        file_id: &Arc<String>,
+        content_length: u64,
+        already_downloaded: u64,
+        connections: usize,
+    ) -> Vec<Self> {
+        let already_downloaded = already_downloaded.min(content_length);
+        let mut segments = Vec::new();
+
+        if already_downloaded > 0 {
+            segments.push(Self {
+                file_id: file_id.clone(),
+                idx: 0,
+                start: 0,
+                end: already_downloaded,
+                downloaded: already_downloaded,
+            });
+        }
+
+        let remaining = content_length - already_downloaded;
+        if remaining == 0 {
+            return segments;
+        }
+
+        let count = (connections.max(1) as u64)
+            .min(remaining.div_ceil(MIN_SEGMENT_SIZE))
+            .max(1);
+        let segment_len = remaining.div_ceil(count);
+
+        let mut start = already_downloaded;
+        while start < content_length {
+            let end = (start + segment_len).min(content_length);
+            segments.push(Self {
+                file_id: file_id.clone(),
+                idx: segments.len() as u32,
+                start,
+                end,
+                downloaded: 0,
+            });
+            start = end;
+        }
+
+        segments
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.start + self.downloaded >= self.end
+    }
+
+    pub fn insert_into_db(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "INSERT OR REPLACE INTO download_segments (file_id, idx, start, end, downloaded)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![
+                self.file_id,
+                self.idx,
+                self.start,
+                self.end,
+                self.downloaded
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn update_downloaded(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "UPDATE download_segments SET downloaded = ?3 WHERE file_id = ?1 AND idx = ?2",
+            rusqlite::params![self.file_id, self.idx, self.downloaded],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        Ok(DownloadSegment {
+            file_id: Arc::new(row.get("file_id")?),
+            idx: row.get("idx")?,
+            start: row.get("start")?,
+            end: row.get("end")?,
+            downloaded: row.get("downloaded")?,
+        })
+    }
+
+    pub fn get_by_file(conn: &rusqlite::Connection, file_id: &str) -> rusqlite::Result<Vec<Self>> {
+        let mut stmt =
+            conn.prepare("SELECT * FROM download_segments WHERE file_id = ?1 ORDER BY idx")?;
+        let mut rows = stmt.query([file_id])?;
+        let mut segments = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            segments.push(Self::from_row(row)?);
+        }
+
+        Ok(segments)
+    }
+
+    /// Bytes already downloaded for every file that has a segment map.
+    pub fn downloaded_bytes(conn: &rusqlite::Connection) -> rusqlite::Result<HashMap<String, u64>> {
+        let mut stmt = conn
+            .prepare("SELECT file_id, SUM(downloaded) FROM download_segments GROUP BY file_id")?;
+        let mut rows = stmt.query([])?;
+        let mut result = HashMap::new();
+
+        while let Some(row) = rows.next()? {
+            result.insert(row.get(0)?, row.get(1)?);
+        }
+
+        Ok(result)
+    }
+
+    pub fn remove_by_file(file_id: &str, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "DELETE FROM download_segments WHERE file_id = ?1",
+            rusqlite::params![file_id],
+        )?;
+        Ok(())
+    }
+}
+
+pub fn create_table_download_segments(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS download_segments (
+            file_id TEXT NOT NULL,
+            idx INTEGER NOT NULL,
+            start UNSIGNED BIG INT NOT NULL,
+            end UNSIGNED BIG INT NOT NULL,
+            downloaded UNSIGNED BIG INT DEFAULT 0,
+            PRIMARY KEY (file_id, idx)
+        );",
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn test_sql() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_download_segments(&conn).unwrap();
+
+    let file_id = Arc::new("test#test.gguf".to_string());
+    let mut segments = DownloadSegment::plan(&file_id, 10 * MIN_SEGMENT_SIZE, 1024, 4);
+    assert_eq!(segments.len(), 5);
+    assert!(segments[0].is_complete());
+    assert_eq!(segments.last().unwrap().end, 10 * MIN_SEGMENT_SIZE);
+
+    for segment in &segments {
+        segment.insert_into_db(&conn).unwrap();
+    }
+
+    segments[1].downloaded = 42;
+    segments[1].update_downloaded(&conn).unwrap();
+
+    assert_eq!(
+        DownloadSegment::get_by_file(&conn, &file_id).unwrap(),
+        segments
+    );
+    assert_eq!(
+        DownloadSegment::downloaded_bytes(&conn).unwrap()[file_id.as_str()],
+        1024 + 42
+    );
+
+    DownloadSegment::remove_by_file(&file_id, &conn).unwrap();
+    assert!(DownloadSegment::get_by_file(&conn, &file_id)
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,197 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use anyhow::anyhow;
+
+// Longest string read from the metadata, anything longer is a broken file
+const MAX_STRING_LEN: u64 = 64 * 1024 * 1024;
+
+/// The metadata of a GGUF file needed to format chats with its own template.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChatMetadata {
+    /// `tokenizer.chat_template`, a Jinja template.
+    pub chat_template: Option<String>,
+    pub bos_token: Option<String>,
+    pub eos_token: Option<String>,
+}
+
+/// Read the chat template and special tokens of a GGUF file, only its header is read.
+pub fn read_chat_metadata(path: &Path) -> anyhow::Result<ChatMetadata> {
+    let file = File::open(path).map_err(|e| anyhow!("Failed to open {:?}: {e}", path))?;
+    parse_chat_metadata(BufReader::new(file))
+}
+
+enum Value {
+    Int(u64),
+    String(String),
+    Strings(Vec<String>),
+    Other,
+}
+
+fn parse_chat_metadata<R: Read>(mut reader: R) -> anyhow::Result<ChatMetadata> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != b"GGUF" {
+        return Err(anyhow!("Not a GGUF file"));
+    }
+    // Version 1 used 32 bit lengths, no model around still uses it
+    let version = read_u32(&mut reader)?;
+    if version < 2 {
+        return Err(anyhow!("Unsupported GGUF version {version}"));
+    }
+    let _tensor_count = read_u64(&mut reader)?;
+    let kv_count = read_u64(&mut reader)?;
+
+    let mut metadata = ChatMetadata::default();
+    let mut tokens = vec![];
+    let mut bos_token_id = None;
+    let mut eos_token_id = None;
+
+    for _ in 0..kv_count {
+        let key = read_string(&mut reader)?;
+        let value_type = read_u32(&mut reader)?;
+        let keep_strings = key == "tokenizer.ggml.tokens";
+        let value = read_value(&mut reader, value_type, keep_strings)?;
+
+        match (key.as_str(), value) {
+            ("tokenizer.chat_template", Value::String(template)) => {
+                metadata.chat_template = Some(template)
+            }
+            ("tokenizer.ggml.tokens", Value::Strings(strings)) => tokens = strings,
+            ("tokenizer.ggml.bos_token_id", Value::Int(id)) => bos_token_id = Some(id),
+            ("tokenizer.ggml.eos_token_id", Value::Int(id)) => eos_token_id = Some(id),
+            _ => {}
+        }
+    }
+
+    let token = |id: Option<u64>| id.and_then(|id| tokens.get(id as usize).cloned());
+    metadata.bos_token = token(bos_token_id);
+    metadata.eos_token = token(eos_token_id);
+
+    Ok(metadata)
+}
+
+fn read_value<R: Read>(
+    reader: &mut R,
+    value_type: u32,
+    keep_strings: bool,
+) -> anyhow::Result<Value> {
+    let value = match value_type {
+        // u8, i8, bool
+        0 | 1 | 7 => Value::Int(read_bytes::<R, 1>(reader)?[0] as u64),
+        // u16, i16
+        2 | 3 => Value::Int(u16::from_le_bytes(read_bytes(reader)?) as u64),
+        // u32, i32
+        4 | 5 => Value::Int(read_u32(reader)? as u64),
+        // f32
+        6 => {
+            read_bytes::<R, 4>(reader)?;
+            Value::Other
+        }
+        8 => Value::String(read_string(reader)?),
+        9 => {
+            let element_type = read_u32(reader)?;
+            let len = read_u64(reader)?;
+            if keep_strings && element_type == 8 {
+                let mut strings = Vec::with_capacity(len.min(1 << 20) as usize);
+                for _ in 0..len {
+                    strings.push(read_string(reader)?);
+                }
+                Value::Strings(strings)
+            } else {
+                for _ in 0..len {
+                    read_value(reader, element_type, false)?;
+                }
+                Value::Other
+            }
+        }
+        // u64, i64
+        10 | 11 => Value::Int(read_u64(reader)?),
+        // f64
+        12 => {
+            read_u64(reader)?;
+            Value::Other
+        }
+        _ => return Err(anyhow!("Unknown GGUF metadata type {value_type}")),
+    };
+
+    Ok(value)
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_string<R: Read>(reader: &mut R) -> anyhow::Result<String> {
+    let len = read_u64(reader)?;
+    if len > MAX_STRING_LEN {
+        return Err(anyhow!("GGUF metadata string too long ({len} bytes)"));
+    }
+    let mut bytes = vec![0u8; len as usize];
+    reader.read_exact(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[test]
+fn test_parse_chat_metadata() {
+    fn string(buf: &mut Vec<u8>, s: &str) {
+        buf.extend((s.len() as u64).to_le_bytes());
+        buf.extend(s.as_bytes());
+    }
+
+    let mut buf = b"GGUF".to_vec();
+    buf.extend(3u32.to_le_bytes());
+    buf.extend(0u64.to_le_bytes());
+    buf.extend(5u64.to_le_bytes());
+
+    string(&mut buf, "general.architecture");
+    buf.extend(8u32.to_le_bytes());
+    string(&mut buf, "llama");
+
+    string(&mut buf, "tokenizer.ggml.tokens");
+    buf.extend(9u32.to_le_bytes());
+    buf.extend(8u32.to_le_bytes());
+    buf.extend(3u64.to_le_bytes());
+    for token in ["<unk>", "<s>", "</s>"] {
+        string(&mut buf, token);
+    }
+
+    string(&mut buf, "tokenizer.ggml.scores");
+    buf.extend(9u32.to_le_bytes());
+    buf.extend(6u32.to_le_bytes());
+    buf.extend(3u64.to_le_bytes());
+    buf.extend([0u8; 12]);
+
+    string(&mut buf, "tokenizer.ggml.bos_token_id");
+    buf.extend(4u32.to_le_bytes());
+    buf.extend(1u32.to_le_bytes());
+
+    string(&mut buf, "tokenizer.chat_template");
+    buf.extend(8u32.to_le_bytes());
+    string(&mut buf, "{{ bos_token }}");
+
+    let metadata = parse_chat_metadata(buf.as_slice()).unwrap();
+    assert_eq!(
+        metadata,
+        ChatMetadata {
+            chat_template: Some("{{ bos_token }}".to_string()),
+            bos_token: Some("<s>".to_string()),
+            eos_token: None,
+        }
+    );
+
+    assert!(parse_chat_metadata(&b"GGML"[..]).is_err());
+}
@@ -0,0 +1,237 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use git2::{FetchOptions, ProxyOptions, Repository};
+
+fn do_fetch<'a>(
+    repo: &'a git2::Repository,
+    refs: &[&str],
+    remote: &'a mut git2::Remote,
+) -> Result<git2::AnnotatedCommit<'a>, git2::Error> {
+    let mut cb = git2::RemoteCallbacks::new();
+
+    // Print out our transfer progress.
+    cb.transfer_progress(|stats| {
+        if stats.received_objects() == stats.total_objects() {
+            log::debug!(
+                "Resolving deltas {}/{}",
+                stats.indexed_deltas(),
+                stats.total_deltas()
+            );
+        } else if stats.total_objects() > 0 {
+            log::debug!(
+                "Received {}/{} objects ({}) in {} bytes",
+                stats.received_objects(),
+                stats.total_objects(),
+                stats.indexed_objects(),
+                stats.received_bytes()
+            );
+        }
+        io::stdout().flush().unwrap();
+        true
+    });
+
+    let mut fo = git2::FetchOptions::new();
+    fo.remote_callbacks(cb);
+    if let Ok(proxy) = std::env::var("https_proxy").or_else(|_| std::env::var("all_proxy")) {
+        let mut proxy_opt = ProxyOptions::new();
+        proxy_opt.url(&proxy);
+        fo.proxy_options(proxy_opt);
+    }
+    // Always fetch all tags.
+    // Perform a download and also update tips
+    // fo.download_tags(git2::AutotagOption::All);
+    log::debug!("Fetching {} for repo", remote.name().unwrap());
+    remote.fetch(refs, Some(&mut fo), None)?;
+
+    // If there are local objects (we got a thin pack), then tell the user
+    // how many objects we saved from having to cross the network.
+    let stats = remote.stats();
+    if stats.local_objects() > 0 {
+        log::debug!(
+            "Received {}/{} objects in {} bytes (used {} local \
+             objects)",
+            stats.indexed_objects(),
+            stats.total_objects(),
+            stats.received_bytes(),
+            stats.local_objects()
+        );
+    } else {
+        log::debug!(
+            "Received {}/{} objects in {} bytes",
+            stats.indexed_objects(),
+            stats.total_objects(),
+            stats.received_bytes()
+        );
+    }
+
+    let fetch_head = repo.find_reference("FETCH_HEAD")?;
+    Ok(repo.reference_to_annotated_commit(&fetch_head)?)
+}
+
+fn fast_forward(
+    repo: &Repository,
+    lb: &mut git2::Reference,
+    rc: &git2::AnnotatedCommit,
+) -> Result<(), git2::Error> {
+    let name = match lb.name() {
+        Some(s) => s.to_string(),
+        None => String::from_utf8_lossy(lb.name_bytes()).to_string(),
+    };
+    let msg = format!("Fast-Forward: Setting {} to id: {}", name, rc.id());
+    log::debug!("{}", msg);
+    lb.set_target(rc.id(), &msg)?;
+    repo.set_head(&name)?;
+    repo.checkout_head(Some(
+        git2::build::CheckoutBuilder::default()
+            // For some reason the force is required to make the working directory actually get updated
+            // I suspect we should be adding some logic to handle dirty working directory states
+            // but this is just an example so maybe not.
+            .force(),
+    ))?;
+    Ok(())
+}
+
+fn normal_merge(
+    repo: &Repository,
+    local: &git2::AnnotatedCommit,
+    remote: &git2::AnnotatedCommit,
+) -> Result<(), git2::Error> {
+    let local_tree = repo.find_commit(local.id())?.tree()?;
+    let remote_tree = repo.find_commit(remote.id())?.tree()?;
+    let ancestor = repo
+        .find_commit(repo.merge_base(local.id(), remote.id())?)?
+        .tree()?;
+    let mut idx = repo.merge_trees(&ancestor, &local_tree, &remote_tree, None)?;
+
+    if idx.has_conflicts() {
+        log::debug!("Merge conflicts detected...");
+        repo.checkout_index(Some(&mut idx), None)?;
+        return Ok(());
+    }
+    let result_tree = repo.find_tree(idx.write_tree_to(repo)?)?;
+    // now create the merge commit
+    let msg = format!("Merge: {} into {}", remote.id(), local.id());
+    let sig = repo.signature()?;
+    let local_commit = repo.find_commit(local.id())?;
+    let remote_commit = repo.find_commit(remote.id())?;
+    // Do our merge commit and set current branch head to that commit.
+    let _merge_commit = repo.commit(
+        Some("HEAD"),
+        &sig,
+        &sig,
+        &msg,
+        &result_tree,
+        &[&local_commit, &remote_commit],
+    )?;
+    // Set working tree to match head.
+    repo.checkout_head(None)?;
+    Ok(())
+}
+
+fn do_merge<'a>(
+    repo: &'a Repository,
+    remote_branch: &str,
+    fetch_commit: git2::AnnotatedCommit<'a>,
+) -> Result<(), git2::Error> {
+    // 1. do a merge analysis
+    let analysis = repo.merge_analysis(&[&fetch_commit])?;
+
+    // 2. Do the appropriate merge
+    if analysis.0.is_fast_forward() {
+        log::debug!("Doing a fast forward");
+        // do a fast forward
+        let refname = format!("refs/heads/{}", remote_branch);
+        match repo.find_reference(&refname) {
+            Ok(mut r) => {
+                fast_forward(repo, &mut r, &fetch_commit)?;
+            }
+            Err(_) => {
+                // The branch doesn't exist so just set the reference to the
+                // commit directly. Usually this is because you are pulling
+                // into an empty repository.
+                repo.reference(
+                    &refname,
+                    fetch_commit.id(),
+                    true,
+                    &format!("Setting {} to {}", remote_branch, fetch_commit.id()),
+                )?;
+                repo.set_head(&refname)?;
+                repo.checkout_head(Some(
+                    git2::build::CheckoutBuilder::default()
+                        .allow_conflicts(true)
+                        .conflict_style_merge(true)
+                        .force(),
+                ))?;
+            }
+        };
+    } else if analysis.0.is_normal() {
+        // do a normal merge
+        let head_commit = repo.reference_to_annotated_commit(&repo.head()?)?;
+        normal_merge(&repo, &head_commit, &fetch_commit)?;
+    } else {
+        log::debug!("Nothing to do...");
+    }
+    Ok(())
+}
+
+pub fn pull(repo: &Repository, remote_name: &str, remote_branch: &str) -> Result<(), git2::Error> {
+    let mut remote = repo.find_remote(remote_name)?;
+    let fetch_commit = do_fetch(&repo, &[remote_branch], &mut remote)?;
+    do_merge(&repo, &remote_branch, fetch_commit)
+}
+
+/// Check out a commit or a tag, fetching it first when it isn't in the local clone.
+pub fn checkout_rev(repo: &Repository, rev: &str) -> Result<(), git2::Error> {
+    if repo.revparse_single(rev).is_err() {
+        let mut remote = repo.find_remote("origin")?;
+        let tag = format!("+refs/tags/{rev}:refs/tags/{rev}");
+        // Commits are found on `main`, tags need their own refspec
+        if do_fetch(repo, &[&tag], &mut remote).is_err() {
+            do_fetch(repo, &["main"], &mut remote)?;
+        }
+    }
+
+    let commit = repo.revparse_single(rev)?.peel_to_commit()?;
+    log::debug!("Checking out {} at {}", rev, commit.id());
+    repo.checkout_tree(
+        commit.as_object(),
+        Some(git2::build::CheckoutBuilder::default().force()),
+    )?;
+    repo.set_head_detached(commit.id())
+}
+
+pub fn open_or_clone<P: AsRef<Path>>(url: &str, repo_path: P) -> Result<Repository, git2::Error> {
+    log::debug!(
+        "open_or_clone: url: {}, repo_path: {:?}",
+        url,
+        repo_path.as_ref()
+    );
+    if let Ok(repo) = Repository::open(&repo_path) {
+        log::debug!("open_or_clone: repo opened");
+        Ok(repo)
+    } else {
+        log::debug!("open_or_clone: cloning repo");
+        let mut builder = if let Ok(proxy) =
+            std::env::var("https_proxy").or_else(|_| std::env::var("all_proxy"))
+        {
+            let mut proxy_opt = ProxyOptions::new();
+            proxy_opt.url(&proxy);
+            let mut fetch_opt = FetchOptions::new();
+            fetch_opt.proxy_options(proxy_opt);
+            let mut builder = git2::build::RepoBuilder::new();
+            builder.fetch_options(fetch_opt);
+            builder
+        } else {
+            git2::build::RepoBuilder::new()
+        };
+
+        for _ in 0..2 {
+            let r = builder.clone(url, repo_path.as_ref());
+            if r.is_ok() {
+                return r;
+            }
+        }
+        builder.clone(url, repo_path.as_ref())
+    }
+}
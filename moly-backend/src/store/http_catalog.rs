@@ -0,0 +1,213 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use reqwest::blocking::Client;
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::model_cards::{self, ModelIndex};
+
+/// Validators of the downloaded files, kept in the local copy of the catalog.
+const CACHE_FILE: &str = ".http-cache.json";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Validators {
+    #[serde(default)]
+    etag: Option<String>,
+    #[serde(default)]
+    last_modified: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Fetched {
+    Changed,
+    Unchanged,
+    Missing,
+}
+
+/// The local copy of a catalog served over HTTP.
+///
+/// Files are requested with `If-None-Match` and `If-Modified-Since`, so a sync
+/// only downloads the ones that changed since the last one.
+struct HttpCatalog {
+    client: Client,
+    base_url: String,
+    dir: PathBuf,
+    // Validators by path in the catalog
+    files: HashMap<String, Validators>,
+    // Files listed by this sync, the others are removed when it is saved
+    seen: HashSet<String>,
+}
+
+impl HttpCatalog {
+    fn open(base_url: &str, dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let files = std::fs::read_to_string(dir.join(CACHE_FILE))
+            .ok()
+            .and_then(|cache| serde_json::from_str(&cache).ok())
+            .unwrap_or_default();
+
+        Ok(Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            dir: dir.to_path_buf(),
+            files,
+            seen: HashSet::new(),
+        })
+    }
+
+    /// Download `remote_path` to `local_path` unless the local copy is up to date.
+    fn fetch(&mut self, remote_path: &str, local_path: &str) -> anyhow::Result<Fetched> {
+        self.seen.insert(local_path.to_string());
+        let path = self.dir.join(local_path);
+
+        let mut request = self
+            .client
+            .get(format!("{}/{}", self.base_url, remote_path));
+        if let Some(validators) = self.files.get(local_path).filter(|_| path.exists()) {
+            if let Some(etag) = &validators.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = request.send()?;
+        match response.status() {
+            StatusCode::NOT_MODIFIED => Ok(Fetched::Unchanged),
+            StatusCode::NOT_FOUND => {
+                let _ = std::fs::remove_file(&path);
+                self.files.remove(local_path);
+                Ok(Fetched::Missing)
+            }
+            _ => {
+                let response = response.error_for_status()?;
+                let header = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .map(str::to_string)
+                };
+                let validators = Validators {
+                    etag: header(ETAG),
+                    last_modified: header(LAST_MODIFIED),
+                };
+                let body = response.bytes()?;
+
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                // An interrupted sync must not leave a truncated file behind
+                let part_path = path.with_extension("part");
+                std::fs::write(&part_path, &body)?;
+                std::fs::rename(&part_path, &path)?;
+
+                self.files.insert(local_path.to_string(), validators);
+                Ok(Fetched::Changed)
+            }
+        }
+    }
+
+    /// Remove the files no longer in the catalog and save the validators.
+    fn save(mut self) -> anyhow::Result<()> {
+        let removed: Vec<String> = self
+            .files
+            .keys()
+            .filter(|path| !self.seen.contains(*path))
+            .cloned()
+            .collect();
+        for path in removed {
+            let _ = std::fs::remove_file(self.dir.join(&path));
+            self.files.remove(&path);
+        }
+
+        std::fs::write(
+            self.dir.join(CACHE_FILE),
+            serde_json::to_string(&self.files)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Split a catalog url into its base url and the name of its index.
+///
+/// The url either points to the index file or to the directory holding `index.json`.
+fn split_index_url(url: &str) -> (String, String) {
+    let url = url.trim_end_matches('/');
+    match url.rsplit_once('/') {
+        Some((base_url, index)) if index.ends_with(".json") => {
+            (base_url.to_string(), index.to_string())
+        }
+        _ => (url.to_string(), "index.json".to_string()),
+    }
+}
+
+/// Update the local copy of an HTTP catalog and return its index list.
+///
+/// The index is the manifest of the catalog: the cards of the models it lists are
+/// laid out next to it like in the catalog repository, each one with its detached
+/// signature if it is signed. `optional_files` are kept in sync too when the
+/// catalog has them.
+pub fn sync(url: &str, dir: &Path, optional_files: &[&str]) -> anyhow::Result<Vec<ModelIndex>> {
+    let (base_url, index) = split_index_url(url);
+    log::info!("Using model catalog index: {}/{}", base_url, index);
+
+    let mut catalog = HttpCatalog::open(&base_url, dir)?;
+    if catalog.fetch(&index, "index.json")? == Fetched::Missing {
+        return Err(anyhow::anyhow!(
+            "No model catalog at {}/{}",
+            base_url,
+            index
+        ));
+    }
+    // Signatures are optional, an unsigned file just can't be verified
+    if let Err(e) = catalog.fetch(&format!("{}.sig", index), "index.json.sig") {
+        log::warn!("download catalog index signature error: {e}");
+    }
+    let index_list = model_cards::read_index_list(&dir.join("index.json"))?;
+
+    let mut changed = 0;
+    for model_index in &index_list {
+        match model_index.card_file().and_then(|card_file| {
+            let signature_file = format!("{}.sig", card_file);
+            let fetched = catalog.fetch(&card_file, &card_file)?;
+            catalog.fetch(&signature_file, &signature_file)?;
+            Ok(fetched)
+        }) {
+            Ok(Fetched::Changed) => changed += 1,
+            Ok(_) => {}
+            Err(e) => log::warn!("download model card {} error: {e}", model_index.id),
+        }
+    }
+    log::info!("{} of {} model cards changed", changed, index_list.len());
+
+    for file in optional_files {
+        if let Err(e) = catalog.fetch(file, file) {
+            log::warn!("download catalog {} error: {e}", file);
+        }
+    }
+
+    catalog.save()?;
+    Ok(index_list)
+}
+
+#[test]
+fn test_split_index_url() {
+    assert_eq!(
+        split_index_url("https://example.com/catalog/"),
+        (
+            "https://example.com/catalog".to_string(),
+            "index.json".to_string()
+        )
+    );
+    assert_eq!(
+        split_index_url("https://example.com/catalog/approved.json"),
+        (
+            "https://example.com/catalog".to_string(),
+            "approved.json".to_string()
+        )
+    );
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use rusqlite::Row;
+
+/// A license the user agreed to before downloading a model.
+///
+/// Rows are never removed, so the table is also the record of what was accepted
+/// and when. A model whose license changes must be accepted again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LicenseAcceptance {
+    pub model_id: String,
+    pub license_id: String,
+    pub license_url: String,
+    pub accepted_at: DateTime<Utc>,
+}
+
+impl LicenseAcceptance {
+    pub fn insert_into_db(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "INSERT OR IGNORE INTO license_acceptances (model_id, license_id, license_url, accepted_at)
+            VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![
+                self.model_id,
+                self.license_id,
+                self.license_url,
+                self.accepted_at.to_rfc3339(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let accepted_at = DateTime::parse_from_rfc3339(&row.get::<_, String>("accepted_at")?)
+            .map(|s| s.to_utc())
+            .unwrap_or_default();
+
+        Ok(Self {
+            model_id: row.get("model_id")?,
+            license_id: row.get("license_id")?,
+            license_url: row.get("license_url")?,
+            accepted_at,
+        })
+    }
+
+    /// The acceptance of the given license of a model, if there is one.
+    pub fn get(
+        conn: &rusqlite::Connection,
+        model_id: &str,
+        license_id: &str,
+    ) -> rusqlite::Result<Option<Self>> {
+        let mut stmt = conn
+            .prepare("SELECT * FROM license_acceptances WHERE model_id = ?1 AND license_id = ?2")?;
+        let mut rows = stmt.query([model_id, license_id])?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(Self::from_row(row)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+pub fn create_table_license_acceptances(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS license_acceptances (
+            model_id TEXT NOT NULL,
+            license_id TEXT NOT NULL,
+            license_url TEXT NOT NULL,
+            accepted_at TEXT NOT NULL,
+            PRIMARY KEY (model_id, license_id)
+        );",
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn test_sql() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_license_acceptances(&conn).unwrap();
+
+    let acceptance = LicenseAcceptance {
+        model_id: "meta-llama/Llama-2-7b".to_string(),
+        license_id: "llama2".to_string(),
+        license_url: "https://ai.meta.com/llama/license/".to_string(),
+        accepted_at: DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+            .unwrap()
+            .to_utc(),
+    };
+    acceptance.insert_into_db(&conn).unwrap();
+
+    // Accepting again keeps the first acceptance
+    LicenseAcceptance {
+        accepted_at: Utc::now(),
+        ..acceptance.clone()
+    }
+    .insert_into_db(&conn)
+    .unwrap();
+
+    assert_eq!(
+        LicenseAcceptance::get(&conn, "meta-llama/Llama-2-7b", "llama2").unwrap(),
+        Some(acceptance)
+    );
+    assert_eq!(
+        LicenseAcceptance::get(&conn, "meta-llama/Llama-2-7b", "llama3").unwrap(),
+        None
+    );
+}
@@ -0,0 +1,144 @@
+use moly_protocol::data::{LoadPreset, LoadPresets};
+use rusqlite::Row;
+
+fn from_row(row: &Row<'_>) -> rusqlite::Result<LoadPreset> {
+    Ok(LoadPreset {
+        name: row.get("name")?,
+        n_ctx: row.get("n_ctx")?,
+        n_batch: row.get("n_batch")?,
+        gpu_layers: row.get("gpu_layers")?,
+        use_mlock: row.get("use_mlock")?,
+        prompt_template: row.get("prompt_template")?,
+    })
+}
+
+/// Save a preset of a file, replacing the one with the same name. A replaced preset
+/// stays selected.
+pub fn save(
+    conn: &rusqlite::Connection,
+    file_id: &str,
+    preset: &LoadPreset,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO load_presets (file_id, name, n_ctx, n_batch, gpu_layers, use_mlock, prompt_template)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        ON CONFLICT (file_id, name) DO UPDATE SET
+            n_ctx = excluded.n_ctx,
+            n_batch = excluded.n_batch,
+            gpu_layers = excluded.gpu_layers,
+            use_mlock = excluded.use_mlock,
+            prompt_template = excluded.prompt_template",
+        rusqlite::params![
+            file_id,
+            preset.name,
+            preset.n_ctx,
+            preset.n_batch,
+            preset.gpu_layers,
+            preset.use_mlock,
+            preset.prompt_template,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn remove(conn: &rusqlite::Connection, file_id: &str, name: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM load_presets WHERE file_id = ?1 AND name = ?2",
+        [file_id, name],
+    )?;
+    Ok(())
+}
+
+/// Use a preset on every load of the file, `None` goes back to the defaults.
+pub fn select(
+    conn: &rusqlite::Connection,
+    file_id: &str,
+    name: Option<&str>,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE load_presets SET selected = (name IS ?2) WHERE file_id = ?1",
+        rusqlite::params![file_id, name],
+    )?;
+    Ok(())
+}
+
+/// The presets of a file by name, with the selected one.
+pub fn get(conn: &rusqlite::Connection, file_id: &str) -> rusqlite::Result<LoadPresets> {
+    let mut stmt = conn.prepare("SELECT * FROM load_presets WHERE file_id = ?1 ORDER BY name")?;
+    let mut rows = stmt.query([file_id])?;
+
+    let mut presets = LoadPresets::default();
+    while let Some(row) = rows.next()? {
+        let preset = from_row(row)?;
+        if row.get("selected")? {
+            presets.selected = Some(preset.name.clone());
+        }
+        presets.presets.push(preset);
+    }
+
+    Ok(presets)
+}
+
+pub fn create_table_load_presets(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS load_presets (
+            file_id TEXT NOT NULL,
+            name TEXT NOT NULL,
+            n_ctx INTEGER,
+            n_batch INTEGER,
+            gpu_layers INTEGER,
+            use_mlock INTEGER NOT NULL DEFAULT 0,
+            prompt_template TEXT,
+            selected INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (file_id, name)
+        );",
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn test_sql() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_load_presets(&conn).unwrap();
+
+    let file_id = "TheBloke/Llama-2-7B-Chat-GGUF#llama-2-7b-chat.Q4_K_M.gguf";
+    let long_context = LoadPreset {
+        name: "long context".to_string(),
+        n_ctx: Some(8192),
+        n_batch: Some(512),
+        gpu_layers: Some(20),
+        use_mlock: true,
+        prompt_template: Some("llama-2-chat".to_string()),
+    };
+    let cpu = LoadPreset {
+        name: "cpu".to_string(),
+        gpu_layers: Some(0),
+        ..Default::default()
+    };
+    save(&conn, file_id, &long_context).unwrap();
+    save(&conn, file_id, &cpu).unwrap();
+    select(&conn, file_id, Some("long context")).unwrap();
+
+    let presets = get(&conn, file_id).unwrap();
+    assert_eq!(presets.presets, vec![cpu.clone(), long_context.clone()]);
+    assert_eq!(presets.selected_preset(), Some(&long_context));
+
+    // Editing the selected preset keeps it selected
+    let long_context = LoadPreset {
+        n_ctx: Some(16384),
+        ..long_context
+    };
+    save(&conn, file_id, &long_context).unwrap();
+    assert_eq!(
+        get(&conn, file_id).unwrap().selected_preset(),
+        Some(&long_context)
+    );
+
+    select(&conn, file_id, None).unwrap();
+    assert_eq!(get(&conn, file_id).unwrap().selected, None);
+
+    remove(&conn, file_id, "cpu").unwrap();
+    assert_eq!(get(&conn, file_id).unwrap().presets, vec![long_context]);
+    assert!(get(&conn, "other").unwrap().presets.is_empty());
+}
@@ -0,0 +1,219 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use rusqlite::{Connection, OptionalExtension};
+
+use super::{
+    download_files, download_segments, license_acceptances, load_presets, models, settings,
+    usage_events,
+};
+
+/// A change of the database schema, run once inside a transaction.
+struct Migration {
+    description: &'static str,
+    run: fn(&Connection) -> rusqlite::Result<()>,
+}
+
+/// Every change of the schema, in order. The schema version of a database is the
+/// number of migrations applied to it.
+///
+/// Never edit a migration that was released, add a new one instead.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        description: "create the models, download files, segments and settings tables",
+        run: create_tables,
+    },
+    Migration {
+        description: "create the license acceptances table",
+        run: license_acceptances::create_table_license_acceptances,
+    },
+    Migration {
+        description: "track when downloaded files were last loaded and which ones are pinned",
+        run: add_last_loaded_and_pinned,
+    },
+    Migration {
+        description: "create the usage events table",
+        run: usage_events::create_table_usage_events,
+    },
+    Migration {
+        description: "create the load presets table",
+        run: load_presets::create_table_load_presets,
+    },
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+fn create_tables(conn: &Connection) -> rusqlite::Result<()> {
+    models::create_table_models(conn)?;
+    download_files::create_table_download_files(conn)?;
+    download_segments::create_table_download_segments(conn)?;
+    settings::create_table_settings(conn)?;
+
+    // Databases written before the schema was versioned may miss columns added
+    // since the tables were created. Only chat models could be downloaded before
+    // the type was saved.
+    add_column_if_missing(conn, "models", "model_type", "TEXT NOT NULL DEFAULT 'chat'")?;
+    add_column_if_missing(conn, "download_files", "context_size", "INT DEFAULT 1024")?;
+    add_column_if_missing(conn, "download_files", "sha256", "TEXT NOT NULL DEFAULT ''")?;
+    add_column_if_missing(
+        conn,
+        "download_files",
+        "parts",
+        "TEXT NOT NULL DEFAULT '[]'",
+    )?;
+
+    Ok(())
+}
+
+fn add_last_loaded_and_pinned(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "ALTER TABLE download_files ADD COLUMN last_loaded_at TEXT;
+        ALTER TABLE download_files ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;",
+    )
+}
+
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let mut rows = stmt.query_map([], |row| {
+        let name: String = row.get(1)?;
+        Ok(name)
+    })?;
+
+    let check = rows.find(|row| matches!(row.as_deref(), Ok(name) if name == column));
+
+    if check.is_none() {
+        conn.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+fn schema_version(conn: &Connection) -> rusqlite::Result<u32> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)",
+        (),
+    )?;
+    let version = conn
+        .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+        .optional()?;
+
+    Ok(version.unwrap_or(0))
+}
+
+fn set_schema_version(conn: &Connection, version: u32) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM schema_version", ())?;
+    conn.execute(
+        "INSERT INTO schema_version (version) VALUES (?1)",
+        [version],
+    )?;
+    Ok(())
+}
+
+/// Whether the database holds anything worth a backup before migrating it.
+fn has_tables(conn: &Connection) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name != 'schema_version')",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// Copy the database file next to it, named after the version it had.
+fn backup(conn: &Connection, version: u32) -> anyhow::Result<Option<PathBuf>> {
+    // In-memory databases have nothing to back up
+    let Some(path) = conn.path().filter(|path| !path.is_empty()) else {
+        return Ok(None);
+    };
+
+    let backup_path = PathBuf::from(format!("{path}.v{version}.bak"));
+    std::fs::copy(path, &backup_path)
+        .with_context(|| format!("Failed to back up the database to {:?}", backup_path))?;
+    Ok(Some(backup_path))
+}
+
+/// Bring the database up to [`SCHEMA_VERSION`].
+///
+/// The database is backed up first when there is anything to migrate. Databases
+/// written by a newer version of Moly are refused, their schema is unknown to us.
+pub fn migrate(conn: &mut Connection) -> anyhow::Result<()> {
+    let version = schema_version(conn)?;
+
+    if version > SCHEMA_VERSION {
+        return Err(anyhow::anyhow!(
+            "The database was written by a newer version of Moly (schema version {}, this version supports up to {}). Update Moly to open it.",
+            version,
+            SCHEMA_VERSION
+        ));
+    }
+
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    if has_tables(conn)? {
+        if let Some(backup_path) = backup(conn, version)? {
+            log::info!("Backed up the database to {:?}", backup_path);
+        }
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let target = i as u32 + 1;
+        log::info!(
+            "Migrating the database to version {target}: {}",
+            migration.description
+        );
+
+        let tx = conn.transaction()?;
+        (migration.run)(&tx)
+            .and_then(|_| set_schema_version(&tx, target))
+            .with_context(|| format!("Failed to {}", migration.description))?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_migrate() {
+    let mut conn = Connection::open_in_memory().unwrap();
+
+    // A models table from before the model type was stored
+    conn.execute(
+        "CREATE TABLE models (
+            id TEXT PRIMARY KEY,
+            name TEXT NOT NULL,
+            summary TEXT NOT NULL,
+            size TEXT NOT NULL,
+            requires TEXT NOT NULL,
+            architecture TEXT NOT NULL,
+            released_at TEXT NOT NULL,
+            prompt_template TEXT DEFAULT '',
+            reverse_prompt TEXT DEFAULT '',
+            author_name TEXT NOT NULL,
+            author_url TEXT NOT NULL,
+            author_description TEXT NOT NULL,
+            like_count INTEGER NOT NULL,
+            download_count INTEGER NOT NULL
+        )",
+        (),
+    )
+    .unwrap();
+
+    migrate(&mut conn).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+    models::Model::get_all(&conn).unwrap();
+
+    // Nothing left to run
+    migrate(&mut conn).unwrap();
+    assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+
+    set_schema_version(&conn, SCHEMA_VERSION + 1).unwrap();
+    assert!(migrate(&mut conn).is_err());
+}
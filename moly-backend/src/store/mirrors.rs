@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use super::model_cards::RemoteFile;
+
+pub const HUGGINGFACE_ENDPOINT: &str = "https://huggingface.co";
+pub const DEFAULT_REGION: &str = "default";
+
+/// Where model files are downloaded from.
+///
+/// The urls of a file are tried in order: the configured mirrors, the
+/// `HF_ENDPOINT` environment variable, the catalog url for the region, the
+/// default catalog url and finally huggingface.co.
+#[derive(Debug, Clone)]
+pub struct DownloadSources {
+    pub region: String,
+    pub mirrors: Vec<String>,
+    pub env_endpoint: Option<String>,
+    /// Bearer tokens by host, sent to the host and its subdomains.
+    pub access_tokens: HashMap<String, String>,
+}
+
+impl DownloadSources {
+    pub fn new(
+        region: String,
+        mirrors: Vec<String>,
+        access_tokens: HashMap<String, String>,
+    ) -> Self {
+        Self {
+            region,
+            mirrors,
+            env_endpoint: std::env::var("HF_ENDPOINT")
+                .ok()
+                .filter(|endpoint| !endpoint.trim().is_empty()),
+            access_tokens,
+        }
+    }
+
+    /// The access token to send along a request to `url`.
+    pub fn access_token(&self, url: &str) -> Option<&str> {
+        let url = reqwest::Url::parse(url).ok()?;
+        let host = url.host_str()?;
+        self.access_tokens
+            .iter()
+            .find(|(token_host, _)| {
+                host == token_host.as_str()
+                    || host
+                        .strip_suffix(token_host.as_str())
+                        .is_some_and(|sub| sub.ends_with('.'))
+            })
+            .map(|(_, token)| token.as_str())
+    }
+
+    pub fn urls(&self, model_id: &str, remote_file: &RemoteFile) -> Vec<String> {
+        let mut urls: Vec<String> = self
+            .mirrors
+            .iter()
+            .chain(self.env_endpoint.iter())
+            .map(|mirror| mirror_url(mirror, model_id, &remote_file.name))
+            .collect();
+
+        urls.extend(remote_file.download.get(&self.region).cloned());
+        urls.extend(remote_file.download.get(DEFAULT_REGION).cloned());
+        urls.push(mirror_url(
+            HUGGINGFACE_ENDPOINT,
+            model_id,
+            &remote_file.name,
+        ));
+
+        let mut seen = std::collections::HashSet::new();
+        urls.retain(|url| seen.insert(url.clone()));
+        urls
+    }
+}
+
+/// The host part of what a user typed as the host of an access token.
+pub fn normalize_host(host: &str) -> Option<String> {
+    let host = host.trim();
+    let host = host.split_once("://").map(|(_, rest)| rest).unwrap_or(host);
+    let host = host
+        .split('/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    Some(host).filter(|host| !host.is_empty())
+}
+
+/// Expand a mirror into the url of a file.
+///
+/// A mirror with `{model_id}` or `{file}` placeholders is used as a template,
+/// anything else is a base url laid out like huggingface.co, as in `HF_ENDPOINT`.
+pub fn mirror_url(mirror: &str, model_id: &str, file_name: &str) -> String {
+    let mirror = mirror.trim();
+    if mirror.contains("{model_id}") || mirror.contains("{file}") {
+        mirror
+            .replace("{model_id}", model_id)
+            .replace("{file}", file_name)
+    } else {
+        format!(
+            "{}/{}/resolve/main/{}",
+            mirror.trim_end_matches('/'),
+            model_id,
+            file_name
+        )
+    }
+}
+
+#[test]
+fn test_urls_order() {
+    let remote_file = RemoteFile {
+        name: "model.gguf".to_string(),
+        download: [
+            ("CN", "https://cn.example.com/model.gguf"),
+            (
+                DEFAULT_REGION,
+                "https://huggingface.co/org/model/resolve/main/model.gguf",
+            ),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect(),
+        ..Default::default()
+    };
+
+    let sources = DownloadSources {
+        region: "CN".to_string(),
+        mirrors: vec![
+            "https://mirror.internal/".to_string(),
+            "https://files.internal/{model_id}/{file}".to_string(),
+        ],
+        env_endpoint: None,
+        access_tokens: Default::default(),
+    };
+
+    assert_eq!(
+        sources.urls("org/model", &remote_file),
+        vec![
+            "https://mirror.internal/org/model/resolve/main/model.gguf",
+            "https://files.internal/org/model/model.gguf",
+            "https://cn.example.com/model.gguf",
+            "https://huggingface.co/org/model/resolve/main/model.gguf",
+        ]
+    );
+}
+
+#[test]
+fn test_access_token() {
+    let sources = DownloadSources {
+        region: DEFAULT_REGION.to_string(),
+        mirrors: vec![],
+        env_endpoint: None,
+        access_tokens: [(
+            normalize_host("https://HuggingFace.co/").unwrap(),
+            "hf_token".to_string(),
+        )]
+        .into_iter()
+        .collect(),
+    };
+
+    let url = "https://huggingface.co/org/model/resolve/main/model.gguf";
+    assert_eq!(sources.access_token(url), Some("hf_token"));
+    assert_eq!(
+        sources.access_token("https://cdn-lfs.huggingface.co/model.gguf"),
+        Some("hf_token")
+    );
+    assert_eq!(
+        sources.access_token("https://nothuggingface.co/model.gguf"),
+        None
+    );
+    assert_eq!(
+        sources.access_token("https://hf-mirror.com/model.gguf"),
+        None
+    );
+}
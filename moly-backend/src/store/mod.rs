@@ -1,6 +1,25 @@
+pub mod bandwidth;
+pub mod card_index;
+pub mod catalog_sources;
+pub mod catalog_sync;
+pub mod catalog_trust;
+pub mod disk_space;
 pub mod download_files;
+pub mod download_queue;
+pub mod download_segments;
+pub mod gguf;
+#[cfg(feature = "git-catalog")]
+pub mod git_catalog;
+pub mod http_catalog;
+pub mod license_acceptances;
+pub mod load_presets;
+pub mod migrations;
+pub mod mirrors;
 pub mod models;
 pub mod remote;
+pub mod search;
+pub mod settings;
+pub mod usage_events;
 
 pub mod model_cards;
 
@@ -10,8 +29,10 @@ use moly_protocol::data::FileID;
 
 pub use remote::*;
 
+/// The downloaded files, `is_outdated` tells which ones have a newer revision in the catalog.
 pub fn get_all_download_file(
     conn: &rusqlite::Connection,
+    is_outdated: impl Fn(&download_files::DownloadedFile) -> bool,
 ) -> rusqlite::Result<Vec<moly_protocol::data::DownloadedFile>> {
     let files = download_files::DownloadedFile::get_finished(&conn)?;
     let models = models::Model::get_all(&conn)?;
@@ -27,6 +48,7 @@ pub fn get_all_download_file(
                 size: model.size.clone(),
                 requires: model.requires.clone(),
                 architecture: model.architecture.clone(),
+                model_type: moly_protocol::data::ModelType::from_catalog(&model.model_type),
                 released_at: model.released_at.clone(),
                 files: vec![],
                 author: moly_protocol::data::Author {
@@ -37,6 +59,8 @@ pub fn get_all_download_file(
                 like_count: model.like_count,
                 download_count: model.download_count,
                 metrics: Default::default(),
+                trust: Default::default(),
+                license: Default::default(),
             }
         } else {
             moly_protocol::data::Model::default()
@@ -47,6 +71,7 @@ pub fn get_all_download_file(
             .join(&file.name);
 
         let downloaded_path = downloaded_path.to_str().map(|s| s.to_string());
+        let update_available = is_outdated(&file);
 
         let downloaded_file = moly_protocol::data::DownloadedFile {
             file: moly_protocol::data::File {
@@ -58,11 +83,14 @@ pub fn get_all_download_file(
                 downloaded_path,
                 tags: file.tags,
                 featured: false,
+                parts: file.parts,
             },
             model,
             downloaded_at: file.downloaded_at,
             compatibility_guess: moly_protocol::data::CompatibilityGuess::PossiblySupported,
             information: String::new(),
+            update_available,
+            pinned: file.pinned,
         };
 
         downloaded_files.push(downloaded_file);
@@ -71,12 +99,71 @@ pub fn get_all_download_file(
     Ok(downloaded_files)
 }
 
+/// Disk use of the downloaded files, largest first.
+pub fn get_files_usage(
+    conn: &rusqlite::Connection,
+) -> rusqlite::Result<Vec<moly_protocol::data::FileUsage>> {
+    let files = download_files::DownloadedFile::get_finished(conn)?;
+    let models = models::Model::get_all(conn)?;
+
+    let mut usage = Vec::with_capacity(files.len());
+    for (_id, file) in files {
+        // What is on disk, the catalog size is only a label
+        let model_dir = Path::new(&file.download_dir).join(&file.model_id);
+        let size = file
+            .file_names()
+            .iter()
+            .filter_map(|name| std::fs::metadata(model_dir.join(name)).ok())
+            .map(|meta| meta.len())
+            .sum();
+
+        usage.push(moly_protocol::data::FileUsage {
+            file_id: file.id.to_string(),
+            model_name: models
+                .get(&file.model_id)
+                .map(|model| model.name.clone())
+                .unwrap_or_else(|| file.model_id.clone()),
+            model_id: file.model_id,
+            name: file.name,
+            quantization: file.quantization,
+            size,
+            downloaded_at: file.downloaded_at,
+            last_loaded_at: file.last_loaded_at,
+            pinned: file.pinned,
+        });
+    }
+
+    usage.sort_by_key(|file| std::cmp::Reverse(file.size));
+    Ok(usage)
+}
+
+/// A downloaded file of an embedding model, to serve along the chat models.
+pub fn get_embedding_file(
+    conn: &rusqlite::Connection,
+    file_id: &str,
+) -> anyhow::Result<download_files::DownloadedFile> {
+    let file = download_files::DownloadedFile::get_by_id(conn, file_id)?;
+    if !file.downloaded {
+        return Err(anyhow::anyhow!("{file_id} is not downloaded"));
+    }
+
+    let model_type = models::Model::get_model_type(conn, &file.model_id)?;
+    if moly_protocol::data::ModelType::from_catalog(&model_type)
+        != moly_protocol::data::ModelType::Embedding
+    {
+        return Err(anyhow::anyhow!("{file_id} is not an embedding model"));
+    }
+
+    Ok(file)
+}
+
 pub fn get_all_pending_downloads(
     conn: &rusqlite::Connection,
 ) -> rusqlite::Result<Vec<moly_protocol::data::PendingDownload>> {
     let files = download_files::DownloadedFile::get_pending(&conn)?;
 
     let models = models::Model::get_all(&conn)?;
+    let segmented = download_segments::DownloadSegment::downloaded_bytes(&conn)?;
 
     let mut result = Vec::with_capacity(files.len());
 
@@ -90,6 +177,7 @@ pub fn get_all_pending_downloads(
             downloaded_path: None,
             tags: file.tags.clone(),
             featured: file.featured,
+            parts: file.parts.clone(),
         };
 
         let model = if let Some(model) = models.get(&file.model_id) {
@@ -100,6 +188,7 @@ pub fn get_all_pending_downloads(
                 size: model.size.clone(),
                 requires: model.requires.clone(),
                 architecture: model.architecture.clone(),
+                model_type: moly_protocol::data::ModelType::from_catalog(&model.model_type),
                 released_at: model.released_at.clone(),
                 files: vec![],
                 author: moly_protocol::data::Author {
@@ -110,20 +199,29 @@ pub fn get_all_pending_downloads(
                 like_count: model.like_count,
                 download_count: model.download_count,
                 metrics: Default::default(),
+                trust: Default::default(),
+                license: Default::default(),
             }
         } else {
             moly_protocol::data::Model::default()
         };
 
-        let file_path = Path::new(&file.download_dir)
-            .join(&file.model_id)
-            .join(&file.name);
+        let model_dir = Path::new(&file.download_dir).join(&file.model_id);
 
-        let downloaded = if let Ok(file_meta) = std::fs::metadata(file_path) {
-            file_meta.len()
-        } else {
-            0
-        };
+        // Segmented downloads are preallocated, so the file length says nothing.
+        let downloaded = file
+            .file_names()
+            .iter()
+            .map(|name| {
+                if let Some(downloaded) = segmented.get(&format!("{}#{}", file.model_id, name)) {
+                    *downloaded
+                } else if let Ok(file_meta) = std::fs::metadata(model_dir.join(name)) {
+                    file_meta.len()
+                } else {
+                    0
+                }
+            })
+            .sum::<u64>();
         let progress = (downloaded as f64 / file.file_size as f64) * 100.0;
 
         let pending_download = moly_protocol::data::PendingDownload {
@@ -131,7 +229,9 @@ pub fn get_all_pending_downloads(
             model,
             progress,
             status: moly_protocol::data::PendingDownloadsStatus::Paused,
-            //status: item.status.into(),
+            queue_position: None,
+            priority: Default::default(),
+            bandwidth_limit: None,
         };
 
         result.push(pending_download);
@@ -140,13 +240,33 @@ pub fn get_all_pending_downloads(
     Ok(result)
 }
 
-pub fn remove_downloaded_file(models_dir: String, file_id: FileID) -> anyhow::Result<()> {
+/// Remove a file from disk, `parts` are the shard names of a split model.
+pub fn remove_downloaded_file(
+    models_dir: String,
+    file_id: FileID,
+    parts: &[String],
+) -> anyhow::Result<()> {
     let (model_id, file) = file_id
         .split_once("#")
         .ok_or_else(|| anyhow::anyhow!("Illegal file_id"))?;
 
-    let filename = format!("{}/{}/{}", models_dir, model_id, file);
+    if parts.is_empty() {
+        let filename = format!("{}/{}/{}", models_dir, model_id, file);
+
+        log::info!("Removing file {}", filename);
+        return Ok(std::fs::remove_file(filename)?);
+    }
+
+    let mut result = Ok(());
+    for part in parts {
+        let filename = format!("{}/{}/{}", models_dir, model_id, part);
 
-    log::info!("Removing file {}", filename);
-    Ok(std::fs::remove_file(filename)?)
+        log::info!("Removing file {}", filename);
+        if let Err(e) = std::fs::remove_file(&filename) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                result = Err(e.into());
+            }
+        }
+    }
+    result
 }
@@ -1,322 +1,176 @@
 use chrono::{DateTime, Utc};
-use git2::{FetchOptions, ProxyOptions, Repository};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::io::{self, Write};
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path};
 use std::str;
 use std::sync::Arc;
 
-fn do_fetch<'a>(
-    repo: &'a git2::Repository,
-    refs: &[&str],
-    remote: &'a mut git2::Remote,
-) -> Result<git2::AnnotatedCommit<'a>, git2::Error> {
-    let mut cb = git2::RemoteCallbacks::new();
-
-    // Print out our transfer progress.
-    cb.transfer_progress(|stats| {
-        if stats.received_objects() == stats.total_objects() {
-            log::debug!(
-                "Resolving deltas {}/{}",
-                stats.indexed_deltas(),
-                stats.total_deltas()
-            );
-        } else if stats.total_objects() > 0 {
-            log::debug!(
-                "Received {}/{} objects ({}) in {} bytes",
-                stats.received_objects(),
-                stats.total_objects(),
-                stats.indexed_objects(),
-                stats.received_bytes()
-            );
-        }
-        io::stdout().flush().unwrap();
-        true
-    });
-
-    let mut fo = git2::FetchOptions::new();
-    fo.remote_callbacks(cb);
-    if let Ok(proxy) = std::env::var("https_proxy").or_else(|_| std::env::var("all_proxy")) {
-        let mut proxy_opt = ProxyOptions::new();
-        proxy_opt.url(&proxy);
-        fo.proxy_options(proxy_opt);
-    }
-    // Always fetch all tags.
-    // Perform a download and also update tips
-    // fo.download_tags(git2::AutotagOption::All);
-    log::debug!("Fetching {} for repo", remote.name().unwrap());
-    remote.fetch(refs, Some(&mut fo), None)?;
-
-    // If there are local objects (we got a thin pack), then tell the user
-    // how many objects we saved from having to cross the network.
-    let stats = remote.stats();
-    if stats.local_objects() > 0 {
-        log::debug!(
-            "Received {}/{} objects in {} bytes (used {} local \
-             objects)",
-            stats.indexed_objects(),
-            stats.total_objects(),
-            stats.received_bytes(),
-            stats.local_objects()
-        );
-    } else {
-        log::debug!(
-            "Received {}/{} objects in {} bytes",
-            stats.indexed_objects(),
-            stats.total_objects(),
-            stats.received_bytes()
-        );
-    }
+use moly_protocol::data::{CatalogSource, CatalogTrust, ModelType, SearchQuery};
 
-    let fetch_head = repo.find_reference("FETCH_HEAD")?;
-    Ok(repo.reference_to_annotated_commit(&fetch_head)?)
-}
+use super::card_index::{CardIndex, CollectionIndex, SourceCards};
+use super::catalog_sources;
+use super::download_files::DownloadedFile;
+use super::license_acceptances::LicenseAcceptance;
 
-fn fast_forward(
-    repo: &Repository,
-    lb: &mut git2::Reference,
-    rc: &git2::AnnotatedCommit,
-) -> Result<(), git2::Error> {
-    let name = match lb.name() {
-        Some(s) => s.to_string(),
-        None => String::from_utf8_lossy(lb.name_bytes()).to_string(),
-    };
-    let msg = format!("Fast-Forward: Setting {} to id: {}", name, rc.id());
-    log::debug!("{}", msg);
-    lb.set_target(rc.id(), &msg)?;
-    repo.set_head(&name)?;
-    repo.checkout_head(Some(
-        git2::build::CheckoutBuilder::default()
-            // For some reason the force is required to make the working directory actually get updated
-            // I suspect we should be adding some logic to handle dirty working directory states
-            // but this is just an example so maybe not.
-            .force(),
-    ))?;
-    Ok(())
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct IpResult {
+    #[serde(default, rename = "countryCode")]
+    country_code: String,
 }
 
-fn normal_merge(
-    repo: &Repository,
-    local: &git2::AnnotatedCommit,
-    remote: &git2::AnnotatedCommit,
-) -> Result<(), git2::Error> {
-    let local_tree = repo.find_commit(local.id())?.tree()?;
-    let remote_tree = repo.find_commit(remote.id())?.tree()?;
-    let ancestor = repo
-        .find_commit(repo.merge_base(local.id(), remote.id())?)?
-        .tree()?;
-    let mut idx = repo.merge_trees(&ancestor, &local_tree, &remote_tree, None)?;
-
-    if idx.has_conflicts() {
-        log::debug!("Merge conflicts detected...");
-        repo.checkout_index(Some(&mut idx), None)?;
-        return Ok(());
+/// Country code of the current IP address, used to pick the closest download urls.
+pub fn detect_region() -> String {
+    match reqwest::blocking::get("http://ip-api.com/json").and_then(|r| r.json::<IpResult>()) {
+        Ok(ip_result) if !ip_result.country_code.is_empty() => {
+            ip_result.country_code.to_ascii_uppercase()
+        }
+        _ => ModelCardManager::DEFAULT_COUNTRY_CODE.to_string(),
     }
-    let result_tree = repo.find_tree(idx.write_tree_to(repo)?)?;
-    // now create the merge commit
-    let msg = format!("Merge: {} into {}", remote.id(), local.id());
-    let sig = repo.signature()?;
-    let local_commit = repo.find_commit(local.id())?;
-    let remote_commit = repo.find_commit(remote.id())?;
-    // Do our merge commit and set current branch head to that commit.
-    let _merge_commit = repo.commit(
-        Some("HEAD"),
-        &sig,
-        &sig,
-        &msg,
-        &result_tree,
-        &[&local_commit, &remote_commit],
-    )?;
-    // Set working tree to match head.
-    repo.checkout_head(None)?;
-    Ok(())
 }
 
-fn do_merge<'a>(
-    repo: &'a Repository,
-    remote_branch: &str,
-    fetch_commit: git2::AnnotatedCommit<'a>,
-) -> Result<(), git2::Error> {
-    // 1. do a merge analysis
-    let analysis = repo.merge_analysis(&[&fetch_commit])?;
-
-    // 2. Do the appropriate merge
-    if analysis.0.is_fast_forward() {
-        log::debug!("Doing a fast forward");
-        // do a fast forward
-        let refname = format!("refs/heads/{}", remote_branch);
-        match repo.find_reference(&refname) {
-            Ok(mut r) => {
-                fast_forward(repo, &mut r, &fetch_commit)?;
-            }
-            Err(_) => {
-                // The branch doesn't exist so just set the reference to the
-                // commit directly. Usually this is because you are pulling
-                // into an empty repository.
-                repo.reference(
-                    &refname,
-                    fetch_commit.id(),
-                    true,
-                    &format!("Setting {} to {}", remote_branch, fetch_commit.id()),
-                )?;
-                repo.set_head(&refname)?;
-                repo.checkout_head(Some(
-                    git2::build::CheckoutBuilder::default()
-                        .allow_conflicts(true)
-                        .conflict_style_merge(true)
-                        .force(),
-                ))?;
+/// The catalog repository and the region to use, `region` skips the IP lookup.
+pub fn get_model_cards_repo(region: Option<&str>) -> (String, String) {
+    let region = region.map(|region| region.to_ascii_uppercase());
+    let repo_url = std::env::var("MODEL_CARDS_REPO");
+    match repo_url {
+        Ok(url) => (
+            url,
+            region.unwrap_or(ModelCardManager::DEFAULT_COUNTRY_CODE.to_string()),
+        ),
+        Err(_) => {
+            let region = region.unwrap_or_else(detect_region);
+            if region == "CN" {
+                (
+                    "https://gitcode.com/xun_csh/model-cards.git".to_string(),
+                    region,
+                )
+            } else {
+                (
+                    "https://github.com/moxin-org/model-cards.git".to_string(),
+                    region,
+                )
             }
-        };
-    } else if analysis.0.is_normal() {
-        // do a normal merge
-        let head_commit = repo.reference_to_annotated_commit(&repo.head()?)?;
-        normal_merge(&repo, &head_commit, &fetch_commit)?;
-    } else {
-        log::debug!("Nothing to do...");
+        }
     }
-    Ok(())
 }
 
-pub fn pull(repo: &Repository, remote_name: &str, remote_branch: &str) -> Result<(), git2::Error> {
-    let mut remote = repo.find_remote(remote_name)?;
-    let fetch_commit = do_fetch(&repo, &[remote_branch], &mut remote)?;
-    do_merge(&repo, &remote_branch, fetch_commit)
-}
+pub static REPO_NAME: &'static str = "model-cards";
 
-pub fn open_or_clone<P: AsRef<Path>>(url: &str, repo_path: P) -> Result<Repository, git2::Error> {
-    log::debug!(
-        "open_or_clone: url: {}, repo_path: {:?}",
-        url,
-        repo_path.as_ref()
-    );
-    if let Ok(repo) = Repository::open(&repo_path) {
-        log::debug!("open_or_clone: repo opened");
-        Ok(repo)
-    } else {
-        log::debug!("open_or_clone: cloning repo");
-        let mut builder = if let Ok(proxy) =
-            std::env::var("https_proxy").or_else(|_| std::env::var("all_proxy"))
-        {
-            let mut proxy_opt = ProxyOptions::new();
-            proxy_opt.url(&proxy);
-            let mut fetch_opt = FetchOptions::new();
-            fetch_opt.proxy_options(proxy_opt);
-            let mut builder = git2::build::RepoBuilder::new();
-            builder.fetch_options(fetch_opt);
-            builder
-        } else {
-            git2::build::RepoBuilder::new()
-        };
+/// Cards of the last successful sync, loaded at startup before any refresh.
+pub static CARDS_CACHE_FILE: &str = "model-cards-cache.json";
 
-        for _ in 0..2 {
-            let r = builder.clone(url, repo_path.as_ref());
-            if r.is_ok() {
-                return r;
-            }
-        }
-        builder.clone(url, repo_path.as_ref())
-    }
+pub fn read_index_list(path: &Path) -> anyhow::Result<Vec<ModelIndex>> {
+    let index_list = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&index_list)?)
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct IpResult {
-    #[serde(default, rename = "countryCode")]
-    country_code: String,
-}
+/// Load the catalog from the cached index and the local copies of the sources,
+/// without any network access so the backend can start right away.
+pub fn load_cached_model_cards<P: AsRef<Path>>(
+    app_data_dir: P,
+    region: Option<&str>,
+    sources: &[CatalogSource],
+) -> ModelCardManager {
+    let app_data_dir = app_data_dir.as_ref();
+    let mut manager = ModelCardManager::empty();
+    if let Some(region) = region {
+        manager.country_code = region.to_ascii_uppercase();
+    }
 
-fn get_model_cards_repo() -> (String, String) {
-    let repo_url = std::env::var("MODEL_CARDS_REPO");
-    match repo_url {
-        Ok(url) => (url, ModelCardManager::DEFAULT_COUNTRY_CODE.to_string()),
+    let cache_path = app_data_dir.join(CARDS_CACHE_FILE);
+    match CardIndex::load(&cache_path) {
+        Ok(cards) => manager.cards = cards,
+        // Only the first start after a clone, or with a damaged cache, reads every card
         Err(_) => {
-            match reqwest::blocking::get("http://ip-api.com/json")
-                .and_then(|r| r.json::<IpResult>())
-            {
-                Ok(ip_result) if ip_result.country_code.to_ascii_uppercase() == "CN" => (
-                    "https://gitcode.com/xun_csh/model-cards.git".to_string(),
-                    "CN".to_string(),
-                ),
-                Ok(ip_result) => (
-                    "https://github.com/moxin-org/model-cards.git".to_string(),
-                    ip_result.country_code.to_ascii_uppercase(),
-                ),
-                _ => (
-                    "https://github.com/moxin-org/model-cards.git".to_string(),
-                    ModelCardManager::DEFAULT_COUNTRY_CODE.to_string(),
-                ),
+            let cards: Vec<SourceCards> = sources
+                .iter()
+                .filter_map(|source| {
+                    let source_dir = catalog_sources::source_dir(app_data_dir, source);
+                    match catalog_sources::read_local_index(source, &source_dir) {
+                        Ok((index_list, trust)) => {
+                            Some(SourceCards::read(&source_dir, source, index_list, trust))
+                        }
+                        Err(e) => {
+                            log::warn!("No local copy of the {} catalog: {e}", source.name);
+                            None
+                        }
+                    }
+                })
+                .collect();
+
+            if !cards.is_empty() {
+                manager.cards = CardIndex::merge(cards);
+                if let Err(e) = manager.cards.save(&cache_path) {
+                    log::error!("write {:?} error: {e}", cache_path);
+                }
             }
         }
     }
+
+    manager
 }
 
-pub static REPO_NAME: &'static str = "model-cards";
+/// The catalog fetched by a sync.
+#[derive(Debug)]
+pub struct CatalogUpdate {
+    pub country_code: String,
+    pub cards: CardIndex,
+    // Sources that failed to update, their local copy is used instead
+    pub errors: Vec<String>,
+}
 
-pub fn sync_model_cards_repo<P: AsRef<Path>>(app_data_dir: P) -> anyhow::Result<ModelCardManager> {
-    let (repo_url, country_code) = get_model_cards_repo();
-    log::info!("Using model_cards repo: {}", repo_url);
-    let repo_dirs = app_data_dir.as_ref().join(REPO_NAME);
-
-    let repo = open_or_clone(&repo_url, &repo_dirs)?;
-    let mut r = Ok(());
-    for _ in 0..2 {
-        r = pull(&repo, "origin", "main");
-        if r.is_ok() {
-            break;
+/// Update every catalog source and merge them by priority.
+///
+/// This blocks on the network, so it runs on a background thread. A source that
+/// fails keeps its local copy, the sync only fails when all of them do.
+pub fn sync_model_cards_repo<P: AsRef<Path>>(
+    app_data_dir: P,
+    region: Option<&str>,
+    sources: &[CatalogSource],
+) -> anyhow::Result<CatalogUpdate> {
+    let app_data_dir = app_data_dir.as_ref();
+    let (repo_url, country_code) = get_model_cards_repo(region);
+
+    let mut errors = vec![];
+    let mut cards = vec![];
+    for source in sources {
+        let source_dir = catalog_sources::source_dir(app_data_dir, source);
+        let (index_list, trust) = match catalog_sources::sync_source(source, &source_dir, &repo_url)
+        {
+            Ok(index) => index,
+            Err(e) => {
+                log::error!("sync catalog source {} error: {e}", source.name);
+                errors.push(format!("{}: {e}", source.name));
+                match catalog_sources::read_local_index(source, &source_dir) {
+                    Ok(index) => index,
+                    Err(_) => continue,
+                }
+            }
+        };
+        if trust != CatalogTrust::Verified {
+            log::warn!("the index of the {} catalog is {:?}", source.name, trust);
         }
+        cards.push(SourceCards::read(&source_dir, source, index_list, trust));
     }
 
-    if let Err(e) = r {
-        log::error!("Failed to pull: {:?}", e);
-        log::error!("please remove the repo({:?}) and try again", &repo_dirs);
+    if errors.len() == sources.len() {
+        return Err(anyhow::anyhow!(
+            "Failed to update the model catalog: {}",
+            errors.join("; ")
+        ));
     }
 
-    let index_url = format!("{}/releases/download/index_release/index.json", repo_url);
-
-    let index_list = if let Ok(remote_index) =
-        reqwest::blocking::get(index_url).and_then(|r| r.json::<Vec<ModelIndex>>())
-    {
-        remote_index
-    } else {
-        let index_list = std::fs::read_to_string(repo_dirs.join("index.json"))?;
-        let index_list: Vec<ModelIndex> = serde_json::from_str(&index_list)?;
-        index_list
-    };
-
-    let mut indexs = HashMap::with_capacity(index_list.len());
-    for index in index_list {
-        indexs.insert(index.id.clone(), index);
+    let cards = CardIndex::merge(cards);
+    log::info!("indexed {} model cards", cards.len());
+    let cache_path = app_data_dir.join(CARDS_CACHE_FILE);
+    if let Err(e) = cards.save(&cache_path) {
+        log::error!("write {:?} error: {e}", cache_path);
     }
 
-    let embedding_index =
-        if let Ok(embedding_index) = std::fs::read_to_string(repo_dirs.join("embedding.json")) {
-            let embedding_index: EmbeddingIndex = serde_json::from_str(&embedding_index)?;
-            if !embedding_index.check_file_exist(app_data_dir.as_ref()) {
-                let app_data_dir_path = app_data_dir.as_ref().to_path_buf();
-                let r = std::thread::spawn(move || {
-                    if let Ok(_) = embedding_index.download(&app_data_dir_path) {
-                        log::debug!("Downloaded embedding model ok");
-                        Some(embedding_index)
-                    } else {
-                        log::warn!("Failed to download embedding model");
-                        None
-                    }
-                });
-                EmbeddingState::Pending(r)
-            } else {
-                EmbeddingState::Finish(Some(embedding_index))
-            }
-        } else {
-            EmbeddingState::Finish(None)
-        };
-
-    Ok(ModelCardManager {
-        app_data_dir: app_data_dir.as_ref().to_path_buf(),
-        embedding_index,
+    Ok(CatalogUpdate {
         country_code,
-        indexs,
-        caches: HashMap::new(),
+        cards,
+        errors,
     })
 }
 
@@ -339,7 +193,8 @@ pub struct ModelIndex {
 }
 
 impl ModelIndex {
-    pub fn load_model_card(&self, app_data_dir: &Path) -> anyhow::Result<ModelCard> {
+    /// Path of the model card in a catalog, like `org/name.json`.
+    pub fn card_file(&self) -> anyhow::Result<String> {
         let (org_name, model_name) = self
             .id
             .split_once("/")
@@ -351,14 +206,29 @@ impl ModelIndex {
             model_name
         };
 
-        let model_card_path = app_data_dir
-            .join(REPO_NAME)
-            .join(org_name)
-            .join(format!("{}.json", sub_name));
+        // The id and name come from a remote index, they must not lead out of the
+        // catalog directory
+        let card_file = format!("{}/{}.json", org_name, sub_name);
+        let path = Path::new(&card_file);
+        if path.is_absolute()
+            || !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+        {
+            return Err(anyhow::anyhow!("Invalid model card path: {}", card_file));
+        }
+
+        Ok(card_file)
+    }
+
+    pub fn load_model_card(&self, source_dir: &Path) -> anyhow::Result<ModelCard> {
+        let model_card_path = source_dir.join(self.card_file()?);
         let model_card = std::fs::read_to_string(model_card_path)?;
         let mut model_card: ModelCard = serde_json::from_str(&model_card)?;
+        model_card.files = group_split_files(std::mem::take(&mut model_card.files));
         model_card.like_count = self.like_count;
         model_card.download_count = self.download_count;
+        model_card.model_type = self.model_type.clone();
 
         Ok(model_card)
     }
@@ -394,6 +264,9 @@ pub struct ModelCard {
     pub requires: String,
     #[serde(default)]
     pub architecture: String,
+    // Taken from the index, cards don't have it
+    #[serde(default)]
+    pub model_type: String,
     pub released_at: DateTime<Utc>,
     #[serde(default)]
     pub files: Vec<RemoteFile>,
@@ -407,6 +280,13 @@ pub struct ModelCard {
     pub download_count: u32,
     #[serde(default)]
     pub metrics: Option<HashMap<String, f32>>,
+    #[serde(default)]
+    pub license: String,
+    #[serde(default)]
+    pub license_url: String,
+    // Restrictive terms the user must accept before downloading
+    #[serde(default)]
+    pub license_requires_acceptance: bool,
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
@@ -423,6 +303,80 @@ pub struct RemoteFile {
     pub sha256: Option<String>,
     #[serde(default)]
     pub download: HashMap<String, String>,
+    /// The shards of a model split in several files, in order. The file itself
+    /// is named after the first shard.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<RemoteFile>,
+}
+
+/// Parse a shard name like `name-00001-of-00005.gguf` into `(name, 1, 5)`.
+pub fn split_file_part(file_name: &str) -> Option<(&str, u32, u32)> {
+    let stem = file_name.strip_suffix(".gguf")?;
+    let (rest, count) = stem.rsplit_once("-of-")?;
+    let (prefix, index) = rest.rsplit_once('-')?;
+
+    if index.len() != 5 || count.len() != 5 {
+        return None;
+    }
+
+    let index = index.parse().ok()?;
+    let count = count.parse().ok()?;
+    (index >= 1 && index <= count).then_some((prefix, index, count))
+}
+
+/// Merge the shards of split models into a single logical file.
+///
+/// Shards are only merged when all of them are listed, otherwise they are left
+/// as they are.
+pub fn group_split_files(files: Vec<RemoteFile>) -> Vec<RemoteFile> {
+    let order: HashMap<String, usize> = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| (file.name.clone(), i))
+        .collect();
+    let mut groups: HashMap<(String, u32), Vec<RemoteFile>> = HashMap::new();
+    let mut result = Vec::with_capacity(files.len());
+
+    for file in files {
+        match split_file_part(&file.name) {
+            Some((prefix, _, count)) if count > 1 && file.parts.is_empty() => {
+                let key = (prefix.to_string(), count);
+                groups.entry(key).or_default().push(file);
+            }
+            _ => result.push(file),
+        }
+    }
+
+    for ((_, count), mut parts) in groups {
+        parts.sort_by_key(|part| split_file_part(&part.name).map(|(_, index, _)| index));
+        parts.dedup_by(|a, b| a.name == b.name);
+
+        if parts.len() != count as usize {
+            result.extend(parts);
+            continue;
+        }
+
+        let size = parts
+            .iter()
+            .map(|part| part.size.parse::<u64>().ok())
+            .sum::<Option<u64>>()
+            .map(|size| size.to_string())
+            .unwrap_or_else(|| parts[0].size.clone());
+
+        result.push(RemoteFile {
+            name: parts[0].name.clone(),
+            size,
+            quantization: parts[0].quantization.clone(),
+            tags: parts[0].tags.clone(),
+            sha256: None,
+            download: parts[0].download.clone(),
+            parts,
+        });
+    }
+
+    // Keep the order of the card, split models take the place of their first shard
+    result.sort_by_key(|file| order.get(&file.name).copied());
+    result
 }
 
 impl ModelCard {
@@ -463,6 +417,11 @@ impl ModelCard {
                     downloaded_path,
                     tags: remote_f.tags.clone(),
                     featured: false,
+                    parts: remote_f
+                        .parts
+                        .iter()
+                        .map(|part| part.name.clone())
+                        .collect(),
                 };
 
                 files.push(file);
@@ -481,6 +440,7 @@ impl ModelCard {
                 size: remote_m.size.clone(),
                 requires: remote_m.requires.clone(),
                 architecture: remote_m.architecture.clone(),
+                model_type: ModelType::from_catalog(&remote_m.model_type),
                 released_at: remote_m.released_at.clone(),
                 files: to_file(&remote_m.id, &remote_m.files, &files)?,
                 author: moly_protocol::data::Author {
@@ -491,6 +451,15 @@ impl ModelCard {
                 like_count: remote_m.like_count.clone(),
                 download_count: remote_m.download_count.clone(),
                 metrics: remote_m.metrics.clone().unwrap_or_default(),
+                // Filled in from the card index
+                trust: Default::default(),
+                license: moly_protocol::data::ModelLicense {
+                    id: remote_m.license.clone(),
+                    url: remote_m.license_url.clone(),
+                    requires_acceptance: remote_m.license_requires_acceptance,
+                    accepted_at: LicenseAcceptance::get(conn, &remote_m.id, &remote_m.license)?
+                        .map(|acceptance| acceptance.accepted_at),
+                },
             };
 
             models.push(model);
@@ -501,145 +470,280 @@ impl ModelCard {
 }
 
 pub struct ModelCardManager {
-    app_data_dir: PathBuf,
     pub country_code: String,
-    embedding_index: EmbeddingState,
-    indexs: HashMap<String, ModelIndex>,
-    caches: HashMap<String, ModelCard>,
-}
-
-pub enum EmbeddingState {
-    Pending(std::thread::JoinHandle<Option<EmbeddingIndex>>),
-    Finish(Option<EmbeddingIndex>),
+    cards: CardIndex,
 }
 
 impl ModelCardManager {
     const DEFAULT_COUNTRY_CODE: &'static str = "default";
 
-    pub fn empty(app_data_dir: PathBuf) -> Self {
+    pub fn empty() -> Self {
         Self {
-            app_data_dir,
-            indexs: HashMap::new(),
-            caches: HashMap::new(),
+            cards: CardIndex::default(),
             country_code: Self::DEFAULT_COUNTRY_CODE.to_string(),
-            embedding_index: EmbeddingState::Finish(None),
         }
     }
 
-    pub fn load_model_card(&mut self, index: &ModelIndex) -> anyhow::Result<ModelCard> {
-        let r = self
-            .caches
-            .entry(index.id.clone())
-            .or_insert(index.load_model_card(&self.app_data_dir)?);
-        Ok(r.clone())
+    /// Switch to a freshly synced catalog.
+    pub fn apply_update(&mut self, update: CatalogUpdate) {
+        self.country_code = update.country_code;
+        self.cards = update.cards;
     }
 
-    pub fn get_index_by_id(&self, id: &str) -> Option<&ModelIndex> {
-        self.indexs.get(id)
+    pub fn get_card(&self, id: &str) -> Option<&ModelCard> {
+        self.cards.get(id).map(|entry| &entry.card)
     }
 
-    pub fn search(
-        &self,
-        search_text: &str,
-        limit: usize,
-        offset: usize,
-    ) -> reqwest::Result<Vec<ModelIndex>> {
-        let search_text = search_text.trim().to_ascii_lowercase();
-        Ok(self
-            .indexs
-            .values()
-            .filter(|index| {
-                (index.model_type == "instruct" || index.model_type == "chat")
-                    && (index.name.to_ascii_lowercase().contains(&search_text)
-                        || index
-                            .architecture
-                            .to_ascii_lowercase()
-                            .contains(&search_text)
-                        || index.id.to_ascii_lowercase().contains(&search_text)
-                        || index.summary.to_ascii_lowercase().contains(&search_text))
-            })
-            .map(Clone::clone)
-            .skip(offset)
-            .take(limit)
-            .collect::<Vec<ModelIndex>>())
+    /// Models matching `query`, ranked and paginated, along with the total number of matches.
+    pub fn search(&self, query: &SearchQuery) -> (Vec<ModelCard>, usize) {
+        self.cards.search(query)
     }
 
-    pub fn get_featured_model(
-        &self,
-        limit: usize,
-        offset: usize,
-    ) -> reqwest::Result<Vec<ModelIndex>> {
-        Ok(self
-            .indexs
-            .values()
-            .filter(|index| {
-                (index.model_type == "instruct" || index.model_type == "chat") && index.featured
-            })
-            .map(Clone::clone)
-            .skip(offset)
-            .take(limit)
-            .collect::<Vec<ModelIndex>>())
+    pub fn get_featured_model(&self, limit: usize, offset: usize) -> Vec<ModelCard> {
+        self.cards.featured(limit, offset)
     }
 
-    pub fn embedding_model(&mut self) -> Option<(PathBuf, u64)> {
-        match &self.embedding_index {
-            EmbeddingState::Pending(res) => {
-                if res.is_finished() {
-                    ()
-                } else {
-                    return None;
-                }
-            }
-            EmbeddingState::Finish(None) => return None,
-            EmbeddingState::Finish(Some(index)) => {
-                return Some((index.file_path(&self.app_data_dir), index.ctx))
-            }
-        };
+    pub fn get_collections(&self) -> Vec<(&CollectionIndex, Vec<ModelCard>)> {
+        self.cards.collections()
+    }
 
-        let state = std::mem::replace(&mut self.embedding_index, EmbeddingState::Finish(None));
-        let res = match state {
-            EmbeddingState::Pending(res) => res.join().unwrap(),
-            EmbeddingState::Finish(_) => unreachable!(),
-        };
+    /// Whether the catalog lists another revision of a downloaded file. Files no
+    /// longer in the catalog have nothing to update to.
+    pub fn is_outdated(&self, file: &DownloadedFile) -> bool {
+        self.get_card(&file.model_id)
+            .and_then(|card| card.files.iter().find(|f| f.name == file.name))
+            .is_some_and(|remote_file| file.is_outdated(remote_file))
+    }
 
-        match res {
-            Some(index) => {
-                let r = (index.file_path(&self.app_data_dir), index.ctx);
-                self.embedding_index = EmbeddingState::Finish(Some(index));
-                Some(r)
-            }
-            None => None,
+    /// Id of a model in its catalog source, before adding the namespace.
+    pub fn get_source_id(&self, id: &str) -> Option<&str> {
+        self.cards.source_id(id)
+    }
+
+    /// Whether the card of a model is signed by a key trusted for its source.
+    pub fn get_card_trust(&self, id: &str) -> CatalogTrust {
+        self.cards.trust(id)
+    }
+
+    /// Whether the source of a model has trusted keys, so its card must be verified
+    /// before downloading it.
+    pub fn card_requires_signature(&self, id: &str) -> bool {
+        self.cards.requires_signature(id)
+    }
+
+    /// Sources whose index couldn't be verified by the last sync.
+    pub fn untrusted_sources(&self) -> &[String] {
+        self.cards.untrusted_sources()
+    }
+
+    /// Like [`ModelCard::to_model`], with the trust of each card filled in.
+    pub fn to_models(
+        &self,
+        cards: &[ModelCard],
+        conn: &rusqlite::Connection,
+    ) -> rusqlite::Result<Vec<moly_protocol::data::Model>> {
+        let mut models = ModelCard::to_model(cards, conn)?;
+        for model in models.iter_mut() {
+            model.trust = self.get_card_trust(&model.id);
         }
+        Ok(models)
+    }
+
+    /// The file of the embedding model used when the user picked none, the first
+    /// one of the public catalog.
+    pub fn default_embedding_file(&self) -> Option<&str> {
+        self.cards.default_embedding()
     }
 }
 
+/// An embedding model of the public catalog, listed in `embedding.json`.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct EmbeddingIndex {
     id: String,
     file: String,
     ctx: u64,
     download: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    summary: String,
+    // Size of the file in bytes
+    #[serde(default)]
+    size: String,
+    #[serde(default)]
+    sha256: Option<String>,
+}
+
+/// `embedding.json` used to hold a single model, it can now list several.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmbeddingList {
+    One(EmbeddingIndex),
+    Many(Vec<EmbeddingIndex>),
+}
+
+/// The embedding models of a catalog, the default one first.
+pub fn read_embedding_index(path: &Path) -> anyhow::Result<Vec<EmbeddingIndex>> {
+    let embedding_index = std::fs::read_to_string(path)?;
+    Ok(match serde_json::from_str(&embedding_index)? {
+        EmbeddingList::One(index) => vec![index],
+        EmbeddingList::Many(indexs) => indexs,
+    })
 }
 
 impl EmbeddingIndex {
-    pub fn file_path(&self, app_data_dir: &Path) -> PathBuf {
-        app_data_dir.join("embedding").join(&self.file)
+    pub fn file_id(&self) -> String {
+        format!("{}#{}", self.id, self.file)
     }
 
-    pub fn check_file_exist(&self, app_data_dir: &Path) -> bool {
-        let file_path = self.file_path(app_data_dir);
-        file_path.exists()
-    }
+    /// The catalog entry of the model, downloaded like any other model.
+    pub fn to_card(&self) -> (ModelIndex, ModelCard) {
+        let name = if self.name.is_empty() {
+            self.id.rsplit('/').next().unwrap_or(&self.id).to_string()
+        } else {
+            self.name.clone()
+        };
 
-    pub fn download(&self, app_data_dir: &Path) -> anyhow::Result<()> {
-        let file_path = self.file_path(app_data_dir);
+        let index = ModelIndex {
+            id: self.id.clone(),
+            name: name.clone(),
+            architecture: String::new(),
+            model_type: ModelType::Embedding.as_str().to_string(),
+            summary: self.summary.clone(),
+            featured: false,
+            like_count: 0,
+            download_count: 0,
+        };
 
-        let _ = std::fs::create_dir_all(file_path.parent().unwrap());
+        let card = ModelCard {
+            id: self.id.clone(),
+            name,
+            summary: self.summary.clone(),
+            size: String::new(),
+            requires: String::new(),
+            architecture: String::new(),
+            model_type: index.model_type.clone(),
+            released_at: DateTime::<Utc>::default(),
+            files: vec![RemoteFile {
+                name: self.file.clone(),
+                size: self.size.clone(),
+                sha256: self.sha256.clone(),
+                download: HashMap::from([(
+                    super::mirrors::DEFAULT_REGION.to_string(),
+                    self.download.clone(),
+                )]),
+                ..Default::default()
+            }],
+            prompt_template: String::new(),
+            reverse_prompt: String::new(),
+            context_size: self.ctx,
+            author: Author::default(),
+            like_count: 0,
+            download_count: 0,
+            metrics: None,
+            license: String::new(),
+            license_url: String::new(),
+            license_requires_acceptance: false,
+        };
 
-        let client = reqwest::blocking::Client::new();
-        let mut resp = client.get(&self.download).send()?;
-        let mut file = std::fs::File::create(file_path)?;
-        std::io::copy(&mut resp, &mut file)?;
-        Ok(())
+        (index, card)
     }
 }
+
+#[test]
+fn test_group_split_files() {
+    let file = |name: &str, size: &str| RemoteFile {
+        name: name.to_string(),
+        size: size.to_string(),
+        ..Default::default()
+    };
+
+    let files = group_split_files(vec![
+        file("model-Q8_0-00002-of-00002.gguf", "200"),
+        file("model-Q4_0.gguf", "150"),
+        file("model-Q8_0-00001-of-00002.gguf", "100"),
+        file("model-Q6_K-00001-of-00003.gguf", "100"),
+    ]);
+
+    assert_eq!(files.len(), 3);
+    let split = files.iter().find(|f| !f.parts.is_empty()).unwrap();
+    assert_eq!(split.name, "model-Q8_0-00001-of-00002.gguf");
+    assert_eq!(split.size, "300");
+    assert_eq!(split.parts[1].name, "model-Q8_0-00002-of-00002.gguf");
+    assert!(split_file_part("model-Q4_0.gguf").is_none());
+}
+
+#[test]
+fn test_read_embedding_index() {
+    let dir = std::env::temp_dir().join("moly-test-embedding-index");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("embedding.json");
+
+    // The single model of older catalogs
+    std::fs::write(
+        &path,
+        r#"{"id": "nomic-ai/nomic-embed-text-v1.5-GGUF", "file": "nomic-embed-text-v1.5.Q8_0.gguf", "ctx": 2048, "download": "https://example.com/nomic.gguf"}"#,
+    )
+    .unwrap();
+    let indexs = read_embedding_index(&path).unwrap();
+    assert_eq!(indexs.len(), 1);
+    assert_eq!(
+        indexs[0].file_id(),
+        "nomic-ai/nomic-embed-text-v1.5-GGUF#nomic-embed-text-v1.5.Q8_0.gguf"
+    );
+
+    std::fs::write(
+        &path,
+        r#"[
+            {"id": "nomic-ai/nomic-embed-text-v1.5-GGUF", "file": "nomic-embed-text-v1.5.Q8_0.gguf", "ctx": 2048, "download": "https://example.com/nomic.gguf", "size": "146146432", "sha256": "abc"},
+            {"id": "BAAI/bge-small-en-v1.5-GGUF", "name": "BGE small", "file": "bge-small-en-v1.5-q8_0.gguf", "ctx": 512, "download": "https://example.com/bge.gguf"}
+        ]"#,
+    )
+    .unwrap();
+    let indexs = read_embedding_index(&path).unwrap();
+    assert_eq!(indexs.len(), 2);
+
+    let (index, card) = indexs[0].to_card();
+    assert_eq!(
+        ModelType::from_catalog(&index.model_type),
+        ModelType::Embedding
+    );
+    assert_eq!(card.name, "nomic-embed-text-v1.5-GGUF");
+    assert_eq!(card.context_size, 2048);
+    assert_eq!(card.files[0].sha256.as_deref(), Some("abc"));
+    assert_eq!(indexs[1].to_card().1.name, "BGE small");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_card_file() {
+    let index = |id: &str, name: &str| ModelIndex {
+        id: id.to_string(),
+        name: name.to_string(),
+        architecture: String::new(),
+        model_type: String::new(),
+        summary: String::new(),
+        featured: false,
+        like_count: 0,
+        download_count: 0,
+    };
+
+    assert_eq!(
+        index("org/model", "").card_file().unwrap(),
+        "org/model.json"
+    );
+    assert_eq!(
+        index("org/model", "Model-7B").card_file().unwrap(),
+        "org/Model-7B.json"
+    );
+
+    assert!(index("model", "").card_file().is_err());
+    assert!(index("../org/model", "").card_file().is_err());
+    assert!(index("org/model", "../../settings").card_file().is_err());
+    assert!(index("/org/model", "").card_file().is_err());
+    assert!(index("org/model", "model/../../../etc/passwd")
+        .card_file()
+        .is_err());
+    assert!(index("org/../model", "").card_file().is_err());
+}
@@ -21,7 +21,8 @@ pub fn create_table_models(conn: &rusqlite::Connection) -> rusqlite::Result<()>
             author_url TEXT NOT NULL,
             author_description TEXT NOT NULL,
             like_count INTEGER NOT NULL,
-            download_count INTEGER NOT NULL
+            download_count INTEGER NOT NULL,
+            model_type TEXT NOT NULL DEFAULT 'chat'
         )",
         (),
     )?;
@@ -36,6 +37,7 @@ pub struct Model {
     pub size: String,
     pub requires: String,
     pub architecture: String,
+    pub model_type: String,
     pub released_at: DateTime<Utc>,
     pub prompt_template: String,
     pub reverse_prompt: String,
@@ -50,8 +52,8 @@ impl Model {
             "INSERT OR REPLACE INTO models (
                 id, name, summary, size, requires, architecture, released_at, 
                 prompt_template, reverse_prompt, author_name, author_url, 
-                author_description, like_count, download_count)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                author_description, like_count, download_count, model_type)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
             params![
                 self.id,
                 self.name,
@@ -66,12 +68,19 @@ impl Model {
                 self.author.url,
                 self.author.description,
                 self.like_count,
-                self.download_count
+                self.download_count,
+                self.model_type,
             ],
         )?;
         Ok(())
     }
 
+    pub fn get_model_type(conn: &rusqlite::Connection, id: &str) -> rusqlite::Result<String> {
+        conn.query_row("SELECT model_type FROM models WHERE id = ?1", [id], |row| {
+            row.get(0)
+        })
+    }
+
     pub fn get_all(conn: &rusqlite::Connection) -> rusqlite::Result<HashMap<String, Model>> {
         let mut stmt = conn.prepare("SELECT * FROM models")?;
         let mut rows = stmt.query([])?;
@@ -99,6 +108,7 @@ impl Model {
                     size: row.get(3)?,
                     requires: row.get(4)?,
                     architecture: row.get(5)?,
+                    model_type: row.get("model_type")?,
                     released_at,
                     prompt_template: row.get(7)?,
                     reverse_prompt: row.get(8)?,
@@ -133,6 +143,7 @@ fn test_sql() {
         size: "size1".to_string(),
         requires: "requires1".to_string(),
         architecture: "architecture1".to_string(),
+        model_type: "embedding".to_string(),
         released_at: Utc::now(),
         prompt_template: "prompt_template1".to_string(),
         reverse_prompt: "reverse_prompt1".to_string(),
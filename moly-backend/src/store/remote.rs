@@ -1,27 +1,90 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{self, Seek, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 
-use moly_protocol::data::Model;
-use moly_protocol::protocol::FileDownloadResponse;
-use std::time::Duration;
+use moly_protocol::data::{
+    DownloadPriority, FileID, Model, PendingDownload, PendingDownloadsStatus,
+};
+use moly_protocol::protocol::{DownloadAccessError, FileDownloadResponse, InsufficientSpaceError};
+use std::time::{Duration, Instant};
 use tokio::time::timeout;
 
 use crate::backend_impls::DownloadControlCommand;
 
-async fn get_file_content_length(client: &reqwest::Client, url: &str) -> reqwest::Result<u64> {
-    let response = client.head(url).send().await?;
+use super::bandwidth::{BandwidthLimiter, Throttle};
+use super::disk_space::{self, SpaceReservations, SPACE_MARGIN};
+use super::download_queue::DownloadQueue;
+use super::download_segments::DownloadSegment;
+use super::mirrors::DownloadSources;
 
-    let content_length = response
-        .headers()
+/// How long a mirror has to answer before the next one is tried.
+const MIRROR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often running downloads check that the disk still has room for them.
+const SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+struct RemoteFileInfo {
+    content_length: u64,
+    accept_ranges: bool,
+}
+
+/// Send a request with the access token of its host, if there is one.
+async fn send_authorized(
+    request: reqwest::RequestBuilder,
+    url: &str,
+    token: Option<&str>,
+) -> anyhow::Result<reqwest::Response> {
+    let request = match token {
+        Some(token) => request.bearer_auth(token),
+        None => request,
+    };
+    let response = request.send().await?;
+
+    // Gated models answer with 401 or 403 until their license is accepted
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .unwrap_or_else(|| url.to_string());
+        return Err(DownloadAccessError {
+            host,
+            status: status.as_u16(),
+            with_token: token.is_some(),
+        }
+        .into());
+    }
+    Ok(response.error_for_status()?)
+}
+
+async fn get_remote_file_info(
+    client: &reqwest::Client,
+    url: &str,
+    token: Option<&str>,
+) -> anyhow::Result<RemoteFileInfo> {
+    let response = send_authorized(client.head(url), url, token).await?;
+    let headers = response.headers();
+
+    let content_length = headers
         .get(reqwest::header::CONTENT_LENGTH)
         .and_then(|val| val.to_str().ok())
         .and_then(|val| val.parse::<u64>().ok())
         .unwrap_or(0);
 
-    Ok(content_length)
+    let accept_ranges = headers
+        .get(reqwest::header::ACCEPT_RANGES)
+        .and_then(|val| val.to_str().ok())
+        .map(|val| val.contains("bytes"))
+        .unwrap_or(false);
+
+    Ok(RemoteFileInfo {
+        content_length,
+        accept_ranges,
+    })
 }
 
 pub enum DownloadResult {
@@ -33,8 +96,9 @@ async fn download_file<P: AsRef<Path>>(
     client: &reqwest::Client,
     content_length: u64,
     url: &str,
+    token: Option<&str>,
     local_path: P,
-    step: f64,
+    throttle: &Throttle,
     report_fn: &mut (dyn FnMut(f64) -> anyhow::Result<()> + Send),
 ) -> anyhow::Result<DownloadResult> {
     use futures_util::stream::StreamExt;
@@ -47,18 +111,34 @@ async fn download_file<P: AsRef<Path>>(
     let file_length = file.metadata()?.len();
 
     if file_length < content_length {
-        file.seek(io::SeekFrom::End(0))?;
-
-        let range = format!("bytes={}-", file_length);
-        let resp = client
-            .get(url)
-            .header("Range", range)
-            .send()
-            .await
-            .map_err(|e| anyhow::anyhow!(e))?;
+        let mut request = client.get(url);
+        if file_length > 0 {
+            let range = format!("bytes={}-", file_length);
+            request = request.header(reqwest::header::RANGE, range);
+        }
+        let resp = send_authorized(request, url, token).await?;
 
-        let mut downloaded: u64 = file_length;
-        let mut last_progress = 0.0;
+        // A server ignoring the range sends the whole file again, it is written over
+        // the partial one
+        let mut downloaded: u64 = match resp.status() {
+            reqwest::StatusCode::PARTIAL_CONTENT if file_length > 0 => {
+                file.seek(io::SeekFrom::End(0))?;
+                file_length
+            }
+            reqwest::StatusCode::OK => {
+                file.set_len(0)?;
+                file.seek(io::SeekFrom::Start(0))?;
+                let _ = report_fn(0.0);
+                0
+            }
+            status => {
+                return Err(anyhow::anyhow!(
+                    "Request for {} was answered with {}",
+                    url,
+                    status
+                ));
+            }
+        };
 
         let mut stream = resp.bytes_stream();
 
@@ -71,13 +151,12 @@ async fn download_file<P: AsRef<Path>>(
                     downloaded += len as u64;
 
                     let progress = (downloaded as f64 / content_length as f64) * 100.0;
-                    if progress > last_progress + step {
-                        last_progress = progress;
-                        match report_fn(progress) {
-                            Ok(_) => {}
-                            Err(_) => {}
-                        }
+                    match report_fn(progress) {
+                        Ok(_) => {}
+                        Err(_) => {}
                     }
+
+                    throttle.consume(len as u64).await;
                 }
                 None => {
                     // Download is complete
@@ -86,73 +165,569 @@ async fn download_file<P: AsRef<Path>>(
             }
         }
 
-        // TODO I don't know how to handle when it is complete but not 100%
-        // Maybe we should return Completed without any value?
-        Ok(DownloadResult::Completed(
-            (downloaded as f64 / content_length as f64) * 100.0,
-        ))
+        if downloaded < content_length {
+            return Err(anyhow::anyhow!(
+                "Download of {} ended after {} of {} bytes",
+                url,
+                downloaded,
+                content_length
+            ));
+        }
+
+        Ok(DownloadResult::Completed(100.0))
     } else {
         Ok(DownloadResult::Completed(100.0))
     }
 }
 
+/// Where a segmented download fetches its file from and saves its progress,
+/// shared by all its segments.
+struct DownloadContext<'a> {
+    client: &'a reqwest::Client,
+    sql_conn: &'a Mutex<rusqlite::Connection>,
+    url: &'a str,
+    token: Option<&'a str>,
+}
+
+/// Download `segment` with a Range request, writing at its offset in `local_path`.
+///
+/// `downloaded` is only advanced after the bytes are written, so persisting it
+/// never claims more than what is actually on disk.
+async fn download_segment(
+    ctx: &DownloadContext<'_>,
+    local_path: &Path,
+    segment: &DownloadSegment,
+    downloaded: &AtomicU64,
+    throttle: &Throttle,
+) -> anyhow::Result<()> {
+    use futures_util::stream::StreamExt;
+
+    let offset = segment.start + downloaded.load(Ordering::Relaxed);
+    if offset >= segment.end {
+        return Ok(());
+    }
+
+    let range = format!("bytes={}-{}", offset, segment.end - 1);
+    let resp = send_authorized(
+        ctx.client
+            .get(ctx.url)
+            .header(reqwest::header::RANGE, range),
+        ctx.url,
+        ctx.token,
+    )
+    .await?;
+
+    if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(anyhow::anyhow!(
+            "Range request for segment {} was answered with {}",
+            segment.idx,
+            resp.status()
+        ));
+    }
+
+    let mut file = File::options().write(true).open(local_path)?;
+    file.seek(io::SeekFrom::Start(offset))?;
+
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = timeout(Duration::from_secs(10), stream.next()).await? {
+        let chunk = chunk.map_err(|e| anyhow::anyhow!(e))?;
+        let written = segment.start + downloaded.load(Ordering::Relaxed);
+        let len = (chunk.len() as u64).min(segment.end.saturating_sub(written));
+        file.write_all(&chunk[..len as usize])?;
+        downloaded.fetch_add(len, Ordering::Relaxed);
+
+        throttle.consume(len).await;
+    }
+
+    if segment.start + downloaded.load(Ordering::Relaxed) < segment.end {
+        return Err(anyhow::anyhow!(
+            "Connection closed before segment {} was complete",
+            segment.idx
+        ));
+    }
+
+    Ok(())
+}
+
+/// The progress of the segments of a download, written back to the database.
+///
+/// It is written one last time when dropped, so a download stopped in the middle,
+/// like when it is paused, keeps everything it wrote.
+struct SegmentProgress<'a> {
+    sql_conn: &'a Mutex<rusqlite::Connection>,
+    segments: Vec<DownloadSegment>,
+    progress: &'a [AtomicU64],
+}
+
+impl SegmentProgress<'_> {
+    fn persist(&mut self) {
+        let conn = self.sql_conn.lock().unwrap();
+        for (segment, downloaded) in self.segments.iter_mut().zip(self.progress.iter()) {
+            let downloaded = downloaded.load(Ordering::Relaxed);
+            if segment.downloaded != downloaded {
+                segment.downloaded = downloaded;
+                if let Err(e) = segment.update_downloaded(&conn) {
+                    log::error!(
+                        "update segment {} of {} error: {e}",
+                        segment.idx,
+                        segment.file_id
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Drop for SegmentProgress<'_> {
+    fn drop(&mut self) {
+        self.persist();
+    }
+}
+
+/// Download the unfinished `segments` in parallel, one connection per segment.
+///
+/// The segment map is written back to the database periodically, so a paused or
+/// failed download only has to fetch what was not persisted yet.
+async fn download_file_segmented<P: AsRef<Path>>(
+    ctx: &DownloadContext<'_>,
+    local_path: P,
+    segments: Vec<DownloadSegment>,
+    throttle: &Throttle,
+    report_fn: &mut (dyn FnMut(f64) -> anyhow::Result<()> + Send),
+) -> anyhow::Result<DownloadResult> {
+    let content_length = segments.last().map(|s| s.end).unwrap_or_default();
+    let path: &Path = local_path.as_ref();
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    {
+        // Preallocate the whole file, most filesystems keep it sparse until written.
+        let file = File::options()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if file.metadata()?.len() != content_length {
+            file.set_len(content_length)?;
+        }
+    }
+
+    let progress = segments
+        .iter()
+        .map(|segment| AtomicU64::new(segment.downloaded))
+        .collect::<Vec<_>>();
+
+    let plan = segments.clone();
+    // Declared before the workers so it is dropped after them, with every byte
+    // they wrote
+    let mut segments = SegmentProgress {
+        sql_conn: ctx.sql_conn,
+        segments,
+        progress: &progress,
+    };
+
+    let workers = plan
+        .iter()
+        .zip(progress.iter())
+        .filter(|(segment, _)| !segment.is_complete())
+        .map(|(segment, downloaded)| download_segment(ctx, path, segment, downloaded, throttle));
+    let mut workers = Box::pin(futures_util::future::try_join_all(workers));
+
+    let mut ticker = tokio::time::interval(Duration::from_millis(500));
+
+    let result = loop {
+        tokio::select! {
+            r = &mut workers => break r,
+            _ = ticker.tick() => {
+                segments.persist();
+
+                let downloaded: u64 = segments.segments.iter().map(|s| s.downloaded).sum();
+                let _ = report_fn((downloaded as f64 / content_length as f64) * 100.0);
+            }
+        }
+    };
+
+    segments.persist();
+    result?;
+
+    Ok(DownloadResult::Completed(100.0))
+}
+
+/// Check the sha256 digest of a downloaded file, removing the file when it
+/// doesn't match so the next attempt starts from scratch.
+async fn verify_sha256(path: &Path, expected: &str) -> anyhow::Result<()> {
+    let path_ = path.to_path_buf();
+    let digest = tokio::task::spawn_blocking(move || -> io::Result<String> {
+        use sha2::{Digest, Sha256};
+
+        let mut file = File::open(path_)?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)?;
+        Ok(format!("{:x}", hasher.finalize()))
+    })
+    .await??;
+
+    if !digest.eq_ignore_ascii_case(expected) {
+        let _ = std::fs::remove_file(path);
+        return Err(anyhow::anyhow!(
+            "Checksum mismatch for {}: expected {expected}, got {digest}",
+            path.display()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Whether a download fetches a new file or the new revision of a downloaded one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadKind {
+    New,
+    /// Fetched next to the downloaded file, which stays in use until the new
+    /// revision is verified and replaces it.
+    Update,
+}
+
+/// Directory of a model where the new revisions of its files are fetched.
+const UPDATE_DIR: &str = ".update";
+
+/// Remove the parts fetched by a cancelled update along with their segment maps,
+/// so a later update of the file starts over. The downloaded file is kept.
+pub fn remove_update(
+    conn: &rusqlite::Connection,
+    models_dir: &Path,
+    model_id: &str,
+    names: &[String],
+) {
+    let update_dir = models_dir.join(model_id).join(UPDATE_DIR);
+    for name in names {
+        let key = format!("{}#{}/{}", model_id, UPDATE_DIR, name);
+        if let Err(e) = DownloadSegment::remove_by_file(&key, conn) {
+            log::error!("remove update segments of {key} error: {e}");
+        }
+
+        let path = update_dir.join(name);
+        if let Err(e) = std::fs::remove_file(&path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                log::warn!("remove update {:?} error: {e}", path);
+            }
+        }
+    }
+    let _ = std::fs::remove_dir(&update_dir);
+}
+
+pub type DownloadRequest = (
+    super::models::Model,
+    super::download_files::DownloadedFile,
+    super::model_cards::RemoteFile,
+    // Id of the model in its catalog source, the download urls are built from it
+    String,
+    DownloadKind,
+    Sender<anyhow::Result<FileDownloadResponse>>,
+);
+
+/// A file on disk fetched for a download, one per shard for split models.
+#[derive(Debug, Clone)]
+struct DownloadPart {
+    /// Key of the segment map of this file, `{model_id}#{file name}`.
+    key: String,
+    /// Urls to fetch the file from, tried in order when one fails.
+    urls: Vec<String>,
+    local_path: PathBuf,
+    size: u64,
+    accept_ranges: bool,
+    sha256: Option<String>,
+}
+
+#[derive(Debug)]
+struct DownloadTask {
+    file: super::download_files::DownloadedFile,
+    kind: DownloadKind,
+    parts: Vec<DownloadPart>,
+    tx: Sender<anyhow::Result<FileDownloadResponse>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct ModelFileDownloader {
     client: reqwest::Client,
     sql_conn: Arc<Mutex<rusqlite::Connection>>,
     control_tx: tokio::sync::broadcast::Sender<DownloadControlCommand>,
-    country_code: String,
+    sources: Arc<Mutex<DownloadSources>>,
+    connections: usize,
     step: f64,
+    queue: Arc<Mutex<DownloadQueue<DownloadTask>>>,
+    queue_changed: Arc<tokio::sync::Notify>,
+    global_limit: Arc<BandwidthLimiter>,
+    file_limits: Arc<Mutex<HashMap<FileID, Arc<BandwidthLimiter>>>>,
+    // Running downloads stopped for a queued one with a higher priority, queued
+    // again once they stopped
+    preempted: Arc<Mutex<HashSet<FileID>>>,
+    reservations: SpaceReservations,
+    // Set on shutdown, nothing starts anymore
+    paused: Arc<AtomicBool>,
 }
 
 impl ModelFileDownloader {
-    const DEFAULT_COUNTRY_CODE: &'static str = "default";
     pub fn new(
         client: reqwest::Client,
         sql_conn: Arc<Mutex<rusqlite::Connection>>,
         control_tx: tokio::sync::broadcast::Sender<DownloadControlCommand>,
-        country_code: String,
+        sources: DownloadSources,
+        max_downloads: usize,
+        connections: usize,
         step: f64,
     ) -> Self {
         Self {
             client,
             sql_conn,
             control_tx,
-            country_code,
+            sources: Arc::new(Mutex::new(sources)),
+            connections,
             step,
+            queue: Arc::new(Mutex::new(DownloadQueue::new(max_downloads))),
+            queue_changed: Arc::new(tokio::sync::Notify::new()),
+            global_limit: Arc::new(BandwidthLimiter::new(None)),
+            file_limits: Default::default(),
+            preempted: Default::default(),
+            reservations: Default::default(),
+            paused: Default::default(),
+        }
+    }
+
+    pub fn set_bandwidth_limit(&self, bytes_per_sec: Option<u64>) {
+        self.global_limit.set_limit(bytes_per_sec);
+    }
+
+    pub fn set_file_bandwidth_limit(&self, file_id: FileID, bytes_per_sec: Option<u64>) {
+        let mut file_limits = self.file_limits.lock().unwrap();
+        match file_limits.get(&file_id) {
+            Some(limiter) => limiter.set_limit(bytes_per_sec),
+            None => {
+                file_limits.insert(file_id, Arc::new(BandwidthLimiter::new(bytes_per_sec)));
+            }
+        }
+    }
+
+    fn throttle(&self, file_id: &FileID) -> Throttle {
+        let file = self
+            .file_limits
+            .lock()
+            .unwrap()
+            .entry(file_id.clone())
+            .or_insert_with(|| Arc::new(BandwidthLimiter::new(None)))
+            .clone();
+
+        Throttle {
+            global: self.global_limit.clone(),
+            file,
         }
     }
 
-    fn get_download_url(
+    pub fn set_priority(&self, file_id: &FileID, priority: DownloadPriority) -> anyhow::Result<()> {
+        self.queue.lock().unwrap().set_priority(file_id, priority)?;
+        self.queue_changed.notify_one();
+        Ok(())
+    }
+
+    pub fn reorder_queue(&self, file_ids: &[FileID]) {
+        self.queue.lock().unwrap().reorder(file_ids);
+        self.queue_changed.notify_one();
+    }
+
+    pub fn start_next(&self, file_id: &FileID) -> anyhow::Result<()> {
+        self.queue.lock().unwrap().start_next(file_id)?;
+        self.queue_changed.notify_one();
+        Ok(())
+    }
+
+    /// Take a download out of the queue before it started. Dropping it closes
+    /// the response channel of the download.
+    pub fn remove_queued(&self, file_id: &FileID) -> bool {
+        let removed = {
+            let mut queue = self.queue.lock().unwrap();
+            // A preempted download that is stopping is not queued again
+            self.preempted.lock().unwrap().remove(file_id);
+            queue.remove(file_id)
+        };
+        if removed {
+            self.reservations.release(file_id);
+        }
+        removed
+    }
+
+    /// Pause every download, queued or running, and start no other one. Returns the
+    /// files of the paused downloads.
+    ///
+    /// Running downloads save their progress as they stop, see
+    /// [`ModelFileDownloader::wait_stopped`].
+    pub fn pause_all(&self) -> Vec<FileID> {
+        self.paused.store(true, Ordering::Release);
+
+        let (queued, active) = {
+            let queue = self.queue.lock().unwrap();
+            (queue.queued_ids(), queue.active_ids())
+        };
+        for file_id in &queued {
+            self.remove_queued(file_id);
+        }
+        for file_id in &active {
+            let _ = self
+                .control_tx
+                .send(DownloadControlCommand::Stop(file_id.clone()));
+        }
+
+        queued.into_iter().chain(active).collect()
+    }
+
+    /// Wait for the running downloads to stop, returning whether they all did
+    /// before `timeout`.
+    pub fn wait_stopped(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        while !self.queue.lock().unwrap().active_ids().is_empty() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        true
+    }
+
+    /// Forget the settings kept for a download that is not coming back.
+    pub fn forget(&self, file_id: &FileID) {
+        self.remove_queued(file_id);
+        self.file_limits.lock().unwrap().remove(file_id);
+    }
+
+    /// Fill in the queue state and the speed limit of pending downloads, which are
+    /// only known here.
+    pub fn fill_queue_status(&self, downloads: &mut [PendingDownload]) {
+        let queue = self.queue.lock().unwrap();
+        let file_limits = self.file_limits.lock().unwrap();
+        for download in downloads {
+            download.bandwidth_limit = file_limits
+                .get(&download.file.id)
+                .and_then(|limiter| limiter.limit());
+            if queue.is_active(&download.file.id) {
+                download.status = PendingDownloadsStatus::Downloading;
+            } else if let Some(position) = queue.position(&download.file.id) {
+                download.status = PendingDownloadsStatus::Queued;
+                download.queue_position = Some(position);
+            }
+            if let Some(priority) = queue.priority(&download.file.id) {
+                download.priority = priority;
+            }
+        }
+    }
+
+    /// Bytes the queued and running downloads still have to write.
+    pub fn reserved_space(&self) -> u64 {
+        self.reservations.total(None)
+    }
+
+    /// Fail when the models volume can't hold `needed` more bytes for a download
+    /// next to what the other downloads still have to write.
+    fn check_space(&self, dir: &Path, file_id: &FileID, needed: u64) -> anyhow::Result<()> {
+        let free = disk_space::available_space(dir)?;
+        let available = free.saturating_sub(self.reservations.total(Some(file_id)) + SPACE_MARGIN);
+        if needed > available {
+            return Err(InsufficientSpaceError { needed, available }.into());
+        }
+        Ok(())
+    }
+
+    /// Bytes of `part` already on disk, which a resumed download doesn't fetch again.
+    fn downloaded_bytes(&self, part: &DownloadPart) -> u64 {
+        let segments = {
+            let conn = self.sql_conn.lock().unwrap();
+            DownloadSegment::get_by_file(&conn, &part.key).unwrap_or_default()
+        };
+        // Segmented files are preallocated, their length says nothing
+        if !segments.is_empty() {
+            return segments.iter().map(|segment| segment.downloaded).sum();
+        }
+
+        std::fs::metadata(&part.local_path)
+            .map(|meta| meta.len())
+            .unwrap_or(0)
+            .min(part.size)
+    }
+
+    pub fn sources(&self) -> DownloadSources {
+        self.sources.lock().unwrap().clone()
+    }
+
+    pub fn set_mirrors(&self, mirrors: Vec<String>) {
+        self.sources.lock().unwrap().mirrors = mirrors;
+    }
+
+    pub fn set_region(&self, region: String) {
+        self.sources.lock().unwrap().region = region;
+    }
+
+    pub fn set_access_token(&self, host: String, token: Option<String>) {
+        let mut sources = self.sources.lock().unwrap();
+        match token {
+            Some(token) => sources.access_tokens.insert(host, token),
+            None => sources.access_tokens.remove(&host),
+        };
+    }
+
+    /// Find the first of `urls` that answers, moving it to the front.
+    ///
+    /// When none does, a refused access is reported over other errors since it is
+    /// the one the user can do something about.
+    async fn probe_urls(
         &self,
-        file: &super::download_files::DownloadedFile,
-        remote_file: &super::model_cards::RemoteFile,
-    ) -> String {
-        remote_file
-            .download
-            .get(&self.country_code)
-            .cloned()
-            .unwrap_or_else(|| {
-                remote_file
-                    .download
-                    .get(Self::DEFAULT_COUNTRY_CODE)
-                    .cloned()
-                    .unwrap_or(format!(
-                        "https://huggingface.co/{}/resolve/main/{}",
-                        file.model_id, file.name
-                    ))
-            })
+        mut urls: Vec<String>,
+    ) -> anyhow::Result<(Vec<String>, RemoteFileInfo)> {
+        let sources = self.sources();
+        let mut last_error = anyhow::anyhow!("No download url available");
+        let mut access_error = None;
+        for (i, url) in urls.iter().enumerate() {
+            let token = sources.access_token(url);
+            match timeout(
+                MIRROR_TIMEOUT,
+                get_remote_file_info(&self.client, url, token),
+            )
+            .await
+            {
+                Ok(Ok(info)) => {
+                    urls.rotate_left(i);
+                    return Ok((urls, info));
+                }
+                Ok(Err(e)) => {
+                    log::warn!("Mirror {url} failed: {e}");
+                    if e.is::<DownloadAccessError>() {
+                        access_error.get_or_insert(e);
+                    } else {
+                        last_error = e;
+                    }
+                }
+                Err(_) => {
+                    log::warn!("Mirror {url} timed out");
+                    last_error = anyhow::anyhow!("Mirror {url} timed out");
+                }
+            }
+        }
+        Err(access_error.unwrap_or(last_error))
     }
 
+    /// Run a download to its end, returning whether it was stopped before.
     async fn download(
         self,
         file: super::download_files::DownloadedFile,
-        remote_file: super::model_cards::RemoteFile,
+        kind: DownloadKind,
+        parts: Vec<DownloadPart>,
         tx: Sender<anyhow::Result<FileDownloadResponse>>,
-    ) {
+    ) -> bool {
         let file_id = file.id.to_string();
+        let step = self.step;
+        let mut last_progress = 0.0;
 
         let mut send_progress = |progress| {
+            if progress <= last_progress + step {
+                return Ok(());
+            }
+            last_progress = progress;
+
             let r = tx.send(Ok(FileDownloadResponse::Progress(
                 file_id.clone(),
                 progress as f32,
@@ -162,83 +737,272 @@ impl ModelFileDownloader {
         };
 
         let r = self
-            .download_file_from_remote(file, remote_file, &mut send_progress)
+            .download_file_from_remote(file, kind, parts, &mut send_progress)
             .await;
 
         match r {
             Ok(Some(response)) => {
                 let _ = tx.send(Ok(response));
+                false
             }
             Ok(None) => {
                 // TODO Implement file removal when download is stopped, nothing to do when it is paused
+                true
             }
             Err(e) => {
                 let _ = tx.send(Err(e));
+                false
             }
         }
     }
 
     pub async fn run_loop(
         downloader: Self,
-        max_downloader: usize,
-        mut download_rx: tokio::sync::mpsc::UnboundedReceiver<(
-            super::models::Model,
-            super::download_files::DownloadedFile,
-            super::model_cards::RemoteFile,
-            Sender<anyhow::Result<FileDownloadResponse>>,
-        )>,
+        mut download_rx: tokio::sync::mpsc::UnboundedReceiver<DownloadRequest>,
     ) {
-        let semaphore = Arc::new(tokio::sync::Semaphore::new(max_downloader));
+        loop {
+            tokio::select! {
+                request = download_rx.recv() => match request {
+                    Some(request) => downloader.enqueue(request).await,
+                    None => break,
+                },
+                _ = downloader.queue_changed.notified() => {}
+            }
 
-        while let Some((model, mut file, remote_file, tx)) = download_rx.recv().await {
-            let url = downloader.get_download_url(&file, &remote_file);
-            log::info!("Downloading file: {}", url);
+            downloader.start_ready();
+        }
+    }
+
+    async fn enqueue(&self, (model, mut file, remote_file, source_id, kind, tx): DownloadRequest) {
+        let f = async {
+            let model_dir = Path::new(&file.download_dir).join(&file.model_id);
+            let (key_prefix, download_dir) = match kind {
+                DownloadKind::New => (String::new(), model_dir),
+                DownloadKind::Update => (format!("{}/", UPDATE_DIR), model_dir.join(UPDATE_DIR)),
+            };
+
+            // A split model is fetched shard by shard, a single file is its only part.
+            let remote_parts = if remote_file.parts.is_empty() {
+                vec![&remote_file]
+            } else {
+                remote_file.parts.iter().collect()
+            };
 
-            let f = async {
-                let content_length = get_file_content_length(&downloader.client, &url)
-                    .await
-                    .map_err(|e| anyhow::anyhow!(e))?;
+            let mut parts = Vec::with_capacity(remote_parts.len());
+            for remote_part in remote_parts {
+                let urls = self.sources().urls(&source_id, remote_part);
+                let (urls, info) = self.probe_urls(urls).await?;
+                log::info!("Downloading file: {}", urls[0]);
 
-                {
-                    file.file_size = content_length;
-                    let conn = downloader.sql_conn.lock().unwrap();
-                    // insert a pending download
+                parts.push(DownloadPart {
+                    key: format!("{}#{}{}", file.model_id, key_prefix, remote_part.name),
+                    urls,
+                    local_path: download_dir.join(&remote_part.name),
+                    size: info.content_length,
+                    accept_ranges: info.accept_ranges,
+                    sha256: remote_part.sha256.clone().filter(|s| !s.is_empty()),
+                });
+            }
+
+            let needed = parts
+                .iter()
+                .map(|part| part.size.saturating_sub(self.downloaded_bytes(part)))
+                .sum();
+            self.check_space(&download_dir, &file.id, needed)?;
+
+            {
+                file.file_size = parts.iter().map(|part| part.size).sum();
+                let conn = self.sql_conn.lock().unwrap();
+                // insert a pending download, an update keeps the downloaded file until it is done
+                if kind == DownloadKind::New {
                     file.insert_into_db(&conn).map_err(|e| anyhow::anyhow!(e))?;
-                    model.save_to_db(&conn).map_err(|e| anyhow::anyhow!(e))?;
                 }
+                model.save_to_db(&conn).map_err(|e| anyhow::anyhow!(e))?;
+            }
 
-                Ok(())
-            };
-
-            let r: anyhow::Result<()> = f.await;
+            self.reservations.reserve(file.id.to_string(), needed);
+            Ok(parts)
+        };
 
-            if let Err(e) = r {
+        let parts = match f.await {
+            Ok(parts) => parts,
+            Err(e) => {
                 let _ = tx.send(Err(e));
-                continue;
+                return;
             }
+        };
+
+        let file_id = file.id.to_string();
+        let task = DownloadTask {
+            file,
+            kind,
+            parts,
+            tx: tx.clone(),
+        };
+        self.queue
+            .lock()
+            .unwrap()
+            .push(file_id.clone(), DownloadPriority::Normal, task);
+
+        self.start_ready();
+
+        if self.queue.lock().unwrap().position(&file_id).is_some() {
+            let _ = tx.send(Ok(FileDownloadResponse::Queued(file_id)));
+        }
+    }
 
-            let downloader_ = downloader.clone();
-            let semaphore_ = semaphore.clone();
+    /// Start queued downloads while there are free download slots, stopping a
+    /// running download for a queued one with a higher priority.
+    fn start_ready(&self) {
+        if self.paused.load(Ordering::Acquire) {
+            return;
+        }
+
+        loop {
+            let Some(queued) = self.queue.lock().unwrap().pop_ready() else {
+                break;
+            };
+
+            let downloader = self.clone();
             tokio::spawn(async move {
-                let permit = semaphore_.acquire_owned().await.unwrap();
-                downloader_.download(file, remote_file, tx).await;
-                drop(permit);
+                let file_id = queued.file_id;
+                let task = queued.task;
+                let retry = DownloadTask {
+                    file: task.file.clone(),
+                    kind: task.kind,
+                    parts: task.parts.clone(),
+                    tx: task.tx.clone(),
+                };
+                let stopped = downloader
+                    .clone()
+                    .download(task.file, task.kind, task.parts, task.tx)
+                    .await;
+
+                // It resumes from its saved progress when a slot is free again
+                let requeued_tx = {
+                    let mut queue = downloader.queue.lock().unwrap();
+                    let priority = queue.priority(&file_id).unwrap_or_default();
+                    queue.finish(&file_id);
+                    let preempted = downloader.preempted.lock().unwrap().remove(&file_id);
+                    if preempted && stopped {
+                        let tx = retry.tx.clone();
+                        queue.push(file_id.clone(), priority, retry);
+                        Some(tx)
+                    } else {
+                        None
+                    }
+                };
+                match requeued_tx {
+                    Some(tx) => {
+                        let _ = tx.send(Ok(FileDownloadResponse::Queued(file_id)));
+                    }
+                    None => downloader.reservations.release(&file_id),
+                }
+                downloader.queue_changed.notify_one();
             });
         }
+
+        let preemptible = self.queue.lock().unwrap().preemptible();
+        if let Some(file_id) = preemptible {
+            if self.preempted.lock().unwrap().insert(file_id.clone()) {
+                log::info!("Pausing download {file_id} for one with a higher priority");
+                let _ = self.control_tx.send(DownloadControlCommand::Stop(file_id));
+            }
+        }
+    }
+
+    /// Load or create the segment map of `part`, or `None` if it should be fetched
+    /// as a single stream.
+    fn prepare_segments(
+        &self,
+        part: &DownloadPart,
+    ) -> anyhow::Result<Option<Vec<DownloadSegment>>> {
+        let local_path = part.local_path.as_path();
+        let conn = self.sql_conn.lock().unwrap();
+        let mut segments = DownloadSegment::get_by_file(&conn, &part.key)?;
+
+        let segmented = self.connections > 1 && part.accept_ranges && part.size > 0;
+        let map_matches = segments.last().map(|s| s.end) == Some(part.size);
+
+        if !segments.is_empty() && (!segmented || !map_matches) {
+            // The preallocated file can't be resumed from its length, start over.
+            DownloadSegment::remove_by_file(&part.key, &conn)?;
+            segments.clear();
+            if local_path.exists() {
+                File::options().write(true).open(local_path)?.set_len(0)?;
+            }
+        }
+
+        if !segmented {
+            return Ok(None);
+        }
+
+        if segments.is_empty() {
+            let already_downloaded = std::fs::metadata(local_path)
+                .map(|meta| meta.len())
+                .unwrap_or(0);
+            segments = DownloadSegment::plan(
+                &Arc::new(part.key.clone()),
+                part.size,
+                already_downloaded,
+                self.connections,
+            );
+            for segment in &segments {
+                segment.insert_into_db(&conn)?;
+            }
+        }
+
+        Ok(Some(segments))
+    }
+
+    /// Move a verified new revision over the downloaded file and record it.
+    fn replace_with_update(
+        &self,
+        file: &mut super::download_files::DownloadedFile,
+        parts: &[DownloadPart],
+    ) -> anyhow::Result<()> {
+        let conn = self.sql_conn.lock().unwrap();
+        let previous = super::download_files::DownloadedFile::get_by_id(&conn, &file.id)?;
+
+        let model_dir = Path::new(&file.download_dir).join(&file.model_id);
+        let mut new_paths = Vec::with_capacity(parts.len());
+        for part in parts {
+            let file_name = part
+                .local_path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("Invalid file name {:?}", part.local_path))?;
+            let path = model_dir.join(file_name);
+            std::fs::rename(&part.local_path, &path)?;
+            new_paths.push(path);
+        }
+        let _ = std::fs::remove_dir(model_dir.join(UPDATE_DIR));
+
+        // Shards of the previous revision that the new one doesn't overwrite
+        let previous_dir = Path::new(&previous.download_dir).join(&previous.model_id);
+        for name in previous.file_names() {
+            let path = previous_dir.join(name);
+            if !new_paths.contains(&path) {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    log::warn!("remove previous revision {:?} error: {e}", path);
+                }
+            }
+        }
+
+        // Still the same file for the user
+        file.last_loaded_at = previous.last_loaded_at;
+        file.pinned = previous.pinned;
+        file.mark_downloads();
+        file.insert_into_db(&conn)?;
+        Ok(())
     }
 
     async fn download_file_from_remote(
         &self,
         mut file: super::download_files::DownloadedFile,
-        remote_file: super::model_cards::RemoteFile,
+        kind: DownloadKind,
+        parts: Vec<DownloadPart>,
         report_fn: &mut (dyn FnMut(f64) -> anyhow::Result<()> + Send),
     ) -> anyhow::Result<Option<FileDownloadResponse>> {
-        let url = self.get_download_url(&file, &remote_file);
-
-        let local_path = Path::new(&file.download_dir)
-            .join(&file.model_id)
-            .join(&file.name);
-
         let file_id_ = file.id.as_ref().clone();
         let mut control_rx = self.control_tx.subscribe();
 
@@ -252,55 +1016,186 @@ impl ModelFileDownloader {
                 }
             }
         };
+        tokio::pin!(listen_control_cmd);
+
+        let total: u64 = parts.iter().map(|part| part.size).sum();
+        let remaining = self.reservations.remaining(&file_id_);
 
-        let r = tokio::select! {
-            r = download_file(
-                &self.client,
-                file.file_size,
-                &url,
-                &local_path,
-                self.step,
-                report_fn,
-            ) => r?,
-            r = listen_control_cmd => {
-                r
+        // Something else may fill the disk while the download runs
+        let download_dir = parts
+            .first()
+            .and_then(|part| part.local_path.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let watch_space = async {
+            let mut interval = tokio::time::interval(SPACE_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let needed = remaining
+                    .as_ref()
+                    .map_or(0, |remaining| remaining.load(Ordering::Relaxed));
+                match self.check_space(&download_dir, &file_id_, needed) {
+                    Err(e) if e.is::<InsufficientSpaceError>() => return e,
+                    Err(e) => log::warn!("check free space of {:?} error: {e}", download_dir),
+                    Ok(()) => {}
+                }
             }
         };
+        tokio::pin!(watch_space);
 
-        match r {
-            DownloadResult::Completed(_) => {
-                {
-                    let conn = self.sql_conn.lock().unwrap();
-                    file.mark_downloads();
-                    let _ = file.update_downloaded(&conn);
+        let throttle = self.throttle(&file_id_);
+        let mut done: u64 = 0;
+
+        // Shards are fetched one after the other and reported as a single progress.
+        for part in &parts {
+            let mut report_part = |progress: f64| {
+                if total == 0 {
+                    return report_fn(progress);
+                }
+                let downloaded = done as f64 + part.size as f64 * progress / 100.0;
+                if let Some(remaining) = &remaining {
+                    remaining.store(total.saturating_sub(downloaded as u64), Ordering::Relaxed);
                 }
+                report_fn(downloaded / total as f64 * 100.0)
+            };
+
+            // A failed or stalled mirror is replaced by the next one, resuming
+            // from what is already on disk.
+            let mut r = Err(anyhow::anyhow!("No download url available"));
+            for url in &part.urls {
+                let segments = self.prepare_segments(part)?;
+                let sources = self.sources();
+                let token = sources.access_token(url);
 
-                Ok(Some(FileDownloadResponse::Completed(
-                    moly_protocol::data::DownloadedFile {
-                        file: moly_protocol::data::File {
-                            id: file.id.as_ref().clone(),
-                            name: file.name.clone(),
-                            size: file.size.clone(),
-                            quantization: file.quantization.clone(),
-                            downloaded: true,
-                            downloaded_path: Some(
-                                local_path
-                                    .to_str()
-                                    .map(|s| s.to_string())
-                                    .unwrap_or_default(),
-                            ),
-                            tags: file.tags,
-                            featured: false,
-                        },
-                        model: Model::default(),
-                        downloaded_at: file.downloaded_at,
-                        compatibility_guess:
-                            moly_protocol::data::CompatibilityGuess::PossiblySupported,
-                        information: String::new(),
+                let ctx = DownloadContext {
+                    client: &self.client,
+                    sql_conn: &self.sql_conn,
+                    url,
+                    token,
+                };
+                r = match segments {
+                    Some(segments) => tokio::select! {
+                        r = download_file_segmented(
+                            &ctx,
+                            &part.local_path,
+                            segments,
+                            &throttle,
+                            &mut report_part,
+                        ) => r,
+                        r = &mut listen_control_cmd => Ok(r),
+                        e = &mut watch_space => Err(e),
                     },
-                )))
+                    None => tokio::select! {
+                        r = download_file(
+                            &self.client,
+                            part.size,
+                            url,
+                            token,
+                            &part.local_path,
+                            &throttle,
+                            &mut report_part,
+                        ) => r,
+                        r = &mut listen_control_cmd => Ok(r),
+                        e = &mut watch_space => Err(e),
+                    },
+                };
+
+                match &r {
+                    Ok(_) => break,
+                    // Another mirror won't find more room on the disk
+                    Err(e) if e.is::<InsufficientSpaceError>() => break,
+                    Err(e) => log::warn!("Download from {url} failed: {e}"),
+                }
+            }
+            let r = r?;
+
+            if let DownloadResult::Stopped(_) = r {
+                return Ok(None);
+            }
+
+            {
+                let conn = self.sql_conn.lock().unwrap();
+                let _ = DownloadSegment::remove_by_file(&part.key, &conn);
+            }
+            if let Some(sha256) = &part.sha256 {
+                verify_sha256(&part.local_path, sha256).await?;
             }
-            DownloadResult::Stopped(_) => Ok(None),
+
+            done += part.size;
+        }
+
+        match kind {
+            DownloadKind::New => {
+                let conn = self.sql_conn.lock().unwrap();
+                file.mark_downloads();
+                let _ = file.update_downloaded(&conn);
+            }
+            DownloadKind::Update => self.replace_with_update(&mut file, &parts)?,
+        }
+
+        // The first shard is the one to load, llama.cpp finds the others next to it.
+        let local_path = Path::new(&file.download_dir)
+            .join(&file.model_id)
+            .join(&file.name);
+
+        Ok(Some(FileDownloadResponse::Completed(
+            moly_protocol::data::DownloadedFile {
+                file: moly_protocol::data::File {
+                    id: file.id.as_ref().clone(),
+                    name: file.name.clone(),
+                    size: file.size.clone(),
+                    quantization: file.quantization.clone(),
+                    downloaded: true,
+                    downloaded_path: Some(
+                        local_path
+                            .to_str()
+                            .map(|s| s.to_string())
+                            .unwrap_or_default(),
+                    ),
+                    tags: file.tags,
+                    featured: false,
+                    parts: file.parts,
+                },
+                model: Model::default(),
+                downloaded_at: file.downloaded_at,
+                compatibility_guess: moly_protocol::data::CompatibilityGuess::PossiblySupported,
+                information: String::new(),
+                update_available: false,
+                pinned: file.pinned,
+            },
+        )))
+    }
+}
+
+#[test]
+fn test_remove_update() {
+    use super::download_segments::create_table_download_segments;
+
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_download_segments(&conn).unwrap();
+
+    let models_dir = std::env::temp_dir().join("moly-test-remove-update");
+    let _ = std::fs::remove_dir_all(&models_dir);
+    let update_dir = models_dir.join("org/model").join(UPDATE_DIR);
+    std::fs::create_dir_all(&update_dir).unwrap();
+    std::fs::write(update_dir.join("model.gguf"), b"new").unwrap();
+    std::fs::write(models_dir.join("org/model/model.gguf"), b"old").unwrap();
+
+    let update_key = Arc::new("org/model#.update/model.gguf".to_string());
+    let key = Arc::new("org/model#model.gguf".to_string());
+    for key in [&update_key, &key] {
+        for segment in DownloadSegment::plan(key, 1024, 0, 1) {
+            segment.insert_into_db(&conn).unwrap();
         }
     }
+
+    remove_update(&conn, &models_dir, "org/model", &["model.gguf".to_string()]);
+    assert!(DownloadSegment::get_by_file(&conn, &update_key)
+        .unwrap()
+        .is_empty());
+    assert_eq!(DownloadSegment::get_by_file(&conn, &key).unwrap().len(), 1);
+    assert!(!update_dir.exists());
+    assert!(models_dir.join("org/model/model.gguf").exists());
+
+    let _ = std::fs::remove_dir_all(&models_dir);
 }
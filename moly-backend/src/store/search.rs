@@ -0,0 +1,284 @@
+use std::cmp::Ordering;
+
+use moly_protocol::data::{ModelType, SearchQuery, SearchSort};
+
+use super::card_index::IndexedCard;
+use super::model_cards::ModelIndex;
+
+/// A model matching the text of a query.
+#[derive(Debug)]
+pub struct Candidate<'a> {
+    pub entry: &'a IndexedCard,
+    pub score: f32,
+}
+
+/// Split a text in lowercase words, keeping dots so versions like `3.1` stay whole.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '.')
+        .map(|token| token.trim_matches('.'))
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        if current.iter().min().copied().unwrap_or_default() > max {
+            return None;
+        }
+        previous = current;
+    }
+
+    Some(previous[b.len()]).filter(|distance| *distance <= max)
+}
+
+/// How well `token` matches a field, from 0 (no match) to 1 (a whole word).
+fn match_field(token: &str, field: &str, words: &[String]) -> f32 {
+    let max_typos = match token.chars().count() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+
+    let mut best: f32 = 0.0;
+    for word in words {
+        let score = if word == token {
+            1.0
+        } else if word.starts_with(token) {
+            0.8
+        } else if max_typos > 0 && edit_distance(token, word, max_typos).is_some() {
+            0.4
+        } else {
+            0.0
+        };
+        best = best.max(score);
+    }
+
+    if best < 0.6 && field.contains(token) {
+        best = 0.6;
+    }
+    best
+}
+
+/// Relevance of a model for the words of a query, `None` if any word doesn't match.
+pub fn relevance(index: &ModelIndex, tokens: &[String]) -> Option<f32> {
+    if tokens.is_empty() {
+        return Some(0.0);
+    }
+
+    let fields = [
+        (index.name.to_lowercase(), 3.0),
+        (index.id.to_lowercase(), 2.0),
+        (index.architecture.to_lowercase(), 2.0),
+        (index.summary.to_lowercase(), 1.0),
+    ]
+    .map(|(field, weight)| {
+        let words = tokenize(&field);
+        (field, words, weight)
+    });
+
+    let mut total = 0.0;
+    for token in tokens {
+        let best = fields
+            .iter()
+            .map(|(field, words, weight)| weight * match_field(token, field, words))
+            .fold(0.0, f32::max);
+        if best == 0.0 {
+            return None;
+        }
+        total += best;
+    }
+    Some(total)
+}
+
+/// Parse a parameter count like `7B`, `1.5B`, `500M` or `8x7B`.
+pub fn parse_parameters(size: &str) -> Option<u64> {
+    let size = size.trim().to_ascii_uppercase();
+    let (experts, size) = match size.split_once('X') {
+        Some((experts, size)) => (experts.trim().parse::<f64>().ok()?, size.trim()),
+        None => (1.0, size.as_str()),
+    };
+
+    let (number, unit) = size.split_at(size.find(|c: char| c.is_ascii_alphabetic())?);
+    let unit = match unit.trim() {
+        "K" => 1e3,
+        "M" => 1e6,
+        "B" => 1e9,
+        "T" => 1e12,
+        _ => return None,
+    };
+
+    Some((experts * number.trim().parse::<f64>().ok()? * unit) as u64)
+}
+
+pub fn matches_index_filters(index: &ModelIndex, query: &SearchQuery) -> bool {
+    (query.model_types.is_empty()
+        || query
+            .model_types
+            .contains(&ModelType::from_catalog(&index.model_type)))
+        && (query.architectures.is_empty()
+            || query
+                .architectures
+                .iter()
+                .any(|architecture| architecture.eq_ignore_ascii_case(&index.architecture)))
+}
+
+pub fn matches_card_filters(entry: &IndexedCard, query: &SearchQuery) -> bool {
+    let card = &entry.card;
+    if query.min_parameters.is_some() || query.max_parameters.is_some() {
+        let Some(parameters) = entry.parameters else {
+            return false;
+        };
+        if query.min_parameters.is_some_and(|min| parameters < min)
+            || query.max_parameters.is_some_and(|max| parameters > max)
+        {
+            return false;
+        }
+    }
+
+    if !query.quantizations.is_empty()
+        && !card.files.iter().any(|file| {
+            query
+                .quantizations
+                .iter()
+                .any(|quantization| quantization.eq_ignore_ascii_case(&file.quantization))
+        })
+    {
+        return false;
+    }
+
+    if !query.tags.iter().all(|tag| {
+        card.files
+            .iter()
+            .any(|file| file.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+    }) {
+        return false;
+    }
+
+    if let Some(author) = &query.author {
+        if !card
+            .author
+            .name
+            .to_lowercase()
+            .contains(&author.trim().to_lowercase())
+        {
+            return false;
+        }
+    }
+
+    if let Some(max_file_size) = query.max_file_size {
+        if !card.files.iter().any(|file| {
+            file.size
+                .parse::<u64>()
+                .is_ok_and(|size| size <= max_file_size)
+        }) {
+            return false;
+        }
+    }
+
+    true
+}
+
+pub fn sort_candidates(candidates: &mut [Candidate], sort: SearchSort) {
+    let parameters = |c: &Candidate| c.entry.parameters;
+    let released_at = |c: &Candidate| c.entry.card.released_at;
+
+    candidates.sort_by(|a, b| {
+        let order = match sort {
+            SearchSort::Relevance => b
+                .score
+                .partial_cmp(&a.score)
+                .unwrap_or(Ordering::Equal)
+                .then(
+                    b.entry
+                        .index
+                        .download_count
+                        .cmp(&a.entry.index.download_count),
+                ),
+            SearchSort::MostDownloads => b
+                .entry
+                .index
+                .download_count
+                .cmp(&a.entry.index.download_count),
+            SearchSort::LeastDownloads => a
+                .entry
+                .index
+                .download_count
+                .cmp(&b.entry.index.download_count),
+            SearchSort::MostLikes => b.entry.index.like_count.cmp(&a.entry.index.like_count),
+            SearchSort::LeastLikes => a.entry.index.like_count.cmp(&b.entry.index.like_count),
+            SearchSort::Newest => released_at(b).cmp(&released_at(a)),
+            SearchSort::Oldest => released_at(a).cmp(&released_at(b)),
+            SearchSort::Largest => parameters(b).cmp(&parameters(a)),
+            // Models without a known size go last
+            SearchSort::Smallest => parameters(a)
+                .map(|p| (0, p))
+                .unwrap_or((1, 0))
+                .cmp(&parameters(b).map(|p| (0, p)).unwrap_or((1, 0))),
+        };
+        order.then_with(|| a.entry.index.id.cmp(&b.entry.index.id))
+    });
+}
+
+#[test]
+fn test_relevance() {
+    let index = |id: &str, name: &str, summary: &str| ModelIndex {
+        id: id.to_string(),
+        name: name.to_string(),
+        architecture: "llama".to_string(),
+        model_type: "chat".to_string(),
+        summary: summary.to_string(),
+        featured: false,
+        like_count: 0,
+        download_count: 0,
+    };
+
+    let tokens = tokenize("Llama 3.1 instruct");
+    assert_eq!(tokens, vec!["llama", "3.1", "instruct"]);
+
+    let exact = index("meta/Llama-3.1-8B-Instruct", "Llama-3.1-8B-Instruct", "");
+    let summary_only = index(
+        "other/Model-3.1",
+        "Model-3.1",
+        "An instruct model based on llama",
+    );
+    let unrelated = index("other/Phi-3", "Phi-3", "A small model");
+
+    let exact_score = relevance(&exact, &tokens).unwrap();
+    let summary_score = relevance(&summary_only, &tokens).unwrap();
+    assert!(exact_score > summary_score);
+    assert!(relevance(&unrelated, &tokens).is_none());
+
+    // One typo is fine for words of four letters or more
+    assert!(relevance(&exact, &tokenize("llamma")).is_some());
+    assert!(relevance(&exact, &tokenize("qwen")).is_none());
+
+    // "instruct" models are chat models
+    let query = SearchQuery {
+        model_types: vec![ModelType::Chat],
+        ..Default::default()
+    };
+    let mut embedding = index("nomic/nomic-embed-text", "nomic-embed-text", "");
+    embedding.model_type = "embedding".to_string();
+    assert!(!matches_index_filters(&embedding, &query));
+    embedding.model_type = "instruct".to_string();
+    assert!(matches_index_filters(&embedding, &query));
+
+    assert_eq!(parse_parameters("7B"), Some(7_000_000_000));
+    assert_eq!(parse_parameters("1.5B"), Some(1_500_000_000));
+    assert_eq!(parse_parameters("8x7B"), Some(56_000_000_000));
+    assert_eq!(parse_parameters("500M"), Some(500_000_000));
+    assert_eq!(parse_parameters("unknown"), None);
+}
@@ -0,0 +1,56 @@
+//! Settings owned by the backend, stored as JSON values by key.
+//!
+//! These are the ones needed before the frontend can send any command, like the
+//! region picking the model catalog.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+pub const REGION: &str = "region";
+pub const DOWNLOAD_MIRRORS: &str = "download_mirrors";
+pub const CATALOG_LAST_SYNC: &str = "catalog_last_sync";
+pub const CATALOG_SOURCES: &str = "catalog_sources";
+pub const EMBEDDING_MODEL: &str = "embedding_model";
+pub const ALLOW_UNVERIFIED_DOWNLOADS: &str = "allow_unverified_downloads";
+pub const ACCESS_TOKENS: &str = "access_tokens";
+pub const STORAGE_QUOTA: &str = "storage_quota";
+pub const CHAT_TEMPLATES: &str = "chat_templates";
+pub const AUTO_RELOAD_MODEL: &str = "auto_reload_model";
+
+pub fn get<T: DeserializeOwned>(
+    conn: &rusqlite::Connection,
+    key: &str,
+) -> anyhow::Result<Option<T>> {
+    let value = conn.query_row("SELECT value FROM settings WHERE key = ?1", [key], |row| {
+        row.get::<_, String>(0)
+    });
+
+    match value {
+        Ok(value) => Ok(Some(serde_json::from_str(&value)?)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn set<T: Serialize>(conn: &rusqlite::Connection, key: &str, value: &T) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
+        rusqlite::params![key, serde_json::to_string(value)?],
+    )?;
+    Ok(())
+}
+
+pub fn remove(conn: &rusqlite::Connection, key: &str) -> anyhow::Result<()> {
+    conn.execute("DELETE FROM settings WHERE key = ?1", [key])?;
+    Ok(())
+}
+
+pub fn create_table_settings(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );",
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use moly_protocol::data::UsageStats;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageKind {
+    Load,
+    Chat,
+}
+
+impl UsageKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            UsageKind::Load => "load",
+            UsageKind::Chat => "chat",
+        }
+    }
+}
+
+/// Something done with a downloaded file, kept to tell which models are used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageEvent {
+    pub file_id: String,
+    pub kind: UsageKind,
+    pub at: DateTime<Utc>,
+    /// Time to load the model, or to generate the completion tokens of a chat.
+    pub duration_ms: u64,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    /// The first message of a chat.
+    pub new_chat: bool,
+}
+
+impl UsageEvent {
+    pub fn insert_into_db(&self, conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+        conn.execute(
+            "INSERT INTO usage_events (file_id, kind, at, duration_ms, prompt_tokens, completion_tokens, new_chat)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            rusqlite::params![
+                self.file_id,
+                self.kind.as_str(),
+                self.at.to_rfc3339(),
+                self.duration_ms,
+                self.prompt_tokens,
+                self.completion_tokens,
+                self.new_chat,
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+/// The usage of a file, all its events summed up.
+pub fn get_stats(conn: &rusqlite::Connection, file_id: &str) -> rusqlite::Result<UsageStats> {
+    conn.query_row(
+        "SELECT
+            COALESCE(SUM(kind = 'load'), 0),
+            COALESCE(SUM(CASE WHEN kind = 'load' THEN duration_ms ELSE 0 END), 0),
+            MAX(at),
+            COALESCE(SUM(new_chat), 0),
+            COALESCE(SUM(kind = 'chat'), 0),
+            COALESCE(SUM(prompt_tokens), 0),
+            COALESCE(SUM(completion_tokens), 0),
+            COALESCE(SUM(CASE WHEN kind = 'chat' THEN duration_ms ELSE 0 END), 0)
+        FROM usage_events WHERE file_id = ?1",
+        [file_id],
+        |row| {
+            let last_used_at = row
+                .get::<_, Option<String>>(2)?
+                .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                .map(|date| date.to_utc());
+
+            Ok(UsageStats {
+                loads: row.get(0)?,
+                load_time_ms: row.get(1)?,
+                last_used_at,
+                chats_started: row.get(3)?,
+                completions: row.get(4)?,
+                prompt_tokens: row.get(5)?,
+                completion_tokens: row.get(6)?,
+                generation_time_ms: row.get(7)?,
+            })
+        },
+    )
+}
+
+pub fn create_table_usage_events(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS usage_events (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            file_id TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            at TEXT NOT NULL,
+            duration_ms INTEGER NOT NULL,
+            prompt_tokens INTEGER NOT NULL DEFAULT 0,
+            completion_tokens INTEGER NOT NULL DEFAULT 0,
+            new_chat INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE INDEX IF NOT EXISTS usage_events_file_id ON usage_events (file_id);",
+    )?;
+
+    Ok(())
+}
+
+#[test]
+fn test_sql() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    create_table_usage_events(&conn).unwrap();
+
+    let file_id = "TheBloke/Llama-2-7B-Chat-GGUF#llama-2-7b-chat.Q4_K_M.gguf";
+    assert_eq!(get_stats(&conn, file_id).unwrap(), UsageStats::default());
+
+    let at = DateTime::parse_from_rfc3339("2024-01-02T03:04:05Z")
+        .unwrap()
+        .to_utc();
+    let load = UsageEvent {
+        file_id: file_id.to_string(),
+        kind: UsageKind::Load,
+        at,
+        duration_ms: 3000,
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        new_chat: false,
+    };
+    load.insert_into_db(&conn).unwrap();
+
+    let chat = UsageEvent {
+        kind: UsageKind::Chat,
+        at: at + chrono::Duration::minutes(1),
+        duration_ms: 2000,
+        prompt_tokens: 30,
+        completion_tokens: 40,
+        new_chat: true,
+        ..load.clone()
+    };
+    chat.insert_into_db(&conn).unwrap();
+    UsageEvent {
+        new_chat: false,
+        ..chat.clone()
+    }
+    .insert_into_db(&conn)
+    .unwrap();
+
+    let stats = get_stats(&conn, file_id).unwrap();
+    assert_eq!(stats.loads, 1);
+    assert_eq!(stats.average_load_secs(), Some(3.0));
+    assert_eq!(stats.last_used_at, Some(chat.at));
+    assert_eq!(stats.chats_started, 1);
+    assert_eq!(stats.completions, 2);
+    assert_eq!(stats.prompt_tokens, 60);
+    assert_eq!(stats.completion_tokens, 80);
+    assert_eq!(stats.tokens_per_second(), Some(20.0));
+}
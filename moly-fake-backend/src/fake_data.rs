@@ -1,5 +1,5 @@
 use chrono::Utc;
-use moly_protocol::data::{Author, File, Model};
+use moly_protocol::data::{Author, CatalogTrust, File, Model, ModelType};
 
 pub fn get_models() -> Vec<Model> {
     let open_hermes_files = vec![
@@ -12,6 +12,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec![],
             featured: false,
+            parts: vec![],
         },
         File {
             id: "2".to_string(),
@@ -22,6 +23,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec![],
             featured: false,
+            parts: vec![],
         },
         File {
             id: "3".to_string(),
@@ -32,6 +34,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec![],
             featured: false,
+            parts: vec![],
         },
         File {
             id: "4".to_string(),
@@ -42,6 +45,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec![],
             featured: false,
+            parts: vec![],
         },
         File {
             id: "5".to_string(),
@@ -52,6 +56,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec![],
             featured: false,
+            parts: vec![],
         },
         File {
             id: "6".to_string(),
@@ -62,6 +67,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: Some("/home/user/.moly/stablelm-zephyr-3b.Q4_K_S.gguf".to_string()),
             tags: vec!["Small & Fast".to_string()],
             featured: true,
+            parts: vec![],
         },
         File {
             id: "7".to_string(),
@@ -72,6 +78,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec!["Less Compressed".to_string(), "Might be slower".to_string()],
             featured: true,
+            parts: vec![],
         },
     ];
 
@@ -85,6 +92,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec!["Small & Fast".to_string()],
             featured: true,
+            parts: vec![],
         },
         File {
             id: "9".to_string(),
@@ -95,6 +103,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: Some("/home/user/.moly/nexusraven-v2-13b.Q6_K.gguf".to_string()),
             tags: vec!["Less Compressed".to_string(), "Might be slower".to_string()],
             featured: true,
+            parts: vec![],
         },
     ];
 
@@ -108,6 +117,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: Some("/home/user/.moly/nexusraven-v2-13b.Q4_K_S.gguf".to_string()),
             tags: vec!["Small & Fast".to_string()],
             featured: true,
+            parts: vec![],
         },
         File {
             id: "11".to_string(),
@@ -118,6 +128,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec!["Less Compressed".to_string(), "Might be slower".to_string()],
             featured: true,
+            parts: vec![],
         },
     ];
 
@@ -131,6 +142,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec!["Less Compressed".to_string(), "Might be slower".to_string()],
             featured: true,
+            parts: vec![],
         },
         File {
             id: "TheBloke/Llama-2-7B-Chat-GGUF#llama-2-7b-chat.Q2_K.gguf".to_string(),
@@ -141,6 +153,7 @@ pub fn get_models() -> Vec<Model> {
             downloaded_path: None,
             tags: vec!["Less Compressed".to_string(), "Might be slower".to_string()],
             featured: true,
+            parts: vec![],
         },
     ];
 
@@ -153,6 +166,7 @@ pub fn get_models() -> Vec<Model> {
             requires: "8GB+ RAM".to_string(),
             released_at: Utc::now(),
             architecture: "Mistral".to_string(),
+            model_type: ModelType::Chat,
             files: open_hermes_files,
             author: Author {
                 name: "Teknium".to_string(),
@@ -162,6 +176,8 @@ pub fn get_models() -> Vec<Model> {
             like_count: 100,
             download_count: 503,
             metrics: Default::default(),
+            trust: CatalogTrust::Verified,
+            license: Default::default(),
         },
         Model {
             id: "Nexusflow/NexusRaven-V2-13B".to_string(),
@@ -170,6 +186,7 @@ pub fn get_models() -> Vec<Model> {
             size: "13B".to_string(),
             requires: "16GB+ RAM".to_string(),
             architecture: "LLaMa".to_string(),
+            model_type: ModelType::Chat,
             released_at: Utc::now(),
             files: nexus_raven_files,
             author: Author {
@@ -180,6 +197,8 @@ pub fn get_models() -> Vec<Model> {
             like_count: 14,
             download_count: 2003,
             metrics: Default::default(),
+            trust: CatalogTrust::Verified,
+            license: Default::default(),
         },
         Model {
             id: "stabilityai/stablelm-zephyr-3b".to_string(),
@@ -189,6 +208,7 @@ pub fn get_models() -> Vec<Model> {
             requires: "8GB+ RAM".to_string(),
             released_at: Utc::now(),
             architecture: "StableLM".to_string(),
+            model_type: ModelType::Chat,
             files: stable_lm_files,
             author: Author {
                 name: "Stability AI".to_string(),
@@ -198,6 +218,8 @@ pub fn get_models() -> Vec<Model> {
             like_count: 160,
             download_count: 5003,
             metrics: Default::default(),
+            trust: CatalogTrust::Verified,
+            license: Default::default(),
         },
         Model {
             id: "Qwen/Qwen1.5-7B-Chat-GGUF".to_string(),
@@ -207,6 +229,7 @@ pub fn get_models() -> Vec<Model> {
             requires: "8GB+ RAM".to_string(),
             released_at: Utc::now(),
             architecture: "qwen2".to_string(),
+            model_type: ModelType::Chat,
             files: qwen_files,
             author: Author {
                 name: "Qwen Team, Alibaba Group".to_string(),
@@ -216,6 +239,8 @@ pub fn get_models() -> Vec<Model> {
             like_count: 98,
             download_count: 903,
             metrics: Default::default(),
+            trust: CatalogTrust::Verified,
+            license: Default::default(),
         },
     ]
 }
@@ -1,5 +1,6 @@
 pub mod fake_data;
 
+use moly_protocol::data::{ModelCollection, SearchResults};
 use moly_protocol::protocol::Command;
 use std::sync::mpsc;
 
@@ -27,13 +28,31 @@ impl Backend {
                             tx.send(Ok(models)).unwrap();
                             //tx.send(Err(anyhow!("Database query failed"))).unwrap();
                         }
+                        Command::GetFeaturedCollections(tx) => {
+                            let collection = ModelCollection {
+                                id: ModelCollection::FEATURED_ID.to_string(),
+                                name: "Featured".to_string(),
+                                models: fake_data::get_models(),
+                            };
+                            tx.send(Ok(vec![collection])).unwrap();
+                        }
                         Command::SearchModels(query, tx) => {
                             let models = fake_data::get_models();
-                            let filtered = models
+                            let filtered: Vec<_> = models
+                                .into_iter()
+                                .filter(|model| {
+                                    model.name.contains(&query.text)
+                                        && (query.model_types.is_empty()
+                                            || query.model_types.contains(&model.model_type))
+                                })
+                                .collect();
+                            let total = filtered.len();
+                            let models = filtered
                                 .into_iter()
-                                .filter(|model| model.name.contains(&query))
+                                .skip(query.offset)
+                                .take(query.limit)
                                 .collect();
-                            tx.send(Ok(filtered)).unwrap();
+                            tx.send(Ok(SearchResults { models, total })).unwrap();
                         }
                         _ => {}
                     }
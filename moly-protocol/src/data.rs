@@ -19,6 +19,10 @@ pub struct File {
     pub tags: Vec<String>,
     #[serde(default)]
     pub featured: bool,
+    // Shard file names when the model is split in several GGUF files, the first
+    // one is `name`. Empty for single file models.
+    #[serde(default)]
+    pub parts: Vec<String>,
 }
 
 #[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
@@ -28,6 +32,63 @@ pub struct Author {
     pub description: String,
 }
 
+/// What a model is meant for, as categorized by the model catalog.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ModelType {
+    #[default]
+    Chat,
+    Embedding,
+    Code,
+    Vision,
+    // Any other category of the catalog, as it is named there
+    Other(String),
+}
+
+impl ModelType {
+    // Categories that can be browsed in the catalog
+    pub const CATEGORIES: [ModelType; 4] = [
+        ModelType::Chat,
+        ModelType::Embedding,
+        ModelType::Code,
+        ModelType::Vision,
+    ];
+
+    pub fn from_catalog(model_type: &str) -> Self {
+        match model_type.trim().to_lowercase().as_str() {
+            "chat" | "instruct" => ModelType::Chat,
+            "embedding" | "embeddings" => ModelType::Embedding,
+            "code" | "code-completion" | "completion" => ModelType::Code,
+            "vision" | "multimodal" => ModelType::Vision,
+            other => ModelType::Other(other.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            ModelType::Chat => "chat",
+            ModelType::Embedding => "embedding",
+            ModelType::Code => "code",
+            ModelType::Vision => "vision",
+            ModelType::Other(other) => other,
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        match self {
+            ModelType::Chat => "Chat",
+            ModelType::Embedding => "Embedding",
+            ModelType::Code => "Code",
+            ModelType::Vision => "Vision",
+            ModelType::Other(_) => "Other",
+        }
+    }
+
+    /// Whether the model can be picked to chat with.
+    pub fn is_chat(&self) -> bool {
+        matches!(self, ModelType::Chat)
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub enum CompatibilityGuess {
     #[default]
@@ -51,23 +112,398 @@ pub struct DownloadedFile {
     pub downloaded_at: DateTime<Utc>,
     pub compatibility_guess: CompatibilityGuess,
     pub information: String,
+    // The catalog lists another revision of the file since it was downloaded
+    pub update_available: bool,
+    // Never removed by the storage cleanup
+    pub pinned: bool,
 }
 
 #[derive(Clone, Debug, Default)]
 pub enum PendingDownloadsStatus {
     #[default]
     Initializing,
+    // Waiting in the download queue for a free slot
+    Queued,
     Downloading,
     Paused,
     Error,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DownloadPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct PendingDownload {
     pub file: File,
     pub model: Model,
     pub progress: f64,
     pub status: PendingDownloadsStatus,
+    // Position in the download queue, only set while the download is queued
+    pub queue_position: Option<usize>,
+    pub priority: DownloadPriority,
+    // Speed limit of this download alone, in bytes per second
+    pub bandwidth_limit: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DownloadSourceSettings {
+    // Mirrors tried in order before the catalog download urls. Each one is either a
+    // base url like `HF_ENDPOINT` or a template using `{model_id}` and `{file}`.
+    pub mirrors: Vec<String>,
+    // Region picking the catalog download urls, `None` detects it from the IP address
+    pub region: Option<String>,
+    // Region currently in use, either the configured or the detected one
+    pub active_region: String,
+    // Download files of models whose card could not be verified
+    pub allow_unverified: bool,
+    // Hosts with an access token, the tokens themselves stay in the backend
+    pub access_token_hosts: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CatalogSyncStatus {
+    // A refresh of the model catalog is running in the background
+    pub syncing: bool,
+    // Last time the catalog was successfully refreshed
+    pub last_sync: Option<DateTime<Utc>>,
+    // Error of the last refresh, if it failed. The catalog on disk is still in use.
+    pub last_error: Option<String>,
+    // Sources whose index has no valid signature from a trusted key
+    pub untrusted_sources: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CatalogTrust {
+    // Signed by one of the trusted keys of its source
+    Verified,
+    // Not signed, or its source has no trusted keys
+    #[default]
+    Unsigned,
+    // The signature does not match, the file may have been tampered with
+    Invalid,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FileUsage {
+    pub file_id: FileID,
+    pub model_id: ModelID,
+    pub model_name: String,
+    pub name: String,
+    pub quantization: String,
+    // Bytes taken on disk, all the shards of a split model together
+    pub size: u64,
+    pub downloaded_at: DateTime<Utc>,
+    // `None` when the file was never loaded
+    pub last_loaded_at: Option<DateTime<Utc>>,
+    // Never removed by the storage cleanup
+    pub pinned: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StorageQuota {
+    // Bytes the downloaded files may take, `None` for no quota
+    pub limit: Option<u64>,
+    // Remove the least recently used files when over the quota, instead of only
+    // proposing to
+    pub auto_cleanup: bool,
+}
+
+/// Files to remove to get back under the storage quota.
+#[derive(Clone, Debug, Default)]
+pub struct CleanupPlan {
+    // Least recently used first
+    pub files: Vec<FileUsage>,
+    // Bytes the downloaded files take
+    pub used: u64,
+    pub limit: u64,
+    // The quota is set to remove the files without asking
+    pub auto_cleanup: bool,
+}
+
+impl CleanupPlan {
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// Whether removing the files is not enough to get under the quota, the rest is
+    /// pinned or in use.
+    pub fn is_partial(&self) -> bool {
+        self.used.saturating_sub(self.size()) > self.limit
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct StorageUsage {
+    // Downloaded files, largest first
+    pub files: Vec<FileUsage>,
+    // Free bytes on the volume of the models directory
+    pub available: u64,
+    // Bytes set aside for the downloads queued or running
+    pub reserved: u64,
+    pub quota: StorageQuota,
+}
+
+impl StorageUsage {
+    pub fn total(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+
+    /// Disk use of each model by name, largest first.
+    pub fn by_model(&self) -> Vec<(String, u64)> {
+        Self::group_by(&self.files, |file| &file.model_name)
+    }
+
+    /// Disk use of each quantization, largest first.
+    pub fn by_quantization(&self) -> Vec<(String, u64)> {
+        Self::group_by(&self.files, |file| &file.quantization)
+    }
+
+    fn group_by(files: &[FileUsage], key: impl Fn(&FileUsage) -> &String) -> Vec<(String, u64)> {
+        let mut groups: Vec<(String, u64)> = vec![];
+        for file in files {
+            match groups.iter_mut().find(|(name, _)| name == key(file)) {
+                Some((_, size)) => *size += file.size,
+                None => groups.push((key(file).clone(), file.size)),
+            }
+        }
+        groups.sort_by_key(|group| std::cmp::Reverse(group.1));
+        groups
+    }
+}
+
+/// Load settings saved under a name for a downloaded file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadPreset {
+    pub name: String,
+    // Context size, `None` for the one of the model
+    pub n_ctx: Option<u32>,
+    pub n_batch: Option<u32>,
+    // Layers offloaded to the GPU, `None` for all of them
+    pub gpu_layers: Option<u32>,
+    // Keep the model in RAM, instead of letting the OS swap it out
+    pub use_mlock: bool,
+    // Like `chatml`, `None` for the one of the model
+    pub prompt_template: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadPresets {
+    // By name
+    pub presets: Vec<LoadPreset>,
+    // The preset used to load the file, the defaults when `None`
+    pub selected: Option<String>,
+}
+
+impl LoadPresets {
+    pub fn selected_preset(&self) -> Option<&LoadPreset> {
+        let selected = self.selected.as_ref()?;
+        self.presets.iter().find(|preset| preset.name == *selected)
+    }
+}
+
+/// How the messages of a chat are turned into the prompt of a downloaded file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum ChatTemplateSource {
+    // The server formats them with the prompt template name of the model card
+    #[default]
+    Server,
+    // The Jinja template embedded in the GGUF file, `tokenizer.chat_template`
+    Embedded,
+    // A Jinja template written by the user
+    Custom(String),
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChatTemplateSettings {
+    pub source: ChatTemplateSource,
+    // The template embedded in the file, if it has one
+    pub embedded: Option<String>,
+}
+
+/// How much a downloaded file was used, summed from the usage recorded by the backend.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UsageStats {
+    pub loads: u32,
+    // Time spent loading the model, all the loads together
+    pub load_time_ms: u64,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub chats_started: u32,
+    // Answers generated, a chat has one for each message sent
+    pub completions: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    // Time spent generating the completion tokens
+    pub generation_time_ms: u64,
+}
+
+impl UsageStats {
+    pub fn average_load_secs(&self) -> Option<f64> {
+        (self.loads > 0).then(|| self.load_time_ms as f64 / 1000.0 / self.loads as f64)
+    }
+
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        (self.generation_time_ms > 0)
+            .then(|| self.completion_tokens as f64 * 1000.0 / self.generation_time_ms as f64)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ModelLicense {
+    // Like `apache-2.0`, empty when the catalog does not say
+    pub id: String,
+    pub url: String,
+    // The terms must be accepted before downloading the model
+    pub requires_acceptance: bool,
+    // When the current license was accepted, acceptances of another license don't count
+    pub accepted_at: Option<DateTime<Utc>>,
+}
+
+impl ModelLicense {
+    pub fn needs_acceptance(&self) -> bool {
+        self.requires_acceptance && self.accepted_at.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CatalogSourceKind {
+    // A repository laid out like the public model-cards repo
+    #[default]
+    Git,
+    // An `index.json` url, with the model cards next to it. Only the files that
+    // changed since the last sync are downloaded, no git clone is needed.
+    Http,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CatalogSource {
+    // Short name of the source, the model ids of the source are prefixed with it
+    pub name: String,
+    pub kind: CatalogSourceKind,
+    // Repository or index url. Empty for the public catalog picks the one for the region.
+    #[serde(default)]
+    pub url: String,
+    // Commit or tag to use instead of the latest catalog, git sources only
+    #[serde(default)]
+    pub pinned: Option<String>,
+    // Base64 ed25519 keys trusted to sign this source, on top of the bundled ones
+    #[serde(default)]
+    pub public_keys: Vec<String>,
+}
+
+impl CatalogSource {
+    // The built-in catalog, its model ids are not prefixed so they keep matching
+    // the files downloaded before there were other sources
+    pub const PUBLIC: &'static str = "public";
+
+    pub fn public() -> Self {
+        Self {
+            name: Self::PUBLIC.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.name == Self::PUBLIC
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SearchSort {
+    // Best matches first, most downloaded first among equally good matches
+    #[default]
+    Relevance,
+    MostDownloads,
+    LeastDownloads,
+    MostLikes,
+    LeastLikes,
+    Newest,
+    Oldest,
+    // By number of parameters
+    Largest,
+    Smallest,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchQuery {
+    // Free text, split in words that must all match, allowing for small typos.
+    // An empty text matches every model.
+    pub text: String,
+    // Filters, an empty list or `None` doesn't filter anything
+    pub model_types: Vec<ModelType>,
+    pub architectures: Vec<String>,
+    // Number of parameters, as in the model size (e.g. "7B")
+    pub min_parameters: Option<u64>,
+    pub max_parameters: Option<u64>,
+    // Models with at least one file in any of these quantizations
+    pub quantizations: Vec<String>,
+    // Models with files having all these tags
+    pub tags: Vec<String>,
+    pub author: Option<String>,
+    // Models with at least one file of at most this size, in bytes
+    pub max_file_size: Option<u64>,
+    pub sort: SearchSort,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl SearchQuery {
+    pub const DEFAULT_LIMIT: usize = 100;
+
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            model_types: vec![],
+            architectures: vec![],
+            min_parameters: None,
+            max_parameters: None,
+            quantizations: vec![],
+            tags: vec![],
+            author: None,
+            max_file_size: None,
+            sort: SearchSort::default(),
+            offset: 0,
+            limit: Self::DEFAULT_LIMIT,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchResults {
+    // The requested page of results
+    pub models: Vec<Model>,
+    // Number of models matching the query, across all pages
+    pub total: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ModelCollection {
+    pub id: String,
+    pub name: String,
+    // In the order picked by the catalog
+    pub models: Vec<Model>,
+}
+
+impl ModelCollection {
+    // The id of the collection sent when the catalog has none, holding the featured models
+    pub const FEATURED_ID: &'static str = "featured";
+
+    pub fn is_featured_fallback(&self) -> bool {
+        self.id == Self::FEATURED_ID
+    }
 }
 
 // We're using the HuggingFace identifier as the model ID for now
@@ -81,10 +517,17 @@ pub struct Model {
     pub size: String,
     pub requires: String,
     pub architecture: String,
+    #[serde(default)]
+    pub model_type: ModelType,
     pub released_at: DateTime<Utc>,
     pub files: Vec<File>,
     pub author: Author,
     pub like_count: u32,
     pub download_count: u32,
     pub metrics: HashMap<String, f32>,
+    // Whether the card of the model is signed by its catalog
+    #[serde(default)]
+    pub trust: CatalogTrust,
+    #[serde(default)]
+    pub license: ModelLicense,
 }
@@ -138,6 +138,9 @@ pub struct ChatResponseChunkData {
     pub created: u32,
     pub model: ModelID,
     pub system_fingerprint: String,
+    // Only sent with the last chunk, and only by some servers
+    #[serde(default)]
+    pub usage: Option<UsageData>,
 
     #[serde(default = "response_chunk_object")]
     pub object: String,
@@ -1,29 +1,113 @@
 use crate::data::*;
 use crate::open_ai::*;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 
+/// A download refused by the server, usually because the model is gated behind a
+/// license that has to be accepted with an account whose access token is sent.
+#[derive(Clone, Debug)]
+pub struct DownloadAccessError {
+    pub host: String,
+    pub status: u16,
+    // An access token for the host was sent along the request
+    pub with_token: bool,
+}
+
+impl std::fmt::Display for DownloadAccessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.with_token {
+            write!(
+                f,
+                "{} refused the access token ({}). This model requires accepting its license with the account of the token.",
+                self.host, self.status
+            )
+        } else {
+            write!(
+                f,
+                "{} refused the download ({}). This model requires accepting its license and an access token for {}.",
+                self.host, self.status, self.host
+            )
+        }
+    }
+}
+
+impl std::error::Error for DownloadAccessError {}
+
+/// Not enough free space on the models volume for a download, checked before it is
+/// queued and while it runs.
+#[derive(Clone, Debug)]
+pub struct InsufficientSpaceError {
+    // Bytes the download still has to write
+    pub needed: u64,
+    // Free bytes left once the other downloads get the space set aside for them
+    pub available: u64,
+}
+
+impl std::fmt::Display for InsufficientSpaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+        write!(
+            f,
+            "Not enough disk space: the download needs {:.2} GB but only {:.2} GB are free. Remove some models and try again.",
+            self.needed as f64 / BYTES_PER_GB,
+            self.available as f64 / BYTES_PER_GB
+        )
+    }
+}
+
+impl std::error::Error for InsufficientSpaceError {}
+
+/// A download refused because the catalog entry of the model can't be trusted,
+/// retrying doesn't help until the catalog or the settings change.
+#[derive(Clone, Debug)]
+pub struct CatalogTrustError {
+    pub model_id: ModelID,
+    pub trust: CatalogTrust,
+}
+
+impl std::fmt::Display for CatalogTrustError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.trust {
+            CatalogTrust::Invalid => write!(
+                f,
+                "The catalog signature of {} is invalid, refusing to download it",
+                self.model_id
+            ),
+            _ => write!(
+                f,
+                "The catalog entry of {} is not signed by a trusted key, allow unverified downloads in the settings to download it",
+                self.model_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CatalogTrustError {}
+
 #[derive(Clone, Debug)]
 pub enum FileDownloadResponse {
+    // The download is waiting in the queue until a download slot is free
+    Queued(FileID),
     Progress(FileID, f32),
     Completed(DownloadedFile),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ContextOverflowPolicy {
     StopAtLimit,
     TruncateMiddle,
     TruncatePastMessages,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum GPULayers {
     Specific(u32),
     Max,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct LoadModelOptions {
     pub override_server_address: Option<String>,
     pub prompt_template: Option<String>,
@@ -79,28 +163,146 @@ pub enum LocalServerResponse {
     Log(String),
 }
 
+/// Something that happened in the backend, sent to every subscriber whatever
+/// command started it.
+#[derive(Clone, Debug)]
+pub enum BackendEvent {
+    DownloadQueued(FileID),
+    DownloadProgress(FileID, f32),
+    DownloadCompleted(Box<DownloadedFile>),
+    DownloadPaused(FileID),
+    DownloadCancelled(FileID),
+    DownloadFailed(FileID, String),
+    ModelLoaded(LoadedModelInfo),
+    ModelLoadFailed(FileID, String),
+    ModelEjected(FileID),
+    // The loaded model stopped without being ejected, with the reason
+    ModelCrashed(FileID, String),
+    CatalogUpdated(CatalogSyncStatus),
+    // A line about the model server, like it starting or exiting
+    ServerLog(String),
+}
+
 #[derive(Clone, Debug)]
 pub enum Command {
+    // Receive every backend event from now on, until the receiver is dropped
+    Subscribe(Sender<BackendEvent>),
+    // The model currently loaded, `None` when there is none or its load failed
+    GetLoadedModel(Sender<Result<Option<LoadedModelInfo>>>),
+
     GetFeaturedModels(Sender<Result<Vec<Model>>>),
+    // The curated collections of the catalog, or a single one with the featured
+    // models when it defines none
+    GetFeaturedCollections(Sender<Result<Vec<ModelCollection>>>),
+
+    GetCatalogSyncStatus(Sender<Result<CatalogSyncStatus>>),
+    // Receive the sync status every time a catalog refresh finishes, successful or not
+    SubscribeCatalogUpdates(Sender<CatalogSyncStatus>),
+    // Refresh the model catalog in the background, nothing happens if it is already running
+    RefreshCatalog(Sender<Result<()>>),
+    // The catalog sources, highest priority first
+    GetCatalogSources(Sender<Result<Vec<CatalogSource>>>),
+    // Replace the catalog sources and refresh the catalog with them
+    SetCatalogSources(Vec<CatalogSource>, Sender<Result<()>>),
 
     // Change DowanloadFiles Location
     ChangeModelsDir(PathBuf),
 
-    // The argument is a string with the keywords to search for.
-    SearchModels(String, Sender<Result<Vec<Model>>>),
+    SearchModels(SearchQuery, Sender<Result<SearchResults>>),
 
+    // Record that the user accepted the license of a model, required before downloading
+    // models whose license asks for it. Answers with the time of the acceptance.
+    AcceptModelLicense(ModelID, Sender<Result<DateTime<Utc>>>),
     DownloadFile(FileID, Sender<Result<FileDownloadResponse>>),
+    // Download the new revision of an outdated file next to it, the downloaded file
+    // is replaced once the new one is verified
+    UpdateFile(FileID, Sender<Result<FileDownloadResponse>>),
     PauseDownload(FileID, Sender<Result<()>>),
     CancelDownload(FileID, Sender<Result<()>>),
     DeleteFile(FileID, Sender<Result<()>>),
 
+    // Limit the speed of all downloads together, in bytes per second. `None` removes the limit.
+    SetDownloadBandwidthLimit(Option<u64>, Sender<Result<()>>),
+    // Limit the speed of a single download, in bytes per second. `None` removes the limit.
+    SetFileBandwidthLimit(FileID, Option<u64>, Sender<Result<()>>),
+
+    // Downloads start by priority. When every slot is taken, a running download is
+    // paused for a queued one with a higher priority and resumes once a slot is free.
+    SetDownloadPriority(FileID, DownloadPriority, Sender<Result<()>>),
+    // Move the given queued downloads to the front of their priority, in the given order
+    ReorderDownloadQueue(Vec<FileID>, Sender<Result<()>>),
+    // Make a queued download the next one to start, without pausing a running one
+    StartDownloadNext(FileID, Sender<Result<()>>),
+
+    GetDownloadSourceSettings(Sender<Result<DownloadSourceSettings>>),
+    // Replace the ordered list of download mirrors
+    SetDownloadMirrors(Vec<String>, Sender<Result<()>>),
+    // Use an explicit region instead of the IP lookup. `None` goes back to the lookup,
+    // the model catalog picks up a region change on the next start.
+    SetRegion(Option<String>, Sender<Result<()>>),
+    // Store the access token sent as bearer auth to a host, like `huggingface.co`.
+    // `None` removes it.
+    SetAccessToken(String, Option<String>, Sender<Result<()>>),
+    // Allow downloading files of models whose card could not be verified
+    SetAllowUnverifiedDownloads(bool, Sender<Result<()>>),
+
     GetCurrentDownloads(Sender<Result<Vec<PendingDownload>>>),
     GetDownloadedFiles(Sender<Result<Vec<DownloadedFile>>>),
+    // Disk use of the downloaded files and the free space of the models volume
+    GetStorageUsage(Sender<Result<StorageUsage>>),
+    SetStorageQuota(StorageQuota, Sender<Result<()>>),
+    // Keep a file out of the storage cleanup
+    SetFilePinned(FileID, bool, Sender<Result<()>>),
+    // The least recently used files to remove to get under the storage quota, `None`
+    // when under it. The given files, like the loaded model and the ones of recent
+    // chats, are left out along the pinned ones and the embedding model.
+    PlanStorageCleanup(Vec<FileID>, Sender<Result<Option<CleanupPlan>>>),
+    // Loads, chats and tokens generated with a downloaded file
+    GetUsageStats(FileID, Sender<Result<UsageStats>>),
+
+    // Downloaded embedding model served along the chat models, `None` when it is
+    // the default one of the catalog
+    GetEmbeddingModel(Sender<Result<Option<FileID>>>),
+    // Serve a downloaded embedding model instead of the default one, from the next
+    // model load on. `None` goes back to the default.
+    SetEmbeddingModel(Option<FileID>, Sender<Result<()>>),
+    // The embedding model used when none is picked, the first one of the public
+    // catalog. Only its default file is listed, it has to be downloaded like any
+    // other file before it is served.
+    GetDefaultEmbeddingModel(Sender<Result<Option<Model>>>),
+
+    // Named load settings of a downloaded file, with the one in use
+    GetLoadPresets(FileID, Sender<Result<LoadPresets>>),
+    // Add a preset, or replace the one with the same name
+    SaveLoadPreset(FileID, LoadPreset, Sender<Result<()>>),
+    RemoveLoadPreset(FileID, String, Sender<Result<()>>),
+    // Load the file with a preset from now on, `None` goes back to the defaults.
+    // `LoadModel` is sent with the options of the preset, the backend doesn't
+    // apply it on its own.
+    SelectLoadPreset(FileID, Option<String>, Sender<Result<()>>),
+
+    // How chats with a downloaded file are formatted, with its embedded template
+    GetChatTemplate(FileID, Sender<Result<ChatTemplateSettings>>),
+    // Format chats with the file using another template, applied to the loaded model
+    // right away. Templates that fail to render are refused.
+    SetChatTemplate(FileID, ChatTemplateSource, Sender<Result<()>>),
+    // The raw prompt a template renders for the messages. `Server` has nothing to
+    // show, the server formats the prompt on its side.
+    PreviewChatTemplate(
+        FileID,
+        ChatTemplateSource,
+        Vec<Message>,
+        Sender<Result<String>>,
+    ),
 
     LoadModel(FileID, LoadModelOptions, Sender<Result<LoadModelResponse>>),
 
     // Eject currently loaded model, if any is provided
     EjectModel(Sender<Result<()>>),
+    // Whether a model that crashed is loaded again, after a delay growing with
+    // each crash
+    GetAutoReloadModel(Sender<Result<bool>>),
+    SetAutoReloadModel(bool, Sender<Result<()>>),
 
     Chat(ChatRequestData, Sender<Result<ChatResponse>>),
     StopChatCompletion(Sender<Result<()>>),
@@ -109,4 +311,9 @@ pub enum Command {
     StartLocalServer(LocalServerConfig, Sender<Result<LocalServerResponse>>),
     // Command to stop the local server
     StopLocalServer(Sender<Result<()>>),
+
+    // Stop the backend before the app exits. Downloads are paused with their progress
+    // saved, the model is ejected and the database flushed before the answer. No
+    // command is handled after it.
+    Shutdown(Sender<Result<()>>),
 }
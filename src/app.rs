@@ -5,7 +5,7 @@ use crate::data::downloads::download::DownloadFileAction;
 use crate::data::downloads::DownloadPendingNotification;
 use crate::data::store::*;
 use crate::landing::model_files_item::ModelFileItemAction;
-use crate::shared::actions::{ChatAction, DownloadAction};
+use crate::shared::actions::{ChatAction, DownloadAction, EmbeddingAction, StorageAction};
 use crate::shared::download_notification_popup::{
     DownloadNotificationPopupAction, DownloadNotificationPopupRef, DownloadNotificationPopupWidgetRefExt, DownloadResult, PopupAction
 };
@@ -169,7 +169,11 @@ impl LiveRegister for App {
 
 impl AppMain for App {
     fn handle_event(&mut self, cx: &mut Cx, event: &Event) {
-        
+        // Leave the downloads and the model in a clean state before the app exits
+        if let Event::WindowCloseRequested(_) = event {
+            self.store.shutdown();
+        }
+
         // It triggers when the timer expires.
         if self.timer.is_event(event).is_some() {
             if let Some(file_id) = &self.file_id {
@@ -227,6 +231,15 @@ impl MatchEvent for App {
                 StoreAction::Sort(criteria) => {
                     self.store.search.sort_models(criteria);
                 }
+                StoreAction::SetCategory(category) => {
+                    self.store.search.set_category(category);
+                }
+                StoreAction::AcceptModelLicense(model_id) => {
+                    if let Err(err) = self.store.search.accept_model_license(&model_id) {
+                        eprintln!("Error accepting the model license: {:?}", err);
+                    }
+                    self.ui.redraw(cx);
+                }
                 _ => {}
             }
 
@@ -253,9 +266,35 @@ impl MatchEvent for App {
                     self.store.downloads.cancel_download_file(&file_id);
                     self.ui.redraw(cx);
                 }
+                DownloadAction::StartNext(file_id) => {
+                    self.store.downloads.start_download_next(&file_id);
+                    self.ui.redraw(cx);
+                }
+                DownloadAction::SetBandwidthLimit(file_id, bytes_per_sec) => {
+                    self.store
+                        .downloads
+                        .set_file_bandwidth_limit(&file_id, bytes_per_sec);
+                    self.ui.redraw(cx);
+                }
+                DownloadAction::Update(file_id) => {
+                    self.store.downloads.update_file(&file_id);
+                    self.ui.redraw(cx);
+                }
                 _ => {}
             }
 
+            if let EmbeddingAction::Use(file_id) = action.cast() {
+                self.store.downloads.set_embedding_model(file_id);
+                self.ui.redraw(cx);
+            }
+
+            if let StorageAction::SetPinned(file_id, pinned) = action.cast() {
+                if let Err(err) = self.store.set_file_pinned(&file_id, pinned) {
+                    eprintln!("Error pinning file: {:?}", err);
+                }
+                self.ui.redraw(cx);
+            }
+
             if let ChatAction::Start(_) = action.cast() {
                 let chat_radio_button = self.ui.radio_button(id!(chat_tab));
                 chat_radio_button.select(cx, &mut Scope::empty());
@@ -304,6 +343,11 @@ impl App {
                     self.file_id = Some((file.id).clone());
                     self.start_retry_timeout(cx, popup, file);
                 }
+                // Retrying can't help until the license is accepted, a token is added
+                // or disk space is freed
+                DownloadPendingNotification::Refused(file, message) => {
+                    popup.set_data(cx, &file, DownloadResult::Refused(message));
+                }
             }
 
             self.ui.popup_notification(id!(popup_notification)).open(cx);
@@ -101,7 +101,7 @@ impl WidgetMatchEvent for ModelSelectorList {
 
 impl ModelSelectorList {
     fn draw_items(&mut self, cx: &mut Cx2d, store: &Store) {
-        let mut models = store.downloads.downloaded_files.clone();
+        let mut models: Vec<_> = store.downloads.chat_files().cloned().collect();
         models.sort_by(|a, b| b.downloaded_at.cmp(&a.downloaded_at));
 
         self.map_to_downloaded_files = HashMap::new();
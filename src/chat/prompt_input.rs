@@ -263,8 +263,7 @@ impl WidgetMatchEvent for PromptInput {
 
             for (idx, file) in store
                 .downloads
-                .downloaded_files
-                .iter()
+                .chat_files()
                 .map(|f| &f.file)
                 .filter(|f| terms.iter().all(|t| f.name.to_lowercase().contains(t)))
                 .enumerate()
@@ -68,12 +68,15 @@ pub struct Chats {
 
 impl Chats {
     pub fn new(backend: Rc<Backend>) -> Self {
+        let model_loader = ModelLoader::new();
+        model_loader.watch_backend_events(&backend.as_ref().command_sender);
+
         Self {
             backend,
             saved_chats: Vec::new(),
             current_chat_id: None,
             loaded_model: None,
-            model_loader: ModelLoader::new(),
+            model_loader,
             chats_dir: setup_chats_folder(),
             override_port: None,
             mofa_servers: HashMap::new(),
@@ -190,6 +193,31 @@ impl Chats {
         Ok(())
     }
 
+    /// Whether the backend loads the model again when it crashes.
+    pub fn get_auto_reload_model(&self) -> Result<bool> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetAutoReloadModel(tx))
+            .context("Failed to send get auto reload command")?;
+
+        rx.recv()
+            .context("Failed to receive get auto reload response")?
+    }
+
+    pub fn set_auto_reload_model(&self, enabled: bool) -> Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetAutoReloadModel(enabled, tx))
+            .context("Failed to send set auto reload command")?;
+
+        rx.recv()
+            .context("Failed to receive set auto reload response")?
+    }
+
     pub fn remove_file_from_associated_entity(&mut self, file_id: &FileID) {
         for chat in &self.saved_chats {
             let mut chat = chat.borrow_mut();
@@ -1,8 +1,10 @@
 use anyhow::anyhow;
 use makepad_widgets::Cx;
 use moly_protocol::{
-    data::FileID,
-    protocol::{Command, LoadModelOptions, LoadModelResponse, LoadedModelInfo},
+    data::{FileID, LoadPreset},
+    protocol::{
+        BackendEvent, Command, GPULayers, LoadModelOptions, LoadModelResponse, LoadedModelInfo,
+    },
 };
 use std::{
     sync::{
@@ -30,6 +32,7 @@ pub enum ModelLoaderStatus {
 struct ModelLoaderInner {
     status: ModelLoaderStatus,
     file_id: Option<FileID>,
+    options: Option<LoadModelOptions>,
 }
 
 /// Unit for handling the non-blocking loading of models across threads.
@@ -47,26 +50,27 @@ impl ModelLoader {
         command_sender: Sender<Command>,
         override_port: Option<u16>,
     ) -> Result<(), anyhow::Error> {
-        match self.status() {
-            ModelLoaderStatus::Loading => {
-                return Err(anyhow!("ModelLoader is already loading a model"));
-            }
-            ModelLoaderStatus::Loaded(_) => {
-                if override_port.is_none() {
-                    if let Some(prev_file_id) = self.file_id() {
-                        if prev_file_id == file_id {
-                            return Ok(());
-                        }
-                    }
-                }
+        if self.is_loading() {
+            return Err(anyhow!("ModelLoader is already loading a model"));
+        }
+
+        // The preset of the file may have changed since it was loaded
+        let preset = selected_load_preset(&command_sender, &file_id);
+        let options = load_options(preset.as_ref(), override_port);
+
+        if self.is_loaded() && override_port.is_none() {
+            let inner = self.0.lock().unwrap();
+            if inner.file_id.as_ref() == Some(&file_id) && inner.options.as_ref() == Some(&options)
+            {
+                return Ok(());
             }
-            _ => {}
-        };
+        }
 
         self.set_status(ModelLoaderStatus::Loading);
         self.set_file_id(Some(file_id.clone()));
+        self.0.lock().unwrap().options = Some(options.clone());
 
-        let response = dispatch_load_command(command_sender, file_id.clone(), override_port).recv();
+        let response = dispatch_load_command(command_sender, file_id.clone(), options).recv();
 
         let result = if let Ok(response) = response {
             match response {
@@ -105,6 +109,35 @@ impl ModelLoader {
         });
     }
 
+    /// Follow the loaded model in the backend, it fails when the model crashes and
+    /// is loaded again when the backend reloads it.
+    pub fn watch_backend_events(&self, command_sender: &Sender<Command>) {
+        let (tx, rx) = channel();
+        command_sender.send(Command::Subscribe(tx)).unwrap();
+
+        let mut self_clone = self.clone();
+        thread::spawn(move || {
+            while let Ok(event) = rx.recv() {
+                match event {
+                    BackendEvent::ModelCrashed(file_id, reason) => {
+                        if self_clone.is_loaded() && self_clone.file_id() == Some(file_id) {
+                            eprintln!("The loaded model crashed: {}", reason);
+                            self_clone.set_status(ModelLoaderStatus::Failed);
+                        }
+                    }
+                    BackendEvent::ModelLoaded(info) => {
+                        if self_clone.is_failed()
+                            && self_clone.file_id().as_ref() == Some(&info.file_id)
+                        {
+                            self_clone.set_status(ModelLoaderStatus::Loaded(info));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        });
+    }
+
     fn set_status(&mut self, status: ModelLoaderStatus) {
         self.0.lock().unwrap().status = status;
         Cx::post_action(ModelLoaderStatusChanged);
@@ -153,29 +186,50 @@ impl ModelLoader {
     }
 }
 
+/// The preset picked to load the file, `None` for the defaults.
+fn selected_load_preset(command_sender: &Sender<Command>, file_id: &FileID) -> Option<LoadPreset> {
+    let (tx, rx) = channel();
+    command_sender
+        .send(Command::GetLoadPresets(file_id.clone(), tx))
+        .unwrap();
+
+    match rx.recv() {
+        Ok(Ok(presets)) => presets.selected_preset().cloned(),
+        Ok(Err(err)) => {
+            eprintln!("Error fetching the load presets: {:?}", err);
+            None
+        }
+        Err(_) => None,
+    }
+}
+
+fn load_options(preset: Option<&LoadPreset>, override_port: Option<u16>) -> LoadModelOptions {
+    let preset = preset.cloned().unwrap_or_default();
+    let override_server_address = override_port.map(|port| format!("localhost:{}", port));
+
+    LoadModelOptions {
+        override_server_address,
+        prompt_template: preset.prompt_template,
+        gpu_layers: preset
+            .gpu_layers
+            .map_or(GPULayers::Max, GPULayers::Specific),
+        use_mlock: preset.use_mlock,
+        rope_freq_scale: 0.0,
+        rope_freq_base: 0.0,
+        context_overflow_policy: moly_protocol::protocol::ContextOverflowPolicy::StopAtLimit,
+        n_batch: preset.n_batch,
+        n_ctx: preset.n_ctx,
+    }
+}
+
 fn dispatch_load_command(
     command_sender: Sender<Command>,
     file_id: String,
-    override_port: Option<u16>,
+    options: LoadModelOptions,
 ) -> Receiver<Result<LoadModelResponse, anyhow::Error>> {
     let (tx, rx) = channel();
 
-    let override_server_address = override_port.map(|port| format!("localhost:{}", port));
-    let cmd = Command::LoadModel(
-        file_id,
-        LoadModelOptions {
-            override_server_address,
-            prompt_template: None,
-            gpu_layers: moly_protocol::protocol::GPULayers::Max,
-            use_mlock: false,
-            rope_freq_scale: 0.0,
-            rope_freq_base: 0.0,
-            context_overflow_policy: moly_protocol::protocol::ContextOverflowPolicy::StopAtLimit,
-            n_batch: None,
-            n_ctx: None,
-        },
-        tx,
-    );
+    let cmd = Command::LoadModel(file_id, options, tx);
     command_sender.send(cmd).unwrap();
     rx
 }
@@ -1,7 +1,9 @@
 use makepad_widgets::Cx;
 use moly_backend::Backend;
 use moly_protocol::data::*;
-use moly_protocol::protocol::{Command, FileDownloadResponse};
+use moly_protocol::protocol::{
+    CatalogTrustError, Command, DownloadAccessError, FileDownloadResponse, InsufficientSpaceError,
+};
 use std::sync::mpsc::channel;
 use std::thread;
 
@@ -13,14 +15,18 @@ pub struct DownloadFileAction {
 
 #[derive(Debug)]
 enum DownloadFileActionKind {
+    Queued,
     Progress(f64),
     Error,
+    // The server refused the download or the disk is full, with the reason to show
+    Refused(String),
     StreamingDone,
 }
 
 #[derive(Clone, Copy, Debug)]
 pub enum DownloadState {
     Initializing(f64),
+    Queued(f64),
     Downloading(f64),
     Errored(f64),
     Completed,
@@ -31,6 +37,10 @@ pub struct Download {
     pub file: File,
     pub state: DownloadState,
     pub notification_pending: bool,
+    // Fetching the new revision of a downloaded file
+    pub is_update: bool,
+    // Why the download was refused, retrying won't help until it is fixed
+    pub refusal: Option<String>,
 }
 
 impl Download {
@@ -39,6 +49,22 @@ impl Download {
             file: file,
             state: DownloadState::Initializing(progress),
             notification_pending: false,
+            is_update: false,
+            refusal: None,
+        };
+
+        download.start(backend);
+        download
+    }
+
+    /// Download the new revision of a downloaded file, which stays usable meanwhile.
+    pub fn update(file: File, backend: &Backend) -> Self {
+        let mut download = Self {
+            file: file,
+            state: DownloadState::Initializing(0.0),
+            notification_pending: false,
+            is_update: true,
+            refusal: None,
         };
 
         download.start(backend);
@@ -46,9 +72,14 @@ impl Download {
     }
 
     pub fn start(&mut self, backend: &Backend) {
+        self.refusal = None;
         let (tx, rx) = channel();
 
-        let cmd = Command::DownloadFile(self.file.id.clone(), tx);
+        let cmd = if self.is_update {
+            Command::UpdateFile(self.file.id.clone(), tx)
+        } else {
+            Command::DownloadFile(self.file.id.clone(), tx)
+        };
         backend.command_sender.send(cmd).unwrap();
         let file_id = self.file.id.clone();
 
@@ -64,6 +95,12 @@ impl Download {
                                 kind: DownloadFileActionKind::StreamingDone,
                             });
                         }
+                        FileDownloadResponse::Queued(_file) => {
+                            Cx::post_action(DownloadFileAction {
+                                file_id: file_id.clone(),
+                                kind: DownloadFileActionKind::Queued,
+                            })
+                        }
                         FileDownloadResponse::Progress(_file, value) => {
                             Cx::post_action(DownloadFileAction {
                                 file_id: file_id.clone(),
@@ -72,9 +109,17 @@ impl Download {
                         }
                     },
                     Err(err) => {
+                        let kind = if err.is::<DownloadAccessError>()
+                            || err.is::<InsufficientSpaceError>()
+                            || err.is::<CatalogTrustError>()
+                        {
+                            DownloadFileActionKind::Refused(err.to_string())
+                        } else {
+                            DownloadFileActionKind::Error
+                        };
                         Cx::post_action(DownloadFileAction {
                             file_id: file_id.clone(),
-                            kind: DownloadFileActionKind::Error,
+                            kind,
                         });
 
                         eprintln!("Error downloading file: {:?}", err)
@@ -91,19 +136,28 @@ impl Download {
     }
 
     pub fn handle_action(&mut self, action: &DownloadFileAction) {
-        match action.kind {
+        match &action.kind {
             DownloadFileActionKind::StreamingDone => {
                 self.state = DownloadState::Completed;
                 self.notification_pending = true;
             }
+            DownloadFileActionKind::Queued => {
+                self.state = DownloadState::Queued(self.get_progress())
+            }
             DownloadFileActionKind::Progress(value) => {
-                self.state = DownloadState::Downloading(value)
+                self.state = DownloadState::Downloading(*value)
             }
             DownloadFileActionKind::Error => {
                 let current_progress = self.get_progress();
                 self.state = DownloadState::Errored(current_progress);
                 self.notification_pending = true;
             }
+            DownloadFileActionKind::Refused(message) => {
+                let current_progress = self.get_progress();
+                self.state = DownloadState::Errored(current_progress);
+                self.refusal = Some(message.clone());
+                self.notification_pending = true;
+            }
         }
     }
 
@@ -111,6 +165,10 @@ impl Download {
         matches!(self.state, DownloadState::Initializing(..))
     }
 
+    pub fn is_queued(&self) -> bool {
+        matches!(self.state, DownloadState::Queued(..))
+    }
+
     pub fn is_complete(&self) -> bool {
         matches!(self.state, DownloadState::Completed)
     }
@@ -118,6 +176,7 @@ impl Download {
     pub fn get_progress(&self) -> f64 {
         match self.state {
             DownloadState::Initializing(progress) => progress,
+            DownloadState::Queued(progress) => progress,
             DownloadState::Downloading(progress) => progress,
             DownloadState::Errored(progress) => progress,
             DownloadState::Completed => 1.0,
@@ -5,8 +5,13 @@ use download::{Download, DownloadFileAction, DownloadState};
 use makepad_widgets::Action;
 use moly_backend::Backend;
 use moly_protocol::{
-    data::{DownloadedFile, File, FileID, Model, PendingDownload, PendingDownloadsStatus},
-    protocol::Command,
+    data::{
+        ChatTemplateSettings, ChatTemplateSource, CleanupPlan, DownloadSourceSettings,
+        DownloadedFile, File, FileID, LoadPreset, LoadPresets, Model, PendingDownload,
+        PendingDownloadsStatus, StorageQuota, StorageUsage, UsageStats,
+    },
+    open_ai::{Message, Role},
+    protocol::{CatalogTrustError, Command},
 };
 use std::{collections::HashMap, rc::Rc, sync::mpsc::channel};
 
@@ -14,13 +19,24 @@ use std::{collections::HashMap, rc::Rc, sync::mpsc::channel};
 pub enum DownloadPendingNotification {
     DownloadedFile(File),
     DownloadErrored(File),
+    // The server refused the download or the disk is full, with the reason to show
+    Refused(File, String),
 }
 pub struct Downloads {
     pub backend: Rc<Backend>,
     pub downloaded_files: Vec<DownloadedFile>,
+    // Downloaded embedding model in use instead of the default one
+    pub embedding_file_id: Option<FileID>,
     pub pending_downloads: Vec<PendingDownload>,
     pub current_downloads: HashMap<FileID, Download>,
     pub pending_notifications: Vec<DownloadPendingNotification>,
+    // Files proposed for removal to get back under the storage quota
+    pub storage_cleanup: Option<CleanupPlan>,
+    // Fetched when the model info is shown, not kept up to date
+    pub usage_stats: HashMap<FileID, UsageStats>,
+    // Fetched when the model info is shown, and after every change
+    pub load_presets: HashMap<FileID, LoadPresets>,
+    pub chat_templates: HashMap<FileID, ChatTemplateSettings>,
 }
 
 impl Downloads {
@@ -28,9 +44,14 @@ impl Downloads {
         Self {
             backend,
             downloaded_files: Vec::new(),
+            embedding_file_id: None,
             pending_downloads: Vec::new(),
             current_downloads: HashMap::new(),
             pending_notifications: Vec::new(),
+            storage_cleanup: None,
+            usage_stats: HashMap::new(),
+            load_presets: HashMap::new(),
+            chat_templates: HashMap::new(),
         }
     }
 
@@ -52,6 +73,86 @@ impl Downloads {
         };
     }
 
+    pub fn load_embedding_model(&mut self) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetEmbeddingModel(tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(file_id)) => self.embedding_file_id = file_id,
+            Ok(Err(err)) => eprintln!("Error fetching the embedding model: {:?}", err),
+            Err(_) => {}
+        }
+    }
+
+    /// Serve a downloaded embedding model along the chat models, or the default one with `None`.
+    pub fn set_embedding_model(&mut self, file_id: Option<FileID>) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetEmbeddingModel(file_id.clone(), tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(())) => self.embedding_file_id = file_id,
+            Ok(Err(err)) => eprintln!("Error setting the embedding model: {:?}", err),
+            Err(_) => {}
+        }
+        self.download_default_embedding_model();
+    }
+
+    /// Download the default embedding model of the catalog when no other one is
+    /// picked, in the queue like any other file.
+    ///
+    /// Nothing happens if it is downloaded or already pending, a paused download
+    /// stays paused. A default model refused for its catalog signature is skipped.
+    pub fn download_default_embedding_model(&mut self) {
+        if self.embedding_file_id.is_some() {
+            return;
+        }
+
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetDefaultEmbeddingModel(tx))
+            .unwrap();
+
+        let model = match rx.recv() {
+            Ok(Ok(Some(model))) => model,
+            Ok(Ok(None)) | Err(_) => return,
+            // Its catalog entry can't be trusted, asking again won't help
+            Ok(Err(err)) if err.is::<CatalogTrustError>() => {
+                eprintln!("Not downloading the default embedding model: {}", err);
+                return;
+            }
+            Ok(Err(err)) => {
+                eprintln!("Error fetching the default embedding model: {:?}", err);
+                return;
+            }
+        };
+        let Some(file) = model.files.first().cloned() else {
+            return;
+        };
+
+        let pending = self.current_downloads.contains_key(&file.id)
+            || self.pending_downloads.iter().any(|d| d.file.id == file.id);
+        if !file.downloaded && !pending {
+            self.download_file(model, file);
+        }
+    }
+
+    /// Downloaded files that can be picked to chat with.
+    pub fn chat_files(&self) -> impl Iterator<Item = &DownloadedFile> {
+        self.downloaded_files
+            .iter()
+            .filter(|f| f.model.model_type.is_chat())
+    }
+
     pub fn load_pending_downloads(&mut self) {
         let (tx, rx) = channel();
         self.backend
@@ -74,6 +175,8 @@ impl Downloads {
                         if let Some(current) = self.current_downloads.get(&d.file.id) {
                             if current.is_initializing() {
                                 d.status = PendingDownloadsStatus::Initializing;
+                            } else if current.is_queued() {
+                                d.status = PendingDownloadsStatus::Queued;
                             } else {
                                 d.status = PendingDownloadsStatus::Downloading;
                             }
@@ -101,6 +204,9 @@ impl Downloads {
                 model: model.clone(),
                 progress: 0.0,
                 status: PendingDownloadsStatus::Initializing,
+                queue_position: None,
+                priority: Default::default(),
+                bandwidth_limit: None,
             };
             self.pending_downloads.push(pending_download);
         }
@@ -111,6 +217,30 @@ impl Downloads {
         );
     }
 
+    /// Download the new revision of an outdated file, replacing it once verified.
+    pub fn update_file(&mut self, file_id: &FileID) {
+        let Some(file) = self
+            .downloaded_files
+            .iter()
+            .find(|f| f.file.id == *file_id && f.update_available)
+        else {
+            return;
+        };
+
+        self.current_downloads.insert(
+            file_id.clone(),
+            Download::update(file.file.clone(), &self.backend.as_ref()),
+        );
+    }
+
+    /// Progress of the update of a downloaded file, if it is being updated.
+    pub fn update_progress(&self, file_id: &FileID) -> Option<f64> {
+        self.current_downloads
+            .get(file_id)
+            .filter(|download| download.is_update)
+            .map(|download| download.get_progress())
+    }
+
     /// Get a known file. No matter it's status.
     pub fn get_file(&self, file_id: &FileID) -> Option<&File> {
         // Bet this should not be different things just because they have attached status specific data.
@@ -174,6 +304,351 @@ impl Downloads {
         };
     }
 
+    /// Make a queued download the next one to start.
+    pub fn start_download_next(&mut self, file_id: &FileID) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::StartDownloadNext(file_id.clone(), tx))
+            .unwrap();
+
+        if let Ok(response) = rx.recv() {
+            match response {
+                // Refresh the queue positions
+                Ok(()) => self.load_pending_downloads(),
+                Err(err) => eprintln!("Error moving download to the front: {:?}", err),
+            }
+        };
+    }
+
+    /// Limit the speed of all downloads together, `None` removes the limit.
+    pub fn set_bandwidth_limit(&self, bytes_per_sec: Option<u64>) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetDownloadBandwidthLimit(bytes_per_sec, tx))
+            .unwrap();
+
+        if let Ok(Err(err)) = rx.recv() {
+            eprintln!("Error setting the download bandwidth limit: {:?}", err);
+        }
+    }
+
+    /// Limit the speed of a single download, `None` removes the limit.
+    pub fn set_file_bandwidth_limit(&mut self, file_id: &FileID, bytes_per_sec: Option<u64>) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetFileBandwidthLimit(
+                file_id.clone(),
+                bytes_per_sec,
+                tx,
+            ))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(())) => {
+                if let Some(pending) = self
+                    .pending_downloads
+                    .iter_mut()
+                    .find(|d| d.file.id == *file_id)
+                {
+                    pending.bandwidth_limit = bytes_per_sec;
+                }
+            }
+            Ok(Err(err)) => eprintln!("Error setting the download speed limit: {:?}", err),
+            Err(_) => {}
+        }
+    }
+
+    /// Disk use of the downloaded files and the free space left for more.
+    pub fn get_storage_usage(&self) -> Option<StorageUsage> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetStorageUsage(tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(usage)) => Some(usage),
+            Ok(Err(err)) => {
+                eprintln!("Error fetching the storage usage: {:?}", err);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    pub fn set_storage_quota(&self, quota: StorageQuota) -> Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetStorageQuota(quota, tx))
+            .unwrap();
+
+        rx.recv()?
+    }
+
+    /// Keep a file out of the storage cleanup, or let it be removed again.
+    pub fn set_file_pinned(&mut self, file_id: &FileID, pinned: bool) -> Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetFilePinned(file_id.clone(), pinned, tx))
+            .unwrap();
+
+        rx.recv()??;
+        if let Some(file) = self
+            .downloaded_files
+            .iter_mut()
+            .find(|f| f.file.id == *file_id)
+        {
+            file.pinned = pinned;
+        }
+        Ok(())
+    }
+
+    /// The files to remove to get under the storage quota, leaving the `protected` ones.
+    pub fn plan_storage_cleanup(&self, protected: Vec<FileID>) -> Option<CleanupPlan> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::PlanStorageCleanup(protected, tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(plan)) => plan,
+            Ok(Err(err)) => {
+                eprintln!("Error planning the storage cleanup: {:?}", err);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Refresh the usage recorded for a downloaded file.
+    pub fn load_usage_stats(&mut self, file_id: &FileID) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetUsageStats(file_id.clone(), tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(stats)) => {
+                self.usage_stats.insert(file_id.clone(), stats);
+            }
+            Ok(Err(err)) => eprintln!("Error fetching the usage stats: {:?}", err),
+            Err(_) => {}
+        }
+    }
+
+    pub fn load_load_presets(&mut self, file_id: &FileID) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetLoadPresets(file_id.clone(), tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(presets)) => {
+                self.load_presets.insert(file_id.clone(), presets);
+            }
+            Ok(Err(err)) => eprintln!("Error fetching the load presets: {:?}", err),
+            Err(_) => {}
+        }
+    }
+
+    /// Save a load preset of a file and load the file with it from now on.
+    pub fn save_load_preset(&mut self, file_id: &FileID, preset: LoadPreset) -> Result<()> {
+        let name = preset.name.clone();
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SaveLoadPreset(file_id.clone(), preset, tx))
+            .unwrap();
+
+        rx.recv()??;
+        self.select_load_preset(file_id, Some(name))
+    }
+
+    /// Load a file with one of its presets from now on, `None` for the defaults.
+    pub fn select_load_preset(&mut self, file_id: &FileID, name: Option<String>) -> Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SelectLoadPreset(file_id.clone(), name, tx))
+            .unwrap();
+
+        let result = rx.recv()?;
+        self.load_load_presets(file_id);
+        result
+    }
+
+    pub fn remove_load_preset(&mut self, file_id: &FileID, name: String) -> Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::RemoveLoadPreset(file_id.clone(), name, tx))
+            .unwrap();
+
+        let result = rx.recv()?;
+        self.load_load_presets(file_id);
+        result
+    }
+
+    pub fn load_chat_template(&mut self, file_id: &FileID) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetChatTemplate(file_id.clone(), tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(settings)) => {
+                self.chat_templates.insert(file_id.clone(), settings);
+            }
+            Ok(Err(err)) => eprintln!("Error fetching the chat template: {:?}", err),
+            Err(_) => {}
+        }
+    }
+
+    /// Format chats with a file using another template, the loaded model included.
+    pub fn set_chat_template(
+        &mut self,
+        file_id: &FileID,
+        source: ChatTemplateSource,
+    ) -> Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetChatTemplate(file_id.clone(), source, tx))
+            .unwrap();
+
+        let result = rx.recv()?;
+        self.load_chat_template(file_id);
+        result
+    }
+
+    /// The prompt a template renders for a short sample chat.
+    pub fn preview_chat_template(
+        &self,
+        file_id: &FileID,
+        source: ChatTemplateSource,
+    ) -> Result<String> {
+        let message = |role: Role, content: &str| Message {
+            content: content.to_string(),
+            role,
+            name: None,
+        };
+        let messages = vec![
+            message(Role::System, "You are a helpful assistant."),
+            message(Role::User, "Hello!"),
+            message(Role::Assistant, "Hi, how can I help you?"),
+            message(Role::User, "What is the capital of France?"),
+        ];
+
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::PreviewChatTemplate(
+                file_id.clone(),
+                source,
+                messages,
+                tx,
+            ))
+            .unwrap();
+
+        rx.recv()?
+    }
+
+    pub fn get_download_source_settings(&self) -> Option<DownloadSourceSettings> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetDownloadSourceSettings(tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(settings)) => Some(settings),
+            Ok(Err(err)) => {
+                eprintln!("Error fetching the download source settings: {:?}", err);
+                None
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Replace the ordered list of mirrors to download model files from.
+    pub fn set_download_mirrors(&self, mirrors: Vec<String>) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetDownloadMirrors(mirrors, tx))
+            .unwrap();
+
+        if let Ok(Err(err)) = rx.recv() {
+            eprintln!("Error setting the download mirrors: {:?}", err);
+        }
+    }
+
+    /// Pick the download urls for a region, `None` detects it from the IP address.
+    pub fn set_region(&self, region: Option<String>) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetRegion(region, tx))
+            .unwrap();
+
+        if let Ok(Err(err)) = rx.recv() {
+            eprintln!("Error setting the region: {:?}", err);
+        }
+    }
+
+    /// Save the token sent when downloading from `host`, `None` forgets it.
+    pub fn set_access_token(&self, host: String, token: Option<String>) -> Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetAccessToken(host, token, tx))
+            .unwrap();
+
+        rx.recv()?
+    }
+
+    /// Allow downloading files of models whose catalog card could not be verified.
+    pub fn set_allow_unverified_downloads(&self, allow: bool) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetAllowUnverifiedDownloads(allow, tx))
+            .unwrap();
+
+        if let Ok(Err(err)) = rx.recv() {
+            eprintln!("Error setting the unverified downloads override: {:?}", err);
+        }
+    }
+
     pub fn cancel_download_file(&mut self, file_id: &FileID) {
         if let Some(current_download) = self.current_downloads.get(file_id) {
             if current_download.is_initializing() {
@@ -211,6 +686,11 @@ impl Downloads {
             .context("Failed to receive delete file response")?
             .context("Delete file operation failed")?;
 
+        // The backend goes back to the default embedding model
+        if self.embedding_file_id.as_ref() == Some(&file_id) {
+            self.embedding_file_id = None;
+        }
+
         self.load_downloaded_files();
         self.load_pending_downloads();
         Ok(())
@@ -254,15 +734,25 @@ impl Downloads {
                     DownloadState::Initializing(_) => {
                         pending.status = PendingDownloadsStatus::Initializing;
                     }
+                    DownloadState::Queued(_) => {
+                        pending.status = PendingDownloadsStatus::Queued;
+                    }
                     DownloadState::Downloading(_) => {
                         pending.status = PendingDownloadsStatus::Downloading;
                     }
                     DownloadState::Errored(_) => {
                         pending.status = PendingDownloadsStatus::Error;
                         if download.must_show_notification() {
-                            self.pending_notifications.push(
-                                DownloadPendingNotification::DownloadErrored(download.file.clone()),
-                            );
+                            let notification = match &download.refusal {
+                                Some(message) => DownloadPendingNotification::Refused(
+                                    download.file.clone(),
+                                    message.clone(),
+                                ),
+                                None => DownloadPendingNotification::DownloadErrored(
+                                    download.file.clone(),
+                                ),
+                            };
+                            self.pending_notifications.push(notification);
                         }
                     }
                     DownloadState::Completed => {
@@ -285,6 +775,11 @@ impl Downloads {
             self.current_downloads.remove(id);
         }
 
+        // A failed update leaves the downloaded file as it was, it can be tried again
+        self.current_downloads.retain(|_, download| {
+            !(download.is_update && matches!(download.state, DownloadState::Errored(_)))
+        });
+
         // Reload downloaded files and pending downloads from the backend
         if !completed_download_ids.is_empty() {
             self.load_downloaded_files();
@@ -13,6 +13,9 @@ pub struct Preferences {
     pub current_chat_model: Option<FileID>,
     #[serde(default)]
     pub downloaded_files_dir: PathBuf,
+    // Maximum speed of all downloads together, in bytes per second
+    #[serde(default)]
+    pub download_bandwidth_limit: Option<u64>,
 }
 
 impl Preferences {
@@ -33,6 +36,7 @@ impl Preferences {
             Self {
                 current_chat_model: None,
                 downloaded_files_dir: setup_model_downloads_folder(),
+                download_bandwidth_limit: None,
             }
         }
 
@@ -53,6 +57,11 @@ impl Preferences {
         self.downloaded_files_dir = path;
         self.save();
     }
+
+    pub fn set_download_bandwidth_limit(&mut self, bytes_per_sec: Option<u64>) {
+        self.download_bandwidth_limit = bytes_per_sec;
+        self.save();
+    }
 }
 
 fn preferences_path() -> PathBuf {
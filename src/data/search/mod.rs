@@ -4,6 +4,8 @@ use makepad_widgets::{Action, Cx};
 use moly_backend::Backend;
 use moly_protocol::data::*;
 use moly_protocol::protocol::Command;
+use std::cmp::Ordering;
+use std::ops::Range;
 use std::rc::Rc;
 use std::sync::mpsc::channel;
 use std::thread;
@@ -17,10 +19,32 @@ pub enum SortCriteria {
     LeastLikes,
 }
 
+impl From<SortCriteria> for SearchSort {
+    fn from(criteria: SortCriteria) -> Self {
+        match criteria {
+            SortCriteria::MostDownloads => SearchSort::MostDownloads,
+            SortCriteria::LeastDownloads => SearchSort::LeastDownloads,
+            SortCriteria::MostLikes => SearchSort::MostLikes,
+            SortCriteria::LeastLikes => SearchSort::LeastLikes,
+        }
+    }
+}
+
+/// A curated collection of the catalog, shown as a section of the featured models.
+#[derive(Clone, Debug)]
+pub struct ModelSection {
+    pub name: String,
+    // The models of the section in `Search::models`
+    pub range: Range<usize>,
+}
+
 #[derive(Debug)]
 pub enum SearchAction {
-    Results(Vec<Model>),
+    Results(SearchResults),
+    Collections(Vec<ModelCollection>),
     Error,
+    // The backend finished refreshing the model catalog
+    CatalogUpdated(CatalogSyncStatus),
 }
 
 #[derive(Clone)]
@@ -39,23 +63,138 @@ pub enum SearchState {
 pub struct Search {
     pub backend: Rc<Backend>,
     pub models: Vec<Model>,
+    // Sections of the featured models, empty when they are a flat list
+    pub sections: Vec<ModelSection>,
+    // Number of models matching the last search, the backend only sends the first page
+    pub total_results: usize,
     pub sorted_by: SortCriteria,
+    // Only show models of this type, `None` shows them all
+    pub category: Option<ModelType>,
     pub keyword: Option<String>,
     pub state: SearchState,
+    pub catalog_status: CatalogSyncStatus,
 }
 
 impl Search {
     pub fn new(backend: Rc<Backend>) -> Self {
-        let search = Self {
+        let mut search = Self {
             backend,
             models: Vec::new(),
+            sections: Vec::new(),
+            total_results: 0,
             sorted_by: SortCriteria::MostDownloads,
+            category: None,
             keyword: None,
             state: SearchState::Idle,
+            catalog_status: CatalogSyncStatus::default(),
         };
+        search.subscribe_catalog_updates();
         search
     }
 
+    fn subscribe_catalog_updates(&mut self) {
+        // Subscribe before asking for the status so no refresh can be missed in between
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SubscribeCatalogUpdates(tx))
+            .unwrap();
+
+        thread::spawn(move || {
+            while let Ok(status) = rx.recv() {
+                Cx::post_action(SearchAction::CatalogUpdated(status));
+            }
+        });
+
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetCatalogSyncStatus(tx))
+            .unwrap();
+
+        if let Ok(Ok(status)) = rx.recv() {
+            self.catalog_status = status;
+        }
+    }
+
+    /// Ask the backend to refresh the model catalog, results are reloaded once it is done.
+    pub fn refresh_catalog(&mut self) {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::RefreshCatalog(tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(())) => self.catalog_status.syncing = true,
+            Ok(Err(err)) => eprintln!("Error refreshing the model catalog: {:?}", err),
+            Err(_) => {}
+        }
+    }
+
+    pub fn get_catalog_sources(&self) -> Vec<CatalogSource> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::GetCatalogSources(tx))
+            .unwrap();
+
+        match rx.recv() {
+            Ok(Ok(sources)) => sources,
+            Ok(Err(err)) => {
+                eprintln!("Error fetching the catalog sources: {:?}", err);
+                vec![]
+            }
+            Err(_) => vec![],
+        }
+    }
+
+    /// Replace the catalog sources, the catalog is refreshed with them right away.
+    pub fn set_catalog_sources(&mut self, sources: Vec<CatalogSource>) -> anyhow::Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::SetCatalogSources(sources, tx))
+            .unwrap();
+
+        rx.recv()??;
+        self.catalog_status.syncing = true;
+        Ok(())
+    }
+
+    /// Record that the user accepted the license of a model, required to download the
+    /// models whose license asks for it.
+    pub fn accept_model_license(&mut self, model_id: &ModelID) -> anyhow::Result<()> {
+        let (tx, rx) = channel();
+        self.backend
+            .as_ref()
+            .command_sender
+            .send(Command::AcceptModelLicense(model_id.clone(), tx))
+            .unwrap();
+
+        let accepted_at = rx.recv()??;
+        // A model can be listed in more than one section
+        for model in self.models.iter_mut().filter(|m| m.id == *model_id) {
+            model.license.accepted_at = Some(accepted_at);
+        }
+        Ok(())
+    }
+
+    /// Browse the models of a type, keeping the current search if any.
+    pub fn set_category(&mut self, category: Option<ModelType>) {
+        self.category = category;
+        match self.keyword.clone() {
+            Some(keyword) => self.run_or_enqueue(keyword),
+            None => self.load_featured_models(),
+        }
+    }
+
+    /// Loads the featured models, or every model of the selected category.
     pub fn load_featured_models(&mut self) {
         match self.state {
             SearchState::Pending(_, ref mut next_command) => {
@@ -68,19 +207,29 @@ impl Search {
             }
         }
 
+        if let Some(category) = self.category.clone() {
+            let query = SearchQuery {
+                model_types: vec![category],
+                sort: self.sorted_by.into(),
+                ..Default::default()
+            };
+            self.send_search_query(query);
+            return;
+        }
+
         let (tx, rx) = channel();
 
         self.backend
             .as_ref()
             .command_sender
-            .send(Command::GetFeaturedModels(tx))
+            .send(Command::GetFeaturedCollections(tx))
             .unwrap();
 
         thread::spawn(move || {
             if let Ok(response) = rx.recv() {
                 match response {
-                    Ok(models) => {
-                        Cx::post_action(SearchAction::Results(models));
+                    Ok(collections) => {
+                        Cx::post_action(SearchAction::Collections(collections));
                     }
                     Err(err) => {
                         eprintln!("Error fetching models: {:?}", err);
@@ -106,19 +255,28 @@ impl Search {
             }
         }
 
+        let query = SearchQuery {
+            model_types: self.category.clone().into_iter().collect(),
+            sort: self.sorted_by.into(),
+            ..SearchQuery::new(keyword)
+        };
+        self.send_search_query(query);
+    }
+
+    fn send_search_query(&self, query: SearchQuery) {
         let (tx, rx) = channel();
 
         self.backend
             .as_ref()
             .command_sender
-            .send(Command::SearchModels(keyword.clone(), tx))
+            .send(Command::SearchModels(query, tx))
             .unwrap();
 
         thread::spawn(move || {
             if let Ok(response) = rx.recv() {
                 match response {
-                    Ok(models) => {
-                        Cx::post_action(SearchAction::Results(models));
+                    Ok(results) => {
+                        Cx::post_action(SearchAction::Results(results));
                     }
                     Err(err) => {
                         eprintln!("Error fetching models: {:?}", err);
@@ -130,25 +288,51 @@ impl Search {
     }
 
     pub fn sort_models(&mut self, criteria: SortCriteria) {
-        match criteria {
-            SortCriteria::MostDownloads => {
-                self.models
-                    .sort_by(|a, b| b.download_count.cmp(&a.download_count));
-            }
-            SortCriteria::LeastDownloads => {
-                self.models
-                    .sort_by(|a, b| a.download_count.cmp(&b.download_count));
+        let compare = |a: &Model, b: &Model| -> Ordering {
+            match criteria {
+                SortCriteria::MostDownloads => b.download_count.cmp(&a.download_count),
+                SortCriteria::LeastDownloads => a.download_count.cmp(&b.download_count),
+                SortCriteria::MostLikes => b.like_count.cmp(&a.like_count),
+                SortCriteria::LeastLikes => a.like_count.cmp(&b.like_count),
             }
-            SortCriteria::MostLikes => {
-                self.models.sort_by(|a, b| b.like_count.cmp(&a.like_count));
-            }
-            SortCriteria::LeastLikes => {
-                self.models.sort_by(|a, b| a.like_count.cmp(&b.like_count));
+        };
+
+        // Models are only sorted within their section
+        if self.sections.is_empty() {
+            self.models.sort_by(compare);
+        } else {
+            for section in &self.sections {
+                self.models[section.range.clone()].sort_by(compare);
             }
         }
         self.sorted_by = criteria;
     }
 
+    /// Show the curated collections as sections, in the order of the catalog.
+    ///
+    /// The fallback collection with the featured models is shown as a flat list.
+    fn set_collections(&mut self, collections: Vec<ModelCollection>) {
+        if collections.iter().all(|c| c.is_featured_fallback()) {
+            let models: Vec<Model> = collections.into_iter().flat_map(|c| c.models).collect();
+            self.sections.clear();
+            self.total_results = models.len();
+            self.set_models(models);
+            return;
+        }
+
+        self.models.clear();
+        self.sections.clear();
+        for collection in collections {
+            let start = self.models.len();
+            self.models.extend(collection.models);
+            self.sections.push(ModelSection {
+                name: collection.name,
+                range: start..self.models.len(),
+            });
+        }
+        self.total_results = self.models.len();
+    }
+
     pub fn set_models(&mut self, models: Vec<Model>) {
         #[cfg(not(debug_assertions))]
         {
@@ -174,38 +358,73 @@ impl Search {
         self.sort_models(self.sorted_by);
     }
 
+    /// Move on to the next command once results arrive, returning whether they were expected.
+    fn complete_pending(&mut self) -> bool {
+        let previous_state = self.state.to_owned();
+        self.state = SearchState::Idle;
+
+        let SearchState::Pending(current_command, next_command) = previous_state else {
+            eprintln!("Client was not expecting to receive results");
+            return false;
+        };
+
+        if let SearchCommand::Search(keyword) = current_command {
+            self.keyword = Some(keyword.clone());
+        }
+
+        match next_command {
+            Some(SearchCommand::Search(next_keyword)) => {
+                self.run_or_enqueue(next_keyword.clone());
+            }
+            Some(SearchCommand::LoadFeaturedModels) => {
+                self.load_featured_models();
+            }
+            None => {}
+        }
+        true
+    }
+
     pub fn handle_action(&mut self, action: &Action) {
         if let Some(msg) = action.downcast_ref::<SearchAction>() {
             match msg {
-                SearchAction::Results(models) => {
-                    let previous_state = self.state.to_owned();
-                    self.state = SearchState::Idle;
-
-                    if let SearchState::Pending(current_command, next_command) = previous_state {
-                        if let SearchCommand::Search(keyword) = current_command {
-                            self.keyword = Some(keyword.clone());
-                        }
-
-                        match next_command {
-                            Some(SearchCommand::Search(next_keyword)) => {
-                                self.run_or_enqueue(next_keyword.clone());
-                            }
-                            Some(SearchCommand::LoadFeaturedModels) => {
-                                self.load_featured_models();
-                            }
-                            None => {}
-                        }
-                        self.set_models(models.clone());
+                SearchAction::Results(results) => {
+                    self.sections.clear();
+                    if self.complete_pending() {
+                        self.total_results = results.total;
+                        self.set_models(results.models.clone());
                     } else {
+                        self.total_results = 0;
+                        self.set_models(vec![]);
+                    }
+                }
+                SearchAction::Collections(collections) => {
+                    if self.complete_pending() {
+                        self.set_collections(collections.clone());
+                    } else {
+                        self.sections.clear();
+                        self.total_results = 0;
                         self.set_models(vec![]);
-                        eprintln!("Client was not expecting to receive results");
                     }
                 }
                 SearchAction::Error => {
                     self.state = SearchState::Errored;
+                    self.sections.clear();
+                    self.total_results = 0;
                     self.set_models(vec![]);
                     eprintln!("Error fetching models from the server");
                 }
+                SearchAction::CatalogUpdated(status) => {
+                    let synced = status.last_sync != self.catalog_status.last_sync;
+                    self.catalog_status = status.clone();
+
+                    // Show what is in the new catalog, nothing changed if the refresh failed
+                    if synced {
+                        match self.keyword.clone() {
+                            Some(keyword) => self.run_or_enqueue(keyword),
+                            None => self.load_featured_models(),
+                        }
+                    }
+                }
             }
         }
     }
@@ -219,13 +438,11 @@ impl Search {
     }
 
     pub fn update_downloaded_file_in_search_results(&mut self, file_id: &FileID, downloaded: bool) {
-        let model = self
-            .models
-            .iter_mut()
-            .find(|m| m.files.iter().any(|f| f.id == *file_id));
-        if let Some(model) = model {
-            let file = model.files.iter_mut().find(|f| f.id == *file_id).unwrap();
-            file.downloaded = downloaded;
+        // A model can be listed in more than one section
+        for model in self.models.iter_mut() {
+            if let Some(file) = model.files.iter_mut().find(|f| f.id == *file_id) {
+                file.downloaded = downloaded;
+            }
         }
     }
 
@@ -5,7 +5,7 @@ use super::chats::MoFaTestServerAction;
 use super::downloads::download::DownloadFileAction;
 use super::filesystem::project_dirs;
 use super::preferences::Preferences;
-use super::search::SortCriteria;
+use super::search::{SearchAction, SortCriteria};
 use super::{chats::Chats, downloads::Downloads, search::Search};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
@@ -13,23 +13,40 @@ use makepad_widgets::{Action, ActionDefaultRef, DefaultNone};
 use moly_backend::Backend;
 
 use moly_mofa::MofaServerResponse;
-use moly_protocol::data::{Author, DownloadedFile, File, FileID, Model, ModelID, PendingDownload};
+use moly_protocol::data::{
+    Author, CatalogTrust, DownloadedFile, File, FileID, Model, ModelID, ModelLicense, ModelType,
+    PendingDownload, StorageQuota,
+};
+use moly_protocol::protocol::Command;
 use std::rc::Rc;
+use std::sync::mpsc::channel;
+use std::time::Duration;
 
 pub const DEFAULT_MAX_DOWNLOAD_THREADS: usize = 3;
+pub const DEFAULT_DOWNLOAD_CONNECTIONS: usize = 4;
 const DEFAULT_MOFA_ADDRESS: &str = "http://localhost:8000";
 
+/// How long closing the window waits for the backend to stop. It pauses the downloads
+/// and stops the model, each with its own limit.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Chats used within this many days keep their model files out of the storage cleanup.
+const RECENT_CHAT_DAYS: i64 = 30;
+
 #[derive(Clone, DefaultNone, Debug)]
 pub enum StoreAction {
     Search(String),
     ResetSearch,
     Sort(SortCriteria),
+    SetCategory(Option<ModelType>),
+    AcceptModelLicense(ModelID),
     None,
 }
 
 #[derive(Clone, Debug)]
 pub struct FileWithDownloadInfo {
     pub file: File,
+    pub model_type: ModelType,
     pub download: Option<PendingDownload>,
 }
 
@@ -41,6 +58,9 @@ pub struct ModelWithDownloadInfo {
     pub size: String,
     pub requires: String,
     pub architecture: String,
+    pub model_type: ModelType,
+    pub trust: CatalogTrust,
+    pub license: ModelLicense,
     pub released_at: DateTime<Utc>,
     pub author: Author,
     pub like_count: u32,
@@ -74,6 +94,7 @@ impl Store {
             app_data_dir,
             preferences.downloaded_files_dir.clone(),
             DEFAULT_MAX_DOWNLOAD_THREADS,
+            DEFAULT_DOWNLOAD_CONNECTIONS,
         ));
 
         let mut store = Self {
@@ -85,8 +106,15 @@ impl Store {
             preferences,
         };
 
+        if store.preferences.download_bandwidth_limit.is_some() {
+            store
+                .downloads
+                .set_bandwidth_limit(store.preferences.download_bandwidth_limit);
+        }
         store.downloads.load_downloaded_files();
+        store.downloads.load_embedding_model();
         store.downloads.load_pending_downloads();
+        store.downloads.download_default_embedding_model();
 
         store.chats.load_chats();
         store.init_current_chat();
@@ -96,6 +124,7 @@ impl Store {
             .chats
             .register_mofa_server(DEFAULT_MOFA_ADDRESS.to_string());
 
+        store.enforce_storage_quota();
         store
     }
 
@@ -103,6 +132,26 @@ impl Store {
         self.chats.load_model(file, None);
     }
 
+    /// Stop the backend before the app exits, waiting until downloads are paused and
+    /// the model is ejected, or the timeout is over.
+    pub fn shutdown(&mut self) {
+        let (tx, rx) = channel();
+        if self
+            .backend
+            .command_sender
+            .send(Command::Shutdown(tx))
+            .is_err()
+        {
+            return;
+        }
+
+        match rx.recv_timeout(SHUTDOWN_TIMEOUT) {
+            Ok(Err(err)) => eprintln!("Error shutting down the backend: {:?}", err),
+            Err(_) => eprintln!("The backend did not stop within {:?}", SHUTDOWN_TIMEOUT),
+            Ok(Ok(())) => {}
+        }
+    }
+
     pub fn update_server_port(&mut self, server_port: u16) {
         if let Some(file) = &self.chats.loaded_model {
             if !self.chats.model_loader.is_loading() {
@@ -111,6 +160,20 @@ impl Store {
         }
     }
 
+    /// Reload the file if it is the loaded model, so its preset applies right away.
+    pub fn apply_load_preset(&mut self, file_id: &FileID) {
+        if let Some(file) = &self.chats.loaded_model {
+            if file.id == *file_id {
+                self.chats.load_model(&file.clone(), None);
+            }
+        }
+    }
+
+    pub fn set_download_bandwidth_limit(&mut self, bytes_per_sec: Option<u64>) {
+        self.downloads.set_bandwidth_limit(bytes_per_sec);
+        self.preferences.set_download_bandwidth_limit(bytes_per_sec);
+    }
+
     fn update_load_model(&mut self) {
         if self.chats.model_loader.is_loaded() {
             self.chats.loaded_model = self
@@ -249,6 +312,7 @@ impl Store {
 
                 FileWithDownloadInfo {
                     file: file.clone(),
+                    model_type: model.model_type.clone(),
                     download,
                 }
             })
@@ -261,6 +325,9 @@ impl Store {
             size: model.size.clone(),
             requires: model.requires.clone(),
             architecture: model.architecture.clone(),
+            model_type: model.model_type.clone(),
+            trust: model.trust,
+            license: model.license.clone(),
             like_count: model.like_count,
             download_count: model.download_count,
             released_at: model.released_at,
@@ -300,6 +367,78 @@ impl Store {
         Ok(())
     }
 
+    pub fn set_storage_quota(&mut self, quota: StorageQuota) -> Result<()> {
+        self.downloads.set_storage_quota(quota)?;
+        self.enforce_storage_quota();
+        Ok(())
+    }
+
+    pub fn set_file_pinned(&mut self, file_id: &FileID, pinned: bool) -> Result<()> {
+        self.downloads.set_file_pinned(file_id, pinned)?;
+        // The proposed cleanup may have to pick other files
+        self.enforce_storage_quota();
+        Ok(())
+    }
+
+    /// Files the storage cleanup must leave, the loaded model and the models of
+    /// recent chats.
+    fn files_in_use(&self) -> Vec<FileID> {
+        let recent = Utc::now() - chrono::Duration::days(RECENT_CHAT_DAYS);
+        let mut file_ids: Vec<FileID> = self
+            .chats
+            .saved_chats
+            .iter()
+            .filter_map(|chat| {
+                let chat = chat.borrow();
+                match &chat.associated_entity {
+                    Some(ChatEntityId::ModelFile(file_id)) if chat.accessed_at >= recent => {
+                        Some(file_id.clone())
+                    }
+                    _ => None,
+                }
+            })
+            .collect();
+
+        file_ids.extend(self.chats.loaded_model.as_ref().map(|file| file.id.clone()));
+        file_ids
+    }
+
+    /// Check the downloaded files against the storage quota.
+    ///
+    /// When over it, the least recently used files are removed if the quota is set
+    /// to clean up on its own, otherwise they are proposed for removal in My Models.
+    pub fn enforce_storage_quota(&mut self) {
+        let plan = self.downloads.plan_storage_cleanup(self.files_in_use());
+
+        match plan {
+            Some(plan) if plan.auto_cleanup => {
+                self.downloads.storage_cleanup = None;
+                for file in plan.files {
+                    if let Err(err) = self.delete_file(file.file_id) {
+                        eprintln!("Error cleaning up storage: {:?}", err);
+                    }
+                }
+            }
+            plan => self.downloads.storage_cleanup = plan,
+        }
+    }
+
+    /// Remove the files proposed by the storage cleanup.
+    pub fn apply_storage_cleanup(&mut self) -> Result<()> {
+        let Some(plan) = self.downloads.storage_cleanup.take() else {
+            return Ok(());
+        };
+
+        // A proposed file may be in use since it was proposed
+        let in_use = self.files_in_use();
+        for file in plan.files {
+            if !in_use.contains(&file.file_id) {
+                self.delete_file(file.file_id)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn handle_action(&mut self, action: &Action) {
         self.chats.handle_action(action);
         self.search.handle_action(action);
@@ -312,6 +451,16 @@ impl Store {
         if let Some(_) = action.downcast_ref::<DownloadFileAction>() {
             self.update_downloads();
         }
+
+        // A new catalog may have newer revisions of the downloaded files, and the
+        // first sync of a fresh install brings the default embedding model, which
+        // isn't needed once the user picked one
+        if let Some(SearchAction::CatalogUpdated(_)) = action.downcast_ref::<SearchAction>() {
+            self.downloads.load_downloaded_files();
+            if self.downloads.embedding_file_id.is_none() {
+                self.downloads.download_default_embedding_model();
+            }
+        }
     }
 
     fn update_downloads(&mut self) {
@@ -319,10 +468,15 @@ impl Store {
 
         // For search results let's trust on our local cache, but updating
         // the downloaded state of the files
+        let completed = !completed_download_ids.is_empty();
         for file_id in completed_download_ids {
             self.search
                 .update_downloaded_file_in_search_results(&file_id, true);
         }
+
+        if completed {
+            self.enforce_storage_quota();
+        }
     }
 
     fn init_current_chat(&mut self) {
@@ -0,0 +1,66 @@
+use crate::data::store::StoreAction;
+use makepad_widgets::*;
+use moly_protocol::data::ModelType;
+
+live_design! {
+    use link::theme::*;
+    use link::shaders::*;
+    use link::widgets::*;
+
+    use crate::shared::styles::*;
+    use crate::landing::sorting::ModelsDropDown;
+
+    pub Categories = {{Categories}} {
+        width: Fit,
+        height: Fit,
+        align: {x: 0.5, y: 0.5},
+
+        <Label> {
+            draw_text:{
+                text_style: <REGULAR_FONT>{font_size: 9},
+                color: #667085
+            }
+            text: "CATEGORY"
+        }
+
+        options = <ModelsDropDown> {
+            width: 220,
+            height: Fit,
+
+            margin: { left: 20 }
+
+            labels: ["All", "Chat", "Embedding", "Code", "Vision"]
+            values: [All, Chat, Embedding, Code, Vision]
+        }
+    }
+}
+
+#[derive(Live, LiveHook, Widget)]
+pub struct Categories {
+    #[deref]
+    view: View,
+}
+
+impl Widget for Categories {
+    fn handle_event(&mut self, cx: &mut Cx, event: &Event, scope: &mut Scope) {
+        self.view.handle_event(cx, event, scope);
+        self.widget_match_event(cx, event, scope);
+    }
+
+    fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
+        self.view.draw_walk(cx, scope, walk)
+    }
+}
+
+impl WidgetMatchEvent for Categories {
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, _scope: &mut Scope) {
+        if let Some(item_selected) = self.drop_down(id!(options)).selected(&actions) {
+            // The first item shows every category, the rest follow `ModelType::CATEGORIES`
+            let category = item_selected
+                .checked_sub(1)
+                .and_then(|i| ModelType::CATEGORIES.get(i).cloned());
+
+            cx.action(StoreAction::SetCategory(category));
+        }
+    }
+}
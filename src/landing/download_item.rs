@@ -2,7 +2,7 @@ use crate::{
     data::downloads::download::DownloadFileAction,
     shared::{
         actions::DownloadAction,
-        utils::{format_model_downloaded_size, format_model_size},
+        utils::{format_model_downloaded_size, format_model_size, BYTES_PER_MB},
     },
 };
 use makepad_widgets::*;
@@ -15,6 +15,7 @@ live_design! {
 
     use crate::shared::styles::*;
     use crate::shared::widgets::MolyButton;
+    use crate::shared::widgets::MolyTextInput;
 
     ICON_PAUSE = dep("crate://self/resources/icons/pause_download.svg")
     ICON_CANCEL = dep("crate://self/resources/icons/cancel_download.svg")
@@ -156,6 +157,32 @@ live_design! {
 
         align: {x: 0.5, y: 0.5},
 
+        speed_limit = <View> {
+            width: Fit,
+            height: Fit,
+            flow: Right,
+            spacing: 6,
+            align: {x: 0.0, y: 0.5},
+
+            <Label> {
+                draw_text: {
+                    text_style: <REGULAR_FONT>{font_size: 9},
+                    color: #667085
+                }
+                text: "Max MB/s"
+            }
+
+            speed_limit_input = <MolyTextInput> {
+                width: 70,
+                height: Fit,
+                empty_message: "No limit"
+                draw_text: {
+                    text_style: <REGULAR_FONT>{font_size: 9},
+                    color: #000
+                }
+            }
+        }
+
         pause_button = <ActionButton> {
             draw_icon: {
                 svg_file: (ICON_PAUSE),
@@ -170,6 +197,18 @@ live_design! {
             icon_walk: { margin: { left: 6 } }
         }
 
+        start_next_button = <ActionButton> {
+            width: Fit,
+            padding: {left: 12, right: 12},
+            text: "Start next",
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 9},
+                fn get_color(self) -> vec4 {
+                    return #667085;
+                }
+            }
+        }
+
         retry_button = <ActionButton> {
             draw_icon: {
                 svg_file: (ICON_RETRY),
@@ -224,6 +263,14 @@ impl Widget for DownloadItem {
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
         let download = scope.data.get::<PendingDownload>().unwrap();
+        // Not to overwrite the limit being typed, only when showing another download
+        if self.file_id.as_ref() != Some(&download.file.id) {
+            let limit = download
+                .bandwidth_limit
+                .map(|limit| format!("{}", limit as f64 / BYTES_PER_MB))
+                .unwrap_or_default();
+            self.text_input(id!(speed_limit_input)).set_text(cx, &limit);
+        }
         self.file_id = Some(download.file.id.clone());
 
         self.label(id!(filename))
@@ -258,8 +305,39 @@ impl Widget for DownloadItem {
 
                 self.button(id!(pause_button)).set_visible(cx, false);
                 self.button(id!(play_button)).set_visible(cx, false);
+                self.button(id!(start_next_button)).set_visible(cx, false);
                 self.button(id!(retry_button)).set_visible(cx, false);
                 self.button(id!(cancel_button)).set_visible(cx, false);
+                self.view(id!(speed_limit)).set_visible(cx, false);
+            }
+            PendingDownloadsStatus::Queued => {
+                let queued_color = vec3(0.208, 0.349, 0.612); //#35599C
+
+                let text = match download.queue_position {
+                    Some(position) => format!("Queued #{} {:.1}%", position + 1, download.progress),
+                    None => format!("Queued {:.1}%", download.progress),
+                };
+                label.set_text(cx, &text);
+                label.apply_over(
+                    cx,
+                    live! { draw_text: { color: (queued_color) }
+                    },
+                );
+
+                self.view(id!(progress_bar)).apply_over(
+                    cx,
+                    live! {
+                        width: (progress_bar_width)
+                        draw_bg: { color: (queued_color) }
+                    },
+                );
+
+                self.button(id!(pause_button)).set_visible(cx, true);
+                self.button(id!(play_button)).set_visible(cx, false);
+                self.button(id!(start_next_button)).set_visible(cx, true);
+                self.button(id!(retry_button)).set_visible(cx, false);
+                self.button(id!(cancel_button)).set_visible(cx, true);
+                self.view(id!(speed_limit)).set_visible(cx, true);
             }
             PendingDownloadsStatus::Downloading => {
                 let downloading_color = vec3(0.035, 0.572, 0.314); //#099250
@@ -281,8 +359,10 @@ impl Widget for DownloadItem {
 
                 self.button(id!(pause_button)).set_visible(cx, true);
                 self.button(id!(play_button)).set_visible(cx, false);
+                self.button(id!(start_next_button)).set_visible(cx, false);
                 self.button(id!(retry_button)).set_visible(cx, false);
                 self.button(id!(cancel_button)).set_visible(cx, true);
+                self.view(id!(speed_limit)).set_visible(cx, true);
             }
             PendingDownloadsStatus::Paused => {
                 let paused_color = vec3(0.4, 0.44, 0.52); //#667085
@@ -304,8 +384,10 @@ impl Widget for DownloadItem {
 
                 self.button(id!(pause_button)).set_visible(cx, false);
                 self.button(id!(play_button)).set_visible(cx, true);
+                self.button(id!(start_next_button)).set_visible(cx, false);
                 self.button(id!(retry_button)).set_visible(cx, false);
                 self.button(id!(cancel_button)).set_visible(cx, true);
+                self.view(id!(speed_limit)).set_visible(cx, true);
             }
             PendingDownloadsStatus::Error => {
                 let failed_color = vec3(0.7, 0.11, 0.09); // #B42318
@@ -327,8 +409,10 @@ impl Widget for DownloadItem {
 
                 self.button(id!(pause_button)).set_visible(cx, false);
                 self.button(id!(play_button)).set_visible(cx, false);
+                self.button(id!(start_next_button)).set_visible(cx, false);
                 self.button(id!(retry_button)).set_visible(cx, true);
                 self.button(id!(cancel_button)).set_visible(cx, true);
+                self.view(id!(speed_limit)).set_visible(cx, false);
             }
         }
 
@@ -360,6 +444,26 @@ impl WidgetMatchEvent for DownloadItem {
             }
         }
 
+        if self.button(id!(start_next_button)).clicked(&actions) {
+            let Some(file_id) = &self.file_id else { return };
+            cx.action(DownloadAction::StartNext(file_id.clone()));
+        }
+
+        if let Some(limit) = self.text_input(id!(speed_limit_input)).returned(actions) {
+            let Some(file_id) = &self.file_id else { return };
+            // Anything that is not a positive number removes the limit
+            let bytes_per_sec = limit
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|mb| *mb > 0.0)
+                .map(|mb| (mb * BYTES_PER_MB) as u64);
+            cx.action(DownloadAction::SetBandwidthLimit(
+                file_id.clone(),
+                bytes_per_sec,
+            ));
+        }
+
         if self.button(id!(pause_button)).clicked(&actions) {
             let Some(file_id) = &self.file_id else { return };
             cx.action(DownloadAction::Pause(file_id.clone()));
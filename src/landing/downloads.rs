@@ -46,6 +46,14 @@ live_design! {
             text: "1 downloading"
         }
 
+        queued_count = <Label> {
+            draw_text:{
+                text_style: <REGULAR_FONT>{font_size: 9},
+                color: #35599C
+            }
+            text: "1 queued"
+        }
+
         paused_count = <Label> {
             draw_text:{
                 text_style: <REGULAR_FONT>{font_size: 9},
@@ -168,6 +176,18 @@ impl Widget for Downloads {
         self.label(id!(downloading_count))
             .set_text(cx, &format!("{} downloading", download_count));
 
+        let queued_count = pending_downloads
+            .iter()
+            .filter(|d| matches!(d.status, PendingDownloadsStatus::Queued))
+            .count();
+
+        if queued_count > 0 {
+            self.label(id!(queued_count))
+                .set_text(cx, &format!("{} queued", queued_count));
+        } else {
+            self.label(id!(queued_count)).set_text(cx, "");
+        }
+
         let paused_count = pending_downloads
             .iter()
             .filter(|d| matches!(d.status, PendingDownloadsStatus::Paused))
@@ -11,6 +11,7 @@ live_design! {
 
     use crate::shared::styles::*;
     use crate::landing::search_bar::SearchBar;
+    use crate::landing::categories::Categories;
     use crate::landing::model_list::ModelList;
     use crate::landing::downloads::Downloads;
 
@@ -29,11 +30,11 @@ live_design! {
             margin: { left: 50, right: 50},
 
             heading_with_filters = <View> {
-                width: Fit,
+                width: Fill,
                 height: 50,
                 padding: {top: 30},
 
-                align: {x: 0.5, y: 0.5},
+                align: {x: 0.0, y: 0.5},
 
                 results = <Label> {
                     draw_text:{
@@ -49,6 +50,10 @@ live_design! {
                     }
                     text: " for \"Open Hermes\""
                 }
+
+                <View> { width: Fill, height: Fit }
+
+                <Categories> {}
             }
 
             <ModelList> {}
@@ -83,20 +88,29 @@ impl Widget for LandingScreen {
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
         let search = &scope.data.get::<Store>().unwrap().search;
-        if search.is_pending() || search.was_error() {
-            self.view(id!(heading_with_filters)).set_visible(cx, false);
+
+        // The heading holds the category filter, so it is always shown
+        let (results, keyword) = if search.is_pending() || search.was_error() {
+            (String::new(), String::new())
         } else if let Some(keyword) = search.keyword.clone() {
-            self.view(id!(heading_with_filters)).set_visible(cx, true);
-
-            let models = &search.models;
-            let models_count = models.len();
-            self.label(id!(heading_with_filters.results))
-                .set_text(cx, &format!("{} Results", models_count));
-            self.label(id!(heading_with_filters.keyword))
-                .set_text(cx, &format!(" for \"{}\"", keyword));
+            (
+                format!("{} Results", search.total_results),
+                format!(" for \"{}\"", keyword),
+            )
+        } else if let Some(category) = &search.category {
+            (
+                format!("{} Results", search.total_results),
+                format!(" in {}", category.label()),
+            )
+        } else if !search.sections.is_empty() {
+            ("Featured collections".to_string(), String::new())
         } else {
-            self.view(id!(heading_with_filters)).set_visible(cx, false);
-        }
+            ("Featured models".to_string(), String::new())
+        };
+        self.label(id!(heading_with_filters.results))
+            .set_text(cx, &results);
+        self.label(id!(heading_with_filters.keyword))
+            .set_text(cx, &keyword);
 
         self.view.draw_walk(cx, scope, walk)
     }
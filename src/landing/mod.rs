@@ -1,3 +1,4 @@
+pub mod categories;
 pub mod download_item;
 pub mod downloads;
 pub mod landing_screen;
@@ -26,6 +27,7 @@ pub fn live_design(cx: &mut Cx) {
     search_bar::live_design(cx);
     search_loading::live_design(cx);
     sorting::live_design(cx);
+    categories::live_design(cx);
     downloads::live_design(cx);
     download_item::live_design(cx);
 }
@@ -1,9 +1,10 @@
-use crate::data::store::ModelWithDownloadInfo;
+use crate::data::store::{ModelWithDownloadInfo, StoreAction};
 use crate::shared::external_link::ExternalLinkWidgetExt;
 use crate::shared::modal::ModalWidgetExt;
 use crate::shared::utils::hugging_face_model_url;
-use chrono::Utc;
+use chrono::{Local, Utc};
 use makepad_widgets::*;
+use moly_protocol::data::CatalogTrust;
 use unicode_segmentation::UnicodeSegmentation;
 
 live_design! {
@@ -40,12 +41,20 @@ live_design! {
             <View> {
                 width: Fit,
                 height: Fit,
+                spacing: 10,
+                align: {x: 0.0, y: 0.5},
                 model_name = <Label> {
                     draw_text: {
                         text_style: <BOLD_FONT>{font_size: 16},
                         color: #000
                     }
                 }
+                model_trust_label = <Label> {
+                    draw_text: {
+                        text_style: <BOLD_FONT>{font_size: 9},
+                        color: #B54708
+                    }
+                }
             }
 
 
@@ -205,6 +214,68 @@ live_design! {
             model_hugging_face_link = <ExternalLink> { link = { text: "Hugging Face" } }
             <ExternalLinkIcon> {}
         }
+
+        license_view = <View> {
+            width: Fill,
+            height: Fit,
+            flow: Down,
+            spacing: 10,
+
+            <View> {
+                align: {x: 0.5, y: 1.0},
+                width: Fit,
+                height: Fit,
+                license_link = <ExternalLink> {}
+                <ExternalLinkIcon> {}
+            }
+
+            license_acceptance = <View> {
+                visible: false,
+                width: Fill,
+                height: Fit,
+                spacing: 10,
+                align: {x: 0.0, y: 0.5},
+
+                <Label> {
+                    width: Fill,
+                    draw_text: {
+                        text_style: <REGULAR_FONT>{font_size: 9},
+                        color: #B54708,
+                        wrap: Word
+                    }
+                    text: "The terms of this license must be accepted before downloading the model"
+                }
+
+                accept_license_button = <MolyButton> {
+                    width: Fit,
+                    height: Fit,
+                    padding: {top: 6, bottom: 6, left: 12, right: 12}
+
+                    draw_bg: {
+                        border_color: #D0D5DD,
+                        border_width: 1,
+                        color: #fff,
+                        color_hover: #E2F1F1,
+                        radius: 3
+                    }
+
+                    text: "Accept license"
+                    draw_text: {
+                        text_style: <REGULAR_FONT>{font_size: 9},
+                        fn get_color(self) -> vec4 {
+                            return #000;
+                        }
+                    }
+                }
+            }
+
+            license_accepted_label = <Label> {
+                draw_text: {
+                    text_style: <REGULAR_FONT>{font_size: 9},
+                    color: #667085
+                }
+            }
+        }
     }
 
     ModelInformation = <View> {
@@ -350,6 +421,13 @@ impl Widget for ModelCard {
         let name = &model.name;
         self.label(id!(model_name)).set_text(cx, name);
 
+        let trust = match model.trust {
+            CatalogTrust::Verified => "",
+            CatalogTrust::Unsigned => "Unverified",
+            CatalogTrust::Invalid => "Invalid signature",
+        };
+        self.label(id!(model_trust_label)).set_text(cx, trust);
+
         let download_count = &model.download_count;
         self.label(id!(model_download_count.attr_value))
             .set_text(cx, &format!("{}", download_count));
@@ -369,6 +447,10 @@ impl Widget for ModelCard {
         self.label(id!(model_architecture_tag.attr_value))
             .set_text(cx, architecture);
 
+        let model_type = model.model_type.label();
+        self.label(id!(model_type_tag.attr_value))
+            .set_text(cx, model_type);
+
         let summary = &model.summary;
         const MAX_SUMMARY_LENGTH: usize = 500;
         let trimmed_summary = if summary.len() > MAX_SUMMARY_LENGTH {
@@ -394,6 +476,32 @@ impl Widget for ModelCard {
         let mut model_hugging_face_external_link = self.external_link(id!(model_hugging_face_link));
         model_hugging_face_external_link.set_url(&model_hugging_face_url);
 
+        let license = &model.license;
+        self.view(id!(license_view))
+            .set_visible(cx, !license.id.is_empty() || !license.url.is_empty());
+        let license_name = if license.id.is_empty() {
+            "License".to_string()
+        } else {
+            format!("License: {}", license.id)
+        };
+        let mut license_external_link = self.external_link(id!(license_link));
+        license_external_link
+            .link_label(id!(link))
+            .set_text(cx, &license_name);
+        license_external_link.set_url(&license.url);
+
+        self.view(id!(license_acceptance))
+            .set_visible(cx, license.needs_acceptance());
+        let accepted = match license.accepted_at {
+            Some(accepted_at) if license.requires_acceptance => format!(
+                "Accepted on {}",
+                accepted_at.with_timezone(&Local).format("%Y-%m-%d")
+            ),
+            _ => String::new(),
+        };
+        self.label(id!(license_accepted_label))
+            .set_text(cx, &accepted);
+
         let author_description = &model.author.description;
         self.label(id!(author_description))
             .set_text(cx, author_description);
@@ -408,6 +516,10 @@ impl Widget for ModelCard {
 
 impl WidgetMatchEvent for ModelCard {
     fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, _scope: &mut Scope) {
+        if self.button(id!(accept_license_button)).clicked(actions) {
+            cx.action(StoreAction::AcceptModelLicense(self.model_id.clone()));
+        }
+
         if self.link_label(id!(view_all_button.link)).clicked(actions) {
             self.modal(id!(modal)).open(cx);
             self.redraw(cx);
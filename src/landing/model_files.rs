@@ -247,7 +247,10 @@ impl WidgetMatchEvent for ModelFiles {
 
         for action in actions.iter() {
             match action.cast() {
-                StoreAction::Search(_) | StoreAction::ResetSearch | StoreAction::Sort(_) => {
+                StoreAction::Search(_)
+                | StoreAction::ResetSearch
+                | StoreAction::Sort(_)
+                | StoreAction::SetCategory(_) => {
                     self.expand_without_animation(cx);
                     self.actual_height = None;
                     self.radio_button(id!(show_all_button)).select(cx, scope);
@@ -1,5 +1,5 @@
 use makepad_widgets::*;
-use moly_protocol::data::{File, FileID, PendingDownloadsStatus};
+use moly_protocol::data::{File, FileID, ModelType, PendingDownloadsStatus};
 
 use super::model_files_tags::ModelFilesTagsWidgetExt;
 use crate::{
@@ -8,7 +8,7 @@ use crate::{
         store::FileWithDownloadInfo,
     },
     shared::{
-        actions::{ChatAction, DownloadAction},
+        actions::{ChatAction, DownloadAction, EmbeddingAction},
         utils::format_model_size,
     },
 };
@@ -71,6 +71,14 @@ live_design! {
         }
     }
 
+    UseEmbeddingButton = <ModelCardButton> {
+        draw_bg: { color: #fff, color_hover: #09925033, border_color: #d0d5dd }
+        text: "Use for Embeddings"
+        draw_text: {
+            color: #087443;
+        }
+    }
+
     DownloadPendingButton = <MolyButton> {
         width: 25,
         height: 25,
@@ -193,6 +201,15 @@ live_design! {
         cell4 = {
             download_button = <DownloadButton> { visible: false }
             start_chat_button = <StartChatButton> { visible: false }
+            use_embedding_button = <UseEmbeddingButton> { visible: false }
+            downloaded_label = <Label> {
+                visible: false
+                text: "Downloaded"
+                draw_text: {
+                    text_style: <REGULAR_FONT>{font_size: 9},
+                    color: #087443
+                }
+            }
             download_pending_controls = <DownloadPendingControls> { visible: false }
         }
     }
@@ -222,7 +239,10 @@ impl Widget for ModelFilesItem {
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
         let files_info = &scope.props.get::<FileWithDownloadInfo>().unwrap();
         let filename = &files_info.file.name;
-        let size = format_model_size(&files_info.file.size).unwrap_or("-".to_string());
+        let mut size = format_model_size(&files_info.file.size).unwrap_or("-".to_string());
+        if files_info.file.parts.len() > 1 {
+            size = format!("{size} ({} parts)", files_info.file.parts.len());
+        }
         let quantization = &files_info.file.quantization;
         self.apply_over(
             cx,
@@ -244,8 +264,10 @@ impl Widget for ModelFilesItem {
 
             let is_resume_download_visible =
                 matches!(download.status, PendingDownloadsStatus::Paused);
-            let is_pause_download_visible =
-                matches!(download.status, PendingDownloadsStatus::Downloading);
+            let is_pause_download_visible = matches!(
+                download.status,
+                PendingDownloadsStatus::Downloading | PendingDownloadsStatus::Queued
+            );
             let is_retry_download_visible =
                 matches!(download.status, PendingDownloadsStatus::Error);
             let is_cancel_download_visible =
@@ -255,8 +277,9 @@ impl Widget for ModelFilesItem {
                 PendingDownloadsStatus::Downloading | PendingDownloadsStatus::Initializing => {
                     vec3(0.035, 0.572, 0.314)
                 } // #099250
-                PendingDownloadsStatus::Paused => vec3(0.4, 0.44, 0.52), // #667085
-                PendingDownloadsStatus::Error => vec3(0.7, 0.11, 0.09),  // #B42318
+                PendingDownloadsStatus::Queued => vec3(0.208, 0.349, 0.612), // #35599C
+                PendingDownloadsStatus::Paused => vec3(0.4, 0.44, 0.52),     // #667085
+                PendingDownloadsStatus::Error => vec3(0.7, 0.11, 0.09),      // #B42318
             };
 
             self.apply_over(
@@ -294,15 +317,23 @@ impl Widget for ModelFilesItem {
                         }
                     }
                     start_chat_button = { visible: false }
+                    use_embedding_button = { visible: false }
+                    downloaded_label = { visible: false }
                     download_button = { visible: false }
                 }},
             );
         } else if files_info.file.downloaded {
+            // Only chat models can be chatted with, embedding models go to the embedding feature
+            let is_chat = files_info.model_type.is_chat();
+            let is_embedding = files_info.model_type == ModelType::Embedding;
+            let is_other = !is_chat && !is_embedding;
             self.apply_over(
                 cx,
                 live! { cell4 = {
                     download_pending_controls = { visible: false }
-                    start_chat_button = { visible: true }
+                    start_chat_button = { visible: (is_chat) }
+                    use_embedding_button = { visible: (is_embedding) }
+                    downloaded_label = { visible: (is_other) }
                     download_button = { visible: false }
                 }},
             );
@@ -312,6 +343,8 @@ impl Widget for ModelFilesItem {
                 live! { cell4 = {
                     download_pending_controls = { visible: false }
                     start_chat_button = { visible: false }
+                    use_embedding_button = { visible: false }
+                    downloaded_label = { visible: false }
                     download_button = { visible: true }
                 }},
             );
@@ -343,6 +376,10 @@ impl WidgetMatchEvent for ModelFilesItem {
             cx.action(ChatAction::Start(ChatEntityId::ModelFile(file_id.clone())));
         }
 
+        if self.button(id!(use_embedding_button)).clicked(&actions) {
+            cx.action(EmbeddingAction::Use(Some(file_id.clone())));
+        }
+
         if [id!(resume_download_button), id!(retry_download_button)]
             .iter()
             .any(|id| self.button(*id).clicked(&actions))
@@ -167,7 +167,7 @@ impl Widget for ModelList {
                 margin_bottom: f32,
             },
             NoAgentsWarning(&'static str),
-            Header(&'static str),
+            Header(&'a str),
             Model(&'a Model),
         }
 
@@ -196,10 +196,23 @@ impl Widget for ModelList {
                     }
                 }
             }
-            items.push(Item::Header("Models"));
+            if store.search.sections.is_empty() {
+                items.push(Item::Header("Models"));
+            }
         }
 
-        items.extend(store.search.models.iter().map(Item::Model));
+        if store.search.sections.is_empty() {
+            items.extend(store.search.models.iter().map(Item::Model));
+        } else {
+            for section in &store.search.sections {
+                items.push(Item::Header(&section.name));
+                items.extend(
+                    store.search.models[section.range.clone()]
+                        .iter()
+                        .map(Item::Model),
+                );
+            }
+        }
 
         while let Some(view_item) = self.view.draw_walk(cx, &mut Scope::empty(), walk).step() {
             if let Some(mut list) = view_item.as_portal_list().borrow_mut() {
@@ -299,7 +312,7 @@ impl WidgetMatchEvent for ModelList {
             }
 
             match action.cast() {
-                StoreAction::Search(_) | StoreAction::ResetSearch => {
+                StoreAction::Search(_) | StoreAction::ResetSearch | StoreAction::SetCategory(_) => {
                     self.view(id!(search_error)).set_visible(cx, false);
                     self.view(id!(loading)).set_visible(cx, true);
                     self.search_loading(id!(search_loading)).animate(cx);
@@ -85,5 +85,10 @@ live_design! {
             draw_bg: { color: #F0D6F5 },
             attr_name = { text: "Architecture" }
         }
+
+        model_type_tag = <ModelAttributeTag> {
+            draw_bg: { color: #F5EBD6 },
+            attr_name = { text: "Type" }
+        }
     }
 }
@@ -8,7 +8,7 @@ live_design! {
 
     use crate::shared::styles::*;
 
-    ModelsDropDown = <DropDown> {
+    pub ModelsDropDown = <DropDown> {
         width: Fit
         height: Fit
         padding: {top: 20.0, right: 10.0, bottom: 20.0, left: 16.0}
@@ -1,10 +1,16 @@
 use super::{delete_model_modal::DeleteModelModalAction, model_info_modal::ModelInfoModalAction};
 use crate::data::chats::chat_entity::ChatEntityId;
+use crate::data::store::Store;
 use crate::shared::modal::ModalWidgetExt;
 use crate::shared::utils::format_model_size;
-use crate::shared::{actions::ChatAction, utils::human_readable_name};
+use crate::shared::{
+    actions::{ChatAction, DownloadAction, EmbeddingAction, StorageAction},
+    utils::human_readable_name,
+};
 use makepad_widgets::*;
-use moly_protocol::data::{DownloadedFile, FileID};
+use moly_protocol::data::{
+    ChatTemplateSettings, DownloadedFile, FileID, LoadPresets, ModelType, UsageStats,
+};
 
 live_design! {
     use link::theme::*;
@@ -68,6 +74,32 @@ live_design! {
                     draw_bg: { color: #D4E6F7 },
                 }
             }
+            update_tag = <View> {
+                width: Fit
+                align: {x: 0.0, y: 0.5}
+                update_button = <MolyButton> {
+                    visible: false
+                    width: Fit
+                    height: Fit
+                    padding: {top: 4, bottom: 4, left: 8, right: 8}
+
+                    draw_bg: {
+                        border_color: #FEC84B,
+                        border_width: 1,
+                        color: #FFFAEB,
+                        color_hover: #FEF0C7,
+                        radius: 3
+                    }
+
+                    text: "Update available"
+                    draw_text: {
+                        text_style: <REGULAR_FONT>{font_size: 9},
+                        fn get_color(self) -> vec4 {
+                            return #B54708;
+                        }
+                    }
+                }
+            }
         }
         model_version_tag = <View> {
             width: Fit
@@ -95,7 +127,7 @@ live_design! {
     }
 
     RowActions = <View> {
-        width: 250
+        width: 320
         flow: Right
         spacing: 10
         align: {x: 0.0, y: 0.5}
@@ -113,8 +145,37 @@ live_design! {
             }
         }
 
+        use_embedding_button = <DownloadedFilesRowButton> {
+            visible: false
+            width: 140
+            text: "Use for Embeddings",
+            draw_text: {
+                color: (MODEL_CTA_COLOR)
+                text_style: <REGULAR_FONT>{font_size: 9}
+            }
+        }
+
+        stop_embedding_button = <DownloadedFilesRowButton> {
+            visible: false
+            width: 140
+            text: "Stop Using",
+            draw_text: {
+                color: (MODEL_CTA_COLOR)
+                text_style: <REGULAR_FONT>{font_size: 9}
+            }
+        }
+
         <View> { width: Fill, height: Fit }
 
+        pin_button = <DownloadedFilesRowButton> {
+            width: 60
+            text: "Pin",
+            draw_text: {
+                color: (MODEL_CTA_COLOR)
+                text_style: <REGULAR_FONT>{font_size: 9}
+            }
+        }
+
         info_button = <DownloadedFilesRowButton> {
             width: 40
             draw_icon: {
@@ -185,6 +246,14 @@ live_design! {
 
 pub struct DownloadedFilesRowProps {
     pub downloaded_file: DownloadedFile,
+    // The file is the embedding model served along the chat models
+    pub is_embedding_model: bool,
+    // Progress of the download of its new revision, while it is being updated
+    pub update_progress: Option<f64>,
+    // Fetched when the info modal opens
+    pub usage_stats: Option<UsageStats>,
+    pub load_presets: Option<LoadPresets>,
+    pub chat_template: Option<ChatTemplateSettings>,
 }
 
 #[derive(Live, LiveHook, Widget)]
@@ -194,6 +263,12 @@ pub struct DownloadedFilesRow {
 
     #[rust]
     file_id: Option<FileID>,
+
+    #[rust]
+    updating: bool,
+
+    #[rust]
+    pinned: bool,
 }
 
 impl Widget for DownloadedFilesRow {
@@ -237,6 +312,16 @@ impl Widget for DownloadedFilesRow {
         self.label(id!(h_wrapper.model_file.model_version_tag.version))
             .set_text(cx, &filename);
 
+        // Update tag
+        let update_button =
+            self.button(id!(h_wrapper.model_file.h_wrapper.update_tag.update_button));
+        update_button.set_visible(cx, downloaded_file.update_available);
+        match props.update_progress {
+            Some(progress) => update_button.set_text(cx, &format!("Updating {:.0}%", progress)),
+            None => update_button.set_text(cx, "Update available"),
+        }
+        self.updating = props.update_progress.is_some();
+
         // File size tag
         let file_size = format_model_size(&downloaded_file.file.size).unwrap_or("-".to_string());
         self.label(id!(h_wrapper.file_size_tag.label))
@@ -247,19 +332,63 @@ impl Widget for DownloadedFilesRow {
         self.label(id!(h_wrapper.date_added_tag.label))
             .set_text(cx, &formatted_date);
 
+        // Only chat models can be chatted with
+        let model_type = &downloaded_file.model.model_type;
+        let is_embedding = *model_type == ModelType::Embedding;
+        self.button(id!(row_actions.start_chat_button))
+            .set_visible(cx, model_type.is_chat());
+        self.button(id!(row_actions.use_embedding_button))
+            .set_visible(cx, is_embedding && !props.is_embedding_model);
+        self.button(id!(row_actions.stop_embedding_button))
+            .set_visible(cx, is_embedding && props.is_embedding_model);
+
+        // Pinned files are never removed by the storage cleanup
+        self.pinned = downloaded_file.pinned;
+        let pin_text = if self.pinned { "Unpin" } else { "Pin" };
+        self.button(id!(row_actions.pin_button))
+            .set_text(cx, pin_text);
+
         self.view.draw_walk(cx, scope, walk)
     }
 }
 
 impl WidgetMatchEvent for DownloadedFilesRow {
-    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, _scope: &mut Scope) {
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
         if self.button(id!(start_chat_button)).clicked(actions) {
             if let Some(file_id) = &self.file_id {
                 cx.action(ChatAction::Start(ChatEntityId::ModelFile(file_id.clone())));
             }
         }
 
+        if self.button(id!(use_embedding_button)).clicked(actions) {
+            if let Some(file_id) = &self.file_id {
+                cx.action(EmbeddingAction::Use(Some(file_id.clone())));
+            }
+        }
+
+        if self.button(id!(stop_embedding_button)).clicked(actions) {
+            cx.action(EmbeddingAction::Use(None));
+        }
+
+        if self.button(id!(update_button)).clicked(actions) && !self.updating {
+            if let Some(file_id) = &self.file_id {
+                cx.action(DownloadAction::Update(file_id.clone()));
+            }
+        }
+
+        if self.button(id!(row_actions.pin_button)).clicked(actions) {
+            if let Some(file_id) = &self.file_id {
+                cx.action(StorageAction::SetPinned(file_id.clone(), !self.pinned));
+            }
+        }
+
         if self.button(id!(row_actions.info_button)).clicked(actions) {
+            if let Some(file_id) = &self.file_id {
+                let store = scope.data.get_mut::<Store>().unwrap();
+                store.downloads.load_usage_stats(file_id);
+                store.downloads.load_load_presets(file_id);
+                store.downloads.load_chat_template(file_id);
+            }
             self.modal(id!(info_modal)).open(cx);
         }
 
@@ -1,5 +1,5 @@
 use makepad_widgets::*;
-use moly_protocol::data::DownloadedFile;
+use moly_protocol::data::{ChatTemplateSettings, DownloadedFile, LoadPresets, UsageStats};
 
 use crate::data::store::Store;
 
@@ -114,6 +114,44 @@ impl Widget for DownloadedFilesTable {
         let entries_count = self.current_results.len();
         let last_item_id = if entries_count > 0 { entries_count } else { 0 };
 
+        let embedding_file_id = scope
+            .data
+            .get::<Store>()
+            .unwrap()
+            .downloads
+            .embedding_file_id
+            .clone();
+
+        // Updated files keep their place in the list until the new revision is in
+        let update_progress: Vec<Option<f64>> = {
+            let downloads = &scope.data.get::<Store>().unwrap().downloads;
+            self.current_results
+                .iter()
+                .map(|f| downloads.update_progress(&f.file.id))
+                .collect()
+        };
+        let usage_stats: Vec<Option<UsageStats>> = {
+            let downloads = &scope.data.get::<Store>().unwrap().downloads;
+            self.current_results
+                .iter()
+                .map(|f| downloads.usage_stats.get(&f.file.id).cloned())
+                .collect()
+        };
+        let load_presets: Vec<Option<LoadPresets>> = {
+            let downloads = &scope.data.get::<Store>().unwrap().downloads;
+            self.current_results
+                .iter()
+                .map(|f| downloads.load_presets.get(&f.file.id).cloned())
+                .collect()
+        };
+        let chat_templates: Vec<Option<ChatTemplateSettings>> = {
+            let downloads = &scope.data.get::<Store>().unwrap().downloads;
+            self.current_results
+                .iter()
+                .map(|f| downloads.chat_templates.get(&f.file.id).cloned())
+                .collect()
+        };
+
         while let Some(item) = self.view.draw_walk(cx, scope, walk).step() {
             if let Some(mut list) = item.as_portal_list().borrow_mut() {
                 list.set_item_range(cx, 0, last_item_id + 1);
@@ -138,6 +176,12 @@ impl Widget for DownloadedFilesTable {
 
                         let props = DownloadedFilesRowProps {
                             downloaded_file: file_data.clone(),
+                            is_embedding_model: embedding_file_id.as_ref()
+                                == Some(&file_data.file.id),
+                            update_progress: update_progress[item_id - 1],
+                            usage_stats: usage_stats[item_id - 1].clone(),
+                            load_presets: load_presets[item_id - 1].clone(),
+                            chat_template: chat_templates[item_id - 1].clone(),
                         };
                         let mut scope = Scope::with_props(&props);
                         item.draw_all(cx, &mut scope);
@@ -1,5 +1,11 @@
+use crate::data::store::Store;
 use crate::shared::utils::hugging_face_model_url;
+use anyhow::anyhow;
+use chrono::Local;
 use makepad_widgets::*;
+use moly_protocol::data::{
+    ChatTemplateSettings, ChatTemplateSource, FileID, LoadPreset, LoadPresets, UsageStats,
+};
 
 use super::downloaded_files_row::DownloadedFilesRowProps;
 
@@ -9,8 +15,9 @@ live_design! {
     use link::widgets::*;
 
     use crate::shared::styles::*;
-    use crate::shared::widgets::MolyButton;
+    use crate::shared::widgets::{MolyButton, MolyTextInput, MolySwitch};
     use crate::shared::resource_imports::*;
+    use crate::landing::sorting::ModelsDropDown;
 
     MolyHtml = <Html> {
         font_color: #000,
@@ -22,6 +29,46 @@ live_design! {
         code_layout: { padding: 15, }
     }
 
+    ModalButton = <MolyButton> {
+        width: Fit,
+        height: Fit,
+        padding: {top: 10, bottom: 10, left: 14, right: 14}
+
+        draw_bg: {
+            instance radius: 2.0,
+            border_color: #D0D5DD,
+            border_width: 1.2,
+            color: #fff,
+        }
+
+        draw_text:{
+            text_style: <REGULAR_FONT>{font_size: 10},
+            color: #x0
+        }
+    }
+
+    PresetField = <View> {
+        width: Fit,
+        height: Fit,
+        flow: Down,
+        spacing: 4
+
+        label = <Label> {
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 9},
+                color: #667085
+            }
+        }
+        input = <MolyTextInput> {
+            width: 110,
+            height: Fit,
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 10}
+                color: #000
+            }
+        }
+    }
+
     pub ModelInfoModal = {{ModelInfoModal}} {
         width: Fit
         height: Fit
@@ -103,6 +150,182 @@ live_design! {
                 flow: Down,
                 spacing: 20,
 
+                usage = <View> {
+                    width: Fill,
+                    height: Fit,
+                    flow: Down,
+                    spacing: 8
+
+                    <Label> {
+                        text: "Usage"
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10},
+                            color: #344054
+                        }
+                    }
+                    summary = <Label> {
+                        width: Fill
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10},
+                            color: #000
+                            wrap: Word
+                        }
+                    }
+                }
+
+                presets = <View> {
+                    width: Fill,
+                    height: Fit,
+                    flow: Down,
+                    spacing: 8
+
+                    <Label> {
+                        text: "Load presets"
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10},
+                            color: #344054
+                        }
+                    }
+                    <View> {
+                        width: Fill,
+                        height: Fit,
+                        flow: Right,
+                        align: {x: 0.0, y: 0.5}
+                        spacing: 10
+
+                        preset_selector = <ModelsDropDown> {
+                            width: 250,
+                            height: Fit,
+                            padding: {top: 10, right: 10, bottom: 10, left: 12}
+
+                            labels: ["Default settings"]
+                            values: [Default]
+                        }
+                        remove_preset_button = <ModalButton> {
+                            text: "Remove preset"
+                        }
+                    }
+                    fields = <View> {
+                        width: Fill,
+                        height: Fit,
+                        flow: Right,
+                        spacing: 10
+
+                        name = <PresetField> {
+                            label = { text: "Name" }
+                            input = { width: 160, empty_message: "Long context" }
+                        }
+                        n_ctx = <PresetField> {
+                            label = { text: "Context size" }
+                            input = { empty_message: "Model default" }
+                        }
+                        n_batch = <PresetField> {
+                            label = { text: "Batch size" }
+                            input = { empty_message: "Model default" }
+                        }
+                        gpu_layers = <PresetField> {
+                            label = { text: "GPU layers" }
+                            input = { empty_message: "All" }
+                        }
+                        prompt_template = <PresetField> {
+                            label = { text: "Prompt template" }
+                            input = { empty_message: "Model default" }
+                        }
+                    }
+                    <View> {
+                        width: Fill,
+                        height: Fit,
+                        flow: Right,
+                        align: {x: 0.0, y: 0.5}
+                        spacing: 10
+
+                        <Label> {
+                            text: "Lock the model in memory"
+                            draw_text: {
+                                text_style: <REGULAR_FONT>{font_size: 10},
+                                color: #000
+                            }
+                        }
+                        use_mlock_switch = <MolySwitch> {}
+                        <View> { width: Fill, height: Fit }
+                        save_preset_button = <ModalButton> {
+                            text: "Save and use preset"
+                        }
+                    }
+                    error = <Label> {
+                        width: Fill
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10},
+                            color: #B42318
+                            wrap: Word
+                        }
+                    }
+                }
+
+                template = <View> {
+                    width: Fill,
+                    height: Fit,
+                    flow: Down,
+                    spacing: 8
+
+                    <Label> {
+                        text: "Chat template"
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10},
+                            color: #344054
+                        }
+                    }
+                    source = <Label> {
+                        width: Fill
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10},
+                            color: #000
+                            wrap: Word
+                        }
+                    }
+                    template_input = <MolyTextInput> {
+                        width: Fill,
+                        height: Fit,
+                        empty_message: "A Jinja template, like the tokenizer.chat_template of the model"
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10}
+                            color: #000
+                        }
+                    }
+                    <View> {
+                        width: Fill,
+                        height: Fit,
+                        flow: Right,
+                        align: {x: 0.0, y: 0.5}
+                        spacing: 10
+
+                        server_template_button = <ModalButton> {
+                            text: "Use the server template"
+                        }
+                        embedded_template_button = <ModalButton> {
+                            text: "Use the embedded template"
+                        }
+                        custom_template_button = <ModalButton> {
+                            text: "Use this template"
+                        }
+                        preview_template_button = <ModalButton> {
+                            text: "Preview"
+                        }
+                    }
+                    error = <Label> {
+                        width: Fill
+                        draw_text: {
+                            text_style: <REGULAR_FONT>{font_size: 10},
+                            color: #B42318
+                            wrap: Word
+                        }
+                    }
+                    preview = <MolyHtml> {
+                        width: Fill
+                        code_layout: { padding: 9 }
+                    }
+                }
+
                 metadata = <MolyHtml> {}
                 actions = <View> {
                     width: Fill, height: Fit
@@ -175,6 +398,16 @@ pub struct ModelInfoModal {
     model_id: String,
     #[rust]
     stringified_model_data: String,
+    #[rust]
+    file_id: FileID,
+    // The presets the fields were filled from, they are left alone while the presets
+    // don't change so edits are kept
+    #[rust]
+    load_presets: Option<LoadPresets>,
+    // The settings the template input was filled from, it is left alone while they
+    // don't change so edits are kept
+    #[rust]
+    chat_template: Option<ChatTemplateSettings>,
 }
 
 impl Widget for ModelInfoModal {
@@ -188,6 +421,7 @@ impl Widget for ModelInfoModal {
         let downloaded_file = &props.downloaded_file;
 
         self.model_id = downloaded_file.model.id.clone();
+        self.file_id = downloaded_file.file.id.clone();
 
         // filename
         self.label(id!(title.filename))
@@ -206,7 +440,42 @@ impl Widget for ModelInfoModal {
             .expect("Could not serialize model data into json");
         let metadata = format!("<pre>{}</pre>", self.stringified_model_data);
 
-        self.html(id!(wrapper.body.metadata)).set_text(cx, &metadata);
+        self.html(id!(wrapper.body.metadata))
+            .set_text(cx, &metadata);
+
+        // usage
+        let usage = match &props.usage_stats {
+            Some(stats) => usage_summary(stats),
+            None => "-".to_string(),
+        };
+        self.label(id!(wrapper.body.usage.summary))
+            .set_text(cx, &usage);
+
+        // load presets
+        let presets = props.load_presets.clone().unwrap_or_default();
+        if self.load_presets.as_ref() != Some(&presets) {
+            self.show_presets(cx, &presets);
+            self.load_presets = Some(presets);
+        }
+
+        // chat template
+        if props.chat_template != self.chat_template {
+            self.chat_template = props.chat_template.clone();
+            let settings = self.chat_template.clone().unwrap_or_default();
+            let template = match &settings.source {
+                ChatTemplateSource::Custom(template) => template.clone(),
+                _ => settings.embedded.clone().unwrap_or_default(),
+            };
+            self.text_input(id!(wrapper.body.template.template_input))
+                .set_text(cx, &template);
+            self.html(id!(wrapper.body.template.preview))
+                .set_text(cx, "");
+        }
+        let settings = self.chat_template.clone().unwrap_or_default();
+        self.label(id!(wrapper.body.template.source))
+            .set_text(cx, template_source_text(&settings.source));
+        self.button(id!(wrapper.body.template.embedded_template_button))
+            .set_visible(cx, settings.embedded.is_some());
 
         self.view
             .draw_walk(cx, scope, walk.with_abs_pos(DVec2 { x: 0., y: 0. }))
@@ -214,12 +483,104 @@ impl Widget for ModelInfoModal {
 }
 
 impl WidgetMatchEvent for ModelInfoModal {
-    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, _scope: &mut Scope) {
-
+    fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
         if self.button(id!(close_button)).clicked(actions) {
             cx.action(ModelInfoModalAction::ModalDismissed);
         }
 
+        let presets = self.load_presets.clone().unwrap_or_default();
+
+        // The first item is the default settings, then the presets by name
+        if let Some(item) = self
+            .drop_down(id!(wrapper.body.presets.preset_selector))
+            .selected(actions)
+        {
+            let name = item
+                .checked_sub(1)
+                .and_then(|index| presets.presets.get(index))
+                .map(|preset| preset.name.clone());
+            let store = scope.data.get_mut::<Store>().unwrap();
+            let result = store.downloads.select_load_preset(&self.file_id, name);
+            store.apply_load_preset(&self.file_id);
+            self.show_preset_result(cx, result);
+        }
+
+        let fields: [&[LiveId]; 5] = [
+            id!(wrapper.body.presets.fields.name.input),
+            id!(wrapper.body.presets.fields.n_ctx.input),
+            id!(wrapper.body.presets.fields.n_batch.input),
+            id!(wrapper.body.presets.fields.gpu_layers.input),
+            id!(wrapper.body.presets.fields.prompt_template.input),
+        ];
+        let field_returned = fields
+            .into_iter()
+            .any(|field| self.text_input(field).returned(actions).is_some());
+        if field_returned
+            || self
+                .button(id!(wrapper.body.presets.save_preset_button))
+                .clicked(actions)
+        {
+            let result = self.preset_from_fields(cx).and_then(|preset| {
+                let store = scope.data.get_mut::<Store>().unwrap();
+                store.downloads.save_load_preset(&self.file_id, preset)?;
+                store.apply_load_preset(&self.file_id);
+                Ok(())
+            });
+            self.show_preset_result(cx, result);
+        }
+
+        if self
+            .button(id!(wrapper.body.presets.remove_preset_button))
+            .clicked(actions)
+        {
+            if let Some(name) = presets.selected.clone() {
+                let store = scope.data.get_mut::<Store>().unwrap();
+                let result = store.downloads.remove_load_preset(&self.file_id, name);
+                store.apply_load_preset(&self.file_id);
+                self.show_preset_result(cx, result);
+            }
+        }
+
+        let template_source = if self
+            .button(id!(wrapper.body.template.server_template_button))
+            .clicked(actions)
+        {
+            Some(ChatTemplateSource::Server)
+        } else if self
+            .button(id!(wrapper.body.template.embedded_template_button))
+            .clicked(actions)
+        {
+            Some(ChatTemplateSource::Embedded)
+        } else if self
+            .button(id!(wrapper.body.template.custom_template_button))
+            .clicked(actions)
+        {
+            Some(ChatTemplateSource::Custom(self.template_input_text()))
+        } else {
+            None
+        };
+        if let Some(source) = template_source {
+            let store = scope.data.get_mut::<Store>().unwrap();
+            let result = store.downloads.set_chat_template(&self.file_id, source);
+            self.show_template_result(cx, result.map(|_| String::new()));
+        }
+
+        if self
+            .button(id!(wrapper.body.template.preview_template_button))
+            .clicked(actions)
+        {
+            // What is in the input, or else what is in use
+            let template = self.template_input_text();
+            let source = if template.trim().is_empty() {
+                self.chat_template.clone().unwrap_or_default().source
+            } else {
+                ChatTemplateSource::Custom(template)
+            };
+            let store = scope.data.get_mut::<Store>().unwrap();
+            let result = store.downloads.preview_chat_template(&self.file_id, source);
+            self.show_template_result(cx, result);
+        }
+
         if self
             .button(id!(wrapper.body.actions.copy_button))
             .clicked(actions)
@@ -238,3 +599,171 @@ impl WidgetMatchEvent for ModelInfoModal {
         }
     }
 }
+
+impl ModelInfoModal {
+    /// List the presets in the selector and fill the fields with the one in use.
+    fn show_presets(&mut self, cx: &mut Cx, presets: &LoadPresets) {
+        let labels = std::iter::once("Default settings".to_string())
+            .chain(presets.presets.iter().map(|preset| preset.name.clone()))
+            .collect();
+        let selected = presets
+            .selected
+            .as_ref()
+            .and_then(|name| presets.presets.iter().position(|p| p.name == *name))
+            .map_or(0, |index| index + 1);
+        let selector = self.drop_down(id!(wrapper.body.presets.preset_selector));
+        selector.set_labels(cx, labels);
+        selector.set_selected_item(cx, selected);
+
+        self.button(id!(wrapper.body.presets.remove_preset_button))
+            .set_visible(cx, presets.selected.is_some());
+
+        // The defaults leave the fields empty, ready for a new preset
+        let preset = presets.selected_preset().cloned().unwrap_or_default();
+        let number = |value: Option<u32>| value.map(|v| v.to_string()).unwrap_or_default();
+        self.text_input(id!(wrapper.body.presets.fields.name.input))
+            .set_text(cx, &preset.name);
+        self.text_input(id!(wrapper.body.presets.fields.n_ctx.input))
+            .set_text(cx, &number(preset.n_ctx));
+        self.text_input(id!(wrapper.body.presets.fields.n_batch.input))
+            .set_text(cx, &number(preset.n_batch));
+        self.text_input(id!(wrapper.body.presets.fields.gpu_layers.input))
+            .set_text(cx, &number(preset.gpu_layers));
+        self.text_input(id!(wrapper.body.presets.fields.prompt_template.input))
+            .set_text(cx, preset.prompt_template.as_deref().unwrap_or_default());
+
+        let use_mlock = self.check_box(id!(wrapper.body.presets.use_mlock_switch));
+        // Avoid triggering the animator when nothing changes
+        if use_mlock.selected(cx) != preset.use_mlock {
+            use_mlock.set_selected(cx, preset.use_mlock);
+        }
+    }
+
+    /// The preset written in the fields, empty fields keep the defaults of the model.
+    fn preset_from_fields(&self, cx: &Cx) -> anyhow::Result<LoadPreset> {
+        let field = |path: &[LiveId]| self.text_input(path).text().trim().to_string();
+
+        let name = field(id!(wrapper.body.presets.fields.name.input));
+        if name.is_empty() {
+            return Err(anyhow!("A preset needs a name"));
+        }
+        let prompt_template = field(id!(wrapper.body.presets.fields.prompt_template.input));
+
+        Ok(LoadPreset {
+            name,
+            n_ctx: parse_setting(
+                &field(id!(wrapper.body.presets.fields.n_ctx.input)),
+                "The context size",
+            )?,
+            n_batch: parse_setting(
+                &field(id!(wrapper.body.presets.fields.n_batch.input)),
+                "The batch size",
+            )?,
+            gpu_layers: parse_setting(
+                &field(id!(wrapper.body.presets.fields.gpu_layers.input)),
+                "The number of GPU layers",
+            )?,
+            use_mlock: self
+                .check_box(id!(wrapper.body.presets.use_mlock_switch))
+                .selected(cx),
+            prompt_template: Some(prompt_template).filter(|template| !template.is_empty()),
+        })
+    }
+
+    fn show_preset_result(&mut self, cx: &mut Cx, result: anyhow::Result<()>) {
+        let error = match result {
+            Ok(()) => String::new(),
+            Err(err) => err.to_string(),
+        };
+        self.label(id!(wrapper.body.presets.error))
+            .set_text(cx, &error);
+        self.redraw(cx);
+    }
+
+    fn template_input_text(&self) -> String {
+        self.text_input(id!(wrapper.body.template.template_input))
+            .text()
+    }
+
+    /// Show the rendered prompt, or why the template could not be used.
+    fn show_template_result(&mut self, cx: &mut Cx, result: anyhow::Result<String>) {
+        let (preview, error) = match result {
+            Ok(prompt) if prompt.is_empty() => (String::new(), String::new()),
+            Ok(prompt) => (
+                format!("<pre>{}</pre>", escape_html(&prompt)),
+                String::new(),
+            ),
+            Err(err) => (String::new(), err.to_string()),
+        };
+        self.html(id!(wrapper.body.template.preview))
+            .set_text(cx, &preview);
+        self.label(id!(wrapper.body.template.error))
+            .set_text(cx, &error);
+        self.redraw(cx);
+    }
+}
+
+fn template_source_text(source: &ChatTemplateSource) -> &'static str {
+    match source {
+        ChatTemplateSource::Server => {
+            "Chats are formatted by the server, with the prompt template of the model card"
+        }
+        ChatTemplateSource::Embedded => {
+            "Chats are formatted with the template embedded in the file"
+        }
+        ChatTemplateSource::Custom(_) => "Chats are formatted with a custom template",
+    }
+}
+
+// Prompts are full of tags like `<|user|>`
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A number setting of a preset, `None` when left empty for the default.
+fn parse_setting(text: &str, setting: &str) -> anyhow::Result<Option<u32>> {
+    if text.is_empty() {
+        return Ok(None);
+    }
+    text.parse::<u32>()
+        .map(Some)
+        .map_err(|_| anyhow!("{} must be a number, not {:?}", setting, text))
+}
+
+fn usage_summary(stats: &UsageStats) -> String {
+    if stats.loads == 0 && stats.completions == 0 {
+        return "Not used yet".to_string();
+    }
+
+    let mut lines = vec![];
+
+    let mut loads = format!("Loaded {} times", stats.loads);
+    if let Some(secs) = stats.average_load_secs() {
+        loads.push_str(&format!(", {:.1} s on average", secs));
+    }
+    if let Some(last_used_at) = stats.last_used_at {
+        loads.push_str(&format!(
+            ". Last used on {}",
+            last_used_at.with_timezone(&Local).format("%d/%m/%Y %H:%M")
+        ));
+    }
+    lines.push(loads);
+
+    lines.push(format!(
+        "{} chats started, {} answers generated",
+        stats.chats_started, stats.completions
+    ));
+
+    let mut tokens = format!(
+        "{} prompt tokens, {} completion tokens",
+        stats.prompt_tokens, stats.completion_tokens
+    );
+    if let Some(tokens_per_second) = stats.tokens_per_second() {
+        tokens.push_str(&format!(", {:.1} tokens/s", tokens_per_second));
+    }
+    lines.push(tokens);
+
+    lines.join("\n")
+}
@@ -1,9 +1,15 @@
 use makepad_widgets::*;
-use moly_protocol::data::DownloadedFile;
+use moly_protocol::data::{CleanupPlan, DownloadedFile, StorageUsage};
 use moly_protocol::protocol::Command;
 use std::path::PathBuf;
 
-use crate::{data::store::Store, shared::utils::BYTES_PER_MB};
+use crate::{
+    data::store::Store,
+    shared::utils::{format_bytes, BYTES_PER_MB},
+};
+
+// Files listed as the largest ones in the storage view
+const LARGEST_FILES: usize = 5;
 
 live_design! {
     use link::theme::*;
@@ -68,6 +74,67 @@ live_design! {
         text: "Show in Folder"
     }
 
+    StorageButton = <MolyButton> {
+        width: Fit,
+        height: 28,
+        margin: {left: 10}
+        padding: {top: 6, bottom: 6, left: 14, right: 14}
+
+        draw_bg: {
+            radius: 2.0,
+            color: #FEFEFE,
+            color_hover: #999,
+        }
+
+        draw_text:{
+            text_style: <REGULAR_FONT>{font_size: 11},
+            color: #000
+        }
+        text: "Storage"
+    }
+
+    StorageColumn = <View> {
+        width: Fill,
+        height: Fit,
+        flow: Down,
+        spacing: 8,
+
+        title = <Label> {
+            draw_text:{
+                text_style: <BOLD_FONT>{font_size: 11}
+                color: #000
+            }
+        }
+
+        content = <Label> {
+            width: Fill,
+            draw_text:{
+                text_style: <REGULAR_FONT>{font_size: 10}
+                color: #535353
+                wrap: Word
+            }
+        }
+    }
+
+    StorageCleanupButton = <MolyButton> {
+        width: Fit,
+        height: 28,
+        padding: {top: 6, bottom: 6, left: 14, right: 14}
+
+        draw_bg: {
+            radius: 2.0,
+            color: #FEFEFE,
+            color_hover: #999,
+            border_width: 1.0,
+            border_color: #D0D5DD,
+        }
+
+        draw_text:{
+            text_style: <REGULAR_FONT>{font_size: 11},
+            color: #000
+        }
+    }
+
     SearchBar = <RoundedView> {
         width: Fit,
         height: Fit,
@@ -162,10 +229,69 @@ live_design! {
 
                 download_location = <DownloadLocationButton> {}
                 show_in_files = <ShowInFilesButton> {}
+                storage_button = <StorageButton> {}
                 <View> { width: Fill, height: Fit }
                 search = <SearchBar> {}
             }
 
+            storage_cleanup = <RoundedView> {
+                visible: false,
+                width: Fill,
+                height: Fit,
+                margin: {top: 20}
+                padding: 20,
+                spacing: 10,
+                flow: Down,
+
+                show_bg: true,
+                draw_bg: {
+                    color: #FFF6ED,
+                    radius: 3.0,
+                    border_color: #FEC84B,
+                    border_width: 1.0,
+                }
+
+                message = <Label> {
+                    width: Fill,
+                    draw_text:{
+                        text_style: <REGULAR_FONT>{font_size: 10}
+                        color: #000
+                        wrap: Word
+                    }
+                }
+
+                <View> {
+                    width: Fit,
+                    height: Fit,
+                    spacing: 10,
+
+                    cleanup_button = <StorageCleanupButton> { text: "Remove these files" }
+                    dismiss_cleanup_button = <StorageCleanupButton> { text: "Not now" }
+                }
+            }
+
+            storage = <RoundedView> {
+                visible: false,
+                width: Fill,
+                height: Fit,
+                margin: {top: 20}
+                padding: 20,
+                spacing: 30,
+
+                show_bg: true,
+                draw_bg: {
+                    color: #fff,
+                    radius: 3.0,
+                    border_color: #D0D5DD,
+                    border_width: 1.0,
+                }
+
+                free_space = <StorageColumn> { title = { text: "Free space" } }
+                by_model = <StorageColumn> { title = { text: "By model" } }
+                by_quantization = <StorageColumn> { title = { text: "By quantization" } }
+                largest_files = <StorageColumn> { title = { text: "Largest files" } }
+            }
+
             table = <DownloadedFilesTable> {
                 margin: {top: 20}
             }
@@ -177,6 +303,9 @@ live_design! {
 pub struct MyModelsScreen {
     #[deref]
     view: View,
+
+    #[rust]
+    show_storage: bool,
 }
 
 impl Widget for MyModelsScreen {
@@ -186,17 +315,22 @@ impl Widget for MyModelsScreen {
     }
 
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
-        let downloaded_files = &scope
-            .data
-            .get::<Store>()
-            .unwrap()
-            .downloads
-            .downloaded_files;
-
-        let summary = generate_models_summary(&downloaded_files);
+        let downloads = &scope.data.get::<Store>().unwrap().downloads;
+
+        let summary = generate_models_summary(&downloads.downloaded_files);
         let models_summary_label = self.view.label(id!(header.models_summary));
         models_summary_label.set_text(cx, &summary);
 
+        let cleanup = downloads.storage_cleanup.as_ref();
+        self.view(id!(storage_cleanup))
+            .set_visible(cx, cleanup.is_some());
+        if let Some(plan) = cleanup {
+            self.label(id!(storage_cleanup.message))
+                .set_text(cx, &storage_cleanup_message(plan));
+            self.button(id!(cleanup_button))
+                .set_visible(cx, !plan.files.is_empty());
+        }
+
         self.view
             .button(id!(show_in_files))
             .set_text(cx, &file_manager_label());
@@ -235,6 +369,38 @@ impl WidgetMatchEvent for MyModelsScreen {
                 });
         }
 
+        if self.button(id!(storage_button)).clicked(actions) {
+            self.show_storage = !self.show_storage;
+            if self.show_storage {
+                // Fetched on open, the disk changes behind our back
+                let store = scope.data.get::<Store>().unwrap();
+                if let Some(usage) = store.downloads.get_storage_usage() {
+                    self.set_storage_usage(cx, &usage);
+                }
+            }
+            self.view(id!(storage)).set_visible(cx, self.show_storage);
+            self.redraw(cx);
+        }
+
+        if self.button(id!(cleanup_button)).clicked(actions) {
+            let store = scope.data.get_mut::<Store>().unwrap();
+            if let Err(err) = store.apply_storage_cleanup() {
+                eprintln!("Error cleaning up storage: {:?}", err);
+            }
+            if self.show_storage {
+                if let Some(usage) = store.downloads.get_storage_usage() {
+                    self.set_storage_usage(cx, &usage);
+                }
+            }
+            self.redraw(cx);
+        }
+
+        if self.button(id!(dismiss_cleanup_button)).clicked(actions) {
+            let store = scope.data.get_mut::<Store>().unwrap();
+            store.downloads.storage_cleanup = None;
+            self.redraw(cx);
+        }
+
         if self.button(id!(download_location)).clicked(actions) {
             let scope = &mut scope.data.get_mut::<Store>().unwrap();
             let models_dir = &scope.preferences.downloaded_files_dir;
@@ -267,6 +433,75 @@ impl WidgetMatchEvent for MyModelsScreen {
     }
 }
 
+impl MyModelsScreen {
+    fn set_storage_usage(&mut self, cx: &mut Cx, usage: &StorageUsage) {
+        let mut free_space = format!(
+            "{} free\n{} used by models",
+            format_bytes(usage.available),
+            format_bytes(usage.total())
+        );
+        if usage.reserved > 0 {
+            free_space.push_str(&format!(
+                "\n{} set aside for downloads",
+                format_bytes(usage.reserved)
+            ));
+        }
+        if let Some(limit) = usage.quota.limit {
+            free_space.push_str(&format!("\nQuota of {}", format_bytes(limit)));
+        }
+        self.label(id!(storage.free_space.content))
+            .set_text(cx, &free_space);
+
+        self.label(id!(storage.by_model.content))
+            .set_text(cx, &storage_rows(usage.by_model()));
+        self.label(id!(storage.by_quantization.content))
+            .set_text(cx, &storage_rows(usage.by_quantization()));
+
+        let largest_files = usage
+            .files
+            .iter()
+            .take(LARGEST_FILES)
+            .map(|file| (file.name.clone(), file.size))
+            .collect();
+        self.label(id!(storage.largest_files.content))
+            .set_text(cx, &storage_rows(largest_files));
+    }
+}
+
+fn storage_cleanup_message(plan: &CleanupPlan) -> String {
+    let mut message = format!(
+        "Downloaded models take {}, over the storage quota of {}.",
+        format_bytes(plan.used),
+        format_bytes(plan.limit)
+    );
+
+    if !plan.files.is_empty() {
+        let names: Vec<&str> = plan.files.iter().map(|file| file.name.as_str()).collect();
+        message.push_str(&format!(
+            " Removing the least recently used files frees {}: {}.",
+            format_bytes(plan.size()),
+            names.join(", ")
+        ));
+    }
+    if plan.is_partial() {
+        message.push_str(
+            " Pinned files and the models of recent chats are kept, they alone go over the quota.",
+        );
+    }
+    message
+}
+
+fn storage_rows(rows: Vec<(String, u64)>) -> String {
+    if rows.is_empty() {
+        return "-".to_string();
+    }
+
+    rows.iter()
+        .map(|(name, size)| format!("{}: {}", name, format_bytes(*size)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[derive(Clone, DefaultNone, Debug)]
 pub enum MyModelsSearchAction {
     Search(String),
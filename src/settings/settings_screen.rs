@@ -1,10 +1,14 @@
+use chrono::Local;
 use makepad_code_editor::code_view::CodeViewWidgetExt;
 use makepad_widgets::*;
+use moly_protocol::data::{CatalogSource, CatalogSourceKind, StorageQuota};
 
 use crate::data::{
     chats::model_loader::{ModelLoaderStatus, ModelLoaderStatusChanged},
+    search::SearchAction,
     store::Store,
 };
+use crate::shared::utils::{BYTES_PER_GB, BYTES_PER_MB};
 
 live_design! {
     use link::theme::*;
@@ -15,10 +19,53 @@ live_design! {
     use crate::shared::styles::*;
     use crate::shared::widgets::*;
     use crate::settings::mofa_settings::MofaSettings;
+    use crate::landing::sorting::ModelsDropDown;
 
     BG_IMAGE = dep("crate://self/resources/images/my_models_bg_image.png")
     ICON_EDIT = dep("crate://self/resources/icons/edit.svg")
 
+    CatalogButton = <MolyButton> {
+        width: Fit
+        height: Fit
+        padding: {top: 6, bottom: 6, left: 12, right: 12}
+
+        draw_bg: {
+            border_color: #D0D5DD,
+            border_width: 1,
+            color: #fff,
+            color_hover: #E2F1F1,
+            radius: 3
+        }
+
+        draw_text: {
+            text_style: <REGULAR_FONT>{font_size: 10},
+            fn get_color(self) -> vec4 {
+                return #000;
+            }
+        }
+    }
+
+    CatalogSourceField = <View> {
+        width: Fit, height: Fit
+        flow: Down
+        spacing: 4
+
+        label = <Label> {
+            draw_text:{
+                text_style: <REGULAR_FONT>{font_size: 10}
+                color: #667085
+            }
+        }
+        input = <MolyTextInput> {
+            width: 150,
+            height: Fit,
+            draw_text: {
+                text_style: <REGULAR_FONT>{font_size: 12}
+                color: #000
+            }
+        }
+    }
+
     pub SettingsScreen = {{SettingsScreen}} {
         width: Fill
         height: Fill
@@ -174,6 +221,383 @@ live_design! {
                             }
                         }
                     }
+
+                    <View> {
+                        width: Fit, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Load the model again after a crash:"
+                        }
+
+                        auto_reload_switch = <MolySwitch> {}
+                    }
+                }
+
+                downloads_section = <View> {
+                    width: Fill, height: Fit
+                    flow: Down
+                    spacing: 20
+
+                    <Label> {
+                        draw_text:{
+                            text_style: <BOLD_FONT>{font_size: 16}
+                            color: #000
+                        }
+                        text: "Downloads"
+                    }
+
+                    <View> {
+                        width: Fit, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Maximum download speed (MB/s):"
+                        }
+
+                        bandwidth_limit_input = <MolyTextInput> {
+                            width: 100,
+                            height: Fit,
+                            empty_message: "Unlimited"
+                            draw_text: {
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                        }
+                    }
+
+                    <View> {
+                        width: Fit, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Storage quota for models (GB):"
+                        }
+
+                        storage_quota_input = <MolyTextInput> {
+                            width: 100,
+                            height: Fit,
+                            empty_message: "Unlimited"
+                            draw_text: {
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                        }
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Remove least recently used files without asking:"
+                        }
+
+                        auto_cleanup_switch = <MolySwitch> {}
+                    }
+
+                    <View> {
+                        width: Fill, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Mirrors (comma separated):"
+                        }
+
+                        mirrors_input = <MolyTextInput> {
+                            width: Fill,
+                            height: Fit,
+                            empty_message: "https://hf-mirror.example.com, https://files.example.com/{model_id}/{file}"
+                            draw_text: {
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                        }
+                    }
+
+                    <View> {
+                        width: Fit, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Region:"
+                        }
+
+                        region_input = <MolyTextInput> {
+                            width: 100,
+                            height: Fit,
+                            empty_message: "Automatic"
+                            draw_text: {
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                        }
+
+                        active_region_label = <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 10}
+                                color: #667085
+                            }
+                        }
+                    }
+
+                    <View> {
+                        width: Fit, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Allow downloads from unverified catalogs:"
+                        }
+
+                        allow_unverified_switch = <MolySwitch> {}
+                    }
+
+                    <View> {
+                        width: Fill, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Access token (host token):"
+                        }
+
+                        access_token_input = <MolyTextInput> {
+                            width: Fill,
+                            height: Fit,
+                            empty_message: "huggingface.co hf_xxx, or just the host to remove it"
+                            draw_text: {
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                        }
+                    }
+
+                    access_tokens_label = <Label> {
+                        draw_text:{
+                            text_style: <REGULAR_FONT>{font_size: 10}
+                            color: #667085
+                        }
+                    }
+
+                    access_token_error_label = <Label> {
+                        width: Fill
+                        draw_text:{
+                            text_style: <REGULAR_FONT>{font_size: 10}
+                            color: #B42318
+                            wrap: Word
+                        }
+                    }
+                }
+
+                catalog_section = <View> {
+                    width: Fill, height: Fit
+                    flow: Down
+                    spacing: 20
+
+                    <Label> {
+                        draw_text:{
+                            text_style: <BOLD_FONT>{font_size: 16}
+                            color: #000
+                        }
+                        text: "Model catalog"
+                    }
+
+                    <View> {
+                        width: Fit, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        catalog_status_label = <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                        }
+
+                        refresh_catalog_button = <MolyButton> {
+                            width: Fit
+                            height: Fit
+                            padding: {top: 6, bottom: 6, left: 12, right: 12}
+
+                            draw_bg: {
+                                border_color: #D0D5DD,
+                                border_width: 1,
+                                color: #fff,
+                                color_hover: #E2F1F1,
+                                radius: 3
+                            }
+
+                            text: "Refresh"
+                            draw_text: {
+                                text_style: <REGULAR_FONT>{font_size: 10},
+                                fn get_color(self) -> vec4 {
+                                    return #000;
+                                }
+                            }
+                        }
+                    }
+
+                    catalog_error_label = <Label> {
+                        width: Fill
+                        draw_text:{
+                            text_style: <REGULAR_FONT>{font_size: 10}
+                            color: #B42318
+                            wrap: Word
+                        }
+                    }
+
+                    catalog_trust_label = <Label> {
+                        width: Fill
+                        draw_text:{
+                            text_style: <REGULAR_FONT>{font_size: 10}
+                            color: #B54708
+                            wrap: Word
+                        }
+                    }
+
+                    <View> {
+                        width: Fill, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 0.5}
+
+                        <Label> {
+                            draw_text:{
+                                text_style: <REGULAR_FONT>{font_size: 12}
+                                color: #000
+                            }
+                            text: "Sources (highest priority first):"
+                        }
+
+                        catalog_source_selector = <ModelsDropDown> {
+                            width: 220,
+                            height: Fit,
+                            padding: {top: 10, right: 10, bottom: 10, left: 12}
+
+                            labels: ["New source"]
+                            values: [New]
+                        }
+
+                        move_catalog_source_up_button = <CatalogButton> {
+                            text: "Move up"
+                        }
+
+                        remove_catalog_source_button = <CatalogButton> {
+                            text: "Remove"
+                        }
+                    }
+
+                    catalog_source_fields = <View> {
+                        width: Fill, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 1.0}
+
+                        name = <CatalogSourceField> {
+                            label = { text: "Name" }
+                            input = { width: 120, empty_message: "internal" }
+                        }
+
+                        <View> {
+                            width: Fit, height: Fit
+                            flow: Down
+                            spacing: 4
+
+                            <Label> {
+                                draw_text:{
+                                    text_style: <REGULAR_FONT>{font_size: 10}
+                                    color: #667085
+                                }
+                                text: "Kind"
+                            }
+                            kind = <ModelsDropDown> {
+                                width: 100,
+                                height: Fit,
+                                padding: {top: 10, right: 10, bottom: 10, left: 12}
+
+                                labels: ["Git", "HTTP"]
+                                values: [Git, Http]
+                            }
+                        }
+
+                        url = <CatalogSourceField> {
+                            width: Fill
+                            label = { text: "Url, the public catalog can leave it empty" }
+                            input = { width: Fill, empty_message: "https://models.example.com/index.json" }
+                        }
+                    }
+
+                    catalog_source_options = <View> {
+                        width: Fill, height: Fit
+                        flow: Right
+                        spacing: 10
+                        align: {x: 0.0, y: 1.0}
+
+                        pinned = <CatalogSourceField> {
+                            label = { text: "Pinned commit or tag, git only" }
+                            input = { width: 200, empty_message: "Latest" }
+                        }
+
+                        public_keys = <CatalogSourceField> {
+                            width: Fill
+                            label = { text: "Trusted public keys (comma separated)" }
+                            input = { width: Fill, empty_message: "BASE64_KEY" }
+                        }
+
+                        save_catalog_source_button = <CatalogButton> {
+                            text: "Save source"
+                        }
+                    }
+
+                    catalog_sources_error_label = <Label> {
+                        width: Fill
+                        draw_text:{
+                            text_style: <REGULAR_FONT>{font_size: 10}
+                            color: #B42318
+                            wrap: Word
+                        }
+                    }
                 }
 
                 mofa_section = <View> {
@@ -210,6 +634,19 @@ pub struct SettingsScreen {
 
     #[rust]
     override_port: Option<u16>,
+
+    #[rust]
+    download_settings_loaded: bool,
+
+    #[rust]
+    storage_quota: StorageQuota,
+
+    #[rust]
+    catalog_sources: Vec<CatalogSource>,
+
+    // The source in the fields, `None` for a new one
+    #[rust]
+    selected_catalog_source: Option<usize>,
 }
 
 impl Widget for SettingsScreen {
@@ -221,6 +658,102 @@ impl Widget for SettingsScreen {
     fn draw_walk(&mut self, cx: &mut Cx2d, scope: &mut Scope, walk: Walk) -> DrawStep {
         let store = scope.data.get_mut::<Store>().unwrap();
 
+        if !self.download_settings_loaded {
+            self.download_settings_loaded = true;
+            let limit = store
+                .preferences
+                .download_bandwidth_limit
+                .map(|limit| format!("{}", limit as f64 / BYTES_PER_MB))
+                .unwrap_or_default();
+            self.text_input(id!(bandwidth_limit_input))
+                .set_text(cx, &limit);
+
+            if let Some(usage) = store.downloads.get_storage_usage() {
+                self.storage_quota = usage.quota;
+                let quota = usage
+                    .quota
+                    .limit
+                    .map(|limit| format!("{}", limit as f64 / BYTES_PER_GB))
+                    .unwrap_or_default();
+                self.text_input(id!(storage_quota_input))
+                    .set_text(cx, &quota);
+
+                let auto_cleanup = self.check_box(id!(auto_cleanup_switch));
+                if auto_cleanup.selected(cx) != usage.quota.auto_cleanup {
+                    auto_cleanup.set_selected(cx, usage.quota.auto_cleanup);
+                }
+            }
+
+            match store.chats.get_auto_reload_model() {
+                Ok(auto_reload) => {
+                    let switch = self.check_box(id!(auto_reload_switch));
+                    if switch.selected(cx) != auto_reload {
+                        switch.set_selected(cx, auto_reload);
+                    }
+                }
+                Err(err) => eprintln!("Error fetching the auto reload setting: {:?}", err),
+            }
+
+            if let Some(sources) = store.downloads.get_download_source_settings() {
+                self.text_input(id!(mirrors_input))
+                    .set_text(cx, &sources.mirrors.join(", "));
+                self.text_input(id!(region_input))
+                    .set_text(cx, &sources.region.unwrap_or_default());
+                self.label(id!(active_region_label))
+                    .set_text(cx, &format!("In use: {}", sources.active_region));
+
+                let allow_unverified = self.check_box(id!(allow_unverified_switch));
+                // Avoid triggering the animator when nothing changes
+                if allow_unverified.selected(cx) != sources.allow_unverified {
+                    allow_unverified.set_selected(cx, sources.allow_unverified);
+                }
+
+                let access_tokens = if sources.access_token_hosts.is_empty() {
+                    "No access tokens saved".to_string()
+                } else {
+                    format!(
+                        "Tokens saved for: {}",
+                        sources.access_token_hosts.join(", ")
+                    )
+                };
+                self.label(id!(access_tokens_label))
+                    .set_text(cx, &access_tokens);
+            }
+
+            self.catalog_sources = store.search.get_catalog_sources();
+            self.show_catalog_sources(cx, Some(0));
+        }
+
+        let catalog_status = &store.search.catalog_status;
+        let last_sync = catalog_status
+            .last_sync
+            .map(|last_sync| {
+                last_sync
+                    .with_timezone(&Local)
+                    .format("%d/%m/%Y %H:%M")
+                    .to_string()
+            })
+            .unwrap_or("never".to_string());
+        let catalog_text = if catalog_status.syncing {
+            format!("Updating... (last updated: {})", last_sync)
+        } else {
+            format!("Last updated: {}", last_sync)
+        };
+        self.label(id!(catalog_status_label))
+            .set_text(cx, &catalog_text);
+        self.label(id!(catalog_error_label))
+            .set_text(cx, catalog_status.last_error.as_deref().unwrap_or_default());
+        let trust_text = if catalog_status.untrusted_sources.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "Untrusted catalog: the index of {} has no valid signature, its models can't be downloaded unless unverified downloads are allowed",
+                catalog_status.untrusted_sources.join(", ")
+            )
+        };
+        self.label(id!(catalog_trust_label))
+            .set_text(cx, &trust_text);
+
         match self.server_port_state {
             ServerPortState::OnEdit => {
                 self.view.view(id!(port_editable)).set_visible(cx, false);
@@ -283,11 +816,114 @@ curl http://localhost:{}/v1/chat/completions \\
     }
 }
 
+impl SettingsScreen {
+    /// List the catalog sources in the selector and fill the fields with the
+    /// selected one, or leave them empty for a new source.
+    fn show_catalog_sources(&mut self, cx: &mut Cx, selected: Option<usize>) {
+        self.selected_catalog_source = selected.filter(|index| *index < self.catalog_sources.len());
+
+        let labels = self
+            .catalog_sources
+            .iter()
+            .map(|source| source.name.clone())
+            .chain(std::iter::once("New source".to_string()))
+            .collect();
+        let selector = self.drop_down(id!(catalog_source_selector));
+        selector.set_labels(cx, labels);
+        selector.set_selected_item(
+            cx,
+            self.selected_catalog_source
+                .unwrap_or(self.catalog_sources.len()),
+        );
+
+        let source = self
+            .selected_catalog_source
+            .map(|index| self.catalog_sources[index].clone())
+            .unwrap_or_default();
+        self.text_input(id!(catalog_source_fields.name.input))
+            .set_text(cx, &source.name);
+        self.drop_down(id!(catalog_source_fields.kind))
+            .set_selected_item(
+                cx,
+                match source.kind {
+                    CatalogSourceKind::Git => 0,
+                    CatalogSourceKind::Http => 1,
+                },
+            );
+        self.text_input(id!(catalog_source_fields.url.input))
+            .set_text(cx, &source.url);
+        self.text_input(id!(catalog_source_options.pinned.input))
+            .set_text(cx, source.pinned.as_deref().unwrap_or_default());
+        self.text_input(id!(catalog_source_options.public_keys.input))
+            .set_text(cx, &source.public_keys.join(", "));
+
+        self.button(id!(move_catalog_source_up_button)).set_visible(
+            cx,
+            self.selected_catalog_source.is_some_and(|index| index > 0),
+        );
+        self.button(id!(remove_catalog_source_button))
+            .set_visible(cx, self.selected_catalog_source.is_some());
+    }
+
+    /// The source written in the fields, the backend trims and checks it.
+    fn catalog_source_from_fields(&self) -> CatalogSource {
+        let pinned = self
+            .text_input(id!(catalog_source_options.pinned.input))
+            .text();
+        CatalogSource {
+            name: self
+                .text_input(id!(catalog_source_fields.name.input))
+                .text(),
+            kind: match self
+                .drop_down(id!(catalog_source_fields.kind))
+                .selected_item()
+            {
+                1 => CatalogSourceKind::Http,
+                _ => CatalogSourceKind::Git,
+            },
+            url: self.text_input(id!(catalog_source_fields.url.input)).text(),
+            pinned: Some(pinned).filter(|pinned| !pinned.trim().is_empty()),
+            public_keys: self
+                .text_input(id!(catalog_source_options.public_keys.input))
+                .text()
+                .split(',')
+                .map(|key| key.trim().to_string())
+                .filter(|key| !key.is_empty())
+                .collect(),
+        }
+    }
+
+    fn set_catalog_sources(
+        &mut self,
+        cx: &mut Cx,
+        store: &mut Store,
+        sources: Vec<CatalogSource>,
+        selected: usize,
+    ) {
+        let error = match store.search.set_catalog_sources(sources) {
+            Ok(()) => {
+                // What was saved, trimmed by the backend
+                self.catalog_sources = store.search.get_catalog_sources();
+                self.show_catalog_sources(cx, Some(selected));
+                String::new()
+            }
+            Err(err) => err.to_string(),
+        };
+        self.label(id!(catalog_sources_error_label))
+            .set_text(cx, &error);
+        self.redraw(cx);
+    }
+}
+
 impl WidgetMatchEvent for SettingsScreen {
     fn handle_actions(&mut self, cx: &mut Cx, actions: &Actions, scope: &mut Scope) {
         let store = scope.data.get_mut::<Store>().unwrap();
 
         for action in actions {
+            if let Some(SearchAction::CatalogUpdated(_)) = action.downcast_ref::<SearchAction>() {
+                self.redraw(cx);
+            }
+
             // Once the modals are reloaded, let's clear the override port
             if let Some(_) = action.downcast_ref::<ModelLoaderStatusChanged>() {
                 if store.chats.model_loader.is_loaded() {
@@ -325,6 +961,153 @@ impl WidgetMatchEvent for SettingsScreen {
             self.redraw(cx);
         }
 
+        if let Some(limit) = self
+            .text_input(id!(bandwidth_limit_input))
+            .returned(actions)
+        {
+            // Anything that is not a positive number removes the limit
+            let bytes_per_sec = limit
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|mb| *mb > 0.0)
+                .map(|mb| (mb * BYTES_PER_MB) as u64);
+            store.set_download_bandwidth_limit(bytes_per_sec);
+        }
+
+        if let Some(limit) = self.text_input(id!(storage_quota_input)).returned(actions) {
+            // Anything that is not a positive number removes the quota
+            self.storage_quota.limit = limit
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|gb| *gb > 0.0)
+                .map(|gb| (gb * BYTES_PER_GB) as u64);
+            if let Err(err) = store.set_storage_quota(self.storage_quota) {
+                eprintln!("Error setting the storage quota: {:?}", err);
+            }
+        }
+
+        if let Some(auto_cleanup) = self.check_box(id!(auto_cleanup_switch)).changed(actions) {
+            self.storage_quota.auto_cleanup = auto_cleanup;
+            if let Err(err) = store.set_storage_quota(self.storage_quota) {
+                eprintln!("Error setting the storage quota: {:?}", err);
+            }
+        }
+
+        if let Some(auto_reload) = self.check_box(id!(auto_reload_switch)).changed(actions) {
+            if let Err(err) = store.chats.set_auto_reload_model(auto_reload) {
+                eprintln!("Error setting the auto reload setting: {:?}", err);
+            }
+        }
+
+        if self.button(id!(refresh_catalog_button)).clicked(actions) {
+            store.search.refresh_catalog();
+            self.redraw(cx);
+        }
+
+        // The last item is a new source
+        if let Some(item) = self
+            .drop_down(id!(catalog_source_selector))
+            .selected(actions)
+        {
+            self.show_catalog_sources(cx, Some(item));
+            self.redraw(cx);
+        }
+
+        let fields: [&[LiveId]; 4] = [
+            id!(catalog_source_fields.name.input),
+            id!(catalog_source_fields.url.input),
+            id!(catalog_source_options.pinned.input),
+            id!(catalog_source_options.public_keys.input),
+        ];
+        let field_returned = fields
+            .into_iter()
+            .any(|field| self.text_input(field).returned(actions).is_some());
+        if field_returned
+            || self
+                .button(id!(save_catalog_source_button))
+                .clicked(actions)
+        {
+            let source = self.catalog_source_from_fields();
+            let mut sources = self.catalog_sources.clone();
+            let selected = match self.selected_catalog_source {
+                Some(index) => {
+                    sources[index] = source;
+                    index
+                }
+                None => {
+                    sources.push(source);
+                    sources.len() - 1
+                }
+            };
+            self.set_catalog_sources(cx, store, sources, selected);
+        }
+
+        if let Some(index) = self.selected_catalog_source {
+            if self
+                .button(id!(move_catalog_source_up_button))
+                .clicked(actions)
+                && index > 0
+            {
+                let mut sources = self.catalog_sources.clone();
+                sources.swap(index, index - 1);
+                self.set_catalog_sources(cx, store, sources, index - 1);
+            } else if self
+                .button(id!(remove_catalog_source_button))
+                .clicked(actions)
+            {
+                let mut sources = self.catalog_sources.clone();
+                sources.remove(index);
+                self.set_catalog_sources(cx, store, sources, index.saturating_sub(1));
+            }
+        }
+
+        if let Some(allow) = self
+            .check_box(id!(allow_unverified_switch))
+            .changed(actions)
+        {
+            store.downloads.set_allow_unverified_downloads(allow);
+        }
+
+        if let Some(mirrors) = self.text_input(id!(mirrors_input)).returned(actions) {
+            let mirrors = mirrors.split(',').map(|m| m.trim().to_string()).collect();
+            store.downloads.set_download_mirrors(mirrors);
+        }
+
+        let access_token_input = self.text_input(id!(access_token_input));
+        if let Some(entry) = access_token_input.returned(actions) {
+            // A host without a token forgets the saved one
+            let mut parts = entry.split_whitespace();
+            let error = match parts.next() {
+                Some(host) => {
+                    let token = parts.next().map(str::to_string);
+                    match store.downloads.set_access_token(host.to_string(), token) {
+                        Ok(()) => String::new(),
+                        Err(err) => err.to_string(),
+                    }
+                }
+                None => String::new(),
+            };
+            self.label(id!(access_token_error_label))
+                .set_text(cx, &error);
+
+            // Never keep the token on screen, show the saved hosts instead
+            access_token_input.set_text(cx, "");
+            self.download_settings_loaded = false;
+            self.redraw(cx);
+        }
+
+        if let Some(region) = self.text_input(id!(region_input)).returned(actions) {
+            // An empty region goes back to detecting it
+            let region = Some(region.trim().to_string()).filter(|r| !r.is_empty());
+            store.downloads.set_region(region);
+
+            // Show the region now in use
+            self.download_settings_loaded = false;
+            self.redraw(cx);
+        }
+
         if let TextInputAction::Escape =
             actions.find_widget_action_cast(port_number_input.widget_uid())
         {
@@ -15,5 +15,24 @@ pub enum DownloadAction {
     Play(FileID),
     Pause(FileID),
     Cancel(FileID),
+    StartNext(FileID),
+    // Limit the speed of a download, in bytes per second, `None` removes the limit
+    SetBandwidthLimit(FileID, Option<u64>),
+    // Download the new revision of an outdated downloaded file
+    Update(FileID),
+    None,
+}
+
+#[derive(Clone, DefaultNone, Debug)]
+pub enum EmbeddingAction {
+    // Serve this downloaded embedding model along the chat models, `None` for the default one
+    Use(Option<FileID>),
+    None,
+}
+
+#[derive(Clone, DefaultNone, Debug)]
+pub enum StorageAction {
+    // Keep the file out of the storage cleanup, or let it be removed again
+    SetPinned(FileID, bool),
     None,
 }
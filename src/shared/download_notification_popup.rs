@@ -213,6 +213,8 @@ pub enum DownloadResult {
     #[default]
     Success,
     Failure,
+    // The server refused the download or the disk is full, with the reason to show
+    Refused(String),
 }
 
 #[derive(Live, LiveHook, Widget)]
@@ -278,9 +280,13 @@ impl WidgetMatchEvent for DownloadNotificationPopup {
 
 impl DownloadNotificationPopup {
     pub fn update_content(&mut self, cx: &mut Cx) {
-        match self.download_result {
+        match &self.download_result {
             DownloadResult::Success => self.show_success_content(cx),
             DownloadResult::Failure => self.show_failure_content(cx),
+            DownloadResult::Refused(message) => {
+                let message = message.clone();
+                self.show_refused_content(cx, &message)
+            }
         }
     }
 
@@ -316,6 +322,20 @@ impl DownloadNotificationPopup {
         );
     }
 
+    fn show_refused_content(&mut self, cx: &mut Cx, message: &str) {
+        self.view(id!(success_icon)).set_visible(cx, false);
+        self.view(id!(failure_icon)).set_visible(cx, true);
+
+        // Retry once the reason is dealt with
+        self.view(id!(success_actions)).set_visible(cx, false);
+        self.view(id!(failure_actions)).set_visible(cx, true);
+
+        self.label(id!(title))
+            .set_text(cx, "The download can't continue");
+
+        self.label(id!(summary)).set_text(cx, message);
+    }
+
     pub fn show_retry_content(&mut self, cx: &mut Cx) {
 
         let content = self.label(id!(summary));
@@ -2,6 +2,7 @@ use anyhow::Result;
 use makepad_widgets::math_f32::{vec4, Vec4};
 
 pub const BYTES_PER_MB: f64 = 1_048_576.0; // (1024^2)
+pub const BYTES_PER_GB: f64 = 1_073_741_824.0; // (1024^3)
 pub const HUGGING_FACE_BASE_URL: &str = "https://huggingface.co";
 
 pub fn format_model_size(size: &str) -> Result<String> {
@@ -24,6 +25,16 @@ pub fn format_model_downloaded_size(size: &str, progress: f64) -> Result<String>
     }
 }
 
+pub fn format_bytes(bytes: u64) -> String {
+    let size_mb = bytes as f64 / BYTES_PER_MB;
+
+    if size_mb >= 1024.0 {
+        format!("{:.2} GB", size_mb / 1024.0)
+    } else {
+        format!("{} MB", size_mb as i32)
+    }
+}
+
 pub fn hugging_face_model_url(model_id: &str) -> String {
     format!("{}/{}", HUGGING_FACE_BASE_URL, model_id)
 }